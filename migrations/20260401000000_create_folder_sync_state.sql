@@ -0,0 +1,9 @@
+-- Per-folder incremental sync state (CONDSTORE/QRESYNC, RFC 7162)
+CREATE TABLE IF NOT EXISTS folder_sync_state (
+    account_id TEXT NOT NULL,
+    folder TEXT NOT NULL,
+    highest_modseq INTEGER,
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    PRIMARY KEY (account_id, folder),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
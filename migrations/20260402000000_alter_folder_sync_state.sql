@@ -0,0 +1,3 @@
+-- Track UIDVALIDITY/UIDNEXT so renumbered mailboxes can be detected and resynced
+ALTER TABLE folder_sync_state ADD COLUMN uidvalidity INTEGER;
+ALTER TABLE folder_sync_state ADD COLUMN uidnext INTEGER;
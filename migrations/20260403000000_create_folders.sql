@@ -0,0 +1,17 @@
+-- Per-account folder catalogue refreshed on sync (LIST/LSUB/STATUS, RFC 6154 special-use)
+CREATE TABLE IF NOT EXISTS folders (
+    account_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    delimiter TEXT,
+    attributes TEXT NOT NULL DEFAULT '[]',
+    special_use TEXT,
+    subscribed INTEGER NOT NULL DEFAULT 0,
+    selectable INTEGER NOT NULL DEFAULT 1,
+    total_count INTEGER,
+    unread_count INTEGER,
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    PRIMARY KEY (account_id, name),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_folders_special_use ON folders(account_id, special_use);
@@ -0,0 +1,16 @@
+-- Gmail (X-GM-EXT-1): All Mail is synced once; ids and labels are kept per message
+ALTER TABLE messages ADD COLUMN gm_msgid INTEGER;
+ALTER TABLE messages ADD COLUMN gm_thrid INTEGER;
+ALTER TABLE messages ADD COLUMN gm_labels TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_gm_thrid ON messages(account_id, gm_thrid);
+
+-- Label membership resolved to folder names, so label folders can be listed virtually
+CREATE TABLE IF NOT EXISTS message_labels (
+    message_id INTEGER NOT NULL,
+    folder TEXT NOT NULL,
+    PRIMARY KEY (message_id, folder),
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_labels_folder ON message_labels(folder);
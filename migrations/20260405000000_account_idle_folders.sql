@@ -0,0 +1,2 @@
+-- Folders watched by the account's IDLE/NOTIFY watcher (JSON array; NULL = MAILORA_IDLE_FOLDERS)
+ALTER TABLE accounts ADD COLUMN idle_folders TEXT;
@@ -0,0 +1,15 @@
+-- Full header capture: address lists as JSON ({name, address}) next to the text columns
+-- (from_addr, to_addr, cc, bcc, reply_to), plus threading and mailing-list headers
+ALTER TABLE messages ADD COLUMN from_json TEXT;
+ALTER TABLE messages ADD COLUMN to_json TEXT;
+ALTER TABLE messages ADD COLUMN cc_json TEXT;
+ALTER TABLE messages ADD COLUMN bcc_json TEXT;
+ALTER TABLE messages ADD COLUMN reply_to_json TEXT;
+ALTER TABLE messages ADD COLUMN in_reply_to TEXT;
+ALTER TABLE messages ADD COLUMN references_ids TEXT; -- JSON array of Message-Ids, oldest first
+ALTER TABLE messages ADD COLUMN list_id TEXT;
+ALTER TABLE messages ADD COLUMN auto_submitted TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_message_id ON messages(account_id, message_id);
+CREATE INDEX IF NOT EXISTS idx_messages_in_reply_to ON messages(account_id, in_reply_to);
+CREATE INDEX IF NOT EXISTS idx_messages_list_id ON messages(account_id, list_id);
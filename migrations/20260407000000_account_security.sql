@@ -0,0 +1,4 @@
+-- Transport security per account: 'tls' (implicit), 'starttls' or 'none' (local dev only).
+-- NULL keeps the port-based default (143/587/25 -> starttls, otherwise tls).
+ALTER TABLE accounts ADD COLUMN imap_security TEXT;
+ALTER TABLE accounts ADD COLUMN smtp_security TEXT;
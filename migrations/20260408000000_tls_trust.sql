@@ -0,0 +1,11 @@
+-- Certificate trust per account: 'verify' (CA chain + hostname, default) or 'pin'
+-- (trust on first use: the first certificate seen per endpoint is pinned, later mismatches fail).
+ALTER TABLE accounts ADD COLUMN tls_policy TEXT;
+
+CREATE TABLE IF NOT EXISTS tls_pins (
+    account_id TEXT NOT NULL,
+    endpoint TEXT NOT NULL,          -- host:port
+    sha256 TEXT NOT NULL,            -- colon-separated hex of the leaf certificate DER
+    pinned_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (account_id, endpoint)
+);
@@ -0,0 +1,4 @@
+-- OAuth2 token lifecycle: 'ok' or 'reauth_required' (refresh token revoked/expired; the user
+-- must consent again via /oauth/start). Tokens themselves are stored sealed ("enc:" prefix).
+ALTER TABLE accounts ADD COLUMN oauth_status TEXT;
+ALTER TABLE accounts ADD COLUMN oauth_error TEXT;
@@ -0,0 +1,20 @@
+-- History of sync jobs run by the sync job queue (services/sync_job_service.rs).
+-- Jobs still queued/running when the process stops are marked 'interrupted' on the next start.
+CREATE TABLE IF NOT EXISTS sync_jobs (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    folder TEXT,                     -- NULL: every folder of the account
+    priority TEXT NOT NULL,          -- user, scheduled
+    status TEXT NOT NULL,            -- queued, running, done, failed, cancelled, interrupted
+    new_messages INTEGER NOT NULL DEFAULT 0,
+    updated_messages INTEGER NOT NULL DEFAULT 0,
+    deleted_messages INTEGER NOT NULL DEFAULT 0,
+    folders_synced INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at INTEGER NOT NULL,
+    started_at INTEGER,
+    finished_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_jobs_account ON sync_jobs(account_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_sync_jobs_status ON sync_jobs(status);
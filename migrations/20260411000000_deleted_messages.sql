@@ -0,0 +1,16 @@
+-- Messages moved to Trash by a delete, kept for `MAILORA_UNDO_TTL_SECS` so the delete can be
+-- undone with its token. trash_uid is NULL when the server did not report it (no UIDPLUS);
+-- undo then finds the message in Trash by Message-ID.
+CREATE TABLE IF NOT EXISTS deleted_messages (
+    token TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    folder TEXT NOT NULL,            -- where the message was deleted from
+    uid INTEGER NOT NULL,
+    trash_folder TEXT NOT NULL,
+    trash_uid INTEGER,
+    message_id TEXT,
+    deleted_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_deleted_messages_token ON deleted_messages(token);
+CREATE INDEX IF NOT EXISTS idx_deleted_messages_deleted_at ON deleted_messages(deleted_at);
@@ -0,0 +1,37 @@
+-- Drafts: saved locally on every autosave (each save is a new version, the last
+-- MAILORA_DRAFT_VERSIONS kept in draft_versions) and APPENDed to the account's \Drafts folder
+-- shortly after, replacing the previous server copy. synced_version < version means the
+-- server copy is behind.
+CREATE TABLE IF NOT EXISTS drafts (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    to_addr TEXT NOT NULL DEFAULT '',
+    subject TEXT NOT NULL DEFAULT '',
+    body TEXT NOT NULL DEFAULT '',
+    version INTEGER NOT NULL DEFAULT 1,
+    synced_version INTEGER NOT NULL DEFAULT 0,
+    message_id TEXT NOT NULL,        -- Message-ID of every server copy, without angle brackets
+    server_folder TEXT,
+    server_uid INTEGER,
+    status TEXT NOT NULL DEFAULT 'draft', -- draft, queued (handed to the outbox)
+    last_error TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_drafts_account ON drafts(account_id, updated_at DESC);
+
+CREATE TABLE IF NOT EXISTS draft_versions (
+    draft_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    to_addr TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    saved_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    PRIMARY KEY (draft_id, version),
+    FOREIGN KEY (draft_id) REFERENCES drafts(id) ON DELETE CASCADE
+);
+
+-- Outbox rows sent from a draft; the server draft is deleted once the send succeeds
+ALTER TABLE outbox ADD COLUMN draft_id TEXT;
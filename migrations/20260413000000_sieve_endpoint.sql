@@ -0,0 +1,3 @@
+-- ManageSieve server of an account; NULL = `_sieve._tcp` SRV record, else the IMAP host on 4190
+ALTER TABLE accounts ADD COLUMN sieve_host TEXT;
+ALTER TABLE accounts ADD COLUMN sieve_port INTEGER;
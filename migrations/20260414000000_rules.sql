@@ -0,0 +1,35 @@
+-- Client-side rules for accounts without a usable Sieve server: run by the hub on every new
+-- INBOX message, in ascending position. account_id NULL applies the rule to every account.
+CREATE TABLE IF NOT EXISTS rules (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT,
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    match_any INTEGER NOT NULL DEFAULT 0,
+    conditions TEXT NOT NULL DEFAULT '[]', -- JSON
+    actions TEXT NOT NULL DEFAULT '[]',    -- JSON
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_rules_position ON rules(position, created_at);
+
+-- One row per rule that matched a message, with the actions it ran
+CREATE TABLE IF NOT EXISTS rule_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id TEXT NOT NULL,
+    rule_name TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    folder TEXT NOT NULL,
+    uid INTEGER NOT NULL,
+    message_id TEXT,
+    subject TEXT,
+    actions TEXT NOT NULL,  -- JSON
+    error TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_rule_log_account ON rule_log(account_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_rule_log_rule ON rule_log(rule_id, created_at DESC);
@@ -0,0 +1,26 @@
+-- Mailbox quota (RFC 9208) as last read during sync, per quota root and resource. Units are
+-- the server's: STORAGE in KiB, MESSAGE in messages.
+CREATE TABLE IF NOT EXISTS quota_state (
+    account_id TEXT NOT NULL,
+    root TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    usage INTEGER NOT NULL,
+    quota_limit INTEGER NOT NULL,
+    checked_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    PRIMARY KEY (account_id, root, resource),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+-- A sample whenever usage or limit changes, and at least daily
+CREATE TABLE IF NOT EXISTS quota_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id TEXT NOT NULL,
+    root TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    usage INTEGER NOT NULL,
+    quota_limit INTEGER NOT NULL,
+    checked_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_quota_history_account ON quota_history(account_id, checked_at DESC);
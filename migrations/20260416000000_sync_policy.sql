@@ -0,0 +1,7 @@
+-- Per-account sync policy (services::sync_policy_service); NULL = default.
+-- Folder lists are JSON arrays of patterns; priority lists the folders to sync first.
+ALTER TABLE accounts ADD COLUMN sync_include TEXT;
+ALTER TABLE accounts ADD COLUMN sync_exclude TEXT;
+ALTER TABLE accounts ADD COLUMN sync_priority TEXT;
+ALTER TABLE accounts ADD COLUMN sync_window_days INTEGER;
+ALTER TABLE accounts ADD COLUMN sync_max_body_bytes INTEGER;
@@ -0,0 +1,21 @@
+-- Message changes applied to the cache while the server was unreachable, replayed to IMAP in
+-- id order per account. A move re-keys the cached row to (dest, local_uid) until the server
+-- reports the real UID.
+CREATE TABLE IF NOT EXISTS pending_ops (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id TEXT NOT NULL,
+    folder TEXT NOT NULL,
+    uid INTEGER NOT NULL,
+    mutation TEXT NOT NULL,  -- JSON
+    dest TEXT,
+    local_uid INTEGER,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'failed', 'conflict')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    last_error TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_ops_account ON pending_ops(account_id, status, id);
+CREATE INDEX IF NOT EXISTS idx_pending_ops_message ON pending_ops(account_id, folder, uid);
@@ -60,6 +60,40 @@ pub async fn check_and_fix_schema(pool: &SqlitePool) -> Result<()> {
     Ok(())
 }
 
+/// `events` started as a mail log with an IN/OUT direction; hub alerts (mailbox quota) are
+/// logged there as ALERT. SQLite can't alter a CHECK constraint, so the table is rebuilt once.
+pub async fn widen_event_directions(pool: &SqlitePool) -> Result<()> {
+    let sql: Option<String> = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'events'")
+        .fetch_optional(pool)
+        .await?;
+    match sql {
+        Some(sql) if !sql.contains("'ALERT'") => {}
+        _ => return Ok(()),
+    }
+    let mut tx = pool.begin().await?;
+    for stmt in [
+        "CREATE TABLE events_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            direction TEXT NOT NULL CHECK(direction IN ('IN','OUT','ALERT')),
+            mailbox TEXT NOT NULL,
+            actor TEXT,
+            peer TEXT,
+            subject TEXT,
+            ts INTEGER NOT NULL
+        )",
+        "INSERT INTO events_new (id, direction, mailbox, actor, peer, subject, ts) SELECT id, direction, mailbox, actor, peer, subject, ts FROM events",
+        "DROP TABLE events",
+        "ALTER TABLE events_new RENAME TO events",
+        "CREATE INDEX IF NOT EXISTS idx_events_ts ON events(ts DESC)",
+        "CREATE INDEX IF NOT EXISTS idx_events_mailbox ON events(mailbox)",
+    ] {
+        sqlx::query(stmt).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    tracing::info!("events table now accepts ALERT entries");
+    Ok(())
+}
+
 fn tokenize_sql_statements(sql: &str) -> Vec<String> {
     // Tokenize SQL into statements, preserving CREATE TRIGGER ... END; blocks
     let mut stmts: Vec<String> = Vec::new();
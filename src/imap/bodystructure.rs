@@ -0,0 +1,188 @@
+/// BODYSTRUCTURE helpers: attachment metadata without downloading the message
+use async_imap::imap_proto::{BodyParams, BodyStructure};
+
+use crate::imap::sync::decode_subject;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentPart {
+    pub filename: Option<String>,
+    pub content_type: String,
+    /// Encoded size in octets as reported by the server
+    pub size: u32,
+    pub content_id: Option<String>,
+    pub is_inline: bool,
+}
+
+/// Walk a BODYSTRUCTURE and return the parts the UI treats as attachments:
+/// anything with a filename, plus media parts that are not the text/html body.
+pub fn attachments(bs: &BodyStructure<'_>) -> Vec<AttachmentPart> {
+    let mut out = Vec::new();
+    collect(bs, &mut out);
+    out
+}
+
+fn collect(bs: &BodyStructure<'_>, out: &mut Vec<AttachmentPart>) {
+    let (common, other) = match bs {
+        BodyStructure::Multipart { bodies, .. } => {
+            for b in bodies {
+                collect(b, out);
+            }
+            return;
+        }
+        BodyStructure::Basic { common, other, .. }
+        | BodyStructure::Text { common, other, .. }
+        | BodyStructure::Message { common, other, .. } => (common, other),
+    };
+
+    let c_type = common.ty.ty.to_ascii_lowercase();
+    let subtype = common.ty.subtype.to_ascii_lowercase();
+    let is_body = c_type == "text" && (subtype == "plain" || subtype == "html");
+    let is_media = matches!(c_type.as_str(), "image" | "video" | "audio" | "application");
+
+    let filename = common
+        .disposition
+        .as_ref()
+        .and_then(|d| mime_param(&d.params, "filename"))
+        .or_else(|| mime_param(&common.ty.params, "name"));
+
+    if filename.is_none() && (!is_media || is_body) {
+        return;
+    }
+
+    let content_id = other
+        .id
+        .as_ref()
+        .map(|s| s.trim().trim_matches(['<', '>']).to_string());
+    let inline_disposition = common
+        .disposition
+        .as_ref()
+        .map(|d| d.ty.eq_ignore_ascii_case("inline"))
+        .unwrap_or(false);
+
+    out.push(AttachmentPart {
+        filename,
+        content_type: format!("{}/{}", c_type, subtype),
+        size: other.octets,
+        is_inline: inline_disposition || content_id.is_some(),
+        content_id,
+    });
+}
+
+/// Look up a MIME parameter, decoding RFC 2047 encoded-words and RFC 2231
+/// (`name*=charset''value`, `name*0*=...`) continuations.
+fn mime_param(params: &BodyParams<'_>, key: &str) -> Option<String> {
+    let params = params.as_ref()?;
+
+    if let Some((_, v)) = params.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+        return Some(if v.contains("=?") {
+            decode_subject(v.as_bytes())
+        } else {
+            v.to_string()
+        });
+    }
+
+    // RFC 2231: collect `key*`, `key*N` and `key*N*` pieces in order
+    let prefix = format!("{}*", key.to_ascii_lowercase());
+    let mut pieces: Vec<(usize, bool, &str)> = Vec::new();
+    for (k, v) in params {
+        let k = k.to_ascii_lowercase();
+        let Some(rest) = k.strip_prefix(&prefix) else { continue };
+        let (idx, extended) = match rest {
+            "" => (0, true),
+            r => {
+                let extended = r.ends_with('*');
+                match r.trim_end_matches('*').parse::<usize>() {
+                    Ok(i) => (i, extended),
+                    Err(_) => continue,
+                }
+            }
+        };
+        pieces.push((idx, extended, v.as_ref()));
+    }
+    if pieces.is_empty() {
+        return None;
+    }
+    pieces.sort_by_key(|(i, _, _)| *i);
+
+    let mut bytes = Vec::new();
+    for (n, (_, extended, v)) in pieces.iter().enumerate() {
+        if !extended {
+            bytes.extend_from_slice(v.as_bytes());
+            continue;
+        }
+        // Only the first piece carries charset'language'
+        let encoded = if n == 0 {
+            v.splitn(3, '\'').nth(2).unwrap_or(v)
+        } else {
+            v
+        };
+        bytes.extend(percent_decode(encoded));
+    }
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'%' && i + 2 < b.len() {
+            let hex = std::str::from_utf8(&b[i + 1..i + 3]).ok();
+            if let Some(v) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(v);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(b[i]);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_imap::imap_proto::{parser::parse_response, AttributeValue, Response};
+
+    fn parse_bodystructure(line: &str) -> Vec<AttachmentPart> {
+        let (_, resp) = parse_response(line.as_bytes()).expect("parse");
+        match resp {
+            Response::Fetch(_, attrs) => attrs
+                .iter()
+                .find_map(|a| match a {
+                    AttributeValue::BodyStructure(bs) => Some(attachments(bs)),
+                    _ => None,
+                })
+                .expect("bodystructure"),
+            _ => panic!("not a FETCH response"),
+        }
+    }
+
+    #[test]
+    fn test_plain_message_has_no_attachments() {
+        let atts = parse_bodystructure(
+            "* 1 FETCH (BODYSTRUCTURE (\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"7BIT\" 12 1 NIL NIL NIL NIL))\r\n",
+        );
+        assert!(atts.is_empty());
+    }
+
+    #[test]
+    fn test_multipart_with_pdf_and_inline_image() {
+        let atts = parse_bodystructure(concat!(
+            "* 1 FETCH (BODYSTRUCTURE (",
+            "(\"TEXT\" \"HTML\" (\"CHARSET\" \"utf-8\") NIL NIL \"QUOTED-PRINTABLE\" 300 10 NIL NIL NIL NIL)",
+            "(\"IMAGE\" \"PNG\" (\"NAME\" \"logo.png\") \"<logo@x>\" NIL \"BASE64\" 1200 NIL (\"INLINE\" NIL) NIL NIL)",
+            "(\"APPLICATION\" \"PDF\" NIL NIL NIL \"BASE64\" 5000 NIL (\"ATTACHMENT\" (\"FILENAME*\" \"utf-8''G%C3%B6nderilen.pdf\")) NIL NIL)",
+            " \"MIXED\" (\"BOUNDARY\" \"b1\") NIL NIL NIL))\r\n"
+        ));
+        assert_eq!(atts.len(), 2);
+        assert_eq!(atts[0].filename.as_deref(), Some("logo.png"));
+        assert_eq!(atts[0].content_id.as_deref(), Some("logo@x"));
+        assert!(atts[0].is_inline);
+        assert_eq!(atts[1].filename.as_deref(), Some("Gönderilen.pdf"));
+        assert_eq!(atts[1].content_type, "application/pdf");
+        assert_eq!(atts[1].size, 5000);
+        assert!(!atts[1].is_inline);
+    }
+}
@@ -1,21 +1,164 @@
 use anyhow::Result;
 use async_imap::Session;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
 use tokio_native_tls::native_tls::TlsConnector;
-use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_native_tls::TlsStream;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
 use tokio::time::{sleep, timeout, Duration};
 
+use crate::imap::xoauth2::XOAuth2;
+use crate::models::account::SecurityMode;
+use crate::services::mail_auth_service::MailAuth;
+
 pub struct ImapCapabilities {
     pub condstore: bool,
     pub qresync: bool,
+    /// Gmail extensions (X-GM-MSGID, X-GM-THRID, X-GM-LABELS)
+    pub gmail: bool,
+    /// NOTIFY (RFC 5465): one connection can watch several mailboxes
+    pub notify: bool,
+    /// MOVE (RFC 6851)
+    pub move_cmd: bool,
+    /// UIDPLUS (RFC 4315): COPYUID/APPENDUID response codes and UID EXPUNGE
+    pub uidplus: bool,
+    /// QUOTA (RFC 9208, or its predecessor RFC 2087): GETQUOTAROOT/GETQUOTA
+    pub quota: bool,
+}
+
+impl ImapCapabilities {
+    fn from_server(caps: &async_imap::types::Capabilities) -> Self {
+        let qresync = caps.has_str("QRESYNC");
+        ImapCapabilities {
+            // QRESYNC implies CONDSTORE (RFC 7162 §3.2.3)
+            condstore: qresync || caps.has_str("CONDSTORE"),
+            qresync,
+            gmail: caps.has_str("X-GM-EXT-1"),
+            notify: caps.has_str("NOTIFY"),
+            move_cmd: caps.has_str("MOVE"),
+            uidplus: caps.has_str("UIDPLUS"),
+            // RFC 9208 servers may advertise only the QUOTA=RES-* resources they support
+            quota: caps.has_str("QUOTA") || caps.has_str("QUOTA=RES-STORAGE") || caps.has_str("QUOTA=RES-MESSAGE"),
+        }
+    }
+}
+
+/// Mailbox name as an IMAP quoted string, for commands built by hand
+pub fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Socket under an IMAP session: TLS (implicit or after STARTTLS) or plain TCP
+#[derive(Debug)]
+pub enum MailStream {
+    Tls(Box<TlsStream<TcpStream>>),
+    Plain(TcpStream),
+}
+
+impl AsyncRead for MailStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MailStream::Tls(s) => Pin::new(s.as_mut()).poll_read(cx, buf),
+            MailStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MailStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MailStream::Tls(s) => Pin::new(s.as_mut()).poll_write(cx, buf),
+            MailStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MailStream::Tls(s) => Pin::new(s.as_mut()).poll_flush(cx),
+            MailStream::Plain(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MailStream::Tls(s) => Pin::new(s.as_mut()).poll_shutdown(cx),
+            MailStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+pub type RawSession = Session<Compat<MailStream>>;
+
+/// Drop queued unsolicited responses. async-imap's channel holds 100 and a full one stalls the
+/// connection, so a session running many commands has to empty it between them.
+pub fn clear_unsolicited(session: &mut RawSession) {
+    while session.unsolicited_responses.try_recv().is_ok() {}
 }
 
 pub struct ImapSession {
-    pub session: Session<tokio_util::compat::Compat<tokio_native_tls::TlsStream<TcpStream>>>,
+    pub session: RawSession,
     pub caps: ImapCapabilities,
 }
 
-pub async fn connect(host: &str, port: u16, user: &str, pass: &str) -> Result<ImapSession> {
+/// TLS handshake honouring the account's certificate policy (CA verification or pinning)
+pub(crate) async fn secure(account_id: &str, host: &str, port: u16, tcp: TcpStream) -> Result<MailStream> {
+    let pinned = crate::tls::accepts_self_signed(account_id);
+    let connector = TlsConnector::builder().danger_accept_invalid_certs(pinned).build()?;
+    let stream = tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, tcp)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "TLS certificate verification failed for {}:{}: {} (use tls_policy \"pin\" for self-signed servers)",
+                host,
+                port,
+                e
+            )
+        })?;
+    if pinned {
+        let cert = stream
+            .get_ref()
+            .peer_certificate()?
+            .ok_or_else(|| anyhow::anyhow!("{}:{} presented no TLS certificate", host, port))?;
+        crate::tls::check_peer(account_id, host, port, &cert.to_der()?)?;
+    }
+    Ok(MailStream::Tls(Box::new(stream)))
+}
+
+/// Open the socket and secure it according to `security`; STARTTLS consumes the greeting
+async fn open_stream(account_id: &str, host: &str, port: u16, security: SecurityMode) -> Result<MailStream> {
+    security.ensure_allowed(host)?;
+    let tcp = TcpStream::connect((host, port)).await?;
+    match security {
+        SecurityMode::Tls => secure(account_id, host, port, tcp).await,
+        SecurityMode::StartTls => {
+            let mut client = async_imap::Client::new(tcp.compat());
+            client
+                .read_response()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("connection closed before greeting"))??;
+            client
+                .run_command_and_check_ok("STARTTLS", None)
+                .await
+                .map_err(|e| anyhow::anyhow!("STARTTLS failed: {}", e))?;
+            secure(account_id, host, port, client.into_inner().into_inner()).await
+        }
+        SecurityMode::None => Ok(MailStream::Plain(tcp)),
+    }
+}
+
+/// Log in to `host` (LOGIN or AUTHENTICATE XOAUTH2); `account_id` selects the TLS certificate
+/// policy (see [`crate::tls`])
+pub async fn connect(
+    account_id: &str,
+    host: &str,
+    port: u16,
+    security: SecurityMode,
+    auth: &MailAuth,
+) -> Result<ImapSession> {
     let attempts: u32 = std::env::var("MAILORA_IMAP_RETRIES")
         .ok()
         .and_then(|v| v.parse().ok())
@@ -23,27 +166,34 @@ pub async fn connect(host: &str, port: u16, user: &str, pass: &str) -> Result<Im
     let mut last_err: Option<anyhow::Error> = None;
     for i in 0..attempts {
         let res = timeout(Duration::from_secs(10), async {
-            let tcp = TcpStream::connect((host, port)).await?;
-            let tls = TlsConnector::builder()
-                .danger_accept_invalid_certs(true)
-                .build()?; // TODO: remove danger in prod
-            let tls = tokio_native_tls::TlsConnector::from(tls);
-            let tls_stream = tls.connect(host, tcp).await?;
-            let compat = tls_stream.compat();
-            let client = async_imap::Client::new(compat);
-            let session = client
-                .login(user, pass)
-                .await
-                .map_err(|e| anyhow::anyhow!("login failed: {:?}", e))?;
+            let stream = open_stream(account_id, host, port, security).await?;
+            let client = async_imap::Client::new(stream.compat());
+            let session = match auth {
+                MailAuth::Password { user, password } => client
+                    .login(user, password)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("login failed: {:?}", e.0))?,
+                MailAuth::XOAuth2 { user, access_token } => client
+                    .authenticate("XOAUTH2", XOAuth2::new(user, access_token))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("XOAUTH2 authentication failed: {:?}", e.0))?,
+            };
             Ok::<_, anyhow::Error>(session)
         })
         .await;
         match res {
-            Ok(Ok(session)) => {
-                let caps = ImapCapabilities {
-                    condstore: false,
-                    qresync: false,
+            Ok(Ok(mut session)) => {
+                let mut caps = match session.capabilities().await {
+                    Ok(c) => ImapCapabilities::from_server(&c),
+                    Err(e) => {
+                        tracing::debug!("CAPABILITY failed, assuming none: {e}");
+                        ImapCapabilities { condstore: false, qresync: false, gmail: false, notify: false, move_cmd: false, uidplus: false, quota: false }
+                    }
                 };
+                // QRESYNC must be ENABLEd before VANISHED responses are sent
+                if caps.qresync && session.run_command_and_check_ok("ENABLE QRESYNC").await.is_err() {
+                    caps.qresync = false;
+                }
                 return Ok(ImapSession { session, caps });
             }
             Ok(Err(e)) => last_err = Some(e),
@@ -1,9 +1,11 @@
 use anyhow::Result;
-use async_imap::Session;
-use futures::StreamExt;
-use tokio::net::TcpStream;
-use tokio_native_tls::native_tls::TlsConnector;
-use tokio_util::compat::TokioAsyncReadCompatExt; // for .next()
+use async_imap::imap_proto::NameAttribute;
+use base64::Engine;
+use futures::StreamExt; // for .next()
+
+use crate::imap::conn::RawSession;
+use crate::models::account::SecurityMode;
+use crate::services::mail_auth_service::MailAuth;
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct FolderInfo {
@@ -11,56 +13,171 @@ pub struct FolderInfo {
     pub flags: Vec<String>,
 }
 
-async fn connect_and_login(
-    host: &str,
-    port: u16,
-    user: &str,
-    pass: &str,
-) -> Result<Session<tokio_util::compat::Compat<tokio_native_tls::TlsStream<TcpStream>>>> {
-    let tcp = TcpStream::connect((host, port)).await?;
-    let tls = TlsConnector::builder().build()?;
-    let tls = tokio_native_tls::TlsConnector::from(tls);
-    let tls_stream = tls.connect(host, tcp).await?;
-    let compat = tls_stream.compat();
-    let client = async_imap::Client::new(compat);
-    let session = client
-        .login(user, pass)
-        .await
-        .map_err(|e| anyhow::anyhow!("login failed: {:?}", e))?;
-    Ok(session)
-}
-
 pub async fn list_mailboxes(
+    account_id: &str,
     host: &str,
     port: u16,
-    user: &str,
-    pass: &str,
+    security: SecurityMode,
+    auth: &MailAuth,
 ) -> Result<Vec<FolderInfo>> {
-    let mut session = connect_and_login(host, port, user, pass).await?;
+    let mut imap = crate::imap::pool::acquire(account_id, host, port, security, auth).await?;
+    let session = &mut imap.session;
     let mut out = Vec::new();
+    let mut clean = false;
     if let Ok(list_stream) = session.list(None, Some("*")).await {
         let mut list = list_stream;
+        clean = true;
         while let Some(item) = list.next().await {
             let mailbox = match item {
                 Ok(m) => m,
                 Err(e) => {
                     tracing::warn!("list item error: {e}");
+                    clean = false;
                     continue;
                 }
             };
             let name = mailbox.name().to_string();
-            let attrs: Vec<String> = mailbox
-                .attributes()
-                .iter()
-                .map(|a| format!("{:?}", a))
-                .collect();
+            let attrs: Vec<String> = mailbox.attributes().iter().map(attribute_str).collect();
             out.push(FolderInfo { name, flags: attrs });
         }
     }
-    let _ = session.logout().await;
+    if clean {
+        imap.release();
+    }
     Ok(out)
 }
 
+/// Modified UTF-7 (RFC 3501 §5.1.3) form of a mailbox name, as sent on the wire
+pub fn encode_utf7(name: &str) -> String {
+    fn flush(out: &mut String, pending: &mut Vec<u16>) {
+        if pending.is_empty() {
+            return;
+        }
+        let bytes: Vec<u8> = pending.iter().flat_map(|u| u.to_be_bytes()).collect();
+        out.push('&');
+        out.push_str(&MUTF7.encode(bytes));
+        out.push('-');
+        pending.clear();
+    }
+
+    let mut out = String::with_capacity(name.len());
+    let mut pending: Vec<u16> = Vec::new();
+    for c in name.chars() {
+        if (' '..='~').contains(&c) {
+            flush(&mut out, &mut pending);
+            if c == '&' {
+                out.push_str("&-");
+            } else {
+                out.push(c);
+            }
+        } else {
+            pending.extend(c.encode_utf16(&mut [0; 2]).iter());
+        }
+    }
+    flush(&mut out, &mut pending);
+    out
+}
+
+/// Human-readable form of a wire mailbox name; names that aren't valid modified UTF-7 are
+/// returned unchanged
+pub fn decode_utf7(wire: &str) -> String {
+    fn decode(wire: &str) -> Option<String> {
+        let mut out = String::with_capacity(wire.len());
+        let mut rest = wire;
+        while let Some(start) = rest.find('&') {
+            out.push_str(&rest[..start]);
+            let end = start + rest[start..].find('-')?;
+            let chunk = &rest[start + 1..end];
+            if chunk.is_empty() {
+                out.push('&');
+            } else {
+                let bytes = MUTF7.decode(chunk).ok()?;
+                if bytes.len() % 2 != 0 {
+                    return None;
+                }
+                let units: Vec<u16> = bytes.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+                out.push_str(&String::from_utf16(&units).ok()?);
+            }
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        Some(out)
+    }
+    decode(wire).unwrap_or_else(|| wire.to_string())
+}
+
+const MUTF7: base64::engine::GeneralPurpose =
+    base64::engine::GeneralPurpose::new(&base64::alphabet::IMAP_MUTF7, base64::engine::general_purpose::NO_PAD);
+
+/// CREATE, RENAME, DELETE, SUBSCRIBE and UNSUBSCRIBE take wire names (see [`encode_utf7`]);
+/// async-imap quotes them
+pub async fn create(session: &mut RawSession, wire_name: &str) -> Result<()> {
+    session.create(wire_name).await?;
+    Ok(())
+}
+
+pub async fn rename(session: &mut RawSession, from: &str, to: &str) -> Result<()> {
+    session.rename(from, to).await?;
+    Ok(())
+}
+
+pub async fn delete(session: &mut RawSession, wire_name: &str) -> Result<()> {
+    session.delete(wire_name).await?;
+    Ok(())
+}
+
+pub async fn set_subscribed(session: &mut RawSession, wire_name: &str, subscribed: bool) -> Result<()> {
+    if subscribed {
+        session.subscribe(wire_name).await?;
+    } else {
+        session.unsubscribe(wire_name).await?;
+    }
+    Ok(())
+}
+
+/// Wire form of a LIST attribute (`\Sent`, `\Noselect`, `\HasChildren`, ...)
+pub fn attribute_str(attr: &NameAttribute<'_>) -> String {
+    match attr {
+        NameAttribute::NoInferiors => "\\Noinferiors".into(),
+        NameAttribute::NoSelect => "\\Noselect".into(),
+        NameAttribute::Marked => "\\Marked".into(),
+        NameAttribute::Unmarked => "\\Unmarked".into(),
+        NameAttribute::All => "\\All".into(),
+        NameAttribute::Archive => "\\Archive".into(),
+        NameAttribute::Drafts => "\\Drafts".into(),
+        NameAttribute::Flagged => "\\Flagged".into(),
+        NameAttribute::Junk => "\\Junk".into(),
+        NameAttribute::Sent => "\\Sent".into(),
+        NameAttribute::Trash => "\\Trash".into(),
+        NameAttribute::Extension(s) => s.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// RFC 6154 special-use role of a mailbox, if advertised
+pub fn special_use(attrs: &[NameAttribute<'_>]) -> Option<&'static str> {
+    attrs.iter().find_map(|a| match a {
+        NameAttribute::All => Some("\\All"),
+        NameAttribute::Archive => Some("\\Archive"),
+        NameAttribute::Drafts => Some("\\Drafts"),
+        NameAttribute::Flagged => Some("\\Flagged"),
+        NameAttribute::Junk => Some("\\Junk"),
+        NameAttribute::Sent => Some("\\Sent"),
+        NameAttribute::Trash => Some("\\Trash"),
+        _ => None,
+    })
+}
+
+/// Move `name` to the front of a candidate list, inserting it if missing
+pub fn promote(candidates: &mut Vec<String>, name: &str) {
+    if let Some(pos) = candidates.iter().position(|f| f == name) {
+        let f = candidates.remove(pos);
+        candidates.insert(0, f);
+    } else {
+        candidates.insert(0, name.to_string());
+    }
+}
+
 pub fn detect_sent_candidates(names: &[String]) -> Vec<String> {
     let mut out = Vec::new();
     for n in names {
@@ -82,3 +199,20 @@ pub fn detect_sent_candidates(names: &[String]) -> Vec<String> {
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modified_utf7() {
+        assert_eq!(encode_utf7("Gönderilmiş"), "G&APY-nderilmi&AV8-");
+        assert_eq!(decode_utf7("G&APY-nderilmi&AV8-"), "Gönderilmiş");
+        assert_eq!(encode_utf7("Tom & Jerry"), "Tom &- Jerry");
+        assert_eq!(decode_utf7("Tom &- Jerry"), "Tom & Jerry");
+        // RFC 3501 example
+        assert_eq!(encode_utf7("~peter/mail/台北/日本語"), "~peter/mail/&U,BTFw-/&ZeVnLIqe-");
+        assert_eq!(decode_utf7("INBOX"), "INBOX");
+        assert_eq!(decode_utf7("broken&AAA"), "broken&AAA");
+    }
+}
@@ -0,0 +1,128 @@
+/// Gmail IMAP extensions (X-GM-EXT-1): message/thread ids and labels
+use anyhow::Result;
+use async_imap::imap_proto::{AttributeValue, Response};
+use async_imap::types::UnsolicitedResponse;
+use std::collections::HashMap;
+
+use crate::imap::conn::RawSession;
+use crate::services::folder_service::FolderRecord;
+
+/// async-imap has no accessors for X-GM-* attributes, so the FETCH responses are read from the
+/// unsolicited channel, which holds 100 entries and drops the rest; stay below that per command.
+pub const META_CHUNK: usize = 90;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GmailMeta {
+    pub uid: u32,
+    pub msgid: Option<u64>,
+    pub thrid: Option<u64>,
+    pub labels: Vec<String>,
+}
+
+/// `UID FETCH <uids> (UID X-GM-MSGID X-GM-THRID X-GM-LABELS)` in the selected mailbox.
+/// At most [`META_CHUNK`] UIDs per call.
+pub async fn fetch_meta(session: &mut RawSession, uids: &[u32]) -> Result<Vec<GmailMeta>> {
+    debug_assert!(uids.len() <= META_CHUNK);
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Leftovers from earlier commands would count against the channel capacity
+    crate::imap::conn::clear_unsolicited(session);
+
+    let uid_set = uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
+    session
+        .run_command_and_check_ok(format!(
+            "UID FETCH {} (UID X-GM-MSGID X-GM-THRID X-GM-LABELS)",
+            uid_set
+        ))
+        .await?;
+
+    let mut out = Vec::new();
+    while let Ok(resp) = session.unsolicited_responses.try_recv() {
+        if let UnsolicitedResponse::Other(data) = resp {
+            if let Some(meta) = parse_meta(data.parsed()) {
+                out.push(meta);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn parse_meta(resp: &Response<'_>) -> Option<GmailMeta> {
+    let Response::Fetch(_, attrs) = resp else { return None };
+    let mut meta = GmailMeta { uid: 0, msgid: None, thrid: None, labels: Vec::new() };
+    for attr in attrs {
+        match attr {
+            AttributeValue::Uid(u) => meta.uid = *u,
+            AttributeValue::GmailMsgId(id) => meta.msgid = Some(*id),
+            AttributeValue::GmailThrId(id) => meta.thrid = Some(*id),
+            // Quoted system labels arrive with the backslash still escaped ("\\Important")
+            AttributeValue::GmailLabels(ls) => {
+                meta.labels = ls.iter().map(|l| l.replace("\\\\", "\\")).collect()
+            }
+            _ => {}
+        }
+    }
+    (meta.uid != 0).then_some(meta)
+}
+
+/// Folder names by LIST attribute (`\All`, `\Sent`, `\Important`, ...) from the folder catalogue
+pub fn role_folders(folders: &[FolderRecord]) -> HashMap<String, String> {
+    let mut roles = HashMap::new();
+    for f in folders {
+        for attr in &f.flags {
+            if matches!(
+                attr.as_str(),
+                "\\All" | "\\Sent" | "\\Drafts" | "\\Flagged" | "\\Important" | "\\Junk" | "\\Trash"
+            ) {
+                roles.entry(attr.clone()).or_insert_with(|| f.name.clone());
+            }
+        }
+    }
+    roles
+}
+
+/// Folder a Gmail label is exposed as. System labels map to their special-use folder;
+/// user labels are folders of the same name.
+pub fn label_folder(label: &str, roles: &HashMap<String, String>) -> Option<String> {
+    let role = match label {
+        "\\Inbox" => return Some("INBOX".to_string()),
+        "\\Sent" => "\\Sent",
+        "\\Draft" => "\\Drafts",
+        "\\Starred" => "\\Flagged",
+        "\\Important" => "\\Important",
+        l if l.starts_with('\\') => return None,
+        l => return Some(l.to_string()),
+    };
+    roles.get(role).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_imap::imap_proto::parser::parse_response;
+
+    #[test]
+    fn test_parse_meta_and_label_folders() {
+        let line = "* 12 FETCH (X-GM-THRID 1278455344230334865 X-GM-MSGID 1278455344230334866 X-GM-LABELS (\\Inbox \\Sent \"\\\\Important\" Work/Projects) UID 42)\r\n";
+        let (_, resp) = parse_response(line.as_bytes()).expect("parse");
+        let meta = parse_meta(&resp).expect("meta");
+        assert_eq!(meta.uid, 42);
+        assert_eq!(meta.msgid, Some(1278455344230334866));
+        assert_eq!(meta.thrid, Some(1278455344230334865));
+        assert_eq!(meta.labels, vec!["\\Inbox", "\\Sent", "\\Important", "Work/Projects"]);
+
+        let mut roles = HashMap::new();
+        roles.insert("\\Sent".to_string(), "[Gmail]/Sent Mail".to_string());
+        let folders: Vec<Option<String>> = meta.labels.iter().map(|l| label_folder(l, &roles)).collect();
+        assert_eq!(
+            folders,
+            vec![
+                Some("INBOX".to_string()),
+                Some("[Gmail]/Sent Mail".to_string()),
+                None,
+                Some("Work/Projects".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+/// RFC 5322 header capture for synced messages (from `BODY[HEADER]` or a full message)
+use mail_parser::{Addr, HeaderValue, Message};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Address {
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageHeaders {
+    pub subject: String,
+    pub message_id: String,
+    /// RFC 3339
+    pub date: Option<String>,
+    pub from: Vec<Address>,
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+    pub bcc: Vec<Address>,
+    pub reply_to: Vec<Address>,
+    /// Message-Ids without angle brackets
+    pub in_reply_to: Vec<String>,
+    pub references: Vec<String>,
+    pub list_id: Option<String>,
+    pub auto_submitted: Option<String>,
+}
+
+impl Address {
+    /// `Name <addr>` or bare `addr`
+    pub fn display(&self) -> String {
+        let email = self.address.as_deref().unwrap_or("");
+        match self.name.as_deref().filter(|n| !n.is_empty()) {
+            Some(name) => format!("{} <{}>", name, email),
+            None => email.to_string(),
+        }
+    }
+}
+
+/// Comma-separated display form of an address list, for the searchable text columns
+pub fn display_list(list: &[Address]) -> String {
+    list.iter().map(Address::display).collect::<Vec<_>>().join(", ")
+}
+
+fn to_address(addr: &Addr<'_>) -> Address {
+    Address {
+        name: addr.name.as_ref().map(|n| n.to_string()),
+        address: addr.address.as_ref().map(|a| a.to_string()),
+    }
+}
+
+/// Every address of a header, flattening RFC 5322 groups
+fn addresses(value: &HeaderValue<'_>) -> Vec<Address> {
+    match value {
+        HeaderValue::Address(a) => vec![to_address(a)],
+        HeaderValue::AddressList(list) => list.iter().map(to_address).collect(),
+        HeaderValue::Group(g) => g.addresses.iter().map(to_address).collect(),
+        HeaderValue::GroupList(groups) => groups
+            .iter()
+            .flat_map(|g| g.addresses.iter().map(to_address))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn ids(value: &HeaderValue<'_>) -> Vec<String> {
+    value
+        .as_text_list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|id| id.trim_matches(|c| c == '<' || c == '>').to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+/// Parse a header block (a full message works too); unparseable input yields empty headers
+pub fn parse(raw: &[u8]) -> MessageHeaders {
+    let Some(msg) = Message::parse(raw) else { return MessageHeaders::default() };
+
+    // List-Id is parsed as an address: "Name <list.example.com>"
+    let list_id = match msg.list_id() {
+        HeaderValue::Address(a) => a.address.as_ref().map(|a| a.to_string()),
+        HeaderValue::Text(t) => Some(t.trim_matches(|c| c == '<' || c == '>').to_string()),
+        _ => None,
+    };
+
+    MessageHeaders {
+        subject: msg.subject().unwrap_or("").to_string(),
+        message_id: msg.message_id().unwrap_or("").to_string(),
+        date: msg.date().map(|d| d.to_rfc3339()),
+        from: addresses(msg.from()),
+        to: addresses(msg.to()),
+        cc: addresses(msg.cc()),
+        bcc: addresses(msg.bcc()),
+        reply_to: addresses(msg.reply_to()),
+        in_reply_to: ids(msg.in_reply_to()),
+        references: ids(msg.references()),
+        list_id,
+        auto_submitted: msg
+            .header_raw("Auto-Submitted")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .filter(|v| !v.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let raw = concat!(
+            "From: \"Ada Lovelace\" <ada@example.com>\r\n",
+            "To: bob@example.com, Carol <carol@example.com>\r\n",
+            "Cc: Team: dave@example.com, erin@example.com;\r\n",
+            "Reply-To: list@example.com\r\n",
+            "Subject: Re: Engines\r\n",
+            "Message-ID: <m2@example.com>\r\n",
+            "In-Reply-To: <m1@example.com>\r\n",
+            "References: <m0@example.com> <m1@example.com>\r\n",
+            "List-Id: Engines <engines.example.com>\r\n",
+            "Auto-Submitted: Auto-Replied\r\n",
+            "\r\n"
+        );
+        let h = parse(raw.as_bytes());
+        assert_eq!(h.subject, "Re: Engines");
+        assert_eq!(h.message_id, "m2@example.com");
+        assert_eq!(display_list(&h.from), "Ada Lovelace <ada@example.com>");
+        assert_eq!(display_list(&h.to), "bob@example.com, Carol <carol@example.com>");
+        assert_eq!(display_list(&h.cc), "dave@example.com, erin@example.com");
+        assert!(h.bcc.is_empty());
+        assert_eq!(display_list(&h.reply_to), "list@example.com");
+        assert_eq!(h.in_reply_to, vec!["m1@example.com"]);
+        assert_eq!(h.references, vec!["m0@example.com", "m1@example.com"]);
+        assert_eq!(h.list_id.as_deref(), Some("engines.example.com"));
+        assert_eq!(h.auto_submitted.as_deref(), Some("auto-replied"));
+    }
+}
@@ -1,111 +1,99 @@
-/// IMAP IDLE watcher for real-time mail notifications
+/// IMAP IDLE (RFC 2177) and NOTIFY (RFC 5465) primitives for long-lived watcher sessions.
+///
+/// The session is handed back after every wakeup, so a watcher keeps a single login for
+/// its whole lifetime and can run a sync on it before going back to IDLE.
 use anyhow::Result;
-use async_imap::{Client, Session};
+use async_imap::extensions::idle::IdleResponse;
+use async_imap::imap_proto::{MailboxDatum, Response};
+use async_imap::types::UnsolicitedResponse;
+use std::collections::BTreeSet;
 use std::time::Duration;
-use tokio::net::TcpStream;
-use tokio_native_tls::TlsStream;
-use tokio_util::compat::Compat;
 
-#[allow(dead_code)]
-type ImapSession = Session<Compat<TlsStream<TcpStream>>>;
+use crate::imap::conn::{quote, ImapSession};
 
-/// Configuration for an IDLE watcher task
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct IdleConfig {
-    pub account_id: String,
-    pub host: String,
-    pub port: u16,
-    pub email: String,
-    pub password: String,
-    pub folder: String, // typically "INBOX"
-}
+/// Servers may drop an IDLE after 30 minutes (RFC 2177 §3); re-issue it a little earlier
+pub const IDLE_REFRESH: Duration = Duration::from_secs(29 * 60);
 
-/// Start an IDLE watcher task that monitors new mail and triggers callbacks
-pub async fn start_idle_watcher(
-    config: IdleConfig,
-    on_new_mail: impl Fn(String, String) + Send + Sync + 'static,
-) -> Result<tokio::task::JoinHandle<()>> {
-    let handle = tokio::spawn(async move {
-        if let Err(e) = idle_loop(config.clone(), on_new_mail).await {
-            tracing::error!(account=%config.account_id, "IDLE loop failed: {e}");
-        }
-    });
-    Ok(handle)
+const NOTIFY_EVENTS: &str = "(MessageNew MessageExpunge FlagChange)";
+
+/// `NOTIFY SET` for the selected mailbox plus `others`, reported as untagged STATUS
+pub async fn notify_set(imap: &mut ImapSession, others: &[String]) -> Result<()> {
+    let list = others.iter().map(|f| quote(f)).collect::<Vec<_>>().join(" ");
+    imap.session
+        .run_command_and_check_ok(format!(
+            "NOTIFY SET (selected {ev}) (mailboxes ({list}) {ev})",
+            ev = NOTIFY_EVENTS,
+            list = list
+        ))
+        .await?;
+    Ok(())
 }
 
-async fn idle_loop(
-    config: IdleConfig,
-    on_new_mail: impl Fn(String, String) + Send + Sync + 'static,
-) -> Result<()> {
-    let mut backoff_secs = 5;
-    loop {
-        match run_idle_session(&config, &on_new_mail).await {
-            Ok(_) => {
-                tracing::info!(account=%config.account_id, "IDLE session ended gracefully");
-                backoff_secs = 5; // reset
-            }
-            Err(e) => {
-                tracing::warn!(account=%config.account_id, backoff_secs, "IDLE error: {e}, retrying...");
-                tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
-                backoff_secs = (backoff_secs * 2).min(300); // exponential backoff, max 5min
+/// IDLE on the selected mailbox until the server reports a change or [`IDLE_REFRESH`] elapses.
+/// Returns the session and the mailboxes that changed (empty on refresh); EXISTS/EXPUNGE/FETCH
+/// count for `selected`, NOTIFY STATUS responses for the mailbox they name.
+pub async fn wait_for_change(imap: ImapSession, selected: &str) -> Result<(ImapSession, BTreeSet<String>)> {
+    let ImapSession { session, caps } = imap;
+    let mut idle = session.idle();
+    idle.init().await?;
+
+    let mut changed = BTreeSet::new();
+    {
+        // The built-in timeout restarts on every "* OK still here", so bound the wait from outside
+        let (wait, _stop) = idle.wait_with_timeout(IDLE_REFRESH);
+        if let Ok(resp) = tokio::time::timeout(IDLE_REFRESH, wait).await {
+            if let IdleResponse::NewData(data) = resp? {
+                changed.extend(changed_mailbox(data.parsed(), selected));
             }
         }
     }
-}
-
-async fn run_idle_session(
-    config: &IdleConfig,
-    on_new_mail: &(impl Fn(String, String) + Send + Sync),
-) -> Result<()> {
-    let mut session =
-        connect_and_login(&config.host, config.port, &config.email, &config.password).await?;
-
-    tracing::info!(account=%config.account_id, folder=%config.folder, "Selecting folder for IDLE");
-    session.select(&config.folder).await?;
-
-    loop {
-        tracing::debug!(account=%config.account_id, folder=%config.folder, "Entering IDLE");
-        let mut idle_handle = session.idle();
+    let session = idle.done().await?;
 
-        // Wait for server notification (EXISTS, EXPUNGE, FETCH, etc.)
-        // async-imap 0.9 API: idle_handle.wait() returns (Future, StopSource)
-        let (idle_wait, _interrupt) = idle_handle.wait();
-
-        match tokio::time::timeout(Duration::from_secs(29 * 60), idle_wait).await {
-            Ok(Ok(_)) => {
-                tracing::info!(account=%config.account_id, folder=%config.folder, "IDLE notification received");
-                on_new_mail(config.account_id.clone(), config.folder.clone());
-            }
-            Ok(Err(e)) => {
-                tracing::warn!(account=%config.account_id, "IDLE wait error: {e}");
-                return Err(e.into());
+    // Whatever arrived after the wakeup (while sending DONE) is on the unsolicited channel
+    while let Ok(resp) = session.unsolicited_responses.try_recv() {
+        match resp {
+            UnsolicitedResponse::Status { mailbox, .. } => {
+                changed.insert(mailbox);
             }
-            Err(_) => {
-                // Timeout - reconnect to avoid server disconnect
-                tracing::debug!(account=%config.account_id, "IDLE timeout, reconnecting");
+            UnsolicitedResponse::Exists(_) | UnsolicitedResponse::Expunge(_) | UnsolicitedResponse::Recent(_) => {
+                changed.insert(selected.to_string());
             }
+            UnsolicitedResponse::Other(data) => changed.extend(changed_mailbox(data.parsed(), selected)),
         }
-
-        // After notification, reconnect the session (IDLE consumes it)
-        session =
-            connect_and_login(&config.host, config.port, &config.email, &config.password).await?;
-        session.select(&config.folder).await?;
     }
+
+    Ok((ImapSession { session, caps }, changed))
 }
 
-async fn connect_and_login(host: &str, port: u16, user: &str, pass: &str) -> Result<ImapSession> {
-    let tls = native_tls::TlsConnector::builder().build()?;
-    let tls = tokio_native_tls::TlsConnector::from(tls);
-    let stream = TcpStream::connect((host, port)).await?;
-    let tls_stream = tls.connect(host, stream).await?;
-    let compat = tokio_util::compat::TokioAsyncReadCompatExt::compat(tls_stream);
-    let client = Client::new(compat);
+fn changed_mailbox(resp: &Response<'_>, selected: &str) -> Option<String> {
+    match resp {
+        Response::MailboxData(MailboxDatum::Status { mailbox, .. }) => Some(mailbox.to_string()),
+        Response::MailboxData(MailboxDatum::Exists(_))
+        | Response::MailboxData(MailboxDatum::Recent(_))
+        | Response::Expunge(_)
+        | Response::Vanished { .. }
+        | Response::Fetch(..) => Some(selected.to_string()),
+        _ => None,
+    }
+}
 
-    let session = client
-        .login(user, pass)
-        .await
-        .map_err(|e| anyhow::anyhow!("login failed: {:?}", e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_imap::imap_proto::parser::parse_response;
 
-    Ok(session)
+    #[test]
+    fn test_changed_mailbox() {
+        let cases = [
+            ("* 23 EXISTS\r\n", Some("INBOX")),
+            ("* 5 EXPUNGE\r\n", Some("INBOX")),
+            ("* 7 FETCH (FLAGS (\\Seen))\r\n", Some("INBOX")),
+            ("* STATUS \"Work/Reports\" (MESSAGES 12 UIDNEXT 40)\r\n", Some("Work/Reports")),
+            ("* OK Still here\r\n", None),
+        ];
+        for (line, expected) in cases {
+            let (_, resp) = parse_response(line.as_bytes()).expect("parse");
+            assert_eq!(changed_mailbox(&resp, "INBOX").as_deref(), expected, "{}", line.trim());
+        }
+    }
 }
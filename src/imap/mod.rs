@@ -1,8 +1,13 @@
 // filepath: /mailora-hub-imap/mailora-hub-imap/src/imap/mod.rs
 
 // IMAP module exports connection helpers and folder listing
+pub mod bodystructure;
 pub mod conn;
 pub mod folders;
+pub mod gmail;
+pub mod headers;
 pub mod idle;
+pub mod pool;
 pub mod sync;
+pub mod transfer;
 pub mod xoauth2;
@@ -0,0 +1,220 @@
+/// Per-account IMAP session pool shared by sync, routes and SMTP APPEND.
+///
+/// Sessions are keyed by account id and bounded by `MAILORA_IMAP_POOL_MAX` concurrent
+/// checkouts per account. Sessions [`PooledSession::release`]d after a clean command are kept
+/// for `MAILORA_IMAP_POOL_IDLE_SECS`, NOOP'd every `MAILORA_IMAP_POOL_KEEPALIVE_SECS` and
+/// health-checked on checkout; a dead session is dropped and a fresh one logged in
+/// transparently. IDLE watchers keep their own dedicated connections.
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{timeout, Duration, Instant};
+use tracing::debug;
+
+use crate::imap::conn::{self, ImapSession};
+use crate::models::account::{Account, SecurityMode};
+use crate::services::mail_auth_service::{self, MailAuth};
+
+static POOL: Lazy<Mutex<HashMap<String, Arc<AccountSlots>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn env_u64(key: &str, default: u64) -> u64 {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn max_per_account() -> usize {
+    env_u64("MAILORA_IMAP_POOL_MAX", 3).max(1) as usize
+}
+
+fn idle_timeout() -> Duration {
+    Duration::from_secs(env_u64("MAILORA_IMAP_POOL_IDLE_SECS", 300))
+}
+
+fn keepalive_interval() -> Duration {
+    Duration::from_secs(env_u64("MAILORA_IMAP_POOL_KEEPALIVE_SECS", 60).max(5))
+}
+
+fn checkout_wait() -> Duration {
+    Duration::from_secs(env_u64("MAILORA_IMAP_POOL_WAIT_SECS", 30))
+}
+
+struct AccountSlots {
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<IdleSession>>,
+}
+
+struct IdleSession {
+    imap: ImapSession,
+    /// Hash of host/port/security/credentials the session was opened with
+    endpoint: u64,
+    idle_since: Instant,
+    checked_at: Instant,
+}
+
+/// A checked-out session. It is closed on drop unless [`PooledSession::release`]d: a session
+/// left on an error, a timeout or a half-read response is out of step with the server, and a
+/// NOOP on checkout doesn't notice that.
+pub struct PooledSession {
+    imap: Option<ImapSession>,
+    endpoint: u64,
+    slots: Arc<AccountSlots>,
+    /// Set by `release`; only then does drop return the session to the pool
+    reusable: bool,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledSession {
+    fn new(imap: ImapSession, endpoint: u64, slots: Arc<AccountSlots>, permit: OwnedSemaphorePermit) -> Self {
+        Self { imap: Some(imap), endpoint, slots, reusable: false, _permit: permit }
+    }
+
+    /// Return to the pool; only after the last command completed and its responses were read
+    pub fn release(mut self) {
+        self.reusable = true;
+    }
+
+    /// Close instead of returning to the pool (what dropping does as well)
+    pub fn discard(mut self) {
+        self.imap = None;
+    }
+
+    /// Close the session and log in anew on the same checkout, for a caller that carries on
+    /// after a command failed part-way
+    pub async fn reconnect(&mut self, account: &Account) -> Result<()> {
+        self.imap = None;
+        let auth = mail_auth_service::for_account(account).await?;
+        let security = account.imap_security_mode();
+        self.endpoint = endpoint_hash(&account.imap_host, account.imap_port, security, &auth);
+        self.imap = Some(conn::connect(&account.id, &account.imap_host, account.imap_port, security, &auth).await?);
+        Ok(())
+    }
+}
+
+impl Deref for PooledSession {
+    type Target = ImapSession;
+    fn deref(&self) -> &ImapSession {
+        self.imap.as_ref().expect("pooled session already released")
+    }
+}
+
+impl DerefMut for PooledSession {
+    fn deref_mut(&mut self) -> &mut ImapSession {
+        self.imap.as_mut().expect("pooled session already released")
+    }
+}
+
+impl Drop for PooledSession {
+    fn drop(&mut self) {
+        let Some(imap) = self.imap.take() else { return };
+        if self.reusable {
+            let now = Instant::now();
+            let mut idle = self.slots.idle.lock().unwrap();
+            if idle.len() < max_per_account() {
+                idle.push(IdleSession { imap, endpoint: self.endpoint, idle_since: now, checked_at: now });
+            }
+        }
+    }
+}
+
+fn slots_for(account_id: &str) -> Arc<AccountSlots> {
+    let mut pool = POOL.lock().unwrap();
+    pool.entry(account_id.to_string())
+        .or_insert_with(|| {
+            Arc::new(AccountSlots {
+                permits: Arc::new(Semaphore::new(max_per_account())),
+                idle: Mutex::new(Vec::new()),
+            })
+        })
+        .clone()
+}
+
+// Credentials are part of the key so a password change never reuses an old login. An OAuth2
+// session stays authenticated after its token is refreshed, so only the user counts there.
+fn endpoint_hash(host: &str, port: u16, security: SecurityMode, auth: &MailAuth) -> u64 {
+    let mut h = DefaultHasher::new();
+    (host, port, security, auth.user()).hash(&mut h);
+    if let MailAuth::Password { password, .. } = auth {
+        password.hash(&mut h);
+    }
+    h.finish()
+}
+
+/// Check out a session for `account_id`, reusing a healthy idle one or logging in anew.
+pub async fn acquire(
+    account_id: &str,
+    host: &str,
+    port: u16,
+    security: SecurityMode,
+    auth: &MailAuth,
+) -> Result<PooledSession> {
+    let slots = slots_for(account_id);
+    let permit = timeout(checkout_wait(), slots.permits.clone().acquire_owned())
+        .await
+        .map_err(|_| anyhow::anyhow!("IMAP pool: no free connection for account {} after {:?}", account_id, checkout_wait()))??;
+    let endpoint = endpoint_hash(host, port, security, auth);
+
+    loop {
+        let candidate = slots.idle.lock().unwrap().pop();
+        let Some(mut idle) = candidate else { break };
+        if idle.endpoint != endpoint || idle.idle_since.elapsed() > idle_timeout() {
+            continue;
+        }
+        // Stale EXISTS/EXPUNGE from the previous user are meaningless to the next one
+        conn::clear_unsolicited(&mut idle.imap.session);
+        match timeout(Duration::from_secs(10), idle.imap.session.noop()).await {
+            Ok(Ok(())) => return Ok(PooledSession::new(idle.imap, endpoint, slots, permit)),
+            _ => debug!(account_id, "IMAP pool: idle session failed health check, dropping"),
+        }
+    }
+
+    let imap = conn::connect(account_id, host, port, security, auth).await?;
+    Ok(PooledSession::new(imap, endpoint, slots, permit))
+}
+
+/// [`acquire`] with the account's stored server and credentials (password or OAuth2)
+pub async fn acquire_account(account: &Account) -> Result<PooledSession> {
+    let auth = mail_auth_service::for_account(account).await?;
+    acquire(&account.id, &account.imap_host, account.imap_port, account.imap_security_mode(), &auth).await
+}
+
+/// Drop all idle sessions of an account (e.g. after it was deleted or its server changed)
+pub fn invalidate(account_id: &str) {
+    let slots = POOL.lock().unwrap().remove(account_id);
+    if let Some(slots) = slots {
+        slots.idle.lock().unwrap().clear();
+    }
+}
+
+/// Background task: evict expired idle sessions and NOOP the rest so servers don't time them out
+pub fn spawn_keepalive() {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(keepalive_interval());
+        loop {
+            tick.tick().await;
+            let all: Vec<Arc<AccountSlots>> = POOL.lock().unwrap().values().cloned().collect();
+            for slots in all {
+                let sessions = std::mem::take(&mut *slots.idle.lock().unwrap());
+                let mut alive = Vec::with_capacity(sessions.len());
+                for mut s in sessions {
+                    if s.idle_since.elapsed() > idle_timeout() {
+                        continue;
+                    }
+                    if s.checked_at.elapsed() >= keepalive_interval() {
+                        match timeout(Duration::from_secs(10), s.imap.session.noop()).await {
+                            Ok(Ok(())) => s.checked_at = Instant::now(),
+                            _ => continue,
+                        }
+                    }
+                    alive.push(s);
+                }
+                let mut idle = slots.idle.lock().unwrap();
+                let room = max_per_account().saturating_sub(idle.len());
+                idle.extend(alive.into_iter().take(room));
+            }
+        }
+    });
+}
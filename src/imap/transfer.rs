@@ -0,0 +1,169 @@
+/// Moving and copying messages between mailboxes.
+///
+/// MOVE (RFC 6851) is used when advertised; otherwise a move is COPY, STORE \Deleted and
+/// UID EXPUNGE (UIDPLUS) or EXPUNGE. With UIDPLUS the server reports the new UIDs in a COPYUID
+/// response code, which async-imap discards, so these commands read their responses directly.
+use anyhow::Result;
+use async_imap::imap_proto::{Response, ResponseCode, Status, UidSetMember};
+use futures::StreamExt;
+
+use crate::imap::conn::{quote, ImapSession, RawSession};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    Move,
+    Copy,
+}
+
+/// COPYUID (RFC 4315 §3): UIDVALIDITY of the destination and source -> destination UIDs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyUid {
+    pub uidvalidity: u32,
+    pub uids: Vec<(u32, u32)>,
+}
+
+/// Compact UID set (`1:3,7`) for a command
+pub fn uid_set(uids: &[u32]) -> String {
+    let mut sorted = uids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut parts = Vec::new();
+    let mut iter = sorted.into_iter().peekable();
+    while let Some(start) = iter.next() {
+        let mut end = start;
+        while end.checked_add(1).is_some_and(|next| iter.peek() == Some(&next)) {
+            end = iter.next().unwrap();
+        }
+        parts.push(if start == end { start.to_string() } else { format!("{}:{}", start, end) });
+    }
+    parts.join(",")
+}
+
+/// Move or copy `uids` from the selected mailbox to `dest`. Returns the new UIDs when the
+/// server reports them (UIDPLUS); `None` means the caller has to resync `dest` to learn them.
+pub async fn transfer(imap: &mut ImapSession, uids: &[u32], dest: &str, mode: Transfer) -> Result<Option<CopyUid>> {
+    if uids.is_empty() {
+        return Ok(None);
+    }
+    let set = uid_set(uids);
+    let dest = quote(dest);
+
+    if mode == Transfer::Move && imap.caps.move_cmd {
+        // COPYUID comes in an untagged OK before the EXPUNGEs (RFC 6851 §4.3)
+        return run(&mut imap.session, &format!("UID MOVE {} {}", set, dest)).await;
+    }
+
+    let copied = run(&mut imap.session, &format!("UID COPY {} {}", set, dest)).await?;
+    if mode == Transfer::Move {
+        expunge_uids(imap, uids).await?;
+    }
+    Ok(copied)
+}
+
+/// `UID STORE <uids> <item>` in the selected mailbox, e.g. `+FLAGS.SILENT (\Seen)`
+pub async fn store(session: &mut RawSession, uids: &[u32], item: &str) -> Result<()> {
+    let mut stored = session.uid_store(uid_set(uids), item).await?;
+    while let Some(res) = stored.next().await {
+        res?;
+    }
+    Ok(())
+}
+
+/// Mark `uids` \Deleted and expunge exactly those from the selected mailbox. Without UIDPLUS
+/// the other \Deleted messages are unmarked around a plain EXPUNGE so they survive it.
+pub async fn expunge_uids(imap: &mut ImapSession, uids: &[u32]) -> Result<()> {
+    store(&mut imap.session, uids, "+FLAGS.SILENT (\\Deleted)").await?;
+    if imap.caps.uidplus {
+        run(&mut imap.session, &format!("UID EXPUNGE {}", uid_set(uids))).await?;
+        return Ok(());
+    }
+
+    let others: Vec<u32> = imap
+        .session
+        .uid_search("DELETED")
+        .await?
+        .into_iter()
+        .filter(|u| !uids.contains(u))
+        .collect();
+    if !others.is_empty() {
+        store(&mut imap.session, &others, "-FLAGS.SILENT (\\Deleted)").await?;
+    }
+    let expunged = async {
+        let mut expunged = std::pin::pin!(imap.session.expunge().await?);
+        while expunged.next().await.is_some() {}
+        anyhow::Ok(())
+    }
+    .await;
+    if !others.is_empty() {
+        store(&mut imap.session, &others, "+FLAGS.SILENT (\\Deleted)").await?;
+    }
+    expunged
+}
+
+/// Run a tagged command, collecting a COPYUID code from the tagged or an untagged OK.
+/// Other untagged responses (EXPUNGE/VANISHED for the moved messages) are not needed: callers
+/// update the local cache themselves.
+async fn run(session: &mut RawSession, command: &str) -> Result<Option<CopyUid>> {
+    let tag = session.run_command(command).await?;
+    let mut copyuid = None;
+    while let Some(resp) = session.read_response().await {
+        let resp = resp?;
+        match resp.parsed() {
+            Response::Done { tag: t, status, code, information } if *t == tag => {
+                if let Some(ResponseCode::CopyUid(validity, src, dst)) = code {
+                    copyuid = pair_uids(*validity, src, dst);
+                }
+                return match status {
+                    Status::Ok => Ok(copyuid),
+                    _ => anyhow::bail!(
+                        "{} failed: {}",
+                        command.split(' ').take(2).collect::<Vec<_>>().join(" "),
+                        information.as_deref().unwrap_or("no reason given")
+                    ),
+                };
+            }
+            Response::Data { status: Status::Ok, code: Some(ResponseCode::CopyUid(validity, src, dst)), .. } => {
+                copyuid = pair_uids(*validity, src, dst);
+            }
+            _ => {}
+        }
+    }
+    anyhow::bail!("connection closed while waiting for {}", command)
+}
+
+fn expand(set: &[UidSetMember]) -> Vec<u32> {
+    set.iter()
+        .flat_map(|m| match m {
+            UidSetMember::Uid(u) => *u..=*u,
+            UidSetMember::UidRange(r) => r.clone(),
+        })
+        .collect()
+}
+
+/// The source and destination sets list UIDs in matching order; mismatched lengths are ignored
+fn pair_uids(uidvalidity: u32, src: &[UidSetMember], dst: &[UidSetMember]) -> Option<CopyUid> {
+    let (src, dst) = (expand(src), expand(dst));
+    (src.len() == dst.len()).then(|| CopyUid { uidvalidity, uids: src.into_iter().zip(dst).collect() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uid_set() {
+        assert_eq!(uid_set(&[7, 1, 2, 3, 9, 10, 3]), "1:3,7,9:10");
+        assert_eq!(uid_set(&[42]), "42");
+        assert_eq!(uid_set(&[u32::MAX, u32::MAX - 1, 5]), format!("5,{}:{}", u32::MAX - 1, u32::MAX));
+    }
+
+    #[test]
+    fn test_copyuid_pairs() {
+        use async_imap::imap_proto::parser::parse_response;
+        let (_, resp) = parse_response(b"A3 OK [COPYUID 38505 304,319:320 3956:3958] Done\r\n").unwrap();
+        let Response::Done { code: Some(ResponseCode::CopyUid(v, src, dst)), .. } = resp else { panic!("no COPYUID") };
+        let copy = pair_uids(v, &src, &dst).unwrap();
+        assert_eq!(copy.uidvalidity, 38505);
+        assert_eq!(copy.uids, vec![(304, 3956), (319, 3957), (320, 3958)]);
+    }
+}
@@ -1,12 +1,8 @@
 /// XOAUTH2 authentication for IMAP
 ///
-/// This module provides OAuth2 authentication for IMAP connections
-/// using the XOAUTH2 SASL mechanism.
-// use async_imap::Session;
-// use tokio::net::TcpStream;
-// use tokio_native_tls::TlsStream;
-// use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
-#[allow(unused_imports)] // keep Compat for future use
+/// async-imap has no built-in XOAUTH2 support; [`XOAuth2`] plugs the SASL exchange into
+/// `Client::authenticate`, which `imap::conn::connect` uses for OAuth2 accounts.
+use async_imap::Authenticator;
 
 /// Generate XOAUTH2 authentication string
 ///
@@ -15,23 +11,29 @@ pub fn generate_xoauth2_string(email: &str, access_token: &str) -> String {
     format!("user={}\x01auth=Bearer {}\x01\x01", email, access_token)
 }
 
-/// Authenticate with IMAP using XOAUTH2
-///
-/// async-imap doesn't support XOAUTH2 directly, so we use login() which
-/// will send credentials. For real XOAUTH2, we need to send raw commands.
-///
-/// For now, we'll use a workaround: store the OAuth token and use it
-/// when connecting. The actual XOAUTH2 SASL flow needs raw IMAP commands.
-#[allow(dead_code)]
-pub async fn connect_with_oauth(
-    host: &str,
-    port: u16,
-    email: &str,
-    access_token: &str,
-) -> Result<(), String> {
-    // Placeholder: not implemented yet
-    let _ = (host, port, email, access_token);
-    Err("XOAUTH2 flow not implemented".into())
+/// SASL XOAUTH2 client for `AUTHENTICATE XOAUTH2`
+pub struct XOAuth2 {
+    auth: String,
+    sent: bool,
+}
+
+impl XOAuth2 {
+    pub fn new(email: &str, access_token: &str) -> Self {
+        Self { auth: generate_xoauth2_string(email, access_token), sent: false }
+    }
+}
+
+impl Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(&mut self, _challenge: &[u8]) -> String {
+        // A second challenge carries the JSON error; an empty reply makes the server send NO
+        if self.sent {
+            return String::new();
+        }
+        self.sent = true;
+        self.auth.clone()
+    }
 }
 
 #[cfg(test)]
@@ -49,4 +51,11 @@ mod tests {
         assert!(auth_string.contains("auth=Bearer ya29.test_token"));
         assert!(auth_string.ends_with("\x01\x01"));
     }
+
+    #[test]
+    fn test_xoauth2_error_challenge() {
+        let mut auth = XOAuth2::new("test@example.com", "ya29.test_token");
+        assert_eq!(auth.process(b""), generate_xoauth2_string("test@example.com", "ya29.test_token"));
+        assert_eq!(auth.process(br#"{"status":"401"}"#), "");
+    }
 }
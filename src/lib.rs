@@ -9,7 +9,9 @@ pub mod pim;
 pub mod rbac;
 pub mod routes;
 pub mod services;
+pub mod sieve;
 pub mod smtp;
+pub mod tls;
 #[path = "telemetry/mod.rs"]
 pub mod telemetry; // explicitly use directory module
                    // pub mod stalwart_client; // Deprecated - using direct IMAP/SMTP now
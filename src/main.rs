@@ -13,7 +13,9 @@ mod pim;
 mod rbac;
 mod routes;
 mod services;
+mod sieve;
 mod smtp;
+mod tls;
 
 #[derive(Clone)]
 struct AppState {
@@ -78,12 +80,24 @@ async fn main() -> Result<()> {
             let msg = e.to_string();
             if msg.contains("already exists") { tracing::info!("migration benign: {msg}"); } else { tracing::warn!("migration error: {msg}"); }
         }
+        if let Err(e) = db::widen_event_directions(&pool).await {
+            tracing::warn!("events table upgrade failed: {e}");
+        }
         if let Err(e) = db::seed_account(&pool).await {
             tracing::info!("seed skipped: {e}");
         }
+        if let Err(e) = tls::load(&pool).await {
+            tracing::warn!("TLS trust load failed: {e}");
+        }
+        services::mail_auth_service::init(&pool);
+        match services::mail_auth_service::seal_stored_tokens(&pool).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("sealed stored OAuth2 tokens of {n} account(s)"),
+            Err(e) => tracing::warn!("sealing stored OAuth2 tokens failed: {e}"),
+        }
 
         // Create idle watcher manager
-        let idle_manager = Arc::new(services::idle_watcher_service::IdleWatcherManager::new());
+        let idle_manager = Arc::new(services::idle_watcher_service::IdleWatcherManager::new(pool.clone()));
 
         // Create OAuth manager
         let oauth_manager = Arc::new(oauth::OAuthManager::new());
@@ -94,9 +108,28 @@ async fn main() -> Result<()> {
             oauth_manager: oauth_manager.clone(),
         };
 
+        if let Err(e) = services::sync_job_service::init(&pool).await {
+            tracing::warn!("sync job history init failed: {e}");
+        }
+
+        if let Err(e) = services::draft_service::init(&pool).await {
+            tracing::warn!("draft resume failed: {e}");
+        }
+
+        if let Err(e) = services::rule_service::init(&pool).await {
+            tracing::warn!("rule engine init failed: {e}");
+        }
+
+        if let Err(e) = services::pending_op_service::init(&pool).await {
+            tracing::warn!("pending op replay init failed: {e}");
+        }
+
         // Start background scheduler
         crate::services::scheduler::start(pool.clone());
 
+        // Keep pooled IMAP sessions alive
+        crate::imap::pool::spawn_keepalive();
+
         // Start background maintenance service
         {
             let p = pool.clone();
@@ -113,6 +146,14 @@ async fn main() -> Result<()> {
             });
         }
 
+        // Keep OAuth2 tokens fresh ahead of expiry
+        {
+            let p = pool.clone();
+            tokio::spawn(async move {
+                services::mail_auth_service::start_token_refresh_loop(p).await;
+            });
+        }
+
         // Initial full sync and IDLE start on startup (non-blocking)
         {
             let pool_clone = pool.clone();
@@ -134,7 +175,7 @@ async fn main() -> Result<()> {
                                     if final_acc.password.is_empty() {
                                          if let Ok(a) = final_acc.clone().with_password() { final_acc = a; }
                                     }
-                                    if !final_acc.password.is_empty() {
+                                    if !final_acc.password.is_empty() || final_acc.is_oauth() {
                                         if let Err(e) = mgr.start_watcher(final_acc).await {
                                             tracing::warn!("Failed to auto-start IDLE for {}: {}", acc_for_idle.email, e);
                                         }
@@ -142,26 +183,18 @@ async fn main() -> Result<()> {
                                 });
                             }
 
-                            // Skip Gmail sync for now (legacy logic)
-                            if matches!(acc.provider, crate::models::account::EmailProvider::Gmail) { continue; }
-                            
                             let mut acc_dec = acc.clone();
                             if acc_dec.password.is_empty() {
                                 if let Ok(a) = acc_dec.clone().with_password() { acc_dec = a; } else { continue; }
                             }
-                            if acc_dec.password.is_empty() { continue; }
+                            if acc_dec.password.is_empty() && !acc_dec.is_oauth() { continue; }
                             
-                            let p = pool_clone.clone();
-                            tokio::spawn(async move {
-                                // Use sync_account (which now reuses session)
-                                match services::message_sync_service::sync_account_messages(&p, &acc_dec).await {
-                                    Ok(stats) => {
-                                        tracing::info!(email=%acc_dec.email, folders=%stats.len(), "initial sync completed");
-                                        let _ = services::account_service::update_last_sync(&p, &acc_dec.id).await;
-                                    }
-                                    Err(e) => tracing::warn!(email=%acc_dec.email, error=%e.to_string(), "initial sync failed"),
-                                }
-                            });
+                            services::sync_job_service::enqueue(
+                                &acc_dec.id,
+                                None,
+                                services::sync_job_service::JobPriority::Scheduled,
+                            )
+                            .await;
                         }
                     }
                     Err(e) => tracing::warn!("initial sync: list_accounts failed: {e}"),
@@ -116,6 +116,19 @@ pub struct Account {
     pub color: Option<String>,
     pub carddav_url: Option<String>,
     pub caldav_url: Option<String>,
+    // Transport security (tls|starttls|none); NULL infers from the port
+    pub imap_security: Option<String>,
+    pub smtp_security: Option<String>,
+    // 'password' (default) or 'oauth2'; tokens are refreshed by services::mail_auth_service
+    // and kept sealed as stored (see Account::open_secret)
+    pub auth_method: Option<String>,
+    #[serde(skip)]
+    pub oauth_access_token: Option<String>,
+    #[serde(skip)]
+    pub oauth_refresh_token: Option<String>,
+    pub oauth_expires_at: Option<i64>,
+    pub oauth_status: Option<String>, // ok|reauth_required
+    pub oauth_error: Option<String>,
     // Helper field for password (populated from credentials_encrypted)
     #[sqlx(skip)]
     #[serde(skip)]
@@ -123,8 +136,11 @@ pub struct Account {
 }
 
 impl Account {
-    /// Load account and decrypt password
+    /// Load account and decrypt password (OAuth2 accounts have none)
     pub fn with_password(mut self) -> Result<Self> {
+        if self.is_oauth() && self.credentials_encrypted.is_empty() {
+            return Ok(self);
+        }
         let (_, password) = Self::decode_credentials(&self.credentials_encrypted)?;
         self.password = password;
         Ok(self)
@@ -193,6 +209,19 @@ impl Account {
         Ok((parts[0].to_string(), parts[1].to_string()))
     }
 
+    /// Seal a secret (e.g. an OAuth2 token) for storage, with the same key as passwords
+    pub fn seal_secret(secret: &str) -> String {
+        format!("enc:{}", Self::encrypt(secret))
+    }
+
+    /// Reverse of [`Account::seal_secret`]; values stored before sealing are returned as is
+    pub fn open_secret(stored: &str) -> Result<String> {
+        match stored.strip_prefix("enc:") {
+            Some(sealed) => Self::decrypt(sealed),
+            None => Ok(stored.to_string()),
+        }
+    }
+
     /// Get credentials for this account
     pub fn get_credentials(&self) -> Result<(String, String)> {
         Self::decode_credentials(&self.credentials_encrypted)
@@ -204,6 +233,24 @@ impl Account {
             .map(|s| AppendPolicy::from_str(s))
             .unwrap_or(AppendPolicy::Auto)
     }
+
+    pub fn is_oauth(&self) -> bool {
+        self.auth_method.as_deref() == Some("oauth2")
+    }
+
+    pub fn imap_security_mode(&self) -> SecurityMode {
+        self.imap_security
+            .as_deref()
+            .and_then(SecurityMode::parse)
+            .unwrap_or_else(|| SecurityMode::for_imap_port(self.imap_port))
+    }
+
+    pub fn smtp_security_mode(&self) -> SecurityMode {
+        self.smtp_security
+            .as_deref()
+            .and_then(SecurityMode::parse)
+            .unwrap_or_else(|| SecurityMode::for_smtp_port(self.smtp_port))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -238,9 +285,84 @@ impl AppendPolicy {
     }
 }
 
+/// How IMAP/SMTP connections are secured
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityMode {
+    Tls,      // implicit TLS (993/465)
+    StartTls, // plaintext greeting upgraded with STARTTLS (143/587)
+    None,     // no encryption; refused unless the host is loopback or MAILORA_ALLOW_PLAINTEXT=1
+}
+
+impl SecurityMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "tls" | "ssl" => Some(Self::Tls),
+            "starttls" => Some(Self::StartTls),
+            "none" | "plain" => Some(Self::None),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Tls => "tls",
+            Self::StartTls => "starttls",
+            Self::None => "none",
+        }
+    }
+    pub fn for_imap_port(port: u16) -> Self {
+        if port == 143 { Self::StartTls } else { Self::Tls }
+    }
+    pub fn for_smtp_port(port: u16) -> Self {
+        if port == 465 { Self::Tls } else { Self::StartTls }
+    }
+    /// Plaintext is for local development only
+    pub fn ensure_allowed(&self, host: &str) -> Result<()> {
+        if *self != Self::None {
+            return Ok(());
+        }
+        let loopback = host == "localhost"
+            || host.parse::<std::net::IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false);
+        let allowed = std::env::var("MAILORA_ALLOW_PLAINTEXT").map(|v| v == "1" || v == "true").unwrap_or(false);
+        if !loopback && !allowed {
+            anyhow::bail!("plaintext connection to {} refused (set MAILORA_ALLOW_PLAINTEXT=1 for local development)", host);
+        }
+        Ok(())
+    }
+}
+
 // Database mapping helpers
 impl Account {
     pub fn provider_str(&self) -> String {
         self.provider.as_str().to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_security_mode() {
+        assert_eq!(SecurityMode::parse("STARTTLS"), Some(SecurityMode::StartTls));
+        assert_eq!(SecurityMode::parse("ssl"), Some(SecurityMode::Tls));
+        assert_eq!(SecurityMode::parse("bogus"), None);
+        assert_eq!(SecurityMode::for_imap_port(143), SecurityMode::StartTls);
+        assert_eq!(SecurityMode::for_imap_port(993), SecurityMode::Tls);
+        assert_eq!(SecurityMode::for_smtp_port(587), SecurityMode::StartTls);
+        assert_eq!(SecurityMode::for_smtp_port(465), SecurityMode::Tls);
+        assert!(SecurityMode::None.ensure_allowed("127.0.0.1").is_ok());
+        assert!(SecurityMode::None.ensure_allowed("localhost").is_ok());
+        assert!(SecurityMode::Tls.ensure_allowed("imap.example.com").is_ok());
+    }
+
+    #[test]
+    fn test_sealed_secret_roundtrip() {
+        let sealed = Account::seal_secret("ya29.token");
+        assert!(sealed.starts_with("enc:"));
+        assert_ne!(sealed, "enc:ya29.token");
+        assert_eq!(Account::open_secret(&sealed).unwrap(), "ya29.token");
+        // Tokens written before sealing was introduced
+        assert_eq!(Account::open_secret("ya29.legacy").unwrap(), "ya29.legacy");
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Draft {
+    pub id: String,
+    pub account_id: String,
+    pub to_addr: String,
+    pub subject: String,
+    pub body: String,
+    pub version: i64,
+    /// Last version APPENDed to the server; behind `version` while a save is pending
+    pub synced_version: i64,
+    pub message_id: String,
+    pub server_folder: Option<String>,
+    pub server_uid: Option<i64>,
+    pub status: String,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DraftVersion {
+    pub version: i64,
+    pub to_addr: String,
+    pub subject: String,
+    pub body: String,
+    pub saved_at: i64,
+}
@@ -6,4 +6,7 @@ pub mod message;
 pub mod thread;
 pub mod user;
 pub mod outbox;
+pub mod draft;
 pub mod calendar;
+pub mod rule;
+pub mod pending_op;
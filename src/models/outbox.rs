@@ -10,6 +10,8 @@ pub struct OutboxEmail {
     pub status: String,
     pub retries: i32,
     pub last_error: Option<String>,
+    /// Set when sent from a draft
+    pub draft_id: Option<String>,
     pub created_at: i64, // using i64 for timestamp (strftime %s)
     pub updated_at: i64,
 }
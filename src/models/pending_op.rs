@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// A message change waiting to be replayed to the server
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PendingOp {
+    pub id: i64,
+    pub account_id: String,
+    /// Where the message was when the change was made
+    pub folder: String,
+    pub uid: i64,
+    #[sqlx(json)]
+    pub mutation: Mutation,
+    /// Moves only: where the cached row is now, under a provisional UID
+    pub dest: Option<String>,
+    pub local_uid: Option<i64>,
+    /// `pending`; `failed` after the last attempt; `conflict` when the server no longer has
+    /// the message
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: i64,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Mutation {
+    Flags { add: Vec<String>, remove: Vec<String> },
+    /// To Trash for a delete
+    Move { to: String },
+    /// The copy shows up in `to` with the sync that follows the replay
+    Copy { to: String },
+    /// Delete from Trash
+    Expunge,
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+/// A client-side rule, run by the hub on new INBOX messages
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Rule {
+    pub id: String,
+    /// `None`: the rule applies to every account
+    pub account_id: Option<String>,
+    pub name: String,
+    /// Rules run in ascending position, global and per-account rules in one order
+    pub position: i64,
+    pub enabled: bool,
+    /// Match when any condition holds instead of all of them
+    pub match_any: bool,
+    /// No conditions: the rule applies to every message
+    #[sqlx(json)]
+    pub conditions: Vec<Condition>,
+    #[sqlx(json)]
+    pub actions: Vec<Action>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Condition {
+    #[serde(flatten)]
+    pub test: Test,
+    #[serde(default)]
+    pub not: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum Test {
+    From { op: TextOp, value: String },
+    /// To or Cc
+    To { op: TextOp, value: String },
+    Subject { op: TextOp, value: String },
+    ListId { op: TextOp, value: String },
+    HasAttachment,
+    /// Bytes
+    SizeOver { bytes: i64 },
+    SizeUnder { bytes: i64 },
+    /// Regular expression over the plain-text body
+    Body { pattern: String },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextOp {
+    /// Case-insensitive, like the rest of these except `regex`
+    Contains,
+    Is,
+    StartsWith,
+    EndsWith,
+    Regex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Runs after every other action of the rule and ends processing of the message
+    Move { folder: String },
+    Copy { folder: String },
+    Flag,
+    MarkRead,
+    AddKeyword { keyword: String },
+    /// Queued in the outbox as a plain-text forward
+    Forward { to: String },
+    /// Later rules don't run
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RuleLogEntry {
+    pub id: i64,
+    pub rule_id: String,
+    pub rule_name: String,
+    pub account_id: String,
+    pub folder: String,
+    pub uid: i64,
+    pub message_id: Option<String>,
+    pub subject: Option<String>,
+    #[sqlx(json)]
+    pub actions: Vec<Action>,
+    pub error: Option<String>,
+    pub created_at: i64,
+}
@@ -185,7 +185,11 @@ impl OAuthManager {
             .exchange_refresh_token(&oauth2::RefreshToken::new(refresh_token.to_string()))
             .request_async(async_http_client)
             .await
-            .map_err(|e| format!("Token refresh failed: {}", e))?;
+            .map_err(|e| match e {
+                // Keep the provider's error code (e.g. invalid_grant for a revoked grant)
+                oauth2::RequestTokenError::ServerResponse(resp) => format!("Token refresh failed: {}", resp),
+                other => format!("Token refresh failed: {}", other),
+            })?;
 
         Ok(OAuthTokens {
             access_token: token_result.access_token().secret().clone(),
@@ -200,13 +204,14 @@ impl OAuthManager {
         })
     }
 
-    /// Get valid access token (auto-refresh if expired)
+    /// Get valid tokens (auto-refresh if expired). The given tokens come back unchanged while
+    /// still fresh; a different `access_token` means a refresh happened and should be persisted.
     pub async fn get_valid_token(
         provider: &str,
         access_token: &str,
         refresh_token: Option<&str>,
         expires_at: Option<i64>,
-    ) -> Result<String, String> {
+    ) -> Result<OAuthTokens, String> {
         // Check if token is expired or will expire soon (5 min buffer)
         let is_expired = expires_at
             .map(|exp| exp < chrono::Utc::now().timestamp() + 300)
@@ -214,13 +219,17 @@ impl OAuthManager {
 
         if is_expired {
             if let Some(refresh) = refresh_token {
-                let new_tokens = Self::refresh_token(provider, refresh).await?;
-                Ok(new_tokens.access_token)
+                Self::refresh_token(provider, refresh).await
             } else {
                 Err("Token expired and no refresh token available".to_string())
             }
         } else {
-            Ok(access_token.to_string())
+            Ok(OAuthTokens {
+                access_token: access_token.to_string(),
+                refresh_token: refresh_token.map(str::to_string),
+                expires_at,
+                token_type: "Bearer".to_string(),
+            })
         }
     }
 }
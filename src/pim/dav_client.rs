@@ -2,6 +2,10 @@
 /// Supports: PROPFIND, REPORT, GET, PUT, DELETE
 use anyhow::{anyhow, Result};
 use reqwest::{Client, Method, StatusCode};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 pub struct DavClient {
     client: Client,
@@ -10,6 +14,37 @@ pub struct DavClient {
     password: String,
 }
 
+/// Certificate check for `pin` accounts, run during the handshake so a changed certificate
+/// stops the connection before any request (and its credentials) is sent
+struct PinVerifier {
+    account_id: String,
+    /// Host and port of the base URL; other hosts are assumed to be on 443
+    host: String,
+    port: u16,
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => return Err(rustls::Error::General("unsupported server name".to_string())),
+        };
+        let port = if host.eq_ignore_ascii_case(&self.host) { self.port } else { 443 };
+        crate::tls::check_peer(&self.account_id, &host, port, &end_entity.0)
+            .map(|()| ServerCertVerified::assertion())
+            .map_err(|e| rustls::Error::General(e.to_string()))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DavResource {
     pub href: String,
@@ -18,12 +53,24 @@ pub struct DavResource {
 }
 
 impl DavClient {
-    /// Construct a new DAV client.
-    pub fn new(base_url: &str, username: &str, password: &str) -> Self {
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .expect("reqwest client");
+    /// Construct a new DAV client; `account_id` selects the TLS certificate policy.
+    pub fn new(account_id: &str, base_url: &str, username: &str, password: &str) -> Self {
+        let mut builder = Client::builder().timeout(std::time::Duration::from_secs(30));
+        // Pinned accounts skip CA validation; the handshake checks the certificate against the pin
+        if crate::tls::accepts_self_signed(account_id) {
+            let url = reqwest::Url::parse(base_url).ok();
+            let verifier = PinVerifier {
+                account_id: account_id.to_string(),
+                host: url.as_ref().and_then(|u| u.host_str()).unwrap_or("").to_string(),
+                port: url.as_ref().and_then(|u| u.port_or_known_default()).unwrap_or(443),
+            };
+            let tls = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(verifier))
+                .with_no_client_auth();
+            builder = builder.use_preconfigured_tls(tls);
+        }
+        let client = builder.build().expect("reqwest client");
         Self {
             client,
             base_url: base_url.trim_end_matches('/').to_string(),
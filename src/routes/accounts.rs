@@ -20,6 +20,26 @@ pub async fn add_account(
             message: "Password is required for authentication".to_string(),
         });
     }
+    for mode in [&req.imap_security, &req.smtp_security].into_iter().flatten() {
+        if SecurityMode::parse(mode).is_none() {
+            return Json(AddAccountResponse {
+                success: false,
+                account_id: String::new(),
+                message: "Invalid security mode (tls|starttls|none)".to_string(),
+            });
+        }
+    }
+    let tls_policy = match req.tls_policy.as_deref().map(TlsPolicy::parse) {
+        Some(None) => {
+            return Json(AddAccountResponse {
+                success: false,
+                account_id: String::new(),
+                message: "Invalid tls_policy (verify|pin)".to_string(),
+            });
+        }
+        Some(policy) => policy,
+        None => None,
+    };
     // Validate custom provider
     let custom_config = if provider == EmailProvider::Custom {
         if req.imap_host.is_none() || req.smtp_host.is_none() {
@@ -50,6 +70,23 @@ pub async fn add_account(
     {
         Ok(account) => {
             tracing::info!("Account added: {}", account.email);
+            if req.imap_security.is_some() || req.smtp_security.is_some() {
+                if let Err(e) = account_service::set_security(
+                    &pool,
+                    &account.id,
+                    req.imap_security.as_deref().and_then(SecurityMode::parse),
+                    req.smtp_security.as_deref().and_then(SecurityMode::parse),
+                )
+                .await
+                {
+                    tracing::warn!("Failed to store security modes for {}: {}", account.email, e);
+                }
+            }
+            if let Some(policy) = tls_policy {
+                if let Err(e) = crate::tls::set_policy(&pool, &account.id, policy).await {
+                    tracing::warn!("Failed to store TLS policy for {}: {}", account.email, e);
+                }
+            }
             // If it's a Member adding an account, automatically assign it to them
             if auth.role != "Admin" {
                 let _ = sqlx::query("INSERT INTO user_accounts (user_id, account_id) VALUES (?, ?)")
@@ -84,13 +121,38 @@ pub struct AccountResponse {
     pub imap_port: u16,
     pub smtp_host: String,
     pub smtp_port: u16,
+    pub imap_security: String,
+    pub smtp_security: String,
+    pub tls_policy: String,
+    pub auth_method: String,
+    /// OAuth2 accounts only: `ok` or `reauth_required`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth_error: Option<String>,
+    /// Where to re-consent when `oauth_status` is `reauth_required`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reauth_url: Option<String>,
     pub enabled: bool,
     pub last_sync_ts: Option<i64>,
     pub color: Option<String>,
+    /// Single-account responses only: last QUOTA reading and 30 days of history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaReport>,
+    /// Single-account responses only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_policy: Option<SyncPolicy>,
 }
 
 impl From<Account> for AccountResponse {
     fn from(acc: Account) -> Self {
+        let imap_security = acc.imap_security_mode().as_str().to_string();
+        let smtp_security = acc.smtp_security_mode().as_str().to_string();
+        let tls_policy = crate::tls::policy(&acc.id).as_str().to_string();
+        let auth_method = if acc.is_oauth() { "oauth2" } else { "password" }.to_string();
+        let reauth_url = (acc.oauth_status.as_deref() == Some(mail_auth_service::STATUS_REAUTH))
+            .then(|| mail_auth_service::reauth_url(&acc));
+        let oauth_status = if acc.is_oauth() { acc.oauth_status.clone() } else { None };
         Self {
             id: acc.id,
             email: acc.email,
@@ -100,17 +162,27 @@ impl From<Account> for AccountResponse {
             imap_port: acc.imap_port,
             smtp_host: acc.smtp_host,
             smtp_port: acc.smtp_port,
+            imap_security,
+            smtp_security,
+            tls_policy,
+            auth_method,
+            oauth_status,
+            oauth_error: acc.oauth_error,
+            reauth_url,
             enabled: acc.enabled,
             last_sync_ts: acc.last_sync_ts,
             color: acc.color,
+            quota: None,
+            sync_policy: None,
         }
     }
 }
-use crate::models::account::{Account, EmailProvider};
-use crate::services::account_service;
+use crate::models::account::{Account, EmailProvider, SecurityMode};
+use crate::tls::TlsPolicy;
+use crate::services::{account_service, mail_auth_service, quota_service::{self, QuotaReport}, sync_policy_service::{self, SyncPolicy}};
 /// Account management endpoints
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
     debug_handler,
@@ -129,6 +201,9 @@ pub struct AddAccountRequest {
     pub imap_port: Option<u16>,
     pub smtp_host: Option<String>,
     pub smtp_port: Option<u16>,
+    pub imap_security: Option<String>, // tls|starttls|none
+    pub smtp_security: Option<String>,
+    pub tls_policy: Option<String>, // verify|pin
 }
 
 #[derive(Debug, Deserialize)]
@@ -194,7 +269,9 @@ async fn add_oauth_account(
         _ => return Err("OAuth2 not supported for this provider".to_string()),
     };
 
-    // Insert account with OAuth2 credentials
+    // Insert account with OAuth2 credentials, sealed like passwords
+    let sealed_access = Account::seal_secret(&access_token);
+    let sealed_refresh = refresh_token.as_deref().map(Account::seal_secret);
     sqlx::query(
         r#"
         INSERT INTO accounts (
@@ -213,8 +290,8 @@ async fn add_oauth_account(
     .bind(imap_port as i64)
     .bind(&smtp_host)
     .bind(smtp_port as i64)
-    .bind(&access_token)
-    .bind(&refresh_token)
+    .bind(&sealed_access)
+    .bind(&sealed_refresh)
     .bind(expires_at)
     .execute(pool)
     .await
@@ -240,6 +317,14 @@ async fn add_oauth_account(
         color: Some("#3b82f6".to_string()),
         carddav_url: None,
         caldav_url: None,
+        imap_security: None,
+        smtp_security: None,
+        auth_method: Some("oauth2".to_string()),
+        oauth_access_token: Some(sealed_access),
+        oauth_refresh_token: sealed_refresh,
+        oauth_expires_at: expires_at,
+        oauth_status: Some("ok".to_string()),
+        oauth_error: None,
         password: String::new(),
     })
 }
@@ -278,7 +363,7 @@ pub async fn list_accounts(
     }
 }
 
-async fn check_account_access(
+pub(crate) async fn check_account_access(
     pool: &SqlitePool,
     auth: &AuthUser,
     account_id: &str,
@@ -307,7 +392,24 @@ pub async fn get_account(
     }
 
     match account_service::get_account(&pool, &account_id).await {
-        Ok(Some(account)) => Ok(Json(account.into())),
+        Ok(Some(account)) => {
+            let mut res: AccountResponse = account.into();
+            res.quota = match quota_service::report(&pool, &account_id, 30).await {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    tracing::warn!("Failed to load quota of {}: {}", account_id, e);
+                    None
+                }
+            };
+            res.sync_policy = match sync_policy_service::load(&pool, &account_id).await {
+                Ok(policy) => Some(policy),
+                Err(e) => {
+                    tracing::warn!("Failed to load sync policy of {}: {}", account_id, e);
+                    None
+                }
+            };
+            Ok(Json(res))
+        }
         Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
             tracing::error!("Failed to get account: {}", e);
@@ -342,6 +444,45 @@ pub async fn delete_account(
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct TlsPinQuery {
+    /// `host:port`; all pins of the account when omitted
+    pub endpoint: Option<String>,
+}
+
+/// GET /accounts/:id/tls-pins - Certificate fingerprints pinned on first use
+pub async fn list_tls_pins(
+    auth: AuthUser,
+    State(pool): State<SqlitePool>,
+    Path(account_id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if !check_account_access(&pool, &auth, &account_id).await.unwrap_or(false) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(Json(serde_json::json!({
+        "account_id": account_id,
+        "tls_policy": crate::tls::policy(&account_id).as_str(),
+        "pins": crate::tls::pins(&account_id),
+    })))
+}
+
+/// DELETE /accounts/:id/tls-pins - Forget pinned certificates so the next connect pins afresh
+pub async fn clear_tls_pins(
+    auth: AuthUser,
+    State(pool): State<SqlitePool>,
+    Path(account_id): Path<String>,
+    Query(q): Query<TlsPinQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !check_account_access(&pool, &auth, &account_id).await.unwrap_or(false) {
+        return Err((StatusCode::FORBIDDEN, "Forbidden".to_string()));
+    }
+    let cleared = crate::tls::clear_pins(&pool, &account_id, q.endpoint.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    crate::rbac::log_event(&pool, Some(auth.id), Some(&account_id), "CLEAR_TLS_PINS", &format!("{} pin(s) cleared", cleared)).await;
+    Ok(Json(serde_json::json!({ "cleared": cleared })))
+}
+
 /// GET /providers - List available email providers with configs
 #[derive(Debug, Serialize)]
 pub struct ProviderInfo {
@@ -392,12 +533,20 @@ pub struct PatchAccountRequest {
     pub imap_port: Option<u16>,
     pub smtp_host: Option<String>,
     pub smtp_port: Option<u16>,
+    pub imap_security: Option<String>, // tls|starttls|none
+    pub smtp_security: Option<String>,
+    pub tls_policy: Option<String>, // verify|pin
     pub password: Option<String>,
     pub append_policy: Option<String>, // auto|never|force
     pub sent_folder_hint: Option<String>,
     pub color: Option<String>,
     pub carddav_url: Option<String>,
     pub caldav_url: Option<String>,
+    /// ManageSieve server; "" reverts to SRV discovery / the IMAP host
+    pub sieve_host: Option<String>,
+    pub sieve_port: Option<u16>,
+    /// Replaces the sync policy; omitted members revert to their defaults
+    pub sync_policy: Option<SyncPolicy>,
 }
 
 /// PATCH /accounts/:id - Update mutable account settings
@@ -423,6 +572,19 @@ pub async fn patch_account(
              return Json(UpdateAccountResponse { success: false, account: None, error: Some("Invalid append_policy".to_string()) });
         }
     }
+    for mode in [&req.imap_security, &req.smtp_security].into_iter().flatten() {
+        if SecurityMode::parse(mode).is_none() {
+            return Json(UpdateAccountResponse { success: false, account: None, error: Some("Invalid security mode (tls|starttls|none)".to_string()) });
+        }
+    }
+    if let Some(Err(reason)) = req.sync_policy.as_ref().map(sync_policy_service::validate) {
+        return Json(UpdateAccountResponse { success: false, account: None, error: Some(reason) });
+    }
+    let tls_policy = match req.tls_policy.as_deref().map(TlsPolicy::parse) {
+        Some(None) => return Json(UpdateAccountResponse { success: false, account: None, error: Some("Invalid tls_policy (verify|pin)".to_string()) }),
+        Some(policy) => policy,
+        None => None,
+    };
     // Determine new values (fallback to existing)
     let new_display_name = req.display_name.or(existing.display_name.clone());
     let new_enabled = req.enabled.unwrap_or(existing.enabled);
@@ -433,6 +595,8 @@ pub async fn patch_account(
     let new_append_policy = req.append_policy.as_ref().map(|s| s.to_lowercase());
     let new_sent_folder_hint = req.sent_folder_hint.or(existing.sent_folder_hint.clone());
     let new_color = req.color.or(existing.color.clone());
+    let new_imap_security = req.imap_security.map(|m| m.to_lowercase()).or(existing.imap_security.clone());
+    let new_smtp_security = req.smtp_security.map(|m| m.to_lowercase()).or(existing.smtp_security.clone());
 
     // Credentials: if password changed re-encode; we keep email immutable here
     let new_creds_enc = if let Some(pass) = req.password.as_ref() {
@@ -443,13 +607,15 @@ pub async fn patch_account(
 
     // Persist update (provider immutable for now)
     let res = sqlx::query(
-        "UPDATE accounts SET display_name = ?, imap_host = ?, imap_port = ?, smtp_host = ?, smtp_port = ?, enabled = ?, append_policy = ?, sent_folder_hint = ?, color = ?, credentials_encrypted = ?, carddav_url = ?, caldav_url = ?, updated_at = strftime('%s','now') WHERE id = ?"
+        "UPDATE accounts SET display_name = ?, imap_host = ?, imap_port = ?, smtp_host = ?, smtp_port = ?, imap_security = ?, smtp_security = ?, enabled = ?, append_policy = ?, sent_folder_hint = ?, color = ?, credentials_encrypted = ?, carddav_url = ?, caldav_url = ?, updated_at = strftime('%s','now') WHERE id = ?"
     )
     .bind(&new_display_name)
     .bind(&new_imap_host)
     .bind(new_imap_port as i64)
     .bind(&new_smtp_host)
     .bind(new_smtp_port as i64)
+    .bind(&new_imap_security)
+    .bind(&new_smtp_security)
     .bind(new_enabled as i64)
     .bind(&new_append_policy)
     .bind(&new_sent_folder_hint)
@@ -464,6 +630,30 @@ pub async fn patch_account(
     if let Err(e) = res {
          return Json(UpdateAccountResponse { success: false, account: None, error: Some(e.to_string()) });
     }
+    if req.sieve_host.is_some() || req.sieve_port.is_some() {
+        let res = sqlx::query(
+            "UPDATE accounts SET sieve_host = CASE WHEN ? IS NULL THEN sieve_host ELSE NULLIF(?, '') END, sieve_port = COALESCE(?, sieve_port) WHERE id = ?",
+        )
+        .bind(&req.sieve_host)
+        .bind(&req.sieve_host)
+        .bind(req.sieve_port.map(|p| p as i64))
+        .bind(&account_id)
+        .execute(&pool)
+        .await;
+        if let Err(e) = res {
+            return Json(UpdateAccountResponse { success: false, account: None, error: Some(e.to_string()) });
+        }
+    }
+    if let Some(policy) = tls_policy {
+        if let Err(e) = crate::tls::set_policy(&pool, &account_id, policy).await {
+            return Json(UpdateAccountResponse { success: false, account: None, error: Some(e.to_string()) });
+        }
+    }
+    if let Some(policy) = &req.sync_policy {
+        if let Err(e) = sync_policy_service::save(&pool, &account_id, policy).await {
+            return Json(UpdateAccountResponse { success: false, account: None, error: Some(e.to_string()) });
+        }
+    }
 
     // Reload updated account
     let updated = match account_service::get_account(&pool, &account_id).await {
@@ -481,14 +671,17 @@ pub async fn patch_account(
                 entry.password = password;
                 entry.host = new_imap_host;
                 entry.port = new_imap_port;
+                entry.security = Some(updated.imap_security_mode());
             } else {
                 // Optionally insert if not present and enabled
                 if new_enabled {
-                    store.insert(account_id.clone(), crate::services::diff_service::AccountCreds { email, password, host: new_imap_host, port: new_imap_port });
+                    store.insert(account_id.clone(), crate::services::diff_service::AccountCreds { email, password, host: new_imap_host, port: new_imap_port, security: Some(updated.imap_security_mode()) });
                 }
             }
         }
     }
 
-    Json(UpdateAccountResponse { success: true, account: Some(updated.into()), error: None })
+    let mut account: AccountResponse = updated.into();
+    account.sync_policy = sync_policy_service::load(&pool, &account_id).await.ok();
+    Json(UpdateAccountResponse { success: true, account: Some(account), error: None })
 }
@@ -0,0 +1,107 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::rbac::AuthUser;
+use crate::routes::accounts::check_account_access;
+use crate::routes::sync::{search_sql, SearchQs};
+use crate::services::bulk_service::{self, BulkAction, ItemResult, MessageRef};
+
+fn bulk_max() -> usize {
+    std::env::var("MAILORA_BULK_MAX").ok().and_then(|v| v.parse().ok()).unwrap_or(5000)
+}
+
+#[derive(Deserialize)]
+pub struct BulkReq {
+    pub action: BulkAction,
+    /// Destination folder for move/copy
+    pub to: Option<String>,
+    /// Explicit targets...
+    pub messages: Option<Vec<MessageRef>>,
+    /// ...or every message matching a search (same filters as GET /search)
+    pub query: Option<SearchQs>,
+}
+
+/// POST /messages/bulk
+/// `{"action": "mark_read", "query": {"account_id": "...", "folder": "INBOX", "unread": true}}`
+/// `{"action": "move", "to": "Archive", "messages": [{"account_id": "...", "folder": "INBOX", "uid": 42}]}`
+///
+/// At most `MAILORA_BULK_MAX` messages per call; `more: true` means a query matched more and
+/// the call should be repeated.
+pub async fn bulk_action(
+    State(pool): State<SqlitePool>,
+    auth_user: AuthUser,
+    Json(req): Json<BulkReq>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let max = bulk_max();
+    let (mut refs, more) = match (req.messages, req.query) {
+        (Some(messages), None) => {
+            if messages.len() > max {
+                return Err((StatusCode::BAD_REQUEST, format!("at most {} messages per request", max)));
+            }
+            (messages, false)
+        }
+        (None, Some(qs)) => {
+            let narrowed = qs.q.as_deref().is_some_and(|q| !q.trim().is_empty()) || qs.account_id.is_some() || qs.folder.is_some();
+            if !narrowed {
+                return Err((StatusCode::BAD_REQUEST, "query needs at least one of q, account_id or folder".to_string()));
+            }
+            let (mut sql, args) = search_sql(&qs, &auth_user);
+            sql.push_str(" ORDER BY m.date DESC LIMIT ?");
+            #[derive(sqlx::FromRow)]
+            struct Row { account_id: String, folder: String, uid: i64 }
+            let mut q = sqlx::query_as::<_, Row>(&sql);
+            for v in args { q = q.bind(v); }
+            let rows = q
+                .bind(max as i64 + 1)
+                .fetch_all(&pool)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let more = rows.len() > max;
+            let refs = rows
+                .into_iter()
+                .take(max)
+                .map(|r| MessageRef { account_id: r.account_id, folder: r.folder, uid: r.uid as u32 })
+                .collect();
+            (refs, more)
+        }
+        _ => return Err((StatusCode::BAD_REQUEST, "give either messages or query".to_string())),
+    };
+
+    // Search results are already scoped to the user's accounts; explicit references are not
+    let mut denied = Vec::new();
+    if auth_user.role != "Admin" {
+        let mut allowed: HashMap<String, bool> = HashMap::new();
+        for r in &refs {
+            if !allowed.contains_key(&r.account_id) {
+                let ok = check_account_access(&pool, &auth_user, &r.account_id)
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                allowed.insert(r.account_id.clone(), ok);
+            }
+        }
+        let (ok, no): (Vec<_>, Vec<_>) = refs.into_iter().partition(|r| allowed[&r.account_id]);
+        refs = ok;
+        denied = no
+            .into_iter()
+            .map(|message| ItemResult { message, ok: false, queued: false, new_uid: None, local_uid: None, error: Some("forbidden".to_string()) })
+            .collect();
+    }
+
+    let outcome = bulk_service::run(&pool, req.action, req.to.as_deref(), refs).await;
+    let mut results = outcome.results;
+    results.extend(denied);
+    let succeeded = results.iter().filter(|r| r.ok).count();
+
+    Ok(Json(json!({
+        "action": req.action,
+        "total": results.len(),
+        "succeeded": succeeded,
+        "failed": results.len() - succeeded,
+        "more": more,
+        "undo_token": outcome.undo_token,
+        "results": results,
+    })))
+}
@@ -73,7 +73,7 @@ pub async fn probe_diff(
     ))?;
 
     // Enumerate folders and filter out Spam
-    let folders = list_mailboxes(&creds.host, creds.port, &creds.email, &creds.password)
+    let folders = list_mailboxes(&account_id, &creds.host, creds.port, creds.security(), &creds.auth())
         .await
         .map_err(|e| (axum::http::StatusCode::BAD_GATEWAY, e.to_string()))?;
     let targets: Vec<String> = folders
@@ -88,8 +88,8 @@ pub async fn probe_diff(
 
     let mut results = Vec::new();
     for folder in targets {
-        if let Ok(snap) = initial_snapshot(&creds.host, creds.port, &creds.email, &creds.password).await {
-            match fetch_new_since(&creds.host, creds.port, &creds.email, &creds.password, snap.last_uid).await {
+        if let Ok(snap) = initial_snapshot(&account_id, &creds.host, creds.port, creds.security(), &creds.auth()).await {
+            match fetch_new_since(&account_id, &creds.host, creds.port, creds.security(), &creds.auth(), snap.last_uid).await {
                 Ok((new_last, added)) => results.push(FolderProbe { folder, last_uid: snap.last_uid, new_last_uid: new_last, incremental_count: added.len() }),
                 Err(e) => { tracing::debug!(%folder, "probe error: {e}"); }
             }
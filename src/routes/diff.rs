@@ -44,6 +44,8 @@ pub struct BodyResponse {
 #[allow(non_snake_case)]
 pub struct FoldersQs {
     pub accountId: String,
+    /// Re-list over IMAP instead of serving the stored catalogue
+    pub refresh: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -64,9 +66,10 @@ pub struct DownloadQs {
 }
 
 async fn list_all_folders_excluding_spam(
+    account_id: &str,
     creds: &crate::services::diff_service::AccountCreds,
 ) -> Result<Vec<String>, (axum::http::StatusCode, String)> {
-    let folders = list_mailboxes(&creds.host, creds.port, &creds.email, &creds.password)
+    let folders = list_mailboxes(account_id, &creds.host, creds.port, creds.security(), &creds.auth())
         .await
         .map_err(|e| (axum::http::StatusCode::BAD_GATEWAY, e.to_string()))?;
     let names: Vec<String> = folders
@@ -100,7 +103,7 @@ pub async fn diff_handler(
         let target_folders: Vec<String> = if let Some(fset) = multi {
             fset.into_iter().map(|f| f.name).collect()
         } else if cur.folder == "*" {
-            list_all_folders_excluding_spam(&creds).await?
+            list_all_folders_excluding_spam(&account_id, &creds).await?
         } else {
             vec![q.folder.clone().unwrap_or(cur.folder.clone())]
         };
@@ -118,10 +121,11 @@ pub async fn diff_handler(
             // Use fetch_new_since for INBOX only; for other folders we need a folder-aware version (not yet available) so skip incremental for non-INBOX for now.
             let (mut new_last_f, new_msgs) = if folder == "INBOX" {
                 crate::imap::sync::fetch_new_since(
+                    &account_id,
                     &creds.host,
                     creds.port,
-                    &creds.email,
-                    &creds.password,
+                    creds.security(),
+                    &creds.auth(),
                     last_for_folder,
                 )
                 .await
@@ -196,14 +200,15 @@ pub async fn diff_handler(
     }
 
     // Initial: build multi-folder cursor aggregating all (except Spam)
-    let names = list_all_folders_excluding_spam(&creds).await?;
+    let names = list_all_folders_excluding_spam(&account_id, &creds).await?;
     let mut fcs: Vec<FolderCursor> = Vec::new();
     for name in names.iter() {
         if let Ok(snap) = crate::imap::sync::initial_snapshot(
+            &account_id,
             &creds.host,
             creds.port,
-            &creds.email,
-            &creds.password,
+            creds.security(),
+            &creds.auth(),
         )
         .await
         {
@@ -246,10 +251,11 @@ pub async fn body_handler(
     if let Some(folder) = q.folder.as_ref() {
         tracing::debug!(accountId=%q.accountId, %folder, uid=q.uid, "/body: direct folder fetch");
         match crate::imap::sync::fetch_message_body_in(
+            &q.accountId,
             &creds.host,
             creds.port,
-            &creds.email,
-            &creds.password,
+            creds.security(),
+            &creds.auth(),
             q.uid,
             folder,
         )
@@ -280,10 +286,11 @@ pub async fn body_handler(
     // Try INBOX first
     tracing::debug!(accountId=%q.accountId, uid=q.uid, "/body: try INBOX first");
     if let Ok(Some(m)) = crate::imap::sync::fetch_message_body_in(
+        &q.accountId,
         &creds.host,
         creds.port,
-        &creds.email,
-        &creds.password,
+        creds.security(),
+        &creds.auth(),
         q.uid,
         "INBOX",
     )
@@ -302,17 +309,18 @@ pub async fn body_handler(
     }
 
     // Scan non-spam folders
-    let folders = list_all_folders_excluding_spam(&creds).await?;
+    let folders = list_all_folders_excluding_spam(&q.accountId, &creds).await?;
     for name in folders {
         if name == "INBOX" {
             continue;
         }
         tracing::debug!(accountId=%q.accountId, folder=%name, uid=q.uid, "/body: scanning folder");
         if let Ok(Some(m)) = crate::imap::sync::fetch_message_body_in(
+            &q.accountId,
             &creds.host,
             creds.port,
-            &creds.email,
-            &creds.password,
+            creds.security(),
+            &creds.auth(),
             q.uid,
             &name,
         )
@@ -339,24 +347,48 @@ pub async fn body_handler(
 }
 
 pub async fn folders_handler(
+    State(pool): State<sqlx::SqlitePool>,
     Query(q): Query<FoldersQs>,
-) -> Result<Json<Vec<crate::imap::folders::FolderInfo>>, (axum::http::StatusCode, String)> {
+) -> Result<Json<Vec<crate::services::folder_service::FolderRecord>>, (StatusCode, String)> {
+    use crate::services::folder_service;
+
+    let cached = folder_service::list_folders(&pool, &q.accountId)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !cached.is_empty() && !q.refresh.unwrap_or(false) {
+        return Ok(Json(cached));
+    }
+
+    // Catalogue not populated yet (account never synced) or refresh requested: list over IMAP
     let creds_opt = {
         let store = ACCOUNTS.read().await;
         store.get(&q.accountId).cloned()
     };
-    let creds = creds_opt.ok_or((
-        axum::http::StatusCode::NOT_FOUND,
-        "account not logged in".into(),
-    ))?;
-    let folders = crate::imap::folders::list_mailboxes(
-        &creds.host,
-        creds.port,
-        &creds.email,
-        &creds.password,
-    )
-    .await
-    .map_err(|e| (axum::http::StatusCode::BAD_GATEWAY, e.to_string()))?;
+    let creds = if let Some(c) = creds_opt {
+        c
+    } else {
+        let account = crate::services::account_service::get_account(&pool, &q.accountId)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "account not found".into()))?;
+        let (email, password) = account
+            .get_credentials()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        crate::services::diff_service::AccountCreds {
+            email,
+            password,
+            security: Some(account.imap_security_mode()),
+            host: account.imap_host,
+            port: account.imap_port,
+        }
+    };
+    let mut imap = crate::imap::pool::acquire(&q.accountId, &creds.host, creds.port, creds.security(), &creds.auth())
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+    let folders = folder_service::refresh_folders(&pool, &q.accountId, &mut imap.session)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+    imap.release();
     Ok(Json(folders))
 }
 
@@ -383,6 +415,7 @@ pub async fn attachments_handler(
         crate::services::diff_service::AccountCreds {
             email,
             password,
+            security: Some(account.imap_security_mode()),
             host: account.imap_host,
             port: account.imap_port,
         }
@@ -391,10 +424,11 @@ pub async fn attachments_handler(
 
     // Try requested folder first
     let mut atts = crate::imap::sync::list_attachments(
+        &q.accountId,
         &creds.host,
         creds.port,
-        &creds.email,
-        &creds.password,
+        creds.security(),
+        &creds.auth(),
         req_folder,
         q.uid,
     )
@@ -406,14 +440,15 @@ pub async fn attachments_handler(
     if atts.is_empty() {
         // quick probe: try INBOX BODYSTRUCTURE to detect parts even if raw fetch is large-blocked
         // fall back to scanning other folders
-        let folders = list_all_folders_excluding_spam(&creds).await?;
+        let folders = list_all_folders_excluding_spam(&q.accountId, &creds).await?;
         for f in folders.iter() {
             if f == req_folder { continue; }
             match crate::imap::sync::list_attachments(
+                &q.accountId,
                 &creds.host,
                 creds.port,
-                &creds.email,
-                &creds.password,
+                creds.security(),
+                &creds.auth(),
                 f,
                 q.uid,
             ).await {
@@ -487,6 +522,7 @@ pub async fn download_attachment(
         crate::services::diff_service::AccountCreds {
             email,
             password,
+            security: Some(account.imap_security_mode()),
             host: account.imap_host,
             port: account.imap_port,
         }
@@ -495,10 +531,11 @@ pub async fn download_attachment(
 
     // Try requested folder first
     if let Ok(Some((bytes, ctype, filename))) = crate::imap::sync::fetch_attachment_part(
+        &q.accountId,
         &creds.host,
         creds.port,
-        &creds.email,
-        &creds.password,
+        creds.security(),
+        &creds.auth(),
         req_folder,
         q.uid,
         &q.part,
@@ -517,14 +554,15 @@ pub async fn download_attachment(
     }
 
     // Fallback: scan other folders
-    let folders = list_all_folders_excluding_spam(&creds).await?;
+    let folders = list_all_folders_excluding_spam(&q.accountId, &creds).await?;
     for f in folders.iter() {
         if f == req_folder { continue; }
         if let Ok(Some((bytes, ctype, filename))) = crate::imap::sync::fetch_attachment_part(
+            &q.accountId,
             &creds.host,
             creds.port,
-            &creds.email,
-            &creds.password,
+            creds.security(),
+            &creds.auth(),
             f,
             q.uid,
             &q.part,
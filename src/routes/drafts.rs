@@ -0,0 +1,115 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+
+use crate::models::draft::{Draft, DraftVersion};
+use crate::services::{account_service, draft_service::{self, DraftContent, SaveError}};
+
+#[derive(Deserialize)]
+pub struct CreateDraftReq {
+    pub account_id: String,
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+/// Autosave: only the fields present change
+#[derive(Deserialize)]
+pub struct SaveDraftReq {
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    /// Version the editor started from; a newer stored version answers 409
+    pub base_version: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ListDraftsQs {
+    pub account_id: Option<String>,
+}
+
+type ApiError = (StatusCode, String);
+
+fn internal(e: anyhow::Error) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// GET /drafts?account_id=
+pub async fn list_drafts(
+    State(pool): State<SqlitePool>,
+    Query(qs): Query<ListDraftsQs>,
+) -> Result<Json<Vec<Draft>>, ApiError> {
+    draft_service::list(&pool, qs.account_id.as_deref()).await.map(Json).map_err(internal)
+}
+
+/// POST /drafts  {"account_id": "...", "to": "...", "subject": "...", "body": "..."}
+pub async fn create_draft(
+    State(pool): State<SqlitePool>,
+    Json(req): Json<CreateDraftReq>,
+) -> Result<(StatusCode, Json<Draft>), ApiError> {
+    let account = account_service::get_account(&pool, &req.account_id)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))?;
+    let content = DraftContent { to: req.to, subject: req.subject, body: req.body };
+    let draft = draft_service::create(&pool, &account, content).await.map_err(internal)?;
+    Ok((StatusCode::CREATED, Json(draft)))
+}
+
+/// GET /drafts/:id
+pub async fn get_draft(State(pool): State<SqlitePool>, Path(id): Path<String>) -> Result<Json<Draft>, ApiError> {
+    draft_service::get(&pool, &id)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Draft not found".to_string()))
+}
+
+/// PUT /drafts/:id  {"body": "...", "base_version": 3}
+pub async fn save_draft(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+    Json(req): Json<SaveDraftReq>,
+) -> Result<Json<Draft>, (StatusCode, Json<Value>)> {
+    let content = DraftContent { to: req.to, subject: req.subject, body: req.body };
+    match draft_service::save(&pool, &id, content, req.base_version).await {
+        Ok(draft) => Ok(Json(draft)),
+        Err(SaveError::NotFound) => Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Draft not found" })))),
+        Err(SaveError::Conflict(current)) => Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "draft was saved elsewhere since base_version", "current": current })),
+        )),
+        Err(SaveError::Queued) => Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "draft is queued for sending" })),
+        )),
+        Err(SaveError::Db(e)) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e.to_string() })))),
+    }
+}
+
+/// GET /drafts/:id/versions
+pub async fn draft_versions(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<DraftVersion>>, ApiError> {
+    draft_service::versions(&pool, &id).await.map(Json).map_err(internal)
+}
+
+/// DELETE /drafts/:id
+pub async fn delete_draft(State(pool): State<SqlitePool>, Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    match draft_service::delete(&pool, &id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Draft not found".to_string())),
+        Err(e) => Err((StatusCode::BAD_GATEWAY, e.to_string())),
+    }
+}
+
+/// POST /drafts/:id/send
+pub async fn send_draft(State(pool): State<SqlitePool>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    match draft_service::send(&pool, &id).await {
+        Ok(Some(outbox_id)) => Ok(Json(json!({ "ok": true, "outbox_id": outbox_id }))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Draft not found".to_string())),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
@@ -1,8 +1,11 @@
-use axum::{extract::{Path, State}, Json};
+use axum::{extract::{Path, State}, http::StatusCode, Json};
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::SqlitePool;
 
+use crate::models::account::Account;
+use crate::services::{message_service, pending_op_service};
+
 #[derive(Deserialize)]
 pub struct UpdateFlagsReq {
     pub seen: Option<bool>,
@@ -11,6 +14,8 @@ pub struct UpdateFlagsReq {
 }
 
 /// POST /messages/:account_id/:folder/:uid/flags
+/// `deleted: true` moves the message to Trash (or expunges it from Trash) and returns an undo token.
+/// While the server can't be reached the change is applied locally and queued (`"queued": true`).
 pub async fn update_flags(
     State(pool): State<SqlitePool>,
     Path((account_id, folder, uid)): Path<(String, String, u32)>,
@@ -24,67 +29,129 @@ pub async fn update_flags(
         Err(e) => return Json(json!({"ok": false, "error": format!("db error: {}", e)})),
     };
 
-    // Build IMAP STORE command
+    // STORE the non-delete flags first so a deleted message keeps them in Trash
     let mut flags_cmds: Vec<&str> = Vec::new();
-    if let Some(seen) = req.seen { flags_cmds.push(if seen { "+FLAGS (\\Seen)" } else { "-FLAGS (\\Seen)" }); }
-    if let Some(flagged) = req.flagged { flags_cmds.push(if flagged { "+FLAGS (\\Flagged)" } else { "-FLAGS (\\Flagged)" }); }
-    if let Some(deleted) = req.deleted { flags_cmds.push(if deleted { "+FLAGS (\\Deleted)" } else { "-FLAGS (\\Deleted)" }); }
+    let (mut add, mut remove): (Vec<&str>, Vec<&str>) = (Vec::new(), Vec::new());
+    if let Some(seen) = req.seen {
+        flags_cmds.push(if seen { "+FLAGS.SILENT (\\Seen)" } else { "-FLAGS.SILENT (\\Seen)" });
+        if seen { add.push("\\Seen") } else { remove.push("\\Seen") }
+    }
+    if let Some(flagged) = req.flagged {
+        flags_cmds.push(if flagged { "+FLAGS.SILENT (\\Flagged)" } else { "-FLAGS.SILENT (\\Flagged)" });
+        if flagged { add.push("\\Flagged") } else { remove.push("\\Flagged") }
+    }
+    // Clearing \Deleted is a plain flag change; setting it means "delete" (see below)
+    if req.deleted == Some(false) {
+        flags_cmds.push("-FLAGS.SILENT (\\Deleted)");
+        remove.push("\\Deleted");
+    }
 
-    if flags_cmds.is_empty() {
+    if flags_cmds.is_empty() && req.deleted != Some(true) {
         return Json(json!({"ok": false, "error": "no-op"}));
     }
 
+    // Changes queue behind ones still waiting for the server, and whenever it can't be reached
+    let mut queue = match pending_op_service::must_queue(&pool, &account_id, &folder, &[uid]).await {
+        Ok(queue) => queue,
+        Err(e) => return Json(json!({"ok": false, "error": e.to_string()})),
+    };
+
     // Apply to IMAP
-    let res = async {
-        let mut imap = crate::imap::conn::connect(&account.imap_host, account.imap_port, &account.email, &account.password).await?;
-        let session = &mut imap.session;
-        session.select(&folder).await?;
-        for cmd in flags_cmds.iter() {
-            use futures::StreamExt;
-            if let Ok(mut stream) = session.uid_store(&uid.to_string(), cmd).await { while stream.next().await.is_some() {} }
-        }
-        session.expunge().await.ok();
-        let _ = session.logout().await;
-        anyhow::Ok(())
-    }.await;
+    if !flags_cmds.is_empty() && !queue {
+        let res = async {
+            let mut imap = crate::imap::pool::acquire_account(&account).await?;
+            let res = async {
+                imap.session.select(&folder).await?;
+                for cmd in flags_cmds.iter() {
+                    crate::imap::transfer::store(&mut imap.session, &[uid], cmd).await?;
+                }
+                anyhow::Ok(())
+            }.await;
+            if res.is_ok() { imap.release(); }
+            res
+        }.await;
 
-    if let Err(e) = res {
-        return Json(json!({"ok": false, "error": e.to_string()}));
-    }
+        match res {
+            Err(e) if pending_op_service::is_offline(&e) => queue = true,
+            Err(e) => return Json(json!({"ok": false, "error": e.to_string()})),
+            Ok(()) => {
+                // Fallback: ensure message row exists
+                let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM messages WHERE account_id=? AND folder=? AND uid=?")
+                    .bind(&account_id).bind(&folder).bind(uid as i64)
+                    .fetch_one(&pool).await.unwrap_or(false);
+                if !exists {
+                    let _ = sqlx::query("INSERT INTO messages (account_id, folder, uid, subject, from_addr, to_addr, date, flags, size, synced_at) VALUES (?,?,?,?,?,?,?,?,0, datetime('now'))")
+                        .bind(&account_id).bind(&folder).bind(uid as i64)
+                        .bind(Option::<String>::None) // subject
+                        .bind(Option::<String>::None) // from_addr
+                        .bind(Option::<String>::None) // to_addr
+                        .bind(Option::<String>::None) // date
+                        .bind("[]")
+                        .execute(&pool).await;
+                }
 
-    // Update DB flags snapshot
-    let flags_json = serde_json::to_string(&{
-        let mut v = Vec::new();
-        if req.seen.unwrap_or(false) { v.push("\\Seen"); }
-        if req.flagged.unwrap_or(false) { v.push("\\Flagged"); }
-        if req.deleted.unwrap_or(false) { v.push("\\Deleted"); }
-        v
-    }).unwrap_or("[]".into());
+                // Merge into the cached snapshot; flags not named in the request are kept
+                if let Err(e) = message_service::update_cached_flags(&pool, &account_id, &folder, &[uid], &add, &remove).await {
+                    tracing::warn!("flags cache update failed for {}/{}/{}: {}", account_id, folder, uid, e);
+                }
+            }
+        }
+    }
 
-    // Fallback: ensure message row exists
-    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM messages WHERE account_id=? AND folder=? AND uid=?")
-        .bind(&account_id).bind(&folder).bind(uid as i64)
-        .fetch_one(&pool).await.unwrap_or(false);
-    if !exists {
-        let _ = sqlx::query("INSERT INTO messages (account_id, folder, uid, subject, from_addr, to_addr, date, flags, size, synced_at) VALUES (?,?,?,?,?,?,?,?,0, datetime('now'))")
-            .bind(&account_id).bind(&folder).bind(uid as i64)
-            .bind(Option::<String>::None) // subject
-            .bind(Option::<String>::None) // from_addr
-            .bind(Option::<String>::None) // to_addr
-            .bind(Option::<String>::None) // date
-            .bind(&flags_json)
-            .execute(&pool).await;
+    if queue {
+        if !flags_cmds.is_empty() {
+            if let Err(e) = pending_op_service::queue_flags(&pool, &account_id, &folder, uid, &add, &remove).await {
+                return Json(json!({"ok": false, "error": e.to_string()}));
+            }
+        }
+        if req.deleted == Some(true) {
+            return queue_delete(&pool, &account, &folder, uid, None).await;
+        }
+        return Json(json!({"ok": true, "queued": true}));
     }
 
-    let _ = sqlx::query(
-        "UPDATE messages SET flags = ?, synced_at = datetime('now') WHERE account_id = ? AND folder = ? AND uid = ?",
-    )
-    .bind(flags_json)
-    .bind(&account_id)
-    .bind(&folder)
-    .bind(uid as i64)
-    .execute(&pool)
-    .await;
+    // Deleting moves the message to Trash (expunging only when it already is in Trash)
+    if req.deleted == Some(true) {
+        return match message_service::delete_messages(&pool, &account, &folder, &[uid], None).await {
+            Ok(out) => Json(json!({
+                "ok": true,
+                "trash": out.trash,
+                "new_uid": out.messages.first().and_then(|m| m.new_uid),
+                "undo_token": out.undo_token,
+            })),
+            Err(e) if pending_op_service::is_offline(&e) => queue_delete(&pool, &account, &folder, uid, Some(e)).await,
+            Err(e) => Json(json!({"ok": false, "error": e.to_string()})),
+        };
+    }
 
     Json(json!({"ok": true}))
 }
+
+/// Delete while the server is unreachable: move the cached row to Trash (or drop it there) and
+/// queue the change. Not offered on Gmail, where deleting changes labels of All Mail rows;
+/// `offline` is the error to report then.
+async fn queue_delete(pool: &SqlitePool, account: &Account, folder: &str, uid: u32, offline: Option<anyhow::Error>) -> Json<serde_json::Value> {
+    if !pending_op_service::queues_transfers(account) {
+        let error = offline.map(|e| e.to_string()).unwrap_or_else(|| "changes are still waiting for the server; try again later".to_string());
+        return Json(json!({"ok": false, "error": error}));
+    }
+    match pending_op_service::queue_delete(pool, account, folder, uid).await {
+        Ok((_, None)) => Json(json!({"ok": true, "queued": true, "trash": null})),
+        // The UID in Trash is provisional until the change reaches the server; no undo meanwhile
+        Ok((trash, Some(local_uid))) => Json(json!({"ok": true, "queued": true, "trash": trash, "new_uid": null, "local_uid": local_uid, "undo_token": null})),
+        Err(e) => Json(json!({"ok": false, "error": e.to_string()})),
+    }
+}
+
+/// POST /undo/:token - Restore the messages of a recent delete from Trash
+pub async fn undo_delete(
+    State(pool): State<SqlitePool>,
+    Path(token): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let restored = message_service::undo_delete(&pool, &token)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Unknown or expired undo token".to_string()))?;
+    let ok = restored.iter().all(|r| r.restored);
+    Ok(Json(json!({ "ok": ok, "messages": restored })))
+}
@@ -0,0 +1,133 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+
+use crate::models::account::Account;
+use crate::services::{account_service, folder_service::{self, FolderRecord, Refused}};
+
+/// Folder names in paths and bodies may be wire names (as listed by `/folders`) or display
+/// names such as "Gönderilmiş"; leaf names are always display names.
+#[derive(Deserialize)]
+pub struct CreateFolderReq {
+    pub name: String,
+    /// Parent folder; top level when absent
+    pub parent: Option<String>,
+    #[serde(default = "default_true")]
+    pub subscribe: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RenameFolderReq {
+    /// New leaf name
+    pub name: String,
+    /// New parent; absent keeps the current one, "" moves the folder to the top level
+    pub parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteFolderQs {
+    /// Also delete special-use folders (Sent, Trash, ...)
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+type ApiError = (StatusCode, String);
+
+async fn load_account(pool: &SqlitePool, account_id: &str) -> Result<Account, ApiError> {
+    account_service::get_account(pool, account_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))
+}
+
+async fn parent_wire_name(pool: &SqlitePool, account_id: &str, parent: Option<&str>) -> Option<String> {
+    match parent {
+        Some("") => Some(String::new()),
+        Some(p) => Some(folder_service::wire_name(pool, account_id, p).await),
+        None => None,
+    }
+}
+
+/// Refusals are the client's to fix; anything else is the server's (or the cache's) doing
+fn folder_error(e: anyhow::Error) -> ApiError {
+    let status = match e.downcast_ref::<Refused>() {
+        Some(Refused::Invalid(_)) => StatusCode::BAD_REQUEST,
+        Some(Refused::Conflict(_)) => StatusCode::CONFLICT,
+        None if e.is::<sqlx::Error>() => StatusCode::INTERNAL_SERVER_ERROR,
+        None => StatusCode::BAD_GATEWAY,
+    };
+    (status, e.to_string())
+}
+
+/// POST /folders/:account_id  {"name": "Gönderilmiş", "parent": "Projects"}
+pub async fn create_folder(
+    State(pool): State<SqlitePool>,
+    Path(account_id): Path<String>,
+    Json(req): Json<CreateFolderReq>,
+) -> Result<(StatusCode, Json<FolderRecord>), ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    let parent = parent_wire_name(&pool, &account_id, req.parent.as_deref()).await;
+    folder_service::create_folder(&pool, &account, parent.as_deref(), &req.name, req.subscribe)
+        .await
+        .map(|f| (StatusCode::CREATED, Json(f)))
+        .map_err(folder_error)
+}
+
+/// PATCH /folders/:account_id/:folder  {"name": "2026", "parent": "Archive"}
+pub async fn rename_folder(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder)): Path<(String, String)>,
+    Json(req): Json<RenameFolderReq>,
+) -> Result<Json<FolderRecord>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    let folder = folder_service::wire_name(&pool, &account_id, &folder).await;
+    let parent = parent_wire_name(&pool, &account_id, req.parent.as_deref()).await;
+    folder_service::rename_folder(&pool, &account, &folder, parent.as_deref(), &req.name)
+        .await
+        .map(Json)
+        .map_err(folder_error)
+}
+
+/// DELETE /folders/:account_id/:folder?force=true
+pub async fn delete_folder(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder)): Path<(String, String)>,
+    Query(qs): Query<DeleteFolderQs>,
+) -> Result<Json<Value>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    let folder = folder_service::wire_name(&pool, &account_id, &folder).await;
+    let removed = folder_service::delete_folder(&pool, &account, &folder, qs.force)
+        .await
+        .map_err(folder_error)?;
+    Ok(Json(json!({ "deleted": folder, "cached_messages_removed": removed })))
+}
+
+/// POST /folders/:account_id/:folder/subscribe
+pub async fn subscribe_folder(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder)): Path<(String, String)>,
+) -> Result<Json<FolderRecord>, ApiError> {
+    set_subscribed(&pool, &account_id, &folder, true).await
+}
+
+/// DELETE /folders/:account_id/:folder/subscribe
+pub async fn unsubscribe_folder(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder)): Path<(String, String)>,
+) -> Result<Json<FolderRecord>, ApiError> {
+    set_subscribed(&pool, &account_id, &folder, false).await
+}
+
+async fn set_subscribed(pool: &SqlitePool, account_id: &str, folder: &str, subscribed: bool) -> Result<Json<FolderRecord>, ApiError> {
+    let account = load_account(pool, account_id).await?;
+    let folder = folder_service::wire_name(pool, account_id, folder).await;
+    folder_service::set_subscribed(pool, &account, &folder, subscribed)
+        .await
+        .map(Json)
+        .map_err(folder_error)
+}
@@ -6,18 +6,27 @@ use axum::{
     Json,
 };
 use futures::stream::Stream;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::sync::Arc;
 
-use crate::services::{account_service, idle_watcher_service::IdleWatcherManager};
+use crate::services::{account_service, idle_watcher_service, idle_watcher_service::IdleWatcherManager};
+
+#[derive(Debug, Deserialize)]
+pub struct StartIdleRequest {
+    /// Folders to watch (role aliases like "sent" allowed); saved for the account.
+    /// An empty list resets to `MAILORA_IDLE_FOLDERS`.
+    pub folders: Option<Vec<String>>,
+}
 
 /// POST /idle/start/:account_id - Start IDLE watcher for account
 pub async fn start_idle_watcher(
     State(pool): State<SqlitePool>,
     State(idle_manager): State<Arc<IdleWatcherManager>>,
     Path(account_id): Path<String>,
+    body: Option<Json<StartIdleRequest>>,
 ) -> Result<Json<IdleResponse>, (StatusCode, String)> {
     let account = account_service::get_account(&pool, &account_id)
         .await
@@ -34,6 +43,20 @@ pub async fn start_idle_watcher(
             )
         })?;
 
+    if let Some(folders) = body.and_then(|Json(b)| b.folders) {
+        let folders = (!folders.is_empty()).then_some(folders);
+        idle_watcher_service::set_watched_folders(&pool, &account.id, folders.as_deref())
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Database error: {}", e),
+                )
+            })?;
+        // Restart so a running watcher picks up the new folder set
+        let _ = idle_manager.stop_watcher(&account.id).await;
+    }
+
     tracing::info!("Starting IDLE watcher for account: {}", account.email);
 
     idle_manager
@@ -84,6 +107,7 @@ pub async fn idle_status(
 
     Ok(Json(IdleStatusResponse {
         active_watchers: active_count,
+        folders: idle_manager.watched().await,
     }))
 }
 
@@ -121,4 +145,6 @@ pub struct IdleResponse {
 #[derive(Debug, Serialize)]
 pub struct IdleStatusResponse {
     pub active_watchers: usize,
+    /// Watched folders by account id
+    pub folders: HashMap<String, Vec<String>>,
 }
@@ -6,7 +6,7 @@ use axum::extract::State;
 use axum::response::{Html, IntoResponse};
 use axum::{http::StatusCode, Json};
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, patch, post},
     Router,
 };
 use serde::Deserialize; // correct import from crate root
@@ -24,7 +24,14 @@ pub mod sync;
 pub mod test;
 pub mod unified;
 pub mod flags;
+pub mod transfer;
+pub mod bulk;
+pub mod pending_ops;
+pub mod drafts;
+pub mod folders;
 pub mod settings;
+pub mod rules;
+pub mod sieve;
 pub mod snooze;
 pub mod contacts;
 pub mod calendar;
@@ -36,6 +43,8 @@ struct LoginReq {
     password: String,
     host: Option<String>,
     port: Option<u16>,
+    /// "tls" | "starttls" | "none"; inferred from the port when omitted
+    security: Option<String>,
 }
 
 async fn login(Json(payload): Json<LoginReq>) -> impl IntoResponse {
@@ -49,9 +58,17 @@ async fn login(Json(payload): Json<LoginReq>) -> impl IntoResponse {
         .port
         .or_else(|| std::env::var("IMAP_PORT").ok().and_then(|v| v.parse().ok()))
         .unwrap_or(993);
+    let security = match payload.security.as_deref() {
+        Some(s) => match crate::models::account::SecurityMode::parse(s) {
+            Some(m) => m,
+            None => return (StatusCode::BAD_REQUEST, format!("invalid security mode: {}", s)).into_response(),
+        },
+        None => crate::models::account::SecurityMode::for_imap_port(port),
+    };
 
-    let res =
-        crate::imap::sync::initial_snapshot(&host, port, &payload.email, &payload.password).await;
+    // Session logins are stored as account "1"; pooled sessions are keyed by credentials, so bad ones still fail
+    let auth = crate::services::mail_auth_service::MailAuth::password(&payload.email, &payload.password);
+    let res = crate::imap::sync::initial_snapshot("1", &host, port, security, &auth).await;
     match res {
         Ok(snap) => {
             // store creds with accountId = 1 for now
@@ -66,6 +83,7 @@ async fn login(Json(payload): Json<LoginReq>) -> impl IntoResponse {
                         password: payload.password.clone(),
                         host: host.to_string(),
                         port,
+                        security: Some(security),
                     },
                 );
             }
@@ -175,6 +193,15 @@ where
         .route("/diff", get(diff::diff_handler))
         .route("/body", get(diff::body_handler))
         .route("/folders", get(diff::folders_handler))
+        .route("/folders/:account_id", post(folders::create_folder))
+        .route(
+            "/folders/:account_id/:folder",
+            patch(folders::rename_folder).delete(folders::delete_folder),
+        )
+        .route(
+            "/folders/:account_id/:folder/subscribe",
+            post(folders::subscribe_folder).delete(folders::unsubscribe_folder),
+        )
         .route("/attachments", get(diff::attachments_handler))
         .route("/attachments/download", get(diff::download_attachment))
         .route("/unified/inbox", get(unified::unified_inbox))
@@ -188,6 +215,10 @@ where
                 .delete(accounts::delete_account)
                 .patch(accounts::patch_account),
         )
+        .route(
+            "/accounts/:id/tls-pins",
+            get(accounts::list_tls_pins).delete(accounts::clear_tls_pins),
+        )
         .route("/providers", get(accounts::list_providers))
         .route("/test/connection/:account_id", get(test::test_connection))
         .route("/test/messages/:account_id", get(test::fetch_messages))
@@ -199,16 +230,53 @@ where
         .route("/test/update-append-policy/:account_id", post(test::update_append_policy))
         .route("/debug/metrics", get(test::metrics_snapshot))
         .route("/send", post(send_action))
+        .route("/drafts", get(drafts::list_drafts).post(drafts::create_draft))
+        .route(
+            "/drafts/:id",
+            get(drafts::get_draft).put(drafts::save_draft).delete(drafts::delete_draft),
+        )
+        .route("/drafts/:id/versions", get(drafts::draft_versions))
+        .route("/drafts/:id/send", post(drafts::send_draft))
         .route("/debug/state", get(debug::state))
         .route("/debug/probe", get(debug::probe_diff))
         .route("/sync/:account_id", post(sync::sync_account))
         .route("/sync/:account_id/:folder", post(sync::sync_folder))
+        .route("/sync-jobs", get(sync::list_sync_jobs))
+        .route("/sync-jobs/events", get(sync::sync_job_events))
+        .route("/sync-jobs/:id", get(sync::get_sync_job))
+        .route("/sync-jobs/:id/cancel", post(sync::cancel_sync_job))
         .route("/messages/:account_id", get(sync::get_messages))
         .route(
             "/messages/:account_id/:folder",
             get(sync::get_folder_messages),
         )
         .route("/messages/:account_id/:folder/:uid/flags", post(flags::update_flags))
+        .route("/messages/:account_id/:folder/:uid/move", post(transfer::move_message))
+        .route("/messages/:account_id/:folder/:uid/copy", post(transfer::copy_message))
+        .route("/messages/:account_id/:folder/move", post(transfer::move_messages))
+        .route("/messages/:account_id/:folder/copy", post(transfer::copy_messages))
+        .route("/messages/bulk", post(bulk::bulk_action))
+        .route("/undo/:token", post(flags::undo_delete))
+        .route("/pending-ops", get(pending_ops::list_pending_ops))
+        .route("/pending-ops/:id", delete(pending_ops::discard_pending_op))
+        .route("/pending-ops/:id/retry", post(pending_ops::retry_pending_op))
+        .route("/rules", get(rules::list_rules).post(rules::create_rule))
+        .route("/rules/reorder", post(rules::reorder_rules))
+        .route("/rules/dry-run", post(rules::dry_run))
+        .route("/rules/log", get(rules::rule_log))
+        .route(
+            "/rules/:id",
+            get(rules::get_rule).put(rules::update_rule).delete(rules::delete_rule),
+        )
+        .route("/sieve/:account_id", get(sieve::sieve_status))
+        .route("/sieve/:account_id/check", post(sieve::check_script))
+        .route("/sieve/:account_id/deactivate", post(sieve::deactivate_scripts))
+        .route("/sieve/:account_id/rules", get(sieve::get_rules).put(sieve::put_rules))
+        .route(
+            "/sieve/:account_id/scripts/:name",
+            get(sieve::get_script).put(sieve::put_script).delete(sieve::delete_script),
+        )
+        .route("/sieve/:account_id/scripts/:name/activate", post(sieve::activate_script))
         .route("/test/folders/:account_id", get(test::list_folders))
         .route("/settings", get(settings::get_settings))
         .route("/search", get(sync::search_messages))
@@ -56,7 +56,12 @@ pub async fn oauth_callback(
         .handle_callback(params.code, params.state)
         .await
     {
-        Ok((provider, _account_id, tokens)) => {
+        Ok((provider, account_id, tokens)) => {
+            // Re-consent of an existing account (e.g. after a revoked grant): store the new
+            // tokens sealed and clear its reauthorization flag; temp ids match no row
+            if let Err(e) = crate::services::mail_auth_service::save_tokens(&account_id, &tokens).await {
+                tracing::warn!(account_id = %account_id, "failed to store OAuth tokens: {e}");
+            }
             // Success - show page with postMessage to parent window
             let html = format!(
                 r#"
@@ -0,0 +1,53 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::models::pending_op::PendingOp;
+use crate::services::pending_op_service;
+
+#[derive(Deserialize)]
+pub struct ListPendingQs {
+    pub account_id: Option<String>,
+    /// pending|failed|conflict
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+type ApiError = (StatusCode, String);
+
+fn internal(e: anyhow::Error) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// GET /pending-ops?account_id=&status=&limit= - local message changes not on the server yet,
+/// oldest first, and the ones that gave up
+pub async fn list_pending_ops(State(pool): State<SqlitePool>, Query(qs): Query<ListPendingQs>) -> Result<Json<Vec<PendingOp>>, ApiError> {
+    let limit = qs.limit.unwrap_or(200).clamp(1, 1000);
+    pending_op_service::list(&pool, qs.account_id.as_deref(), qs.status.as_deref(), limit)
+        .await
+        .map(Json)
+        .map_err(internal)
+}
+
+/// POST /pending-ops/:id/retry - replay a failed or conflicting change again
+pub async fn retry_pending_op(State(pool): State<SqlitePool>, Path(id): Path<i64>) -> Result<Json<PendingOp>, ApiError> {
+    if !pending_op_service::retry(&pool, id).await.map_err(internal)? {
+        return match pending_op_service::get(&pool, id).await.map_err(internal)? {
+            Some(_) => Err((StatusCode::CONFLICT, "Change is still pending".to_string())),
+            None => Err((StatusCode::NOT_FOUND, "Pending change not found".to_string())),
+        };
+    }
+    pending_op_service::get(&pool, id)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Pending change not found".to_string()))
+}
+
+/// DELETE /pending-ops/:id - drop a change without sending it; the cache is resynced
+pub async fn discard_pending_op(State(pool): State<SqlitePool>, Path(id): Path<i64>) -> Result<StatusCode, ApiError> {
+    match pending_op_service::discard(&pool, id).await.map_err(internal)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((StatusCode::NOT_FOUND, "Pending change not found".to_string())),
+    }
+}
@@ -0,0 +1,138 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::models::rule::{Rule, RuleLogEntry};
+use crate::services::{account_service, rule_service::{self, DryRunOutcome, RuleSpec}};
+
+#[derive(Deserialize)]
+pub struct ListRulesQs {
+    pub account_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReorderReq {
+    pub ids: Vec<String>,
+}
+
+/// Either a stored rule or one being edited
+#[derive(Deserialize)]
+pub struct DryRunReq {
+    pub rule_id: Option<String>,
+    pub rule: Option<RuleSpec>,
+    /// Limits a global rule to one account
+    pub account_id: Option<String>,
+    /// Newest INBOX messages to look at (default 500, at most 10000)
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct LogQs {
+    pub account_id: Option<String>,
+    pub rule_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+type ApiError = (StatusCode, String);
+
+fn internal(e: anyhow::Error) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn check(pool: &SqlitePool, spec: &RuleSpec) -> Result<(), ApiError> {
+    rule_service::validate(spec).map_err(|reason| (StatusCode::UNPROCESSABLE_ENTITY, reason))?;
+    if let Some(account_id) = &spec.account_id {
+        account_service::get_account(pool, account_id)
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))?;
+    }
+    Ok(())
+}
+
+/// GET /rules?account_id= - in run order; with an account, its rules and the global ones
+pub async fn list_rules(State(pool): State<SqlitePool>, Query(qs): Query<ListRulesQs>) -> Result<Json<Vec<Rule>>, ApiError> {
+    rule_service::list(&pool, qs.account_id.as_deref()).await.map(Json).map_err(internal)
+}
+
+/// POST /rules  {"account_id": null, "name": "...", "conditions": [...], "actions": [...]}
+pub async fn create_rule(State(pool): State<SqlitePool>, Json(spec): Json<RuleSpec>) -> Result<(StatusCode, Json<Rule>), ApiError> {
+    check(&pool, &spec).await?;
+    let rule = rule_service::create(&pool, &spec).await.map_err(internal)?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// GET /rules/:id
+pub async fn get_rule(State(pool): State<SqlitePool>, Path(id): Path<String>) -> Result<Json<Rule>, ApiError> {
+    rule_service::get(&pool, &id)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Rule not found".to_string()))
+}
+
+/// PUT /rules/:id - replaces the rule
+pub async fn update_rule(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+    Json(spec): Json<RuleSpec>,
+) -> Result<Json<Rule>, ApiError> {
+    check(&pool, &spec).await?;
+    rule_service::update(&pool, &id, &spec)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Rule not found".to_string()))
+}
+
+/// DELETE /rules/:id
+pub async fn delete_rule(State(pool): State<SqlitePool>, Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    match rule_service::delete(&pool, &id).await.map_err(internal)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((StatusCode::NOT_FOUND, "Rule not found".to_string())),
+    }
+}
+
+/// POST /rules/reorder  {"ids": ["first", "second", ...]}
+pub async fn reorder_rules(State(pool): State<SqlitePool>, Json(req): Json<ReorderReq>) -> Result<Json<Vec<Rule>>, ApiError> {
+    rule_service::reorder(&pool, &req.ids).await.map(Json).map_err(internal)
+}
+
+/// POST /rules/dry-run  {"rule_id": "..."} or {"rule": {...}} - which cached INBOX messages
+/// the rule would match; nothing is changed
+pub async fn dry_run(State(pool): State<SqlitePool>, Json(req): Json<DryRunReq>) -> Result<Json<DryRunOutcome>, ApiError> {
+    let spec = match (req.rule, req.rule_id) {
+        (Some(spec), _) => {
+            rule_service::validate(&spec).map_err(|reason| (StatusCode::UNPROCESSABLE_ENTITY, reason))?;
+            spec
+        }
+        (None, Some(id)) => {
+            let rule = rule_service::get(&pool, &id)
+                .await
+                .map_err(internal)?
+                .ok_or_else(|| (StatusCode::NOT_FOUND, "Rule not found".to_string()))?;
+            RuleSpec {
+                account_id: rule.account_id,
+                name: rule.name,
+                enabled: rule.enabled,
+                match_any: rule.match_any,
+                conditions: rule.conditions,
+                actions: rule.actions,
+                position: Some(rule.position),
+            }
+        }
+        (None, None) => return Err((StatusCode::BAD_REQUEST, "rule or rule_id required".to_string())),
+    };
+    let account_id = spec.account_id.clone().or(req.account_id);
+    let scan = req.limit.unwrap_or(500).clamp(1, 10_000);
+    rule_service::dry_run(&pool, &spec, account_id.as_deref(), scan).await.map(Json).map_err(internal)
+}
+
+/// GET /rules/log?account_id=&rule_id=&limit=
+pub async fn rule_log(State(pool): State<SqlitePool>, Query(qs): Query<LogQs>) -> Result<Json<Vec<RuleLogEntry>>, ApiError> {
+    let limit = qs.limit.unwrap_or(100).clamp(1, 1000);
+    rule_service::log(&pool, qs.account_id.as_deref(), qs.rule_id.as_deref(), limit)
+        .await
+        .map(Json)
+        .map_err(internal)
+}
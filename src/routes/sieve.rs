@@ -0,0 +1,125 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+
+use crate::models::account::Account;
+use crate::services::{account_service, sieve_service::{self, RulesOutcome, RulesState}};
+use crate::sieve::rules::RuleSet;
+
+#[derive(Deserialize)]
+pub struct ScriptReq {
+    pub content: String,
+    #[serde(default)]
+    pub activate: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CheckReq {
+    pub content: String,
+}
+
+type ApiError = (StatusCode, String);
+
+async fn load_account(pool: &SqlitePool, account_id: &str) -> Result<Account, ApiError> {
+    account_service::get_account(pool, account_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))
+}
+
+fn server_error(e: anyhow::Error) -> ApiError {
+    (StatusCode::BAD_GATEWAY, e.to_string())
+}
+
+/// GET /sieve/:account_id - whether the account has a usable ManageSieve server, its
+/// capabilities and scripts
+pub async fn sieve_status(State(pool): State<SqlitePool>, Path(account_id): Path<String>) -> Result<Json<Value>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    Ok(Json(match sieve_service::status(&pool, &account).await {
+        Ok(status) => json!({ "supported": true, "status": status }),
+        Err(e) => json!({ "supported": false, "error": e.to_string() }),
+    }))
+}
+
+/// GET /sieve/:account_id/scripts/:name
+pub async fn get_script(
+    State(pool): State<SqlitePool>,
+    Path((account_id, name)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    let content = sieve_service::get_script(&pool, &account, &name).await.map_err(server_error)?;
+    Ok(Json(json!({ "name": name, "content": content })))
+}
+
+/// PUT /sieve/:account_id/scripts/:name  {"content": "...", "activate": true}
+pub async fn put_script(
+    State(pool): State<SqlitePool>,
+    Path((account_id, name)): Path<(String, String)>,
+    Json(req): Json<ScriptReq>,
+) -> Result<Json<Value>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    match sieve_service::put_script(&pool, &account, &name, &req.content, req.activate).await.map_err(server_error)? {
+        Ok(replaced_active) => Ok(Json(json!({ "name": name, "active": req.activate, "replaced_active": replaced_active }))),
+        Err(reason) => Err((StatusCode::UNPROCESSABLE_ENTITY, reason)),
+    }
+}
+
+/// DELETE /sieve/:account_id/scripts/:name
+pub async fn delete_script(
+    State(pool): State<SqlitePool>,
+    Path((account_id, name)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    sieve_service::delete_script(&pool, &account, &name).await.map_err(server_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /sieve/:account_id/scripts/:name/activate
+pub async fn activate_script(
+    State(pool): State<SqlitePool>,
+    Path((account_id, name)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    sieve_service::set_active(&pool, &account, Some(&name)).await.map_err(server_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /sieve/:account_id/deactivate - no script active
+pub async fn deactivate_scripts(State(pool): State<SqlitePool>, Path(account_id): Path<String>) -> Result<StatusCode, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    sieve_service::set_active(&pool, &account, None).await.map_err(server_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /sieve/:account_id/check  {"content": "..."}
+pub async fn check_script(
+    State(pool): State<SqlitePool>,
+    Path(account_id): Path<String>,
+    Json(req): Json<CheckReq>,
+) -> Result<Json<Value>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    Ok(Json(match sieve_service::check_script(&pool, &account, &req.content).await.map_err(server_error)? {
+        Ok(()) => json!({ "valid": true }),
+        Err(reason) => json!({ "valid": false, "error": reason }),
+    }))
+}
+
+/// GET /sieve/:account_id/rules
+pub async fn get_rules(State(pool): State<SqlitePool>, Path(account_id): Path<String>) -> Result<Json<RulesState>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    sieve_service::get_rules(&pool, &account).await.map(Json).map_err(server_error)
+}
+
+/// PUT /sieve/:account_id/rules  {"rules": [...], "vacation": {...}}
+pub async fn put_rules(
+    State(pool): State<SqlitePool>,
+    Path(account_id): Path<String>,
+    Json(set): Json<RuleSet>,
+) -> Result<Json<RulesOutcome>, ApiError> {
+    let account = load_account(&pool, &account_id).await?;
+    match sieve_service::put_rules(&pool, &account, &set).await.map_err(server_error)? {
+        Ok(outcome) => Ok(Json(outcome)),
+        Err(reason) => Err((StatusCode::UNPROCESSABLE_ENTITY, reason)),
+    }
+}
@@ -1,46 +1,59 @@
 use axum::{
     extract::{Path, State},
     http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
     response::Json,
 };
+use futures::Stream;
+use std::convert::Infallible;
 use serde_json::{json, Value};
 use serde::Deserialize;
 use axum::extract::Query;
 
-use crate::services::message_sync_service::{
-    sync_account_messages, sync_folder_messages, SyncStats, backfill_attachments,
-};
+use crate::routes::accounts::check_account_access;
+use crate::services::message_sync_service::{SyncStats, backfill_attachments};
+use crate::services::search_service::{self, SearchHit, ServerQuery};
+use crate::services::sync_job_service::{self, JobPriority, SyncJob};
 use crate::rbac::AuthUser;
 
-/// POST /sync/:account_id - Sync all folders for an account
+#[derive(Debug, Deserialize)]
+pub struct SyncQs { pub wait: Option<bool> }
+
+async fn ensure_account(pool: &sqlx::SqlitePool, account_id: &str) -> Result<crate::models::account::Account, (StatusCode, String)> {
+    crate::services::account_service::get_account(pool, account_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))
+}
+
+/// POST /sync/:account_id - Sync all folders for an account (runs as a user-priority job;
+/// `?wait=false` returns the queued job instead of waiting for it)
 pub async fn sync_account(
     State(pool): State<sqlx::SqlitePool>,
     Path(account_id): Path<String>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    // Get account from DB
-    let account =
-        sqlx::query_as::<_, crate::models::account::Account>("SELECT * FROM accounts WHERE id = ?")
-            .bind(&account_id)
-            .fetch_optional(&pool)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-            .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))?
-            .with_password()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Query(qs): Query<SyncQs>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, String)> {
+    let account = ensure_account(&pool, &account_id).await?;
 
-    // Start sync
-    let stats = sync_account_messages(&pool, &account)
+    let handle = sync_job_service::enqueue(&account_id, None, JobPriority::User).await;
+    if !qs.wait.unwrap_or(true) {
+        return Ok((StatusCode::ACCEPTED, Json(json!({ "job": handle.job }))));
+    }
+    let job_id = handle.job.id.clone();
+    let stats = handle
+        .wait()
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    Ok(Json(json!({
+    Ok((StatusCode::OK, Json(json!({
         "account_id": account_id,
         "email": account.email,
+        "job_id": job_id,
         "stats": stats,
         "total_new": stats.iter().map(|s| s.new_messages).sum::<u32>(),
         "total_updated": stats.iter().map(|s| s.updated_messages).sum::<u32>(),
         "total_deleted": stats.iter().map(|s| s.deleted_messages).sum::<u32>(),
-    })))
+    }))))
 }
 
 /// POST /sync/:account_id/:folder - Sync a specific folder
@@ -48,25 +61,80 @@ pub async fn sync_folder(
     State(pool): State<sqlx::SqlitePool>,
     Path((account_id, folder)): Path<(String, String)>,
 ) -> Result<Json<SyncStats>, (StatusCode, String)> {
-    // Get account from DB
-    let account =
-        sqlx::query_as::<_, crate::models::account::Account>("SELECT * FROM accounts WHERE id = ?")
-            .bind(&account_id)
-            .fetch_optional(&pool)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-            .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))?
-            .with_password()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    ensure_account(&pool, &account_id).await?;
+
+    let stats = sync_job_service::enqueue(&account_id, Some(&folder), JobPriority::User)
+        .await
+        .wait()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    // Start sync
-    let stats = sync_folder_messages(&pool, &account, &folder)
+    stats
+        .into_iter()
+        .next()
+        .map(Json)
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, "Sync returned no stats".to_string()))
+}
+
+/// GET /sync-jobs - Job history (newest first) plus the live queue
+#[derive(Debug, Deserialize)]
+pub struct JobsQs { pub account_id: Option<String>, pub status: Option<String>, pub limit: Option<u32> }
+pub async fn list_sync_jobs(
+    State(pool): State<sqlx::SqlitePool>,
+    Query(qs): Query<JobsQs>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let limit = qs.limit.unwrap_or(50).min(500) as i64;
+    let jobs = sync_job_service::list(&pool, qs.account_id.as_deref(), qs.status.as_deref(), limit)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(json!({ "active": sync_job_service::active(), "jobs": jobs })))
+}
 
-    Ok(Json(stats))
+/// GET /sync-jobs/:id
+pub async fn get_sync_job(
+    State(pool): State<sqlx::SqlitePool>,
+    Path(id): Path<String>,
+) -> Result<Json<SyncJob>, (StatusCode, String)> {
+    sync_job_service::get(&pool, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Sync job not found".to_string()))
 }
 
+/// POST /sync-jobs/:id/cancel - Drop a queued job or stop a running one at its next batch
+pub async fn cancel_sync_job(Path(id): Path<String>) -> Result<Json<SyncJob>, (StatusCode, String)> {
+    sync_job_service::cancel(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| (StatusCode::CONFLICT, "Sync job is not queued or running".to_string()))
+}
+
+/// GET /sync-jobs/events - SSE stream of job state and progress changes
+pub async fn sync_job_events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut rx = sync_job_service::subscribe();
+
+    let stream = async_stream::stream! {
+        loop {
+            match rx.recv().await {
+                Ok(job) => {
+                    let json = serde_json::to_string(&job).unwrap_or_default();
+                    yield Ok(Event::default().event("sync_job").data(json));
+                }
+                // A slow client missed some progress updates; later ones carry the full state
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Whether a `messages` row has changes queued in `pending_ops`, under its own key or as a
+/// moved row's provisional one
+const PENDING: &str = "EXISTS (SELECT 1 FROM pending_ops p WHERE p.account_id = messages.account_id AND p.status = 'pending' AND ((p.folder = messages.folder AND p.uid = messages.uid) OR (p.dest = messages.folder AND p.local_uid = messages.uid))) AS pending";
+
 /// GET /messages/:account_id - Get synced messages for an account
 pub async fn get_messages(
     State(pool): State<sqlx::SqlitePool>,
@@ -85,17 +153,18 @@ pub async fn get_messages(
         size: Option<i64>,
         has_attachments: bool,
         synced_at: String,
+        pending: bool,
     }
 
-    let messages: Vec<MessageRow> = sqlx::query_as(
+    let messages: Vec<MessageRow> = sqlx::query_as(&format!(
         r#"
-        SELECT id, uid, folder, subject, from_addr, to_addr, date, flags, size, has_attachments, synced_at
+        SELECT id, uid, folder, subject, from_addr, to_addr, date, flags, size, has_attachments, synced_at, {PENDING}
         FROM messages
         WHERE account_id = ?
         ORDER BY date DESC
         LIMIT 100
-        "#,
-    )
+        "#
+    ))
     .bind(&account_id)
     .fetch_all(&pool)
     .await
@@ -108,9 +177,14 @@ pub async fn get_messages(
     })))
 }
 
-/// GET /messages/:account_id/:folder - Get messages from a specific folder
+/// GET /messages/:account_id/:folder - Get messages from a specific folder.
+///
+/// On Gmail a label folder lists All Mail rows, so each row carries the folder its `uid`
+/// belongs to; per-message calls (`/messages/:account_id/:folder/:uid/...`) must use that
+/// folder, not the label. Pages are ordered by (uid, folder); pass the last row's `uid` and
+/// `folder` as `before_uid`/`before_folder` (or the `next` cursor) for the next page.
 #[derive(Debug, Deserialize)]
-pub struct PageQs { pub limit: Option<u32>, pub before_uid: Option<i64>, pub unread: Option<bool>, pub attachments: Option<bool> }
+pub struct PageQs { pub limit: Option<u32>, pub before_uid: Option<i64>, pub before_folder: Option<String>, pub unread: Option<bool>, pub attachments: Option<bool> }
 pub async fn get_folder_messages(
     State(pool): State<sqlx::SqlitePool>,
     Path((account_id, folder)): Path<(String, String)>,
@@ -120,64 +194,146 @@ pub async fn get_folder_messages(
     struct MessageRow {
         id: i64,
         uid: i64,
+        folder: String,
         subject: Option<String>,
         from_addr: Option<String>,
         date: Option<String>,
         flags: Option<String>,
         has_attachments: bool,
+        pending: bool,
     }
 
     let limit = q.limit.unwrap_or(100).min(200) as i64;
     let unread = q.unread.unwrap_or(false);
     let want_atts = q.attachments.unwrap_or(false);
 
-    let mut base_sql = String::from("SELECT id, uid, subject, from_addr, date, flags, has_attachments FROM messages WHERE account_id = ? AND folder = ?");
+    // Gmail label folders are virtual: their messages live under All Mail with a message_labels row
+    let mut base_sql = format!("SELECT id, uid, folder, subject, from_addr, date, flags, has_attachments, {PENDING} FROM messages WHERE account_id = ? AND (folder = ? OR id IN (SELECT message_id FROM message_labels WHERE folder = ?))");
     if unread { base_sql.push_str(" AND (flags IS NULL OR flags NOT LIKE '%\\Seen%')"); }
     if want_atts { base_sql.push_str(" AND has_attachments = 1"); }
 
     let messages: Vec<MessageRow> = if let Some(before) = q.before_uid {
+        // Without `before_folder` this is plain `uid < before_uid`
         let mut sql = base_sql.clone();
-        sql.push_str(" AND uid < ? ORDER BY uid DESC LIMIT ?");
+        sql.push_str(" AND (uid < ? OR (uid = ? AND folder < ?)) ORDER BY uid DESC, folder DESC LIMIT ?");
         sqlx::query_as(&sql)
             .bind(&account_id)
             .bind(&folder)
+            .bind(&folder)
+            .bind(before)
             .bind(before)
+            .bind(q.before_folder.as_deref().unwrap_or(""))
             .bind(limit)
             .fetch_all(&pool)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
     } else {
         let mut sql = base_sql.clone();
-        sql.push_str(" ORDER BY uid DESC LIMIT ?");
+        sql.push_str(" ORDER BY uid DESC, folder DESC LIMIT ?");
         sqlx::query_as(&sql)
             .bind(&account_id)
             .bind(&folder)
+            .bind(&folder)
             .bind(limit)
             .fetch_all(&pool)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
     };
+    let next = messages
+        .last()
+        .filter(|_| messages.len() as i64 == limit)
+        .map(|m| json!({ "before_uid": m.uid, "before_folder": m.folder }));
 
     Ok(Json(json!({
         "account_id": account_id,
         "folder": folder,
         "count": messages.len(),
         "messages": messages,
+        "next": next,
     })))
 }
 
-/// GET /search - search messages (subject/from, optional filters)
+/// GET /search - search messages (subject/from, optional filters). `server=true` also searches
+/// the servers (the `account_id` account, or every account the user can see) in
+/// `server_folders` (comma-separated wire names; default `folder`, else INBOX or all of Gmail)
+/// and merges hits by Message-ID.
 #[derive(Debug, Deserialize)]
-pub struct SearchQs { pub q: Option<String>, pub unread: Option<bool>, pub attachments: Option<bool>, pub folder: Option<String>, pub account_id: Option<String>, pub before_uid: Option<i64>, pub limit: Option<u32>, pub start_date: Option<String>, pub end_date: Option<String> }
+pub struct SearchQs { pub q: Option<String>, pub unread: Option<bool>, pub attachments: Option<bool>, pub folder: Option<String>, pub account_id: Option<String>, pub before_uid: Option<i64>, pub limit: Option<u32>, pub start_date: Option<String>, pub end_date: Option<String>, pub server: Option<bool>, pub server_folders: Option<String> }
 
 pub async fn search_messages(
     State(pool): State<sqlx::SqlitePool>,
     auth_user: AuthUser,
     Query(qs): Query<SearchQs>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    #[derive(sqlx::FromRow, serde::Serialize)]
-    struct Row { account_id: String, folder: String, uid: i64, subject: Option<String>, from_addr: Option<String>, date: Option<String>, flags: Option<String>, has_attachments: bool, size: Option<i64> }
+    let (mut sql, args) = search_sql(&qs, &auth_user);
+    sql.push_str(" ORDER BY m.date DESC LIMIT ?");
+    let limit = qs.limit.unwrap_or(100).min(500) as i64;
 
+    // Execute
+    let mut q = sqlx::query_as::<_, SearchHit>(&sql);
+    for v in args { q = q.bind(v); }
+    q = q.bind(limit);
+
+    let rows = q.fetch_all(&pool).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if qs.server != Some(true) {
+        return Ok(Json(json!({ "total": rows.len(), "messages": rows })));
+    }
+
+    let accounts = match qs.account_id.as_deref() {
+        Some(id) => {
+            if !check_account_access(&pool, &auth_user, id).await.unwrap_or(false) {
+                return Err((StatusCode::FORBIDDEN, "no access to this account".to_string()));
+            }
+            vec![ensure_account(&pool, id).await?]
+        }
+        None => {
+            let mut visible = Vec::new();
+            let all = crate::services::account_service::list_accounts(&pool)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            for account in all.into_iter().filter(|a| a.enabled) {
+                if check_account_access(&pool, &auth_user, &account.id).await.unwrap_or(false) {
+                    visible.push(account);
+                }
+            }
+            visible
+        }
+    };
+    let folders: Vec<String> = match qs.server_folders.as_deref().or(qs.folder.as_deref()) {
+        Some(list) => list.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()).collect(),
+        None => Vec::new(),
+    };
+    let query = ServerQuery {
+        text: qs.q.clone().filter(|q| !q.trim().is_empty()),
+        unread: qs.unread == Some(true),
+        attachments: qs.attachments == Some(true),
+        since: qs.start_date.as_deref().and_then(search_service::parse_date),
+        until: qs.end_date.as_deref().and_then(search_service::parse_date),
+    };
+
+    let mut server_hits = Vec::new();
+    let mut server = Vec::new();
+    for account in &accounts {
+        let mut account_folders = Vec::with_capacity(folders.len());
+        for f in &folders {
+            account_folders.push(crate::services::folder_service::wire_name(&pool, &account.id, f).await);
+        }
+        match search_service::search_account(&pool, account, &account_folders, &query, limit as usize).await {
+            Ok((hits, outcomes)) => {
+                server_hits.extend(hits);
+                server.extend(outcomes.into_iter().map(|o| json!(o)));
+            }
+            Err(e) => server.push(json!({ "account_id": account.id, "error": e.to_string() })),
+        }
+    }
+
+    let messages = search_service::merge(rows, server_hits);
+    Ok(Json(json!({ "total": messages.len(), "messages": messages, "server": server })))
+}
+
+/// WHERE-filtered `SELECT m.account_id, m.folder, m.uid, ...` for a search, with its bind
+/// arguments; callers add ORDER BY/LIMIT. Shared with bulk actions that target a search.
+pub(crate) fn search_sql(qs: &SearchQs, auth_user: &AuthUser) -> (String, Vec<String>) {
     let mut sql = String::new();
     let mut args: Vec<String> = Vec::new(); // using String params for everything for simplicity
 
@@ -186,7 +342,7 @@ pub async fn search_messages(
 
     if has_query {
         // FTS Path
-        sql.push_str("SELECT m.account_id, m.folder, m.uid, m.subject, m.from_addr, m.date, m.flags, m.has_attachments, m.size FROM messages m JOIN messages_fts fts ON m.id = fts.rowid ");
+        sql.push_str("SELECT m.account_id, m.folder, m.uid, m.message_id, m.subject, m.from_addr, m.date, m.flags, m.has_attachments, m.size FROM messages m JOIN messages_fts fts ON m.id = fts.rowid ");
         // Security Join if not Admin
         if auth_user.role != "Admin" {
             sql.push_str(" JOIN user_accounts ua ON m.account_id = ua.account_id ");
@@ -201,7 +357,7 @@ pub async fn search_messages(
         args.push(raw_q.clone());
     } else {
         // Standard Path
-        sql.push_str("SELECT m.account_id, m.folder, m.uid, m.subject, m.from_addr, m.date, m.flags, m.has_attachments, m.size FROM messages m ");
+        sql.push_str("SELECT m.account_id, m.folder, m.uid, m.message_id, m.subject, m.from_addr, m.date, m.flags, m.has_attachments, m.size FROM messages m ");
         if auth_user.role != "Admin" {
             sql.push_str(" JOIN user_accounts ua ON m.account_id = ua.account_id ");
         }
@@ -215,7 +371,11 @@ pub async fn search_messages(
     }
 
     if let Some(acc) = qs.account_id.as_ref() { sql.push_str(" AND m.account_id = ?"); args.push(acc.clone()); }
-    if let Some(f) = qs.folder.as_ref() { sql.push_str(" AND m.folder = ?"); args.push(f.clone()); }
+    if let Some(f) = qs.folder.as_ref() {
+        sql.push_str(" AND (m.folder = ? OR m.id IN (SELECT message_id FROM message_labels WHERE folder = ?))");
+        args.push(f.clone());
+        args.push(f.clone());
+    }
     if let Some(true) = qs.unread { sql.push_str(" AND (m.flags IS NULL OR m.flags NOT LIKE '%\\Seen%')"); }
     if let Some(true) = qs.attachments { sql.push_str(" AND m.has_attachments = 1"); }
     // Start/End date...
@@ -223,16 +383,7 @@ pub async fn search_messages(
     if let Some(ed) = qs.end_date.as_ref() { sql.push_str(" AND m.date <= ?"); args.push(ed.clone()); }
     if let Some(bu) = qs.before_uid { sql.push_str(" AND m.uid < ?"); args.push(bu.to_string()); }
 
-    sql.push_str(" ORDER BY m.date DESC LIMIT ?");
-    let limit = qs.limit.unwrap_or(100).min(500) as i64;
-
-    // Execute
-    let mut q = sqlx::query_as::<_, Row>(&sql);
-    for v in args { q = q.bind(v); }
-    q = q.bind(limit);
-
-    let rows = q.fetch_all(&pool).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    Ok(Json(json!({ "total": rows.len(), "messages": rows })))
+    (sql, args)
 }
 
 /// POST /sync/:account_id/backfill-attachments?folder=INBOX&limit=500
@@ -52,11 +52,16 @@ pub async fn smtp_test(
         }
     };
 
+    let auth = match crate::services::mail_auth_service::for_account(&account).await {
+        Ok(a) => a,
+        Err(e) => return Json(serde_json::json!({"success": false, "error": e.to_string()})),
+    };
     let result = smtp::send_simple(
+        &account.id,
         &account.smtp_host,
         account.smtp_port,
-        &account.email,
-        &account.password,
+        account.smtp_security_mode(),
+        &auth,
         &req.to,
         &req.subject,
         &req.body,
@@ -184,7 +189,13 @@ pub async fn fetch_messages(
         })?;
 
     let limit = query.limit.unwrap_or(10).min(50); // Max 50 messages
-    let folder = query.folder.as_deref().unwrap_or("INBOX");
+    let folder = crate::services::folder_service::resolve_folder_alias(
+        &pool,
+        &account.id,
+        query.folder.as_deref().unwrap_or("INBOX"),
+    )
+    .await;
+    let folder = folder.as_str();
 
     tracing::info!(
         "Fetching {} recent messages for account: {} folder: {}",
@@ -396,7 +407,13 @@ pub async fn smtp_send_and_append(
     let raw = msg.formatted();
 
     // Send via SMTP
-    if let Err(e) = crate::smtp::send_prebuilt(&account.smtp_host, account.smtp_port, &account.email, &account.password, &msg) {
+    let auth = match crate::services::mail_auth_service::for_account(&account).await {
+        Ok(a) => a,
+        Err(e) => {
+            return Json(SmtpSendAndAppendResponse { success: false, folder: None, uid: None, message_id: Some(message_id), error: Some(e.to_string()) })
+        }
+    };
+    if let Err(e) = crate::smtp::send_prebuilt(&account.id, &account.smtp_host, account.smtp_port, account.smtp_security_mode(), &auth, &msg) {
         return Json(SmtpSendAndAppendResponse { success: false, folder: None, uid: None, message_id: Some(message_id), error: Some(format!("SMTP send failed: {}", e)) });
     }
     // bump emails_sent on success
@@ -619,17 +636,17 @@ pub async fn list_folders(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Account not found".to_string()))?;
 
-    // Use stored credentials directly; if encoded, models::account should provide get_credentials
-    let (email, password) = match account.get_credentials() {
-        Ok(v) => v,
-        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
-    };
+    // Password or OAuth2 token, whichever the account uses
+    let auth = crate::services::mail_auth_service::for_account(&account)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let folders = imap_folders::list_mailboxes(
+        &account.id,
         &account.imap_host,
         account.imap_port,
-        &email,
-        &password,
+        account.imap_security_mode(),
+        &auth,
     )
     .await
     .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
@@ -0,0 +1,101 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::imap::transfer::Transfer;
+use crate::services::{account_service, message_service::{self, TransferOutcome, UidMapping}, pending_op_service};
+
+#[derive(Deserialize)]
+pub struct TransferReq {
+    /// Destination folder
+    pub to: String,
+    /// Bulk form only
+    #[serde(default)]
+    pub uids: Vec<u32>,
+}
+
+async fn run(
+    pool: &SqlitePool,
+    account_id: &str,
+    folder: &str,
+    uids: &[u32],
+    dest: &str,
+    mode: Transfer,
+) -> Result<Json<TransferOutcome>, (StatusCode, String)> {
+    if uids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "uids must not be empty".to_string()));
+    }
+    if dest.trim().is_empty() || dest == folder {
+        return Err((StatusCode::BAD_REQUEST, "to must name a different folder".to_string()));
+    }
+    let account = account_service::get_account(pool, account_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Account not found".to_string()))?;
+    // Moves and copies queue behind changes still waiting for the server, and whenever it can't
+    // be reached (not on Gmail, where they relabel All Mail rows)
+    let queueable = pending_op_service::queues_transfers(&account);
+    let waiting = pending_op_service::must_queue(pool, account_id, folder, uids)
+        .await
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    if !(queueable && waiting) {
+        match message_service::transfer_messages(pool, &account, folder, uids, dest, mode).await {
+            Ok(out) => return Ok(Json(out)),
+            Err(e) if queueable && pending_op_service::is_offline(&e) => {}
+            Err(e) => return Err((StatusCode::BAD_GATEWAY, e.to_string())),
+        }
+    }
+
+    let mut messages = Vec::with_capacity(uids.len());
+    for &uid in uids {
+        let local_uid = match mode {
+            Transfer::Move => pending_op_service::queue_move(pool, account_id, folder, uid, dest).await.map(Some),
+            Transfer::Copy => pending_op_service::queue_copy(pool, account_id, folder, uid, dest).await.map(|()| None),
+        }
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        messages.push(UidMapping { uid, new_uid: None, local_uid });
+    }
+    Ok(Json(TransferOutcome {
+        from: folder.to_string(),
+        to: dest.to_string(),
+        messages,
+        resync_queued: false,
+        queued: true,
+    }))
+}
+
+/// POST /messages/:account_id/:folder/:uid/move  {"to": "Archive"}
+pub async fn move_message(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder, uid)): Path<(String, String, u32)>,
+    Json(req): Json<TransferReq>,
+) -> Result<Json<TransferOutcome>, (StatusCode, String)> {
+    run(&pool, &account_id, &folder, &[uid], &req.to, Transfer::Move).await
+}
+
+/// POST /messages/:account_id/:folder/:uid/copy  {"to": "Archive"}
+pub async fn copy_message(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder, uid)): Path<(String, String, u32)>,
+    Json(req): Json<TransferReq>,
+) -> Result<Json<TransferOutcome>, (StatusCode, String)> {
+    run(&pool, &account_id, &folder, &[uid], &req.to, Transfer::Copy).await
+}
+
+/// POST /messages/:account_id/:folder/move  {"uids": [1, 2, 3], "to": "Trash"}
+pub async fn move_messages(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder)): Path<(String, String)>,
+    Json(req): Json<TransferReq>,
+) -> Result<Json<TransferOutcome>, (StatusCode, String)> {
+    run(&pool, &account_id, &folder, &req.uids, &req.to, Transfer::Move).await
+}
+
+/// POST /messages/:account_id/:folder/copy  {"uids": [1, 2, 3], "to": "Archive"}
+pub async fn copy_messages(
+    State(pool): State<SqlitePool>,
+    Path((account_id, folder)): Path<(String, String)>,
+    Json(req): Json<TransferReq>,
+) -> Result<Json<TransferOutcome>, (StatusCode, String)> {
+    run(&pool, &account_id, &folder, &req.uids, &req.to, Transfer::Copy).await
+}
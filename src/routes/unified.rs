@@ -46,11 +46,12 @@ pub async fn unified_inbox(
         let unread_filter_adm = if q.unread_only.unwrap_or(false) { "AND (flags NOT LIKE '%\\Seen%')" } else { "" };
         let sql = format!(
             "SELECT account_id, folder, uid, message_id, subject, from_addr, to_addr, date, flags, size \
-             FROM messages WHERE folder = ? {} {} ORDER BY date DESC LIMIT ? OFFSET ?",
+             FROM messages WHERE (folder = ? OR id IN (SELECT message_id FROM message_labels WHERE folder = ?)) {} {} ORDER BY date DESC LIMIT ? OFFSET ?",
             unread_filter_adm, snooze_filter
         );
         sqlx::query_as::<_, UnifiedMessage>(&sql)
-            .bind(folder)
+            .bind(&folder)
+            .bind(&folder)
             .bind(limit)
             .bind(offset)
             .fetch_all(&pool)
@@ -60,11 +61,12 @@ pub async fn unified_inbox(
             "SELECT m.account_id, m.folder, m.uid, m.message_id, m.subject, m.from_addr, m.to_addr, m.date, m.flags, m.size \
              FROM messages m \
              JOIN user_accounts ua ON m.account_id = ua.account_id \
-             WHERE m.folder = ? AND ua.user_id = ? {} {} ORDER BY m.date DESC LIMIT ? OFFSET ?",
+             WHERE (m.folder = ? OR m.id IN (SELECT message_id FROM message_labels WHERE folder = ?)) AND ua.user_id = ? {} {} ORDER BY m.date DESC LIMIT ? OFFSET ?",
             unread_filter, snooze_filter
         );
         sqlx::query_as::<_, UnifiedMessage>(&sql)
-            .bind(folder)
+            .bind(&folder)
+            .bind(&folder)
             .bind(auth_user.id)
             .bind(limit)
             .bind(offset)
@@ -167,11 +169,11 @@ pub async fn unified_unread(
     let snooze_filter = "(snoozed_until IS NULL OR snoozed_until <= datetime('now'))";
 
     let rows = if auth_user.role == "Admin" {
-         let sql = format!("SELECT account_id, COUNT(*) as c FROM messages WHERE folder='INBOX' AND (flags NOT LIKE '%\\Seen%') AND {} GROUP BY account_id", snooze_filter);
+         let sql = format!("SELECT account_id, COUNT(*) as c FROM messages WHERE (folder='INBOX' OR id IN (SELECT message_id FROM message_labels WHERE folder='INBOX')) AND (flags NOT LIKE '%\\Seen%') AND {} GROUP BY account_id", snooze_filter);
          sqlx::query(&sql)
             .fetch_all(&pool).await
     } else {
-         let sql = format!("SELECT m.account_id, COUNT(*) as c FROM messages m JOIN user_accounts ua ON m.account_id = ua.account_id WHERE m.folder='INBOX' AND (m.flags NOT LIKE '%\\Seen%') AND {} AND ua.user_id = ? GROUP BY m.account_id", snooze_filter);
+         let sql = format!("SELECT m.account_id, COUNT(*) as c FROM messages m JOIN user_accounts ua ON m.account_id = ua.account_id WHERE (m.folder='INBOX' OR m.id IN (SELECT message_id FROM message_labels WHERE folder='INBOX')) AND (m.flags NOT LIKE '%\\Seen%') AND {} AND ua.user_id = ? GROUP BY m.account_id", snooze_filter);
          sqlx::query(&sql)
             .bind(auth_user.id)
             .fetch_all(&pool).await
@@ -1,5 +1,5 @@
 /// Account management service
-use crate::models::account::{Account, EmailProvider};
+use crate::models::account::{Account, EmailProvider, SecurityMode};
 use anyhow::Result;
 use sqlx::SqlitePool;
 use sqlx::Row;
@@ -101,6 +101,14 @@ pub async fn add_account(
         color: Some("#3b82f6".to_string()),
         carddav_url: computed_carddav_url,
         caldav_url: computed_caldav_url,
+        imap_security: None,
+        smtp_security: None,
+        auth_method: Some("password".to_string()),
+        oauth_access_token: None,
+        oauth_refresh_token: None,
+        oauth_expires_at: None,
+        oauth_status: None,
+        oauth_error: None,
         password: String::new(),
     };
 
@@ -136,6 +144,14 @@ pub async fn list_accounts(pool: &SqlitePool) -> Result<Vec<Account>> {
         let color: Option<String> = row.try_get("color").ok();
         let carddav_url: Option<String> = row.try_get("carddav_url").ok();
         let caldav_url: Option<String> = row.try_get("caldav_url").ok();
+        let imap_security: Option<String> = row.try_get("imap_security").ok().flatten();
+        let smtp_security: Option<String> = row.try_get("smtp_security").ok().flatten();
+        let auth_method: Option<String> = row.try_get("auth_method").ok().flatten();
+        let oauth_access_token: Option<String> = row.try_get("oauth_access_token").ok().flatten();
+        let oauth_refresh_token: Option<String> = row.try_get("oauth_refresh_token").ok().flatten();
+        let oauth_expires_at: Option<i64> = row.try_get("oauth_expires_at").ok().flatten();
+        let oauth_status: Option<String> = row.try_get("oauth_status").ok().flatten();
+        let oauth_error: Option<String> = row.try_get("oauth_error").ok().flatten();
 
         accounts.push(Account {
             id,
@@ -157,6 +173,14 @@ pub async fn list_accounts(pool: &SqlitePool) -> Result<Vec<Account>> {
             color,
             carddav_url,
             caldav_url,
+            imap_security,
+            smtp_security,
+            auth_method,
+            oauth_access_token,
+            oauth_refresh_token,
+            oauth_expires_at,
+            oauth_status,
+            oauth_error,
             password: String::new(),
         });
     }
@@ -192,6 +216,14 @@ pub async fn get_account(pool: &SqlitePool, account_id: &str) -> Result<Option<A
             let color: Option<String> = row.try_get("color").ok();
             let carddav_url: Option<String> = row.try_get("carddav_url").ok();
             let caldav_url: Option<String> = row.try_get("caldav_url").ok();
+            let imap_security: Option<String> = row.try_get("imap_security").ok().flatten();
+            let smtp_security: Option<String> = row.try_get("smtp_security").ok().flatten();
+            let auth_method: Option<String> = row.try_get("auth_method").ok().flatten();
+            let oauth_access_token: Option<String> = row.try_get("oauth_access_token").ok().flatten();
+            let oauth_refresh_token: Option<String> = row.try_get("oauth_refresh_token").ok().flatten();
+            let oauth_expires_at: Option<i64> = row.try_get("oauth_expires_at").ok().flatten();
+            let oauth_status: Option<String> = row.try_get("oauth_status").ok().flatten();
+            let oauth_error: Option<String> = row.try_get("oauth_error").ok().flatten();
 
             let mut acc = Account {
                 id,
@@ -213,6 +245,14 @@ pub async fn get_account(pool: &SqlitePool, account_id: &str) -> Result<Option<A
                 color,
                 carddav_url,
                 caldav_url,
+                imap_security,
+                smtp_security,
+                auth_method,
+                oauth_access_token,
+                oauth_refresh_token,
+                oauth_expires_at,
+                oauth_status,
+                oauth_error,
                 password: String::new(),
             };
 
@@ -231,6 +271,12 @@ pub async fn delete_account(pool: &SqlitePool, account_id: &str) -> Result<bool>
     let result = sqlx::query!("DELETE FROM accounts WHERE id = ?", account_id)
         .execute(pool)
         .await?;
+    sqlx::query("DELETE FROM tls_pins WHERE account_id = ?")
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    crate::imap::pool::invalidate(account_id);
+    crate::tls::forget(account_id);
 
     Ok(result.rows_affected() > 0)
 }
@@ -253,6 +299,23 @@ pub async fn update_last_sync(pool: &SqlitePool, account_id: &str) -> Result<()>
     Ok(())
 }
 
+/// Set the IMAP/SMTP security modes (`None` keeps the port-based default)
+pub async fn set_security(
+    pool: &SqlitePool,
+    account_id: &str,
+    imap: Option<SecurityMode>,
+    smtp: Option<SecurityMode>,
+) -> Result<()> {
+    sqlx::query("UPDATE accounts SET imap_security = ?, smtp_security = ? WHERE id = ?")
+        .bind(imap.map(|m| m.as_str().to_string()))
+        .bind(smtp.map(|m| m.as_str().to_string()))
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    crate::imap::pool::invalidate(account_id);
+    Ok(())
+}
+
 /// Update an existing account (partial). Returns updated Account.
 pub async fn update_account(
     pool: &SqlitePool,
@@ -307,5 +370,6 @@ pub async fn update_account(
     .bind(account_id)
     .execute(pool)
     .await?;
+    crate::imap::pool::invalidate(account_id);
     Ok(Some(current))
 }
@@ -0,0 +1,280 @@
+/// Bulk message actions: one flag change, delete, move or copy applied to many messages.
+///
+/// Messages are grouped by account and folder; each group goes out as one STORE/MOVE/COPY over
+/// a compact UID set per `BATCH` UIDs (to keep command lines within what servers accept), and
+/// each of those checks out a pooled session and SELECTs the folder. Every message gets its own
+/// result: a failed command fails its own messages and the ones after it, not those already
+/// done. Like single changes, they are queued while the server can't be reached or older
+/// changes wait for it.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::imap::pool;
+use crate::imap::transfer::{self, Transfer};
+use crate::models::account::Account;
+use crate::services::{account_service, folder_service, message_service, pending_op_service};
+
+/// UIDs per command
+const BATCH: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    MarkRead,
+    MarkUnread,
+    Flag,
+    Unflag,
+    /// Move to Trash (expunge when already in Trash); undoable with the returned token
+    Delete,
+    /// To the folder in `to` (a name or a role alias such as "trash")
+    Move,
+    Copy,
+    /// To the account's `\Archive` folder
+    Archive,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MessageRef {
+    pub account_id: String,
+    pub folder: String,
+    pub uid: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemResult {
+    #[serde(flatten)]
+    pub message: MessageRef,
+    pub ok: bool,
+    /// Waiting for the server in `pending_ops`
+    pub queued: bool,
+    /// UID in the destination folder after move/copy, when the server reported it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_uid: Option<u32>,
+    /// Provisional UID in the destination of a queued move
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkOutcome {
+    pub results: Vec<ItemResult>,
+    /// Undoes every move to Trash of a `delete`
+    pub undo_token: Option<String>,
+}
+
+/// What happened to one folder's messages
+#[derive(Default)]
+struct Applied {
+    new_uids: HashMap<u32, u32>,
+    local_uids: HashMap<u32, u32>,
+    queued: HashSet<u32>,
+    /// Messages the server or the queue refused, with the reason
+    failed: HashMap<u32, String>,
+    undoable: bool,
+}
+
+/// Apply `action` to every message; failures are reported per item, never as a whole
+pub async fn run(pool: &SqlitePool, action: BulkAction, to: Option<&str>, refs: Vec<MessageRef>) -> BulkOutcome {
+    let token = uuid::Uuid::new_v4().to_string();
+    let mut undoable = false;
+    let mut groups: BTreeMap<(String, String), Vec<u32>> = BTreeMap::new();
+    for r in refs {
+        groups.entry((r.account_id, r.folder)).or_default().push(r.uid);
+    }
+
+    let mut accounts: HashMap<String, Result<Account, String>> = HashMap::new();
+    let mut results = Vec::new();
+    for ((account_id, folder), mut uids) in groups {
+        uids.sort_unstable();
+        uids.dedup();
+        if !accounts.contains_key(&account_id) {
+            let account = match account_service::get_account(pool, &account_id).await {
+                Ok(Some(a)) => Ok(a),
+                Ok(None) => Err("account not found".to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            accounts.insert(account_id.clone(), account);
+        }
+
+        let outcome = match &accounts[&account_id] {
+            Ok(account) => run_group(pool, account, &folder, &uids, action, to, &token).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone()),
+        };
+        let (applied, group_error) = match outcome {
+            Ok(applied) => (applied, None),
+            Err(e) => (Applied::default(), Some(e)),
+        };
+        undoable |= applied.undoable;
+
+        for uid in uids {
+            let message = MessageRef { account_id: account_id.clone(), folder: folder.clone(), uid };
+            let error = group_error.clone().or_else(|| applied.failed.get(&uid).cloned());
+            results.push(ItemResult {
+                message,
+                ok: error.is_none(),
+                queued: applied.queued.contains(&uid),
+                new_uid: applied.new_uids.get(&uid).copied(),
+                local_uid: applied.local_uids.get(&uid).copied(),
+                error,
+            });
+        }
+    }
+    BulkOutcome { results, undo_token: undoable.then_some(token) }
+}
+
+/// One folder's messages go to the server, or into the queue while it can't be reached or the
+/// account has changes waiting for it (see `pending_op_service`)
+async fn run_group(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    uids: &[u32],
+    action: BulkAction,
+    to: Option<&str>,
+    token: &str,
+) -> Result<Applied> {
+    let mut applied = Applied::default();
+    let mut sent = 0;
+    if !pending_op_service::must_queue(pool, &account.id, folder, uids).await? {
+        for chunk in uids.chunks(BATCH) {
+            let res = if action == BulkAction::Delete {
+                message_service::delete_messages(pool, account, folder, chunk, Some(token))
+                    .await
+                    .map(|out| {
+                        applied.undoable |= out.undo_token.is_some();
+                        applied.new_uids.extend(out.messages.iter().filter_map(|m| m.new_uid.map(|n| (m.uid, n))));
+                    })
+            } else {
+                apply(pool, account, folder, chunk, action, to, &mut applied.new_uids).await
+            };
+            match res {
+                Ok(()) => sent += chunk.len(),
+                // The rest waits for the server
+                Err(e) if pending_op_service::is_offline(&e) && queueable(account, action) => break,
+                // Earlier chunks stay done (and undoable); this one and the rest fail
+                Err(e) => {
+                    applied.failed.extend(uids[sent..].iter().map(|&u| (u, e.to_string())));
+                    return Ok(applied);
+                }
+            }
+        }
+        if sent == uids.len() {
+            return Ok(applied);
+        }
+    }
+    if let Err(e) = queue(pool, account, folder, &uids[sent..], action, to, &mut applied).await {
+        let queued = &applied.queued;
+        applied.failed.extend(uids[sent..].iter().filter(|u| !queued.contains(u)).map(|&u| (u, e.to_string())));
+    }
+    Ok(applied)
+}
+
+fn queueable(account: &Account, action: BulkAction) -> bool {
+    flag_change(action).is_some() || pending_op_service::queues_transfers(account)
+}
+
+/// STORE item and the flags it adds and removes, for flag actions
+fn flag_change(action: BulkAction) -> Option<(&'static str, &'static [&'static str], &'static [&'static str])> {
+    Some(match action {
+        BulkAction::MarkRead => ("+FLAGS.SILENT (\\Seen)", &["\\Seen"], &[]),
+        BulkAction::MarkUnread => ("-FLAGS.SILENT (\\Seen)", &[], &["\\Seen"]),
+        BulkAction::Flag => ("+FLAGS.SILENT (\\Flagged)", &["\\Flagged"], &[]),
+        BulkAction::Unflag => ("-FLAGS.SILENT (\\Flagged)", &[], &["\\Flagged"]),
+        _ => return None,
+    })
+}
+
+/// Where a move, copy or archive goes; `None` for other actions
+async fn destination(pool: &SqlitePool, account: &Account, folder: &str, action: BulkAction, to: Option<&str>) -> Result<Option<(Transfer, String)>> {
+    let (mode, dest) = match action {
+        BulkAction::Move | BulkAction::Copy => {
+            let to = to.filter(|t| !t.trim().is_empty()).ok_or_else(|| anyhow::anyhow!("`to` is required"))?;
+            let mode = if action == BulkAction::Move { Transfer::Move } else { Transfer::Copy };
+            (mode, folder_service::resolve_folder_alias(pool, &account.id, to).await)
+        }
+        BulkAction::Archive => {
+            let archive = folder_service::folder_by_role(pool, &account.id, "\\Archive")
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("account has no \\Archive folder"))?;
+            (Transfer::Move, archive)
+        }
+        _ => return Ok(None),
+    };
+    if dest == folder {
+        anyhow::bail!("message is already in {}", dest);
+    }
+    Ok(Some((mode, dest)))
+}
+
+async fn apply(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    uids: &[u32],
+    action: BulkAction,
+    to: Option<&str>,
+    new_uids: &mut HashMap<u32, u32>,
+) -> Result<()> {
+    let Some((mode, dest)) = destination(pool, account, folder, action, to).await? else {
+        return update_server(pool, account, folder, uids, action).await;
+    };
+    let outcome = message_service::transfer_messages(pool, account, folder, uids, &dest, mode).await?;
+    new_uids.extend(outcome.messages.iter().filter_map(|m| m.new_uid.map(|n| (m.uid, n))));
+    Ok(())
+}
+
+/// Record the changes for replay and apply them to the cache
+async fn queue(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    uids: &[u32],
+    action: BulkAction,
+    to: Option<&str>,
+    applied: &mut Applied,
+) -> Result<()> {
+    if !queueable(account, action) {
+        anyhow::bail!("changes are still waiting for the server; try again later");
+    }
+    let dest = destination(pool, account, folder, action, to).await?;
+    for &uid in uids {
+        let local_uid = match (&dest, flag_change(action)) {
+            (_, Some((_, add, remove))) => {
+                pending_op_service::queue_flags(pool, &account.id, folder, uid, add, remove).await?;
+                None
+            }
+            (Some((Transfer::Move, dest)), _) => Some(pending_op_service::queue_move(pool, &account.id, folder, uid, dest).await?),
+            (Some((Transfer::Copy, dest)), _) => {
+                pending_op_service::queue_copy(pool, &account.id, folder, uid, dest).await?;
+                None
+            }
+            (None, None) => pending_op_service::queue_delete(pool, account, folder, uid).await?.1,
+        };
+        applied.queued.insert(uid);
+        applied.local_uids.extend(local_uid.map(|l| (uid, l)));
+    }
+    Ok(())
+}
+
+/// Flag changes: one STORE on the folder, then the cache
+async fn update_server(pool: &SqlitePool, account: &Account, folder: &str, uids: &[u32], action: BulkAction) -> Result<()> {
+    let (item, add, remove) = flag_change(action).ok_or_else(|| anyhow::anyhow!("not a flag action"))?;
+
+    let mut imap = pool::acquire_account(account).await?;
+    let res = async {
+        imap.session.select(folder).await?;
+        transfer::store(&mut imap.session, uids, item).await
+    }
+    .await;
+    if let Err(e) = res {
+        imap.discard();
+        return Err(e);
+    }
+    imap.release();
+
+    message_service::update_cached_flags(pool, &account.id, folder, uids, add, remove).await
+}
@@ -16,7 +16,7 @@ pub async fn sync_caldav(pool: &SqlitePool, account_id: &str) -> Result<()> {
     }
     
     let base_url = account.caldav_url.unwrap();
-    let client = DavClient::new(&base_url, &account.email, &account.password);
+    let client = DavClient::new(account_id, &base_url, &account.email, &account.password);
     
     // 2. Discover principal & calendar-home-set
     let principal_url = match client.discover_principal().await {
@@ -29,7 +29,7 @@ pub async fn sync_account(pool: &SqlitePool, account_id: &str) -> Result<SyncRes
     }
 
     let carddav_url = account.carddav_url.as_deref().unwrap().to_string();
-    let client = DavClient::new(&carddav_url, &account.username, &account.password);
+    let client = DavClient::new(account_id, &carddav_url, &account.username, &account.password);
 
     let mut total = SyncResult::default();
 
@@ -5,6 +5,9 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::models::account::SecurityMode;
+use crate::services::mail_auth_service::MailAuth;
+
 // In-memory account credential store (very temporary, not secure)
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[allow(non_snake_case)]
@@ -13,6 +16,18 @@ pub struct AccountCreds {
     pub password: String,
     pub host: String,
     pub port: u16,
+    #[serde(default)]
+    pub security: Option<SecurityMode>,
+}
+
+impl AccountCreds {
+    pub fn security(&self) -> SecurityMode {
+        self.security.unwrap_or_else(|| SecurityMode::for_imap_port(self.port))
+    }
+
+    pub fn auth(&self) -> MailAuth {
+        MailAuth::password(&self.email, &self.password)
+    }
 }
 
 pub static ACCOUNTS: Lazy<Arc<RwLock<std::collections::HashMap<String, AccountCreds>>>> =
@@ -14,6 +14,9 @@ pub struct DiscoveryResult {
     pub source: String, // "ispdb", "dns", "heuristic"
     pub imap: Option<ServerEndpoint>,
     pub smtp: Option<ServerEndpoint>,
+    /// ManageSieve (RFC 5804), from the `_sieve._tcp` SRV record
+    #[serde(default)]
+    pub sieve: Option<ServerEndpoint>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,18 +87,27 @@ impl DiscoveryService {
     pub async fn discover(&self, email: &str) -> Result<DiscoveryResult> {
         let domain = email.split('@').nth(1).ok_or_else(|| anyhow!("Invalid email"))?;
 
-        // 1. Mozilla ISPDB
-        if let Ok(result) = self.lookup_ispdb(domain).await {
-            return Ok(result);
-        }
-
-        // 2. DNS SRV
-        if let Ok(result) = self.lookup_dns_srv(domain).await {
-             return Ok(result);
-        }
+        // 1. Mozilla ISPDB, 2. DNS SRV, 3. Heuristic
+        let mut result = match self.lookup_ispdb(domain).await {
+            Ok(result) => result,
+            Err(_) => match self.lookup_dns_srv(domain).await {
+                Ok(result) => result,
+                Err(_) => self.lookup_heuristic(domain).await?,
+            },
+        };
+        result.sieve = self.lookup_sieve_srv(domain).await;
+        Ok(result)
+    }
 
-        // 3. Heuristic
-        self.lookup_heuristic(domain).await
+    /// `_sieve._tcp` SRV record (RFC 5804 §1.8); ManageSieve always starts with STARTTLS
+    pub async fn lookup_sieve_srv(&self, domain: &str) -> Option<ServerEndpoint> {
+        let records = self.resolver.srv_lookup(format!("_sieve._tcp.{}", domain)).await.ok()?;
+        let record = records.iter().min_by_key(|r| r.priority())?;
+        Some(ServerEndpoint {
+            host: record.target().to_string().trim_end_matches('.').to_string(),
+            port: record.port(),
+            socket_type: "STARTTLS".to_string(),
+        })
     }
 
     async fn lookup_ispdb(&self, domain: &str) -> Result<DiscoveryResult> {
@@ -129,6 +141,7 @@ impl DiscoveryService {
                     port: smtp.port,
                     socket_type: smtp.socketType.clone(),
                 }),
+                sieve: None,
             })
         } else {
              Err(anyhow!("Incomplete config from ISPDB"))
@@ -164,6 +177,7 @@ impl DiscoveryService {
                 source: "dns".to_string(),
                 imap: imap_endpoint,
                 smtp: smtp_endpoint,
+                sieve: None,
             })
         } else {
             Err(anyhow!("DNS lookup failed"))
@@ -215,6 +229,7 @@ impl DiscoveryService {
                 source: "heuristic".to_string(),
                 imap: found_imap,
                 smtp: found_smtp,
+                sieve: None,
             })
         } else {
             Err(anyhow!("Discovery failed"))
@@ -0,0 +1,593 @@
+/// Drafts: local autosave plus a copy in the account's `\Drafts` folder.
+///
+/// Every save is a new local version, so a crash or a bad edit never loses text. The server
+/// copy follows a few seconds later (`MAILORA_DRAFT_SAVE_DELAY_SECS`, saves in between are
+/// coalesced): the new version is APPENDed and older copies carrying the draft's Message-ID are
+/// expunged. After an account sync [`reconcile`] imports drafts written by other clients and
+/// picks up edits to ours. Once the outbox has sent a draft, [`on_sent`] removes both copies; a
+/// server copy that can't be removed then is retried on startup and by [`reconcile`].
+use anyhow::Result;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+
+use crate::imap::conn::{quote, ImapSession};
+use crate::imap::headers;
+use crate::imap::pool;
+use crate::imap::transfer::{self, uid_set};
+use crate::models::account::Account;
+use crate::models::draft::{Draft, DraftVersion};
+use crate::services::{account_service, folder_service, message_body_service, message_service};
+
+pub const STATUS_DRAFT: &str = "draft";
+pub const STATUS_QUEUED: &str = "queued";
+/// Sent, but the server copy is still to be removed; `init` and `reconcile` retry
+pub const STATUS_SENT: &str = "sent";
+
+/// Pushes and reconciles of the `\Drafts` folder run one at a time
+static PUSH: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Drafts with a push already scheduled
+static PENDING: Lazy<std::sync::Mutex<HashSet<String>>> = Lazy::new(|| std::sync::Mutex::new(HashSet::new()));
+
+const SELECT: &str = "SELECT id, account_id, to_addr, subject, body, version, synced_version, message_id, server_folder, server_uid, status, last_error, created_at, updated_at FROM drafts";
+
+fn env_u64(key: &str, default: u64) -> u64 {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DraftContent {
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    NotFound,
+    /// The draft changed since `base_version` (another tab or device saved in between)
+    Conflict(Box<Draft>),
+    /// Already handed to the outbox
+    Queued,
+    Db(anyhow::Error),
+}
+
+impl From<sqlx::Error> for SaveError {
+    fn from(e: sqlx::Error) -> Self {
+        SaveError::Db(e.into())
+    }
+}
+
+impl From<anyhow::Error> for SaveError {
+    fn from(e: anyhow::Error) -> Self {
+        SaveError::Db(e)
+    }
+}
+
+pub async fn get(pool: &SqlitePool, id: &str) -> Result<Option<Draft>> {
+    let draft = sqlx::query_as::<_, Draft>(&format!("{} WHERE id = ?", SELECT))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(draft)
+}
+
+pub async fn list(pool: &SqlitePool, account_id: Option<&str>) -> Result<Vec<Draft>> {
+    let drafts = sqlx::query_as::<_, Draft>(&format!(
+        "{} WHERE (? IS NULL OR account_id = ?) ORDER BY updated_at DESC",
+        SELECT
+    ))
+    .bind(account_id)
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(drafts)
+}
+
+/// Saved versions, newest first
+pub async fn versions(pool: &SqlitePool, id: &str) -> Result<Vec<DraftVersion>> {
+    let versions = sqlx::query_as::<_, DraftVersion>(
+        "SELECT version, to_addr, subject, body, saved_at FROM draft_versions WHERE draft_id = ? ORDER BY version DESC",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    Ok(versions)
+}
+
+/// New draft (version 1); the server copy follows
+pub async fn create(pool: &SqlitePool, account: &Account, content: DraftContent) -> Result<Draft> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let domain = account.email.split('@').nth(1).unwrap_or("mailora.local");
+    let message_id = format!("draft-{}@{}", id, domain);
+    let (to, subject, body) = (
+        content.to.unwrap_or_default(),
+        content.subject.unwrap_or_default(),
+        content.body.unwrap_or_default(),
+    );
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO drafts (id, account_id, to_addr, subject, body, message_id) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(&id)
+        .bind(&account.id)
+        .bind(&to)
+        .bind(&subject)
+        .bind(&body)
+        .bind(&message_id)
+        .execute(&mut *tx)
+        .await?;
+    insert_version(&mut tx, &id, 1, &to, &subject, &body).await?;
+    tx.commit().await?;
+
+    schedule_push(pool, &id);
+    get(pool, &id).await?.ok_or_else(|| anyhow::anyhow!("draft {} vanished", id))
+}
+
+/// Autosave: store the changed fields as a new version. With `base_version` the save is
+/// refused when someone else saved since.
+pub async fn save(pool: &SqlitePool, id: &str, content: DraftContent, base_version: Option<i64>) -> Result<Draft, SaveError> {
+    let mut tx = pool.begin().await?;
+    let current = sqlx::query_as::<_, Draft>(&format!("{} WHERE id = ?", SELECT))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(SaveError::NotFound)?;
+    if current.status != STATUS_DRAFT {
+        return Err(SaveError::Queued);
+    }
+    if base_version.is_some_and(|v| v != current.version) {
+        return Err(SaveError::Conflict(Box::new(current)));
+    }
+
+    let to = content.to.unwrap_or(current.to_addr);
+    let subject = content.subject.unwrap_or(current.subject);
+    let body = content.body.unwrap_or(current.body);
+    let version = current.version + 1;
+    sqlx::query(
+        "UPDATE drafts SET to_addr = ?, subject = ?, body = ?, version = ?, updated_at = strftime('%s','now') WHERE id = ?",
+    )
+    .bind(&to)
+    .bind(&subject)
+    .bind(&body)
+    .bind(version)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    insert_version(&mut tx, id, version, &to, &subject, &body).await?;
+    tx.commit().await?;
+
+    schedule_push(pool, id);
+    get(pool, id).await?.ok_or(SaveError::NotFound)
+}
+
+async fn insert_version(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    id: &str,
+    version: i64,
+    to: &str,
+    subject: &str,
+    body: &str,
+) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO draft_versions (draft_id, version, to_addr, subject, body) VALUES (?, ?, ?, ?, ?)")
+        .bind(id)
+        .bind(version)
+        .bind(to)
+        .bind(subject)
+        .bind(body)
+        .execute(&mut **tx)
+        .await?;
+    let keep = env_u64("MAILORA_DRAFT_VERSIONS", 20).max(1) as i64;
+    sqlx::query("DELETE FROM draft_versions WHERE draft_id = ? AND version <= ?")
+        .bind(id)
+        .bind(version - keep)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Delete the draft locally and on the server
+pub async fn delete(pool: &SqlitePool, id: &str) -> Result<bool> {
+    let Some(draft) = get(pool, id).await? else { return Ok(false) };
+    remove_everywhere(pool, &draft).await?;
+    Ok(true)
+}
+
+/// Hand the draft to the outbox; its server copy is removed once the message is sent
+pub async fn send(pool: &SqlitePool, id: &str) -> Result<Option<String>> {
+    let Some(draft) = get(pool, id).await? else { return Ok(None) };
+    if draft.status != STATUS_DRAFT {
+        anyhow::bail!("draft is already queued for sending");
+    }
+    if draft.to_addr.trim().is_empty() {
+        anyhow::bail!("draft has no recipient");
+    }
+    let outbox_id = crate::services::outbox_service::queue_draft(pool, &draft)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    sqlx::query("UPDATE drafts SET status = ?, updated_at = strftime('%s','now') WHERE id = ?")
+        .bind(STATUS_QUEUED)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(Some(outbox_id))
+}
+
+/// Outbox callback after a successful send
+pub async fn on_sent(pool: &SqlitePool, id: &str) {
+    // Recorded first, so a removal that fails now is retried rather than forgotten
+    let res = sqlx::query("UPDATE drafts SET status = ?, updated_at = strftime('%s','now') WHERE id = ?")
+        .bind(STATUS_SENT)
+        .bind(id)
+        .execute(pool)
+        .await;
+    if let Err(e) = res {
+        tracing::warn!(draft_id = %id, "failed to mark draft as sent: {e}");
+    }
+    let draft = match get(pool, id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!(draft_id = %id, "loading sent draft failed: {e}");
+            return;
+        }
+    };
+    if let Err(e) = remove_everywhere(pool, &draft).await {
+        tracing::warn!(draft_id = %id, "removing sent draft failed, retrying with the next sync: {e}");
+        let _ = sqlx::query("UPDATE drafts SET last_error = ? WHERE id = ?")
+            .bind(e.to_string())
+            .bind(id)
+            .execute(pool)
+            .await;
+    }
+}
+
+/// Outbox callback when the send gave up: the draft becomes editable again
+pub async fn on_send_failed(pool: &SqlitePool, id: &str, error: &str) {
+    let res = sqlx::query("UPDATE drafts SET status = ?, last_error = ?, updated_at = strftime('%s','now') WHERE id = ?")
+        .bind(STATUS_DRAFT)
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await;
+    if let Err(e) = res {
+        tracing::warn!(draft_id = %id, "failed to reopen draft: {e}");
+    }
+}
+
+async fn remove_everywhere(pool: &SqlitePool, draft: &Draft) -> Result<()> {
+    let _guard = PUSH.lock().await;
+    if let Some(folder) = draft.server_folder.as_deref() {
+        if let Some(account) = account_service::get_account(pool, &draft.account_id).await? {
+            let mut imap = pool::acquire_account(&account).await?;
+            let res = async {
+                imap.session.select(folder).await?;
+                let mut uids = copies(&mut imap, &draft.message_id).await?;
+                uids.extend(draft.server_uid.map(|u| u as u32).filter(|u| !uids.contains(u)));
+                if !uids.is_empty() {
+                    transfer::expunge_uids(&mut imap, &uids).await?;
+                }
+                anyhow::Ok(uids)
+            }
+            .await;
+            match res {
+                Ok(uids) => {
+                    imap.release();
+                    message_service::delete_rows(pool, &account.id, folder, &uids).await?;
+                }
+                Err(e) => {
+                    imap.discard();
+                    return Err(e);
+                }
+            }
+        }
+    }
+    forget(pool, &draft.id).await
+}
+
+/// Drop the local draft and its versions
+async fn forget(pool: &SqlitePool, id: &str) -> Result<()> {
+    sqlx::query("DELETE FROM draft_versions WHERE draft_id = ?").bind(id).execute(pool).await?;
+    sqlx::query("DELETE FROM drafts WHERE id = ?").bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// UIDs in the selected folder carrying the draft's Message-ID
+async fn copies(imap: &mut ImapSession, message_id: &str) -> Result<Vec<u32>> {
+    let mut uids: Vec<u32> = imap
+        .session
+        .uid_search(format!("HEADER Message-ID {}", quote(message_id)))
+        .await?
+        .into_iter()
+        .collect();
+    uids.sort_unstable();
+    Ok(uids)
+}
+
+/// Push the draft to the server after the save delay, unless a push is already waiting
+fn schedule_push(pool: &SqlitePool, id: &str) {
+    if !PENDING.lock().unwrap().insert(id.to_string()) {
+        return;
+    }
+    let (pool, id) = (pool.clone(), id.to_string());
+    tokio::spawn(async move {
+        let delay = env_u64("MAILORA_DRAFT_SAVE_DELAY_SECS", 5);
+        tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+        PENDING.lock().unwrap().remove(&id);
+        if let Err(e) = push(&pool, &id).await {
+            tracing::warn!(draft_id = %id, "saving draft to the server failed: {e}");
+            let _ = sqlx::query("UPDATE drafts SET last_error = ? WHERE id = ?")
+                .bind(e.to_string())
+                .bind(&id)
+                .execute(&pool)
+                .await;
+        }
+    });
+}
+
+/// Schedule pushes for drafts whose server copy is behind, e.g. after a restart, and retry
+/// removing the server copies of sent drafts
+pub async fn init(pool: &SqlitePool) -> Result<()> {
+    let ids = sqlx::query_scalar::<_, String>("SELECT id FROM drafts WHERE status = ? AND synced_version < version")
+        .bind(STATUS_DRAFT)
+        .fetch_all(pool)
+        .await?;
+    for id in ids {
+        schedule_push(pool, &id);
+    }
+
+    let sent = sqlx::query_as::<_, Draft>(&format!("{} WHERE status = ?", SELECT))
+        .bind(STATUS_SENT)
+        .fetch_all(pool)
+        .await?;
+    if !sent.is_empty() {
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            for draft in sent {
+                if let Err(e) = remove_everywhere(&pool, &draft).await {
+                    tracing::warn!(draft_id = %draft.id, "removing sent draft failed, retrying with the next sync: {e}");
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// APPEND the current version to `\Drafts` and expunge the copies it replaces
+async fn push(pool: &SqlitePool, id: &str) -> Result<()> {
+    let _guard = PUSH.lock().await;
+    let Some(draft) = get(pool, id).await? else { return Ok(()) };
+    if draft.status != STATUS_DRAFT || draft.synced_version >= draft.version {
+        return Ok(());
+    }
+    let account = account_service::get_account(pool, &draft.account_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account {} not found", draft.account_id))?;
+    let folder = folder_service::folder_by_role(pool, &account.id, "\\Drafts")
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account has no \\Drafts folder"))?;
+    let raw = build_raw(&account, &draft)?;
+
+    let mut imap = pool::acquire_account(&account).await?;
+    let res = async {
+        imap.session.append(&folder, &raw).await?;
+        imap.session.select(&folder).await?;
+        let mut uids = copies(&mut imap, &draft.message_id).await?;
+        let newest = uids.pop();
+        if let Some(uid) = newest {
+            transfer::store(&mut imap.session, &[uid], "+FLAGS.SILENT (\\Draft \\Seen)").await?;
+        }
+        // Copies from other folders' pasts (a previous \Drafts) are left alone
+        let replaced: Vec<u32> = match draft.server_folder.as_deref() {
+            Some(f) if f != folder => uids,
+            _ => uids.into_iter().chain(draft.server_uid.map(|u| u as u32)).filter(|u| Some(*u) != newest).collect(),
+        };
+        if !replaced.is_empty() {
+            transfer::expunge_uids(&mut imap, &replaced).await?;
+        }
+        anyhow::Ok((newest, replaced))
+    }
+    .await;
+    let (uid, replaced) = match res {
+        Ok(r) => r,
+        Err(e) => {
+            imap.discard();
+            return Err(e);
+        }
+    };
+    imap.release();
+
+    message_service::delete_rows(pool, &account.id, &folder, &replaced).await?;
+    sqlx::query(
+        "UPDATE drafts SET synced_version = ?, server_folder = ?, server_uid = ?, last_error = NULL WHERE id = ?",
+    )
+    .bind(draft.version)
+    .bind(&folder)
+    .bind(uid.map(|u| u as i64))
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// RFC 822 form of a draft. Recipients may still be missing or half typed, so the envelope is
+/// set explicitly and an unparseable `To` is left out rather than failing the save.
+fn build_raw(account: &Account, draft: &Draft) -> Result<Vec<u8>> {
+    use lettre::message::{header, Mailboxes};
+
+    let from: lettre::message::Mailbox = account.email.parse()?;
+    let mut builder = lettre::Message::builder()
+        .from(from.clone())
+        .subject(draft.subject.clone())
+        .header(header::MessageId::from(format!("<{}>", draft.message_id)))
+        .envelope(lettre::address::Envelope::new(Some(from.email.clone()), vec![from.email])?);
+    if let Some(to) = draft.to_addr.parse::<Mailboxes>().ok().filter(|m| m.iter().next().is_some()) {
+        builder = builder.mailbox(header::To::from(to));
+    }
+    Ok(builder.body(draft.body.clone())?.formatted())
+}
+
+/// Bring local drafts in line with the account's `\Drafts` folder on an open session: link or
+/// import copies written elsewhere and drop drafts deleted elsewhere. Returns the number of
+/// drafts imported or updated.
+pub async fn reconcile(pool: &SqlitePool, account: &Account, imap: &mut ImapSession) -> Result<usize> {
+    let Some(folder) = folder_service::folder_by_role(pool, &account.id, "\\Drafts").await? else {
+        return Ok(0);
+    };
+    let _guard = PUSH.lock().await;
+    imap.session.select(&folder).await?;
+
+    // Sent drafts whose copies `on_sent` could not remove
+    let sent = sqlx::query_as::<_, Draft>(&format!("{} WHERE account_id = ? AND status = ?", SELECT))
+        .bind(&account.id)
+        .bind(STATUS_SENT)
+        .fetch_all(pool)
+        .await?;
+    for draft in sent {
+        let mut uids = copies(imap, &draft.message_id).await?;
+        if draft.server_folder.as_deref() == Some(folder.as_str()) {
+            uids.extend(draft.server_uid.map(|u| u as u32).filter(|u| !uids.contains(u)));
+        }
+        if !uids.is_empty() {
+            transfer::expunge_uids(imap, &uids).await?;
+            message_service::delete_rows(pool, &account.id, &folder, &uids).await?;
+        }
+        forget(pool, &draft.id).await?;
+    }
+
+    let on_server: HashSet<u32> = imap.session.uid_search("ALL").await?;
+
+    let local = sqlx::query_as::<_, Draft>(&format!("{} WHERE account_id = ?", SELECT))
+        .bind(&account.id)
+        .fetch_all(pool)
+        .await?;
+    let linked: HashSet<u32> = local
+        .iter()
+        .filter(|d| d.server_folder.as_deref() == Some(folder.as_str()))
+        .filter_map(|d| d.server_uid.map(|u| u as u32))
+        .collect();
+    let mut by_message_id: HashMap<String, Draft> = local.iter().map(|d| (d.message_id.clone(), d.clone())).collect();
+
+    let mut unknown: Vec<u32> = on_server.difference(&linked).copied().collect();
+    unknown.sort_unstable();
+    let mut changed = 0;
+    let mut relinked: HashSet<String> = HashSet::new();
+    if !unknown.is_empty() {
+        let mut fetched = Vec::new();
+        {
+            let mut stream = imap.session.uid_fetch(uid_set(&unknown), "(UID BODY.PEEK[])").await?;
+            while let Some(item) = stream.next().await {
+                let item = item?;
+                if let (Some(uid), Some(raw)) = (item.uid, item.body()) {
+                    fetched.push((uid, raw.to_vec()));
+                }
+            }
+        }
+        for (uid, raw) in fetched {
+            let hdrs = headers::parse(&raw);
+            let (text, _) = message_body_service::parse_bodies(&raw);
+            let to = headers::display_list(&hdrs.to);
+            match by_message_id.remove(&hdrs.message_id) {
+                Some(draft) => {
+                    relinked.insert(draft.id.clone());
+                    if draft.status == STATUS_DRAFT && draft.synced_version >= draft.version {
+                        // Edited elsewhere, nothing unsaved here: take the server's text
+                        adopt(pool, &draft, uid, &folder, &to, &hdrs.subject, &text).await?;
+                        changed += 1;
+                    } else {
+                        link(pool, &draft.id, uid, &folder).await?;
+                    }
+                }
+                None => {
+                    import(pool, account, uid, &folder, &hdrs, &text).await?;
+                    changed += 1;
+                }
+            }
+        }
+    }
+
+    for draft in local.iter().filter(|d| !relinked.contains(&d.id)) {
+        let gone = draft.server_folder.as_deref() == Some(folder.as_str())
+            && draft.server_uid.is_some_and(|u| !on_server.contains(&(u as u32)));
+        if !gone || draft.status != STATUS_DRAFT {
+            continue;
+        }
+        if draft.synced_version < draft.version {
+            // Unsaved local edits win; the next push creates a fresh copy
+            sqlx::query("UPDATE drafts SET server_uid = NULL WHERE id = ?").bind(&draft.id).execute(pool).await?;
+            schedule_push(pool, &draft.id);
+        } else {
+            tracing::info!(draft_id = %draft.id, "draft deleted on the server; removing it locally");
+            forget(pool, &draft.id).await?;
+        }
+    }
+    Ok(changed)
+}
+
+async fn link(pool: &SqlitePool, id: &str, uid: u32, folder: &str) -> Result<()> {
+    sqlx::query("UPDATE drafts SET server_folder = ?, server_uid = ? WHERE id = ?")
+        .bind(folder)
+        .bind(uid as i64)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn adopt(pool: &SqlitePool, draft: &Draft, uid: u32, folder: &str, to: &str, subject: &str, body: &str) -> Result<()> {
+    let version = draft.version + 1;
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE drafts SET to_addr = ?, subject = ?, body = ?, version = ?, synced_version = ?, server_folder = ?, server_uid = ?, updated_at = strftime('%s','now') WHERE id = ?",
+    )
+    .bind(to)
+    .bind(subject)
+    .bind(body)
+    .bind(version)
+    .bind(version)
+    .bind(folder)
+    .bind(uid as i64)
+    .bind(&draft.id)
+    .execute(&mut *tx)
+    .await?;
+    insert_version(&mut tx, &draft.id, version, to, subject, body).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn import(
+    pool: &SqlitePool,
+    account: &Account,
+    uid: u32,
+    folder: &str,
+    hdrs: &headers::MessageHeaders,
+    body: &str,
+) -> Result<()> {
+    let id = uuid::Uuid::new_v4().to_string();
+    // A copy without Message-ID gets one on its next save; the old copy is found by UID
+    let message_id = if hdrs.message_id.is_empty() {
+        let domain = account.email.split('@').nth(1).unwrap_or("mailora.local");
+        format!("draft-{}@{}", id, domain)
+    } else {
+        hdrs.message_id.clone()
+    };
+    let to = headers::display_list(&hdrs.to);
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO drafts (id, account_id, to_addr, subject, body, version, synced_version, message_id, server_folder, server_uid) VALUES (?, ?, ?, ?, ?, 1, 1, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&account.id)
+    .bind(&to)
+    .bind(&hdrs.subject)
+    .bind(body)
+    .bind(&message_id)
+    .bind(folder)
+    .bind(uid as i64)
+    .execute(&mut *tx)
+    .await?;
+    insert_version(&mut tx, &id, 1, &to, &hdrs.subject, body).await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,475 @@
+use anyhow::Result;
+use futures::StreamExt;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use tracing::warn;
+
+use crate::imap::conn::{self, RawSession};
+use crate::imap::folders::{self, attribute_str, decode_utf7, encode_utf7, special_use};
+use crate::imap::pool;
+use crate::models::account::Account;
+use crate::services::{message_sync_service, pending_op_service};
+
+/// A folder from the persistent catalogue (`folders` table)
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderRecord {
+    /// Wire name (modified UTF-7); what every other endpoint takes
+    pub name: String,
+    /// `name` decoded for display, e.g. "Gönderilmiş"
+    pub display_name: String,
+    pub delimiter: Option<String>,
+    /// Raw LIST attributes, kept under `flags` for compatibility with the old IMAP-backed response
+    pub flags: Vec<String>,
+    pub special_use: Option<String>,
+    pub subscribed: bool,
+    pub selectable: bool,
+    pub total: Option<i64>,
+    pub unread: Option<i64>,
+}
+
+/// Re-list the account's folders over IMAP (LIST, LSUB, STATUS) and replace the catalogue.
+/// Returns the fresh records.
+pub async fn refresh_folders(
+    pool: &SqlitePool,
+    account_id: &str,
+    session: &mut RawSession,
+) -> Result<Vec<FolderRecord>> {
+    let mut records = Vec::new();
+    {
+        let mut list = session.list(Some(""), Some("*")).await?;
+        while let Some(item) = list.next().await {
+            let name = match item {
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("list item error: {e}");
+                    continue;
+                }
+            };
+            let attrs = name.attributes();
+            let selectable = !attrs
+                .iter()
+                .any(|a| matches!(a, async_imap::imap_proto::NameAttribute::NoSelect));
+            records.push(FolderRecord {
+                name: name.name().to_string(),
+                display_name: decode_utf7(name.name()),
+                delimiter: name.delimiter().map(|d| d.to_string()),
+                flags: attrs.iter().map(attribute_str).collect(),
+                special_use: special_use(attrs).map(|s| s.to_string()),
+                subscribed: false,
+                selectable,
+                total: None,
+                unread: None,
+            });
+        }
+    }
+
+    let mut subscribed: HashSet<String> = HashSet::new();
+    if let Ok(mut lsub) = session.lsub(Some(""), Some("*")).await {
+        // Read to the end even past a bad item, or the session is left mid-response
+        while let Some(item) = lsub.next().await {
+            if let Ok(name) = item {
+                subscribed.insert(name.name().to_string());
+            }
+        }
+    }
+
+    for rec in records.iter_mut() {
+        rec.subscribed = subscribed.contains(&rec.name) || rec.name.eq_ignore_ascii_case("INBOX");
+        if !rec.selectable {
+            continue;
+        }
+        match session.status(&rec.name, "(MESSAGES UNSEEN)").await {
+            Ok(mb) => {
+                rec.total = Some(mb.exists as i64);
+                rec.unread = mb.unseen.map(|u| u as i64);
+            }
+            Err(e) => warn!("STATUS {} failed: {}", rec.name, e),
+        }
+        // A catalogue of hundreds of folders would otherwise fill the unsolicited channel
+        conn::clear_unsolicited(session);
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM folders WHERE account_id = ?")
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+    for rec in &records {
+        sqlx::query(
+            r#"INSERT INTO folders (account_id, name, delimiter, attributes, special_use, subscribed, selectable, total_count, unread_count, updated_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, strftime('%s','now'))"#,
+        )
+        .bind(account_id)
+        .bind(&rec.name)
+        .bind(&rec.delimiter)
+        .bind(serde_json::to_string(&rec.flags)?)
+        .bind(&rec.special_use)
+        .bind(rec.subscribed)
+        .bind(rec.selectable)
+        .bind(rec.total)
+        .bind(rec.unread)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(records)
+}
+
+/// Folders of an account as last seen on sync
+pub async fn list_folders(pool: &SqlitePool, account_id: &str) -> Result<Vec<FolderRecord>> {
+    let rows = sqlx::query(
+        "SELECT name, delimiter, attributes, special_use, subscribed, selectable, total_count, unread_count FROM folders WHERE account_id = ? ORDER BY name",
+    )
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut out = Vec::with_capacity(rows.len());
+    for r in rows {
+        let attrs: String = r.try_get("attributes")?;
+        let name: String = r.try_get("name")?;
+        out.push(FolderRecord {
+            display_name: decode_utf7(&name),
+            name,
+            delimiter: r.try_get("delimiter")?,
+            flags: serde_json::from_str(&attrs).unwrap_or_default(),
+            special_use: r.try_get("special_use")?,
+            subscribed: r.try_get("subscribed")?,
+            selectable: r.try_get("selectable")?,
+            total: r.try_get("total_count")?,
+            unread: r.try_get("unread_count")?,
+        });
+    }
+    Ok(out)
+}
+
+/// Folder carrying the given special-use role (`\Sent`, `\Trash`, `\Drafts`, ...)
+pub async fn folder_by_role(pool: &SqlitePool, account_id: &str, role: &str) -> Result<Option<String>> {
+    let name = sqlx::query_scalar::<_, String>(
+        "SELECT name FROM folders WHERE account_id = ? AND special_use = ? ORDER BY name LIMIT 1",
+    )
+    .bind(account_id)
+    .bind(role)
+    .fetch_optional(pool)
+    .await?;
+    Ok(name)
+}
+
+/// Map role aliases used by the UI ("sent", "trash", "drafts", "spam"/"junk", "archive")
+/// to the catalogued special-use folder; other names are returned unchanged.
+pub async fn resolve_folder_alias(pool: &SqlitePool, account_id: &str, folder: &str) -> String {
+    let role = match folder.to_lowercase().as_str() {
+        "sent" => "\\Sent",
+        "trash" => "\\Trash",
+        "drafts" => "\\Drafts",
+        "spam" | "junk" => "\\Junk",
+        "archive" => "\\Archive",
+        _ => return folder.to_string(),
+    };
+    folder_by_role(pool, account_id, role)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| folder.to_string())
+}
+
+/// Wire name for a folder given either as catalogued (wire) or as displayed
+pub async fn wire_name(pool: &SqlitePool, account_id: &str, folder: &str) -> String {
+    let catalogued = sqlx::query_scalar::<_, String>("SELECT name FROM folders WHERE account_id = ? AND name = ?")
+        .bind(account_id)
+        .bind(folder)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    catalogued.unwrap_or_else(|| encode_utf7(folder))
+}
+
+/// Hierarchy delimiter of the account: the folder's own when catalogued, else the first one
+/// LIST reported, else "/"
+async fn delimiter(pool: &SqlitePool, account_id: &str, folder: Option<&str>) -> Result<String> {
+    let delim = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT delimiter FROM folders WHERE account_id = ? AND delimiter IS NOT NULL ORDER BY name = ? DESC, name LIMIT 1",
+    )
+    .bind(account_id)
+    .bind(folder.unwrap_or(""))
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+    Ok(delim.unwrap_or_else(|| "/".to_string()))
+}
+
+/// A folder change refused before anything was sent to the server
+#[derive(Debug)]
+pub enum Refused {
+    /// Malformed request: empty or unusable name, a folder moved below itself
+    Invalid(String),
+    /// Not allowed for this folder: INBOX, special-use folders without `force`
+    Conflict(String),
+}
+
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(m) | Self::Conflict(m) => f.write_str(m),
+        }
+    }
+}
+
+impl std::error::Error for Refused {}
+
+/// Wire name of `leaf` (display form) under `parent` (wire name; `None` for top level)
+async fn child_name(pool: &SqlitePool, account_id: &str, parent: Option<&str>, leaf: &str) -> Result<String> {
+    let leaf = leaf.trim();
+    let delim = delimiter(pool, account_id, parent).await?;
+    if leaf.is_empty() {
+        return Err(Refused::Invalid("folder name must not be empty".to_string()).into());
+    }
+    if leaf.contains(delim.as_str()) {
+        return Err(Refused::Invalid(format!("folder name must not contain the hierarchy delimiter {:?}; use parent", delim)).into());
+    }
+    let leaf = encode_utf7(leaf);
+    Ok(match parent.filter(|p| !p.is_empty()) {
+        Some(parent) => format!("{}{}{}", parent, delim, leaf),
+        None => leaf,
+    })
+}
+
+/// Run one folder command on a pooled session and re-list the catalogue afterwards
+async fn on_server<F>(pool: &SqlitePool, account: &Account, op: F) -> Result<Vec<FolderRecord>>
+where
+    F: for<'a> FnOnce(&'a mut RawSession) -> futures::future::BoxFuture<'a, Result<()>>,
+{
+    let mut imap = pool::acquire_account(account).await?;
+    let res = async {
+        op(&mut imap.session).await?;
+        refresh_folders(pool, &account.id, &mut imap.session).await
+    }
+    .await;
+    if res.is_ok() {
+        imap.release();
+    }
+    res
+}
+
+fn find(records: Vec<FolderRecord>, name: &str) -> Result<FolderRecord> {
+    records
+        .into_iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| anyhow::anyhow!("server did not list {} afterwards", name))
+}
+
+/// CREATE `leaf` under `parent` and optionally SUBSCRIBE it
+pub async fn create_folder(
+    pool: &SqlitePool,
+    account: &Account,
+    parent: Option<&str>,
+    leaf: &str,
+    subscribe: bool,
+) -> Result<FolderRecord> {
+    let name = child_name(pool, &account.id, parent, leaf).await?;
+    let target = name.clone();
+    let records = on_server(pool, account, move |session| {
+        Box::pin(async move {
+            folders::create(session, &target).await?;
+            if subscribe {
+                folders::set_subscribed(session, &target, true).await?;
+            }
+            Ok(())
+        })
+    })
+    .await?;
+    find(records, &name)
+}
+
+/// RENAME `folder` to `leaf` under `parent` (`None` keeps the current parent, `Some("")` moves
+/// it to the top level). Cached messages, bodies, sync state, queued changes and references to
+/// the folder and its subfolders follow the new name; attachments hang off message rows and
+/// follow with them.
+pub async fn rename_folder(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    parent: Option<&str>,
+    leaf: &str,
+) -> Result<FolderRecord> {
+    if folder.eq_ignore_ascii_case("INBOX") {
+        return Err(Refused::Conflict("INBOX cannot be renamed".to_string()).into());
+    }
+    let delim = delimiter(pool, &account.id, Some(folder)).await?;
+    let parent = match parent {
+        Some(p) => Some(p.to_string()),
+        None => folder.rsplit_once(delim.as_str()).map(|(p, _)| p.to_string()),
+    };
+    let name = child_name(pool, &account.id, parent.as_deref(), leaf).await?;
+    if name == folder {
+        return find(list_folders(pool, &account.id).await?, folder);
+    }
+    if name.starts_with(&format!("{}{}", folder, delim)) {
+        return Err(Refused::Invalid(format!("cannot move {} into its own subfolder", folder)).into());
+    }
+
+    let (from, to) = (folder.to_string(), name.clone());
+    let records = on_server(pool, account, move |session| {
+        Box::pin(async move { folders::rename(session, &from, &to).await })
+    })
+    .await?;
+    rename_cached(pool, &account.id, folder, &name, &delim).await?;
+    find(records, &name)
+}
+
+async fn rename_cached(pool: &SqlitePool, account_id: &str, from: &str, to: &str, delim: &str) -> Result<()> {
+    // `col` is `from` or below it; the rest of the path after `from` is kept
+    let prefix = format!("{}{}", from, delim);
+    let renamed = |col: &str| {
+        format!(
+            "{col} = ? || substr({col}, length(?) + 1) WHERE ({col} = ? OR substr({col}, 1, length(?)) = ?)"
+        )
+    };
+
+    let mut tx = pool.begin().await?;
+    for table in ["messages", "message_bodies", "folder_sync_state"] {
+        sqlx::query(&format!("UPDATE {} SET {} AND account_id = ?", table, renamed("folder")))
+            .bind(to)
+            .bind(from)
+            .bind(from)
+            .bind(&prefix)
+            .bind(&prefix)
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(&format!(
+        "UPDATE message_labels SET {} AND message_id IN (SELECT id FROM messages WHERE account_id = ?)",
+        renamed("folder")
+    ))
+    .bind(to)
+    .bind(from)
+    .bind(from)
+    .bind(&prefix)
+    .bind(&prefix)
+    .bind(account_id)
+    .execute(&mut *tx)
+    .await?;
+    for (table, col) in [("deleted_messages", "folder"), ("deleted_messages", "trash_folder"), ("pending_ops", "folder"), ("pending_ops", "dest")] {
+        sqlx::query(&format!("UPDATE {} SET {} AND account_id = ?", table, renamed(col)))
+            .bind(to)
+            .bind(from)
+            .bind(from)
+            .bind(&prefix)
+            .bind(&prefix)
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(&format!("UPDATE accounts SET {} AND id = ?", renamed("sent_folder_hint")))
+        .bind(to)
+        .bind(from)
+        .bind(from)
+        .bind(&prefix)
+        .bind(&prefix)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let idle = sqlx::query_scalar::<_, Option<String>>("SELECT idle_folders FROM accounts WHERE id = ?")
+        .bind(account_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .flatten()
+        .and_then(|s| serde_json::from_str::<Vec<String>>(&s).ok());
+    if let Some(idle) = idle {
+        let idle: Vec<String> = idle
+            .into_iter()
+            .map(|f| match f.strip_prefix(from) {
+                Some(rest) if rest.is_empty() || rest.starts_with(delim) => format!("{}{}", to, rest),
+                _ => f,
+            })
+            .collect();
+        sqlx::query("UPDATE accounts SET idle_folders = ? WHERE id = ?")
+            .bind(serde_json::to_string(&idle)?)
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// DELETE `folder` on the server and drop its cache, along with any subfolders the server
+/// removed with it. INBOX is refused, special-use folders need `force`, and so is a folder
+/// that queued changes still have to reach; parked ones for it are dropped with the cache.
+pub async fn delete_folder(pool: &SqlitePool, account: &Account, folder: &str, force: bool) -> Result<u64> {
+    if folder.eq_ignore_ascii_case("INBOX") {
+        return Err(Refused::Conflict("INBOX cannot be deleted".to_string()).into());
+    }
+    let before = list_folders(pool, &account.id).await?;
+    if let Some(role) = before.iter().find(|r| r.name == folder).and_then(|r| r.special_use.clone()) {
+        if !force {
+            return Err(Refused::Conflict(format!("{} is the account's {} folder; pass force to delete it anyway", folder, role)).into());
+        }
+    }
+    let delim = delimiter(pool, &account.id, Some(folder)).await?;
+    let prefix = format!("{}{}", folder, delim);
+
+    // Their replay would run against a folder that no longer exists
+    let waiting: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pending_ops WHERE account_id = ? AND status = ? AND (folder = ? OR substr(folder, 1, length(?)) = ? OR dest = ? OR substr(dest, 1, length(?)) = ?)",
+    )
+    .bind(&account.id)
+    .bind(pending_op_service::STATUS_PENDING)
+    .bind(folder)
+    .bind(&prefix)
+    .bind(&prefix)
+    .bind(folder)
+    .bind(&prefix)
+    .bind(&prefix)
+    .fetch_one(pool)
+    .await?;
+    if waiting > 0 {
+        return Err(Refused::Conflict(format!(
+            "{} change(s) to messages in {} are still waiting for the server; try again once they are sent",
+            waiting, folder
+        ))
+        .into());
+    }
+
+    let target = folder.to_string();
+    let after = on_server(pool, account, move |session| {
+        Box::pin(async move { folders::delete(session, &target).await })
+    })
+    .await?;
+
+    let mut removed = 0;
+    for gone in before
+        .iter()
+        .filter(|r| r.name == folder || r.name.starts_with(&prefix))
+        .filter(|r| !after.iter().any(|a| a.name == r.name))
+    {
+        removed += message_sync_service::purge_folder_cache(pool, &account.id, &gone.name).await?;
+        sqlx::query("DELETE FROM message_labels WHERE folder = ? AND message_id IN (SELECT id FROM messages WHERE account_id = ?)")
+            .bind(&gone.name)
+            .bind(&account.id)
+            .execute(pool)
+            .await?;
+        sqlx::query("DELETE FROM pending_ops WHERE account_id = ? AND (folder = ? OR dest = ?)")
+            .bind(&account.id)
+            .bind(&gone.name)
+            .bind(&gone.name)
+            .execute(pool)
+            .await?;
+    }
+    Ok(removed)
+}
+
+/// SUBSCRIBE or UNSUBSCRIBE `folder`
+pub async fn set_subscribed(pool: &SqlitePool, account: &Account, folder: &str, subscribed: bool) -> Result<FolderRecord> {
+    let target = folder.to_string();
+    let records = on_server(pool, account, move |session| {
+        Box::pin(async move { folders::set_subscribed(session, &target, subscribed).await })
+    })
+    .await?;
+    find(records, folder)
+}
@@ -1,22 +1,37 @@
 /// IDLE Watcher Service - Real-time email notifications
-use anyhow::{Context, Result};
-use async_imap::Session;
+///
+/// Each account watches a configurable set of folders: one IDLE connection per folder, or a
+/// single connection with NOTIFY (RFC 5465) when the server supports it. A wakeup queues an
+/// incremental sync of the changed folder as a sync job and publishes the new messages.
+use anyhow::Result;
+use once_cell::sync::Lazy;
 use serde::Serialize;
+use sqlx::{Row, SqlitePool};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::net::TcpStream;
 use tokio::sync::broadcast;
 use tokio::sync::RwLock;
-use tokio_native_tls::{TlsConnector, TlsStream};
-use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
 
+use crate::imap::{conn, idle, pool};
 use crate::models::account::Account;
+use crate::services::sync_job_service::{self, JobPriority};
+use crate::services::{folder_service, mail_auth_service};
 
-#[allow(dead_code)]
-type ImapSession = Session<Compat<TlsStream<TcpStream>>>;
+/// New-message summaries per event; `count` carries the full total
+const MAX_EVENT_MESSAGES: i64 = 50;
+
+/// Process-wide event bus. IDLE watchers and sync passes both publish here,
+/// and `/idle/events` subscribers receive everything.
+static EVENT_TX: Lazy<broadcast::Sender<IdleEvent>> = Lazy::new(|| broadcast::channel(100).0);
+
+/// Publish an event to every `/idle/events` subscriber (no-op when nobody listens)
+pub fn publish(event: IdleEvent) {
+    let _ = EVENT_TX.send(event);
+}
 
 /// Global IDLE watcher manager
 pub struct IdleWatcherManager {
+    pool: SqlitePool,
     watchers: Arc<RwLock<HashMap<String, IdleWatcherHandle>>>,
     event_tx: broadcast::Sender<IdleEvent>,
 }
@@ -24,6 +39,7 @@ pub struct IdleWatcherManager {
 #[allow(dead_code)]
 pub struct IdleWatcherHandle {
     pub account_id: String,
+    pub folders: Vec<String>,
     cancel_tx: tokio::sync::oneshot::Sender<()>,
 }
 
@@ -35,30 +51,72 @@ pub struct IdleEvent {
     pub timestamp: i64,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagUpdate {
+    pub uid: u32,
+    pub flags: Vec<String>,
+}
+
+/// Metadata of a newly synced message, as stored in `messages`
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageSummary {
+    pub id: i64,
+    pub uid: i64,
+    pub message_id: Option<String>,
+    pub subject: Option<String>,
+    pub from: Option<String>,
+    pub date: Option<String>,
+    pub size: Option<i64>,
+    pub has_attachments: bool,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum IdleEventType {
-    NewMessage { count: u32 },
-    MessageDeleted { count: u32 },
-    FlagChange,
+    NewMessage {
+        folder: String,
+        count: u32,
+        messages: Vec<MessageSummary>,
+    },
+    MessageDeleted { folder: String, count: u32 },
+    /// Flags changed on already-synced messages; `updates` is capped, `count` is the full total
+    FlagChange {
+        folder: String,
+        count: usize,
+        updates: Vec<FlagUpdate>,
+    },
+    /// UIDVALIDITY changed: every cached UID in `folder` is stale
+    FolderInvalidated {
+        folder: String,
+        old_uidvalidity: u32,
+        new_uidvalidity: u32,
+    },
+    /// Quota usage went past one of `MAILORA_QUOTA_THRESHOLDS` (percent)
+    QuotaWarning {
+        root: String,
+        resource: String,
+        usage: u64,
+        limit: u64,
+        percent: u64,
+        threshold: u64,
+    },
     Connected,
     Disconnected,
     Error { message: String },
 }
 
 impl IdleWatcherManager {
-    pub fn new() -> Self {
-        let (event_tx, _) = broadcast::channel(100);
+    pub fn new(pool: SqlitePool) -> Self {
         Self {
+            pool,
             watchers: Arc::new(RwLock::new(HashMap::new())),
-            event_tx,
+            event_tx: EVENT_TX.clone(),
         }
     }
 
     /// Start IDLE watcher for an account
     pub async fn start_watcher(&self, account: Account) -> Result<()> {
         let account_id = account.id.clone();
-        let account_id_clone = account_id.clone(); // Clone for later use
 
         // Check if already running
         {
@@ -68,31 +126,24 @@ impl IdleWatcherManager {
             }
         }
 
+        let folders = watched_folders(&self.pool, &account_id).await;
         let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
         let event_tx = self.event_tx.clone();
-        let email = account.email.clone();
+        let pool = self.pool.clone();
+        let task_folders = folders.clone();
 
         // Spawn watcher task
         tokio::spawn(async move {
-            if let Err(e) = run_idle_watcher(account, event_tx.clone(), cancel_rx).await {
-                tracing::error!("IDLE watcher error for {}: {}", email, e);
-                let _ = event_tx.send(IdleEvent {
-                    account_id: account_id.clone(),
-                    email: email.clone(),
-                    event_type: IdleEventType::Error {
-                        message: e.to_string(),
-                    },
-                    timestamp: chrono::Utc::now().timestamp(),
-                });
-            }
+            run_idle_watcher(pool, account, task_folders, event_tx, cancel_rx).await;
         });
 
         // Store handle
         let mut watchers = self.watchers.write().await;
         watchers.insert(
-            account_id_clone.clone(),
+            account_id.clone(),
             IdleWatcherHandle {
-                account_id: account_id_clone,
+                account_id,
+                folders,
                 cancel_tx,
             },
         );
@@ -118,140 +169,245 @@ impl IdleWatcherManager {
     pub async fn active_count(&self) -> usize {
         self.watchers.read().await.len()
     }
+
+    /// Watched folders per running account watcher
+    pub async fn watched(&self) -> HashMap<String, Vec<String>> {
+        self.watchers
+            .read()
+            .await
+            .iter()
+            .map(|(id, h)| (id.clone(), h.folders.clone()))
+            .collect()
+    }
 }
 
-async fn run_idle_watcher(
-    account: Account,
-    event_tx: broadcast::Sender<IdleEvent>,
-    mut cancel_rx: tokio::sync::oneshot::Receiver<()>,
-) -> Result<()> {
-    let account_id = account.id.clone();
-    let email = account.email.clone();
+/// Folders an account's watcher covers: `accounts.idle_folders`, else `MAILORA_IDLE_FOLDERS`
+/// (comma separated, default INBOX). Role aliases such as "sent" resolve via the catalogue.
+pub async fn watched_folders(pool: &SqlitePool, account_id: &str) -> Vec<String> {
+    let stored = sqlx::query_scalar::<_, Option<String>>("SELECT idle_folders FROM accounts WHERE id = ?")
+        .bind(account_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    let names: Vec<String> = stored
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_else(|| {
+            std::env::var("MAILORA_IDLE_FOLDERS")
+                .unwrap_or_else(|_| "INBOX".to_string())
+                .split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect()
+        });
 
-    tracing::info!("Starting IDLE watcher for {}", email);
+    let mut folders: Vec<String> = Vec::new();
+    for name in names {
+        let resolved = folder_service::resolve_folder_alias(pool, account_id, &name).await;
+        if !folders.contains(&resolved) {
+            folders.push(resolved);
+        }
+    }
+    if folders.is_empty() {
+        folders.push("INBOX".to_string());
+    }
+    folders
+}
+
+/// Persist the account's watched folders (`None` falls back to `MAILORA_IDLE_FOLDERS`)
+pub async fn set_watched_folders(pool: &SqlitePool, account_id: &str, folders: Option<&[String]>) -> Result<()> {
+    let json = folders.map(serde_json::to_string).transpose()?;
+    sqlx::query("UPDATE accounts SET idle_folders = ? WHERE id = ?")
+        .bind(json)
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
 
-    // Send connected event
+fn emit(event_tx: &broadcast::Sender<IdleEvent>, account: &Account, event_type: IdleEventType) {
     let _ = event_tx.send(IdleEvent {
-        account_id: account_id.clone(),
-        email: email.clone(),
-        event_type: IdleEventType::Connected,
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        event_type,
         timestamp: chrono::Utc::now().timestamp(),
     });
+}
 
-    loop {
-        // Check for cancellation
-        if cancel_rx.try_recv().is_ok() {
-            tracing::info!("IDLE watcher cancelled for {}", email);
-            break;
+async fn run_idle_watcher(
+    pool: SqlitePool,
+    account: Account,
+    folders: Vec<String>,
+    event_tx: broadcast::Sender<IdleEvent>,
+    cancel_rx: tokio::sync::oneshot::Receiver<()>,
+) {
+    tracing::info!("Starting IDLE watcher for {} on {}", account.email, folders.join(", "));
+    emit(&event_tx, &account, IdleEventType::Connected);
+
+    // One NOTIFY connection covers every folder; probe through the pool so the login is reused
+    let notify = folders.len() > 1
+        && pool::acquire_account(&account)
+            .await
+            .map(|s| {
+                let notify = s.caps.notify;
+                s.release();
+                notify
+            })
+            .unwrap_or(false);
+
+    let watch = async {
+        if notify {
+            watch_folders(&pool, &account, &folders, &event_tx).await;
+        } else {
+            futures::future::join_all(
+                folders
+                    .iter()
+                    .map(|f| watch_folders(&pool, &account, std::slice::from_ref(f), &event_tx)),
+            )
+            .await;
         }
+    };
 
-        match idle_session(&account, &event_tx, &account_id, &email).await {
-            Ok(_) => {
-                tracing::info!("IDLE session ended normally for {}", email);
-            }
-            Err(e) => {
-                tracing::error!("IDLE session error for {}: {}", email, e);
-
-                // Send error event
-                let _ = event_tx.send(IdleEvent {
-                    account_id: account_id.clone(),
-                    email: email.clone(),
-                    event_type: IdleEventType::Error {
-                        message: e.to_string(),
-                    },
-                    timestamp: chrono::Utc::now().timestamp(),
-                });
-
-                // Wait before retry
-                tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
-            }
-        }
+    tokio::select! {
+        _ = cancel_rx => tracing::info!("IDLE watcher cancelled for {}", account.email),
+        _ = watch => {}
     }
 
-    // Send disconnected event
-    let _ = event_tx.send(IdleEvent {
-        account_id: account_id.clone(),
-        email: email.clone(),
-        event_type: IdleEventType::Disconnected,
-        timestamp: chrono::Utc::now().timestamp(),
-    });
+    emit(&event_tx, &account, IdleEventType::Disconnected);
+}
 
-    Ok(())
+/// Keep an IDLE session on `folders` alive, reconnecting with exponential backoff
+async fn watch_folders(
+    pool: &SqlitePool,
+    account: &Account,
+    folders: &[String],
+    event_tx: &broadcast::Sender<IdleEvent>,
+) {
+    let mut backoff_secs = 5;
+    loop {
+        if let Err(e) = idle_session(pool, account, folders, event_tx, &mut backoff_secs).await {
+            tracing::warn!(email=%account.email, folders=%folders.join(", "), backoff_secs, "IDLE error: {e}, retrying...");
+            emit(event_tx, account, IdleEventType::Error { message: e.to_string() });
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(backoff_secs)).await;
+        backoff_secs = (backoff_secs * 2).min(300);
+    }
 }
 
+/// One login: catch up on `folders`, then IDLE on the first one (with NOTIFY for the rest)
+/// and sync whatever the server reports, until the connection fails.
 async fn idle_session(
+    pool: &SqlitePool,
     account: &Account,
+    folders: &[String],
     event_tx: &broadcast::Sender<IdleEvent>,
-    account_id: &str,
-    email: &str,
+    backoff_secs: &mut u64,
 ) -> Result<()> {
-    let (user, pass) = account.get_credentials()?;
-
-    // Connect
-    let tcp_stream = TcpStream::connect((&account.imap_host as &str, account.imap_port))
-        .await
-        .context("TCP connect failed")?;
-
-    let tls_connector = TlsConnector::from(native_tls::TlsConnector::builder().build()?);
-
-    let tls_stream = tls_connector
-        .connect(&account.imap_host, tcp_stream)
-        .await
-        .context("TLS handshake failed")?;
-
-    let client = async_imap::Client::new(tls_stream.compat());
+    // Dedicated connection: an IDLEing session can't serve anyone else
+    let auth = mail_auth_service::for_account(account).await?;
+    let mut imap = conn::connect(&account.id, &account.imap_host, account.imap_port, account.imap_security_mode(), &auth).await?;
 
-    let mut session = client
-        .login(&user, &pass)
-        .await
-        .map_err(|e| anyhow::anyhow!("Login failed: {}", e.0))?;
-
-    // Select INBOX
-    let mailbox = session.select("INBOX").await?;
-    let last_exists = mailbox.exists;
-
-    tracing::info!("IDLE watching {} - {} messages", email, last_exists);
-
-    // Start IDLE (this consumes session)
-    let mut idle = session.idle();
-    idle.init().await?;
-
-    // Wait returns a tuple (future, interrupt_handle)
-    tracing::info!("IDLE session active for {}", email);
-    let (wait_future, _interrupt) = idle.wait();
-    let _idle_response = wait_future.await?;
+    // Whatever arrived while the watcher was down
+    for folder in folders {
+        sync_and_publish(pool, account, folder, event_tx).await?;
+    }
 
-    // Idle done returns the session back
-    let mut session = idle.done().await?;
+    let selected = &folders[0];
+    imap.session.select(selected).await?;
+    if folders.len() > 1 {
+        idle::notify_set(&mut imap, &folders[1..]).await?;
+    }
+    *backoff_secs = 5;
+    tracing::info!("IDLE watching {} on {}", account.email, folders.join(", "));
 
-    // Check for new messages
-    let mailbox = session.select("INBOX").await?;
-    let new_exists = mailbox.exists;
+    loop {
+        let (session, changed) = idle::wait_for_change(imap, selected).await?;
+        imap = session;
+        let changed: Vec<&String> = changed.iter().filter(|f| folders.contains(f)).collect();
+        if changed.is_empty() {
+            continue;
+        }
+        tracing::debug!("IDLE notification for {}: {:?}", account.email, changed);
+        for folder in changed {
+            sync_and_publish(pool, account, folder, event_tx).await?;
+        }
+    }
+}
 
-    if new_exists > last_exists {
-        let new_count = new_exists - last_exists;
-        tracing::info!("New messages detected for {}: +{}", email, new_count);
+/// Incremental sync of `folder` as a sync job, then publish what it found. Going through the
+/// queue keeps the one-job-per-account rule and makes the sync visible and cancellable.
+async fn sync_and_publish(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    event_tx: &broadcast::Sender<IdleEvent>,
+) -> Result<()> {
+    let last_id: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM messages")
+        .fetch_one(pool)
+        .await?;
+    let job = sync_job_service::enqueue(&account.id, Some(folder), JobPriority::Scheduled).await;
+    let stats = match job.wait().await {
+        Ok(stats) => stats.into_iter().next(),
+        // Failed or cancelled: the watcher session is fine, the next notification retries
+        Err(e) => {
+            tracing::warn!("IDLE-triggered sync of {} on {} did not finish: {}", folder, account.email, e);
+            return Ok(());
+        }
+    };
+    let Some(stats) = stats else { return Ok(()) };
 
-        let _ = event_tx.send(IdleEvent {
-            account_id: account_id.to_string(),
-            email: email.to_string(),
-            event_type: IdleEventType::NewMessage { count: new_count },
-            timestamp: chrono::Utc::now().timestamp(),
-        });
-    } else if new_exists < last_exists {
-        let deleted_count = last_exists - new_exists;
-
-        let _ = event_tx.send(IdleEvent {
-            account_id: account_id.to_string(),
-            email: email.to_string(),
-            event_type: IdleEventType::MessageDeleted {
-                count: deleted_count,
+    if stats.new_messages > 0 {
+        let messages = new_messages_since(pool, &account.id, &stats.folder, last_id).await?;
+        tracing::info!("New messages detected for {} in {}: +{}", account.email, folder, stats.new_messages);
+        emit(
+            event_tx,
+            account,
+            IdleEventType::NewMessage {
+                folder: folder.to_string(),
+                count: stats.new_messages,
+                messages,
             },
-            timestamp: chrono::Utc::now().timestamp(),
-        });
+        );
+    }
+    if stats.deleted_messages > 0 {
+        emit(
+            event_tx,
+            account,
+            IdleEventType::MessageDeleted {
+                folder: folder.to_string(),
+                count: stats.deleted_messages,
+            },
+        );
     }
-
-    session.logout().await.ok();
-
     Ok(())
 }
+
+async fn new_messages_since(pool: &SqlitePool, account_id: &str, folder: &str, last_id: i64) -> Result<Vec<MessageSummary>> {
+    let rows = sqlx::query(
+        r#"SELECT id, uid, message_id, subject, from_addr, date, size, has_attachments
+           FROM messages WHERE account_id = ? AND folder = ? AND id > ?
+           ORDER BY id LIMIT ?"#,
+    )
+    .bind(account_id)
+    .bind(folder)
+    .bind(last_id)
+    .bind(MAX_EVENT_MESSAGES)
+    .fetch_all(pool)
+    .await?;
+
+    let mut out = Vec::with_capacity(rows.len());
+    for r in rows {
+        out.push(MessageSummary {
+            id: r.try_get("id")?,
+            uid: r.try_get("uid")?,
+            message_id: r.try_get("message_id")?,
+            subject: r.try_get("subject")?,
+            from: r.try_get("from_addr")?,
+            date: r.try_get("date")?,
+            size: r.try_get("size")?,
+            has_attachments: r.try_get::<Option<bool>, _>("has_attachments")?.unwrap_or(false),
+        });
+    }
+    Ok(out)
+}
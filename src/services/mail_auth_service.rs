@@ -0,0 +1,269 @@
+/// How an account authenticates to its IMAP and SMTP servers.
+///
+/// Password accounts use LOGIN / AUTH PLAIN. Accounts with `auth_method = 'oauth2'` get a
+/// valid access token from `OAuthManager::get_valid_token` and authenticate with SASL XOAUTH2.
+/// Tokens are stored sealed with the password key, refreshed ahead of expiry by
+/// [`start_token_refresh_loop`], and a revoked grant flags the account `reauth_required`.
+use anyhow::Result;
+use once_cell::sync::{Lazy, OnceCell};
+use sqlx::SqlitePool;
+
+use crate::models::account::Account;
+use crate::oauth::{OAuthManager, OAuthTokens};
+
+#[derive(Clone)]
+pub enum MailAuth {
+    Password { user: String, password: String },
+    XOAuth2 { user: String, access_token: String },
+}
+
+impl MailAuth {
+    pub fn password(user: &str, password: &str) -> Self {
+        MailAuth::Password { user: user.to_string(), password: password.to_string() }
+    }
+
+    pub fn user(&self) -> &str {
+        match self {
+            MailAuth::Password { user, .. } | MailAuth::XOAuth2 { user, .. } => user,
+        }
+    }
+}
+
+// Tokens never end up in logs
+impl std::fmt::Debug for MailAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailAuth::Password { user, .. } => write!(f, "Password({})", user),
+            MailAuth::XOAuth2 { user, .. } => write!(f, "XOAuth2({})", user),
+        }
+    }
+}
+
+static DB: OnceCell<SqlitePool> = OnceCell::new();
+
+/// One refresh at a time, so concurrent connects and the background task don't each spend
+/// the refresh token
+static REFRESH: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+pub const STATUS_OK: &str = "ok";
+pub const STATUS_REAUTH: &str = "reauth_required";
+
+fn env_i64(key: &str, default: i64) -> i64 {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Register the pool tokens are read from and refreshed tokens persisted to
+pub fn init(pool: &SqlitePool) {
+    DB.set(pool.clone()).ok();
+}
+
+/// Where the user re-consents an account whose refresh token stopped working
+pub fn reauth_url(account: &Account) -> String {
+    format!("/oauth/start?provider={}&account_id={}", account.provider.as_str(), account.id)
+}
+
+/// Token state as stored: the `Account` may be a long-lived clone (IDLE, sync) that predates
+/// the last refresh, so the row wins when the pool is available
+#[derive(Clone)]
+struct StoredTokens {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: Option<i64>,
+    status: Option<String>,
+}
+
+async fn stored_tokens(account: &Account) -> Result<StoredTokens> {
+    let (access, refresh, expires_at, status) = match DB.get() {
+        Some(pool) => sqlx::query_as::<_, (Option<String>, Option<String>, Option<i64>, Option<String>)>(
+            "SELECT oauth_access_token, oauth_refresh_token, oauth_expires_at, oauth_status FROM accounts WHERE id = ?",
+        )
+        .bind(&account.id)
+        .fetch_optional(pool)
+        .await?
+        .unwrap_or_default(),
+        None => (
+            account.oauth_access_token.clone(),
+            account.oauth_refresh_token.clone(),
+            account.oauth_expires_at,
+            account.oauth_status.clone(),
+        ),
+    };
+    Ok(StoredTokens {
+        access_token: access.as_deref().map(Account::open_secret).transpose()?.unwrap_or_default(),
+        refresh_token: refresh.as_deref().map(Account::open_secret).transpose()?,
+        expires_at,
+        status,
+    })
+}
+
+/// Credentials for the account's IMAP/SMTP login, refreshing its OAuth2 token when needed
+pub async fn for_account(account: &Account) -> Result<MailAuth> {
+    if !account.is_oauth() {
+        let password = if account.password.is_empty() {
+            account.get_credentials()?.1
+        } else {
+            account.password.clone()
+        };
+        return Ok(MailAuth::Password { user: account.email.clone(), password });
+    }
+
+    let _guard = REFRESH.lock().await;
+    let current = stored_tokens(account).await?;
+    if current.status.as_deref() == Some(STATUS_REAUTH) {
+        anyhow::bail!(
+            "account {} needs OAuth2 reauthorization: {}",
+            account.email,
+            reauth_url(account)
+        );
+    }
+    if current.access_token.is_empty() && current.refresh_token.is_none() {
+        anyhow::bail!("account {} uses OAuth2 but has no token; authorize it again", account.email);
+    }
+
+    let tokens = match OAuthManager::get_valid_token(
+        account.provider.as_str(),
+        &current.access_token,
+        current.refresh_token.as_deref(),
+        current.expires_at,
+    )
+    .await
+    {
+        Ok(tokens) => tokens,
+        Err(e) => return Err(refresh_failed(account, &e).await),
+    };
+    if tokens.access_token != current.access_token {
+        store_refreshed(account, &current, &tokens).await;
+    }
+
+    Ok(MailAuth::XOAuth2 { user: account.email.clone(), access_token: tokens.access_token })
+}
+
+/// Background task: refresh OAuth2 tokens before they expire, so connects never wait on the
+/// provider and a revoked grant is noticed even while the account is idle
+pub async fn start_token_refresh_loop(pool: SqlitePool) {
+    init(&pool);
+    let interval = env_i64("MAILORA_OAUTH_REFRESH_INTERVAL_SECS", 300).max(30) as u64;
+    loop {
+        match refresh_expiring(&pool).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("OAuth2: refreshed {} token(s) ahead of expiry", n),
+            Err(e) => tracing::warn!("OAuth2 token refresh pass failed: {e}"),
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+    }
+}
+
+async fn refresh_expiring(pool: &SqlitePool) -> Result<usize> {
+    let ahead = env_i64("MAILORA_OAUTH_REFRESH_AHEAD_SECS", 900);
+    let deadline = chrono::Utc::now().timestamp() + ahead;
+    let mut refreshed = 0;
+    for account in crate::services::account_service::list_accounts(pool).await? {
+        if !account.enabled || !account.is_oauth() {
+            continue;
+        }
+        let _guard = REFRESH.lock().await;
+        let current = stored_tokens(&account).await?;
+        let due = current.expires_at.is_some_and(|exp| exp < deadline);
+        if !due || current.status.as_deref() == Some(STATUS_REAUTH) {
+            continue;
+        }
+        let Some(refresh_token) = current.refresh_token.as_deref() else { continue };
+        match OAuthManager::refresh_token(account.provider.as_str(), refresh_token).await {
+            Ok(tokens) => {
+                store_refreshed(&account, &current, &tokens).await;
+                refreshed += 1;
+            }
+            Err(e) => {
+                let err = refresh_failed(&account, &e).await;
+                tracing::warn!(account_id = %account.id, "{err}");
+            }
+        }
+    }
+    Ok(refreshed)
+}
+
+async fn store_refreshed(account: &Account, previous: &StoredTokens, tokens: &OAuthTokens) {
+    let rotated = tokens.refresh_token.is_some() && tokens.refresh_token != previous.refresh_token;
+    tracing::info!(account_id = %account.id, rotated, "OAuth2 access token refreshed");
+    if let Err(e) = save_tokens(&account.id, tokens).await {
+        tracing::warn!(account_id = %account.id, "failed to persist refreshed OAuth2 token: {e}");
+    }
+}
+
+/// A revoked or expired grant can't be retried; flag the account for reauthorization
+async fn refresh_failed(account: &Account, error: &str) -> anyhow::Error {
+    if !is_revoked(error) {
+        return anyhow::anyhow!("OAuth2 token for {}: {}", account.email, error);
+    }
+    if let Some(pool) = DB.get() {
+        let res = sqlx::query("UPDATE accounts SET oauth_status = ?, oauth_error = ? WHERE id = ?")
+            .bind(STATUS_REAUTH)
+            .bind(error)
+            .bind(&account.id)
+            .execute(pool)
+            .await;
+        if let Err(e) = res {
+            tracing::warn!(account_id = %account.id, "failed to flag account for reauthorization: {e}");
+        }
+    }
+    crate::imap::pool::invalidate(&account.id);
+    anyhow::anyhow!(
+        "OAuth2 grant for {} was revoked ({}); reauthorize at {}",
+        account.email,
+        error,
+        reauth_url(account)
+    )
+}
+
+/// `invalid_grant` (RFC 6749 §5.2): the refresh token is revoked, expired or was rotated away
+fn is_revoked(error: &str) -> bool {
+    error.contains("invalid_grant")
+}
+
+/// Store a token set (sealed) and mark the account healthy; used after refreshes and consents
+pub async fn save_tokens(account_id: &str, tokens: &OAuthTokens) -> Result<()> {
+    let Some(pool) = DB.get() else { return Ok(()) };
+    sqlx::query(
+        "UPDATE accounts SET auth_method = 'oauth2', oauth_access_token = ?, oauth_refresh_token = COALESCE(?, oauth_refresh_token), oauth_expires_at = ?, oauth_status = ?, oauth_error = NULL, updated_at = strftime('%s','now') WHERE id = ?",
+    )
+    .bind(Account::seal_secret(&tokens.access_token))
+    .bind(tokens.refresh_token.as_deref().map(Account::seal_secret))
+    .bind(tokens.expires_at)
+    .bind(STATUS_OK)
+    .bind(account_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// One-time pass at startup: seal OAuth2 tokens stored before sealing existed. `save_tokens`
+/// keeps the stored refresh token when a refresh doesn't rotate it, so they would otherwise
+/// stay in plaintext.
+pub async fn seal_stored_tokens(pool: &SqlitePool) -> Result<usize> {
+    let rows: Vec<(String, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT id, oauth_access_token, oauth_refresh_token FROM accounts WHERE substr(oauth_access_token, 1, 4) != 'enc:' OR substr(oauth_refresh_token, 1, 4) != 'enc:'",
+    )
+    .fetch_all(pool)
+    .await?;
+    let seal = |t: Option<String>| t.map(|t| if t.starts_with("enc:") { t } else { Account::seal_secret(&t) });
+    for (id, access, refresh) in &rows {
+        sqlx::query("UPDATE accounts SET oauth_access_token = ?, oauth_refresh_token = ? WHERE id = ?")
+            .bind(seal(access.clone()))
+            .bind(seal(refresh.clone()))
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_revoked() {
+        assert!(is_revoked("Token refresh failed: invalid_grant: Token has been expired or revoked."));
+        assert!(!is_revoked("Token refresh failed: Request failed"));
+    }
+}
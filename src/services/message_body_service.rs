@@ -41,8 +41,29 @@ pub async fn fetch_message_body(account: &Account, uid: u32, folder: Option<&str
         }
     }
 
+    // Bodies downloaded by the sync prefetch live on the messages row
+    if !force_refresh {
+        if let Ok(Some(row)) = sqlx::query("SELECT subject, from_addr, date, flags, body_plain, body_html FROM messages WHERE account_id=? AND folder=? AND uid=? AND body_plain IS NOT NULL")
+            .bind(&account.id)
+            .bind(folder)
+            .bind(uid as i64)
+            .fetch_optional(pool)
+            .await {
+            let body: String = row.try_get::<Option<String>,_>("body_plain").ok().flatten().unwrap_or_default();
+            let html_text: Option<String> = row.try_get::<Option<String>,_>("body_html").ok().flatten();
+            let subject: String = row.try_get::<Option<String>,_>("subject").ok().flatten().unwrap_or_default();
+            let from: String = row.try_get::<Option<String>,_>("from_addr").ok().flatten().unwrap_or_default();
+            let date: Option<String> = row.try_get::<Option<String>,_>("date").ok().flatten();
+            let flags_json: String = row.try_get::<Option<String>,_>("flags").ok().flatten().unwrap_or_default();
+            let flags: Vec<String> = serde_json::from_str(&flags_json).unwrap_or_default();
+            let raw_size = body.len();
+            return Ok(MessageBody { uid, folder: folder.to_string(), subject, from, date, flags, plain_text: body, html_text, raw_size });
+        }
+    }
+
     // IMAP fetch
-    let fetched = crate::imap::sync::fetch_message_body_in(&account.imap_host, account.imap_port, &account.email, &account.password, uid, folder)
+    let auth = crate::services::mail_auth_service::for_account(account).await?;
+    let fetched = crate::imap::sync::fetch_message_body_in(&account.id, &account.imap_host, account.imap_port, account.imap_security_mode(), &auth, uid, folder)
         .await?
         .ok_or_else(|| anyhow::anyhow!("message not found"))?;
     let body_text = fetched.body.clone();
@@ -64,10 +85,42 @@ pub async fn fetch_message_body(account: &Account, uid: u32, folder: Option<&str
         .bind(serde_json::to_string(&fetched.flags).unwrap_or_else(|_| "[]".into()))
         .execute(pool)
         .await;
+    // Opening a message counts as the lazy body download
+    if let Err(e) = store_message_body(pool, &account.id, folder, uid, &body_text, html_opt.as_deref()).await {
+        tracing::debug!(uid, %folder, "store_message_body failed: {e}");
+    }
 
     Ok(MessageBody { uid, folder: folder.to_string(), subject: fetched.subject, from: fetched.from, date: fetched.date, flags: fetched.flags, plain_text: body_text.clone(), html_text: html_opt, raw_size: body_text.len() })
 }
 
+/// Plain and HTML bodies of a raw RFC 822 message
+pub fn parse_bodies(raw: &[u8]) -> (String, Option<String>) {
+    let Some(message) = mail_parser::Message::parse(raw) else {
+        return (String::from_utf8_lossy(raw).to_string(), None);
+    };
+    let text = message.body_text(0).map(|t| t.into_owned()).unwrap_or_default();
+    let html = message.body_html(0).map(|h| {
+        let mut h = h.into_owned();
+        if h.len() > 5_000_000 { h.truncate(5_000_000); h.push_str("\n...[html truncated]..."); }
+        h
+    });
+    (text, html)
+}
+
+/// Persist a downloaded body on the `messages` row (this also feeds the FTS index).
+/// A non-NULL `body_plain` marks the message as downloaded.
+pub async fn store_message_body(pool: &SqlitePool, account_id: &str, folder: &str, uid: u32, text: &str, html: Option<&str>) -> Result<()> {
+    sqlx::query("UPDATE messages SET body_plain = ?, body_html = ? WHERE account_id = ? AND folder = ? AND uid = ?")
+        .bind(text)
+        .bind(html)
+        .bind(account_id)
+        .bind(folder)
+        .bind(uid as i64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 /// Garbage collect old cache entries (TTL 48h) and cap total entries to max_rows.
 pub async fn gc(pool: &SqlitePool, max_rows: i64) {
     // Delete older than 48h
@@ -1,3 +1,445 @@
-// filepath: /mailora-hub-imap/mailora-hub-imap/src/services/message_service.rs
-// Placeholder message service - will contain sync logic for external accounts
-// Future: IMAP sync, message fetch, thread grouping
+/// Message operations that change the server: moving and copying between folders, deleting
+/// through Trash (with undo), and the matching updates of the local cache.
+///
+/// Deleting moves messages to the `\Trash` special-use folder; only messages already in Trash
+/// are expunged, and then only their UIDs. A delete returns an undo token that moves the
+/// messages back for `MAILORA_UNDO_TTL_SECS`.
+///
+/// The local cache follows the server without a resync when the server reports the new UIDs
+/// (UIDPLUS COPYUID): moved rows are re-keyed to their new folder/uid and copies are duplicated
+/// together with their attachment rows and cached body. Otherwise moved rows are dropped and
+/// the destination folder is queued for a sync.
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+use crate::imap::pool;
+use crate::imap::transfer::{self, CopyUid, Transfer};
+use crate::models::account::Account;
+use crate::services::folder_service;
+use crate::services::message_sync_service::load_folder_state;
+use crate::services::sync_job_service::{self, JobPriority};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UidMapping {
+    pub uid: u32,
+    /// UID in the destination folder, when the server reported it
+    pub new_uid: Option<u32>,
+    /// Provisional UID of the cached row while the move waits for the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_uid: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferOutcome {
+    pub from: String,
+    pub to: String,
+    pub messages: Vec<UidMapping>,
+    /// A sync job was queued because the cache could not be updated in place
+    pub resync_queued: bool,
+    /// Applied to the cache only; replayed once the server can be reached (see `pending_op_service`)
+    pub queued: bool,
+}
+
+/// Move or copy `uids` from `folder` to `dest` on the server, then update the local cache
+pub async fn transfer_messages(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    uids: &[u32],
+    dest: &str,
+    mode: Transfer,
+) -> Result<TransferOutcome> {
+    if folder == dest {
+        anyhow::bail!("source and destination folder are the same");
+    }
+    if uids.is_empty() {
+        anyhow::bail!("no UIDs given");
+    }
+
+    let mut imap = pool::acquire_account(account).await?;
+    if let Err(e) = imap.session.select(folder).await {
+        anyhow::bail!("cannot select {}: {}", folder, e);
+    }
+    let gmail = imap.caps.gmail;
+    let copyuid = match transfer::transfer(&mut imap, uids, dest, mode).await {
+        Ok(c) => c,
+        Err(e) => {
+            // The command may have been cut off mid-response
+            imap.discard();
+            return Err(e);
+        }
+    };
+    imap.release();
+
+    let verb = if mode == Transfer::Move { "moved" } else { "copied" };
+    info!(account_id = %account.id, "{} {} message(s) from {} to {}", verb, uids.len(), folder, dest);
+
+    // Gmail folders are labels over the All Mail rows; let the label sync sort them out
+    let mapped = if gmail { None } else { apply_locally(pool, &account.id, folder, dest, uids, copyuid.as_ref(), mode).await? };
+    let resync_queued = match &mapped {
+        Some(_) => false,
+        None => {
+            let scope = if gmail { None } else { Some(dest) };
+            sync_job_service::enqueue(&account.id, scope, JobPriority::User).await;
+            true
+        }
+    };
+
+    let new_uids = mapped.unwrap_or_default();
+    Ok(TransferOutcome {
+        from: folder.to_string(),
+        to: dest.to_string(),
+        messages: uids
+            .iter()
+            .map(|&uid| UidMapping { uid, new_uid: new_uids.iter().find(|(s, _)| *s == uid).map(|(_, d)| *d), local_uid: None })
+            .collect(),
+        resync_queued,
+        queued: false,
+    })
+}
+
+/// Rewrite the cache after a transfer. Returns the applied UID mapping, or `None` when the
+/// destination needs a sync to learn the new UIDs.
+async fn apply_locally(
+    pool: &SqlitePool,
+    account_id: &str,
+    folder: &str,
+    dest: &str,
+    uids: &[u32],
+    copyuid: Option<&CopyUid>,
+    mode: Transfer,
+) -> Result<Option<Vec<(u32, u32)>>> {
+    // Rows keyed by a UIDVALIDITY we don't hold would be purged by the next sync anyway
+    let usable = match copyuid {
+        Some(c) => match load_folder_state(pool, account_id, dest).await?.uidvalidity {
+            Some(v) if v != c.uidvalidity => {
+                warn!(account_id, "COPYUID for {} has UIDVALIDITY {} but the cache has {}", dest, c.uidvalidity, v);
+                None
+            }
+            _ => Some(c),
+        },
+        None => None,
+    };
+
+    let Some(copy) = usable else {
+        if mode == Transfer::Move {
+            delete_rows(pool, account_id, folder, uids).await?;
+        }
+        return Ok(None);
+    };
+
+    let mut tx = pool.begin().await?;
+    for &(src, dst) in &copy.uids {
+        match mode {
+            Transfer::Move => {
+                sqlx::query("UPDATE OR REPLACE messages SET folder = ?, uid = ?, synced_at = datetime('now') WHERE account_id = ? AND folder = ? AND uid = ?")
+                    .bind(dest).bind(dst).bind(account_id).bind(folder).bind(src)
+                    .execute(&mut *tx).await?;
+                sqlx::query("UPDATE OR REPLACE message_bodies SET folder = ?, uid = ? WHERE account_id = ? AND folder = ? AND uid = ?")
+                    .bind(dest).bind(dst).bind(account_id).bind(folder).bind(src)
+                    .execute(&mut *tx).await?;
+            }
+            Transfer::Copy => copy_row(&mut tx, account_id, folder, src, dest, dst).await?,
+        }
+    }
+    tx.commit().await?;
+    Ok(Some(copy.uids.clone()))
+}
+
+/// Duplicate a cached message (with attachment rows and body) under its new folder/uid
+async fn copy_row(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    account_id: &str,
+    folder: &str,
+    uid: u32,
+    dest: &str,
+    new_uid: u32,
+) -> Result<()> {
+    // Every column except the key, so columns added by later migrations are carried along
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('messages')")
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .filter(|c: &String| !matches!(c.as_str(), "id" | "folder" | "uid" | "synced_at"))
+        .collect();
+    let cols = columns.join(", ");
+
+    let old_id: Option<i64> = sqlx::query_scalar("SELECT id FROM messages WHERE account_id = ? AND folder = ? AND uid = ?")
+        .bind(account_id).bind(folder).bind(uid)
+        .fetch_optional(&mut **tx).await?;
+    let Some(old_id) = old_id else { return Ok(()) };
+
+    let res = sqlx::query(&format!(
+        "INSERT OR IGNORE INTO messages (folder, uid, {cols}) SELECT ?, ?, {cols} FROM messages WHERE id = ?"
+    ))
+    .bind(dest).bind(new_uid).bind(old_id)
+    .execute(&mut **tx).await?;
+    if res.rows_affected() == 0 {
+        return Ok(());
+    }
+    let new_id = res.last_insert_rowid();
+
+    sqlx::query("INSERT INTO attachments (message_id, filename, content_type, size, content_id, is_inline, data, file_path) SELECT ?, filename, content_type, size, content_id, is_inline, data, file_path FROM attachments WHERE message_id = ?")
+        .bind(new_id).bind(old_id)
+        .execute(&mut **tx).await?;
+    sqlx::query("INSERT OR IGNORE INTO message_bodies (account_id, folder, uid, body, html_body, subject, from_addr, date, flags) SELECT account_id, ?, ?, body, html_body, subject, from_addr, date, flags FROM message_bodies WHERE account_id = ? AND folder = ? AND uid = ?")
+        .bind(dest).bind(new_uid).bind(account_id).bind(folder).bind(uid)
+        .execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// Drop cached messages (and their attachment rows and bodies) that left the server folder
+pub async fn delete_rows(pool: &SqlitePool, account_id: &str, folder: &str, uids: &[u32]) -> Result<()> {
+    let placeholders = uids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let mut tx = pool.begin().await?;
+    let attachments = format!(
+        "DELETE FROM attachments WHERE message_id IN (SELECT id FROM messages WHERE account_id = ? AND folder = ? AND uid IN ({}))",
+        placeholders
+    );
+    for sql in [
+        attachments,
+        format!("DELETE FROM messages WHERE account_id = ? AND folder = ? AND uid IN ({})", placeholders),
+        format!("DELETE FROM message_bodies WHERE account_id = ? AND folder = ? AND uid IN ({})", placeholders),
+    ] {
+        let mut q = sqlx::query(&sql).bind(account_id).bind(folder);
+        for uid in uids {
+            q = q.bind(uid);
+        }
+        q.execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn undo_ttl_secs() -> i64 {
+    std::env::var("MAILORA_UNDO_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(1800)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteOutcome {
+    pub folder: String,
+    /// Moved to this Trash folder; `None` when the messages were expunged from Trash
+    pub trash: Option<String>,
+    pub messages: Vec<UidMapping>,
+    /// Restores the messages through [`undo_delete`] until it expires
+    pub undo_token: Option<String>,
+}
+
+/// Delete `uids` from `folder`: move them to Trash, or expunge them when `folder` is Trash.
+/// `undo_token` lets several deletes (e.g. one per folder of a bulk action) share a token.
+pub async fn delete_messages(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    uids: &[u32],
+    undo_token: Option<&str>,
+) -> Result<DeleteOutcome> {
+    let trash = folder_service::folder_by_role(pool, &account.id, "\\Trash")
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account has no \\Trash folder; messages are only removed through Trash"))?;
+
+    if folder == trash {
+        let mut imap = pool::acquire_account(account).await?;
+        let res = async {
+            imap.session.select(folder).await?;
+            transfer::expunge_uids(&mut imap, uids).await
+        }
+        .await;
+        if let Err(e) = res {
+            imap.discard();
+            return Err(e);
+        }
+        imap.release();
+        delete_rows(pool, &account.id, folder, uids).await?;
+        info!(account_id = %account.id, "permanently deleted {} message(s) from {}", uids.len(), folder);
+        return Ok(DeleteOutcome {
+            folder: folder.to_string(),
+            trash: None,
+            messages: uids.iter().map(|&uid| UidMapping { uid, new_uid: None, local_uid: None }).collect(),
+            undo_token: None,
+        });
+    }
+
+    // Message-IDs locate the messages in Trash when the server doesn't report their new UIDs
+    let mut message_ids: HashMap<u32, String> = HashMap::new();
+    for uid in uids {
+        let mid: Option<Option<String>> = sqlx::query_scalar("SELECT message_id FROM messages WHERE account_id = ? AND folder = ? AND uid = ?")
+            .bind(&account.id).bind(folder).bind(uid)
+            .fetch_optional(pool).await?;
+        if let Some(Some(mid)) = mid {
+            message_ids.insert(*uid, mid);
+        }
+    }
+
+    let moved = transfer_messages(pool, account, folder, uids, &trash, Transfer::Move).await?;
+    let token = undo_token.map(str::to_string).unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now().timestamp();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM deleted_messages WHERE deleted_at < ?")
+        .bind(now - undo_ttl_secs())
+        .execute(&mut *tx).await?;
+    for m in &moved.messages {
+        sqlx::query("INSERT INTO deleted_messages (token, account_id, folder, uid, trash_folder, trash_uid, message_id, deleted_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&token).bind(&account.id).bind(folder).bind(m.uid).bind(&trash).bind(m.new_uid)
+            .bind(message_ids.get(&m.uid)).bind(now)
+            .execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(DeleteOutcome { folder: folder.to_string(), trash: Some(trash), messages: moved.messages, undo_token: Some(token) })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoredMessage {
+    pub account_id: String,
+    pub folder: String,
+    /// UID before the delete
+    pub uid: u32,
+    pub restored: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Move the messages of a delete back from Trash. `Ok(None)` when the token is unknown or expired.
+pub async fn undo_delete(pool: &SqlitePool, token: &str) -> Result<Option<Vec<RestoredMessage>>> {
+    let cutoff = chrono::Utc::now().timestamp() - undo_ttl_secs();
+    #[derive(sqlx::FromRow)]
+    struct Row { account_id: String, folder: String, uid: i64, trash_folder: String, trash_uid: Option<i64>, message_id: Option<String> }
+    let rows: Vec<Row> = sqlx::query_as(
+        "SELECT account_id, folder, uid, trash_folder, trash_uid, message_id FROM deleted_messages WHERE token = ? AND deleted_at >= ?",
+    )
+    .bind(token)
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+
+    // (account, trash, original folder) -> [(original uid, trash uid, message-id)]
+    type Pending = Vec<(u32, Option<u32>, Option<String>)>;
+    let mut groups: BTreeMap<(String, String, String), Pending> = BTreeMap::new();
+    for r in rows {
+        groups
+            .entry((r.account_id, r.trash_folder, r.folder))
+            .or_default()
+            .push((r.uid as u32, r.trash_uid.map(|u| u as u32), r.message_id));
+    }
+
+    let mut out = Vec::new();
+    for ((account_id, trash, folder), pending) in groups {
+        let res = restore_group(pool, &account_id, &trash, &folder, &pending).await;
+        let restored = match &res {
+            Ok(found) => found.clone(),
+            Err(_) => Vec::new(),
+        };
+        for (uid, _, _) in &pending {
+            let ok = restored.contains(uid);
+            out.push(RestoredMessage {
+                account_id: account_id.clone(),
+                folder: folder.clone(),
+                uid: *uid,
+                restored: ok,
+                error: match &res {
+                    Err(e) => Some(e.to_string()),
+                    Ok(_) if !ok => Some("no longer in Trash".to_string()),
+                    Ok(_) => None,
+                },
+            });
+            if ok {
+                sqlx::query("DELETE FROM deleted_messages WHERE token = ? AND account_id = ? AND folder = ? AND uid = ?")
+                    .bind(token).bind(&account_id).bind(&folder).bind(uid)
+                    .execute(pool).await?;
+            }
+        }
+    }
+    Ok(Some(out))
+}
+
+/// Returns the original UIDs whose messages were found in Trash and moved back
+async fn restore_group(
+    pool: &SqlitePool,
+    account_id: &str,
+    trash: &str,
+    folder: &str,
+    pending: &[(u32, Option<u32>, Option<String>)],
+) -> Result<Vec<u32>> {
+    let account = crate::services::account_service::get_account(pool, account_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account not found"))?;
+
+    let mut found: Vec<(u32, u32)> = pending.iter().filter_map(|(uid, t, _)| t.map(|t| (*uid, t))).collect();
+    let lookups: Vec<&(u32, Option<u32>, Option<String>)> = pending.iter().filter(|(_, t, mid)| t.is_none() && mid.is_some()).collect();
+    if !lookups.is_empty() {
+        // Released before the move below checks out its own session
+        let mut imap = pool::acquire_account(&account).await?;
+        imap.session.select(trash).await?;
+        for (uid, _, mid) in lookups {
+            let query = format!("HEADER Message-ID {}", crate::imap::conn::quote(mid.as_deref().unwrap_or_default()));
+            if let Some(t) = imap.session.uid_search(&query).await?.into_iter().max() {
+                found.push((*uid, t));
+            }
+        }
+        imap.release();
+    }
+    if found.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let trash_uids: Vec<u32> = found.iter().map(|(_, t)| *t).collect();
+    transfer_messages(pool, &account, trash, &trash_uids, folder, Transfer::Move).await?;
+    info!(account_id, "restored {} deleted message(s) from {} to {}", found.len(), trash, folder);
+    Ok(found.into_iter().map(|(uid, _)| uid).collect())
+}
+
+/// Apply a flag change to the cached flags snapshot (JSON array) of `uids`
+pub async fn update_cached_flags(
+    pool: &SqlitePool,
+    account_id: &str,
+    folder: &str,
+    uids: &[u32],
+    add: &[&str],
+    remove: &[&str],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for uid in uids {
+        let current: Option<Option<String>> = sqlx::query_scalar("SELECT flags FROM messages WHERE account_id = ? AND folder = ? AND uid = ?")
+            .bind(account_id).bind(folder).bind(uid)
+            .fetch_optional(&mut *tx).await?;
+        let Some(current) = current else { continue };
+        sqlx::query("UPDATE messages SET flags = ?, synced_at = datetime('now') WHERE account_id = ? AND folder = ? AND uid = ?")
+            .bind(merge_flags(current.as_deref(), add, remove))
+            .bind(account_id).bind(folder).bind(uid)
+            .execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Add and remove flags in a JSON flags array, keeping every other flag (keywords included)
+pub fn merge_flags(current: Option<&str>, add: &[&str], remove: &[&str]) -> String {
+    let mut flags: Vec<String> = current.and_then(|j| serde_json::from_str(j).ok()).unwrap_or_default();
+    flags.retain(|f| !remove.iter().any(|r| r.eq_ignore_ascii_case(f)));
+    for a in add {
+        if !flags.iter().any(|f| f.eq_ignore_ascii_case(a)) {
+            flags.push(a.to_string());
+        }
+    }
+    serde_json::to_string(&flags).unwrap_or_else(|_| "[]".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_flags() {
+        let current = r#"["\\Answered","$Label1","\\Seen"]"#;
+        assert_eq!(merge_flags(Some(current), &["\\Flagged"], &["\\Seen"]), r#"["\\Answered","$Label1","\\Flagged"]"#);
+        assert_eq!(merge_flags(Some(current), &["\\seen"], &[]), r#"["\\Answered","$Label1","\\Seen"]"#);
+        assert_eq!(merge_flags(None, &["\\Seen"], &[]), r#"["\\Seen"]"#);
+    }
+}
@@ -1,13 +1,14 @@
 use anyhow::{Context, Result};
 use async_imap::types::{Fetch, Flag};
 use sqlx::{Row, SqlitePool};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use tracing::{info, warn};
 use tokio::time::{timeout, Duration};
 
-use crate::imap::conn;
+use crate::imap::{bodystructure, conn, gmail, headers, pool};
 use crate::models::account::Account;
-use mail_parser::MimeHeaders;
+use crate::services::idle_watcher_service::{self, FlagUpdate, IdleEvent, IdleEventType};
+use crate::services::{draft_service, folder_service, message_body_service, pending_op_service, quota_service, rule_service, sync_job_service, sync_policy_service};
 
 fn imap_timeout() -> Duration {
     let secs = std::env::var("MAILORA_IMAP_TIMEOUT_SECS")
@@ -29,6 +30,9 @@ where
     }
 }
 
+/// What a sync fetches per new message: headers and BODYSTRUCTURE, no body
+const HEADER_ITEMS: &str = "(UID FLAGS INTERNALDATE RFC822.SIZE BODYSTRUCTURE BODY.PEEK[HEADER])";
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SyncStats {
     pub account_id: String,
@@ -61,24 +65,47 @@ pub async fn sync_folder_messages(
 ) -> Result<SyncStats> {
     // Connect to IMAP
     let mut imap_session = with_timeout(
-        conn::connect(
-            &account.imap_host,
-            account.imap_port,
-            &account.email,
-            &account.password,
-        ),
+        pool::acquire_account(account),
         "IMAP connect",
     )
     .await?;
 
-    sync_folder_messages_with_session(pool, account, folder, &mut imap_session.session).await
+    // On Gmail a label folder is a view of All Mail; sync that instead of duplicating rows
+    // and refresh the labels
+    if imap_session.caps.gmail {
+        let folders = folder_service::list_folders(pool, &account.id).await?;
+        let roles = gmail::role_folders(&folders);
+        if let Some(all_mail) = gmail_rows_folder(&roles, folder) {
+            let prev_modseq = load_folder_state(pool, &account.id, &all_mail).await?.highest_modseq;
+            let stats = sync_folder_messages_with_session(pool, account, &all_mail, &mut imap_session).await?;
+            match sync_gmail_labels(pool, account, &mut imap_session, &all_mail, prev_modseq, &roles).await {
+                Ok(_) => imap_session.release(),
+                Err(e) => warn!("Gmail label sync failed for {}: {}", account.email, e),
+            }
+            return Ok(stats);
+        }
+    }
+    let stats = sync_folder_messages_with_session(pool, account, folder, &mut imap_session).await?;
+    imap_session.release();
+    Ok(stats)
+}
+
+/// All Mail, when `folder` is a Gmail label view whose rows live there
+fn gmail_rows_folder(roles: &HashMap<String, String>, folder: &str) -> Option<String> {
+    let own_rows = ["\\All", "\\Junk", "\\Trash"]
+        .iter()
+        .any(|r| roles.get(*r).map(|n| n == folder).unwrap_or(false));
+    if own_rows {
+        return None;
+    }
+    roles.get("\\All").cloned()
 }
 
 pub async fn sync_folder_messages_with_session(
     pool: &SqlitePool,
     account: &Account,
     folder: &str,
-    session: &mut async_imap::Session<tokio_util::compat::Compat<tokio_native_tls::TlsStream<tokio::net::TcpStream>>>,
+    imap: &mut conn::ImapSession,
 ) -> Result<SyncStats> {
     let start = std::time::Instant::now();
 
@@ -87,14 +114,48 @@ pub async fn sync_folder_messages_with_session(
         account.email, folder
     );
 
+    let condstore = imap.caps.condstore;
+    let qresync = imap.caps.qresync;
+    let session = &mut imap.session;
 
-    // Select folder
-    let mailbox = with_timeout(session.select(folder), "IMAP SELECT").await?;
+    // Select folder (SELECT ... (CONDSTORE) makes the server report HIGHESTMODSEQ)
+    let mailbox = if condstore {
+        with_timeout(session.select_condstore(folder), "IMAP SELECT").await?
+    } else {
+        with_timeout(session.select(folder), "IMAP SELECT").await?
+    };
+    // EXISTS/EXPUNGE/FETCH noise is not read here; keep the channel from filling up
+    conn::clear_unsolicited(session);
 
     let total_messages = mailbox.exists;
     info!("Folder {} has {} messages", folder, total_messages);
 
+    // UIDVALIDITY change means the server renumbered the mailbox: cached UIDs now
+    // point at different messages, so drop them and let this pass resync from scratch.
+    let mut state = load_folder_state(pool, &account.id, folder).await?;
+    if let (Some(old), Some(new)) = (state.uidvalidity, mailbox.uid_validity) {
+        if old != new {
+            let purged = purge_folder_cache(pool, &account.id, folder).await?;
+            warn!(
+                "UIDVALIDITY changed for {} folder {} ({} -> {}), purged {} cached messages",
+                account.email, folder, old, new, purged
+            );
+            idle_watcher_service::publish(IdleEvent {
+                account_id: account.id.clone(),
+                email: account.email.clone(),
+                event_type: IdleEventType::FolderInvalidated {
+                    folder: folder.to_string(),
+                    old_uidvalidity: old,
+                    new_uidvalidity: new,
+                },
+                timestamp: chrono::Utc::now().timestamp(),
+            });
+            state = FolderSyncState::default();
+        }
+    }
+
     if total_messages == 0 {
+        save_folder_state(pool, &account.id, folder, &mailbox, mailbox.highest_modseq).await?;
         return Ok(SyncStats {
             account_id: account.id.clone(),
             folder: folder.to_string(),
@@ -106,9 +167,9 @@ pub async fn sync_folder_messages_with_session(
         });
     }
 
-    // Get existing UIDs from database
-    let existing_uids: HashSet<u32> =
-        sqlx::query_scalar("SELECT uid FROM messages WHERE account_id = ? AND folder = ?")
+    // Get existing UIDs (and their flags snapshot) from database
+    let existing: HashMap<u32, Option<String>> =
+        sqlx::query_as::<_, (u32, Option<String>)>("SELECT uid, flags FROM messages WHERE account_id = ? AND folder = ?")
             .bind(&account.id)
             .bind(folder)
             .fetch_all(pool)
@@ -116,34 +177,44 @@ pub async fn sync_folder_messages_with_session(
             .into_iter()
             .collect();
 
-    info!("Found {} existing messages in DB", existing_uids.len());
-
-    // Fetch all UIDs from server
-    let sequence = format!("1:{}", total_messages);
-    let messages = with_timeout(session.fetch(&sequence, "UID"), "IMAP FETCH UIDs").await?;
+    info!("Found {} existing messages in DB", existing.len());
 
-    let mut messages_vec: Vec<_> = vec![];
-    {
-        use futures::StreamExt;
-        let mut stream = messages;
-        while let Some(fetch) = stream.next().await {
-            if let Ok(f) = fetch {
-                messages_vec.push(f);
-            }
+    // Without CONDSTORE every pass does a UID+FLAGS listing, which doubles as flag reconciliation
+    let plan = match (state.highest_modseq, mailbox.highest_modseq) {
+        (Some(since), Some(current)) if condstore && !existing.is_empty() => {
+            plan_changes_since(session, &existing, since, current, total_messages, qresync).await?
         }
+        _ => plan_full_scan(session, &existing).await?,
+    };
+    conn::clear_unsolicited(session);
+    let SyncPlan { mut new_uids, mut deleted_uids, mut changed_flags } = plan;
+
+    // Local changes not yet replayed to the server win until they are
+    let held = pending_op_service::held_uids(pool, &account.id, folder).await?;
+    if !held.is_empty() {
+        new_uids.retain(|u| !held.contains(u));
+        deleted_uids.retain(|u| !held.contains(u));
+        changed_flags.retain(|(u, _)| !held.contains(u));
     }
 
-    let server_uids: HashSet<u32> = messages_vec.iter().filter_map(|m| m.uid).collect();
-
-    // Calculate what to sync
-    let new_uids: Vec<u32> = server_uids.difference(&existing_uids).copied().collect();
-
-    let deleted_uids: Vec<u32> = existing_uids.difference(&server_uids).copied().collect();
+    // Messages older than the account's sync window are left on the server
+    let window = sync_policy_service::load(pool, &account.id).await?.window_days;
+    if let (Some(days), false) = (window, new_uids.is_empty()) {
+        let since = (chrono::Utc::now() - chrono::Duration::days(days)).date_naive();
+        let recent = with_timeout(
+            session.uid_search(format!("SINCE {}", since.format("%-d-%b-%Y"))),
+            "IMAP UID SEARCH SINCE",
+        )
+        .await?;
+        conn::clear_unsolicited(session);
+        new_uids.retain(|u| recent.contains(u));
+    }
 
     info!(
-        "Sync plan: {} new, {} to delete",
+        "Sync plan: {} new, {} to delete, {} flag changes",
         new_uids.len(),
-        deleted_uids.len()
+        deleted_uids.len(),
+        changed_flags.len()
     );
 
     // Delete messages that no longer exist on server
@@ -173,23 +244,46 @@ pub async fn sync_folder_messages_with_session(
     let mut new_count = 0;
     let mut updated_count = 0;
     let mut error_count = 0;
+    // Fetched but not stored; like fetch errors, these keep the modseq where it is
+    let mut unsaved = 0;
+
+    // Apply flag changes in one transaction and tell clients which UIDs changed
+    if !changed_flags.is_empty() {
+        let mut tx = pool.begin().await?;
+        for (uid, flags_json) in &changed_flags {
+            updated_count += sqlx::query(
+                "UPDATE messages SET flags = ?, synced_at = datetime('now') WHERE account_id = ? AND folder = ? AND uid = ?",
+            )
+            .bind(flags_json)
+            .bind(&account.id)
+            .bind(folder)
+            .bind(uid)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() as u32;
+        }
+        tx.commit().await?;
+        publish_flag_changes(account, folder, &changed_flags);
+    }
 
     if !new_uids.is_empty() {
-        // Fetch in batches of 50
+        // Fetch in batches of 50; a cancelled job stops between batches, leaving the session idle
+        let mut fetched = 0;
         for chunk in new_uids.chunks(50) {
+            sync_job_service::report_fetched(fetched, new_uids.len());
+            sync_job_service::check_cancelled()?;
+            fetched += chunk.len();
             let uid_set = chunk
                 .iter()
                 .map(|u| u.to_string())
                 .collect::<Vec<_>>()
                 .join(",");
 
-            // Include headers and BODY.PEEK[] so we can parse everything manually
+            // Phase one: headers + BODYSTRUCTURE only; bodies are downloaded later by
+            // `prefetch_bodies` or on demand when the message is opened.
             // We AVOID fetching ENVELOPE because it crashes on some bad UTF-8 headers (e.g. from Gmail Sent Items)
             let messages = with_timeout(
-                session.uid_fetch(
-                    &uid_set,
-                    "(UID FLAGS INTERNALDATE RFC822.SIZE BODY.PEEK[HEADER.FIELDS (FROM TO CC SUBJECT DATE MESSAGE-ID)] BODY.PEEK[])",
-                ),
+                session.uid_fetch(&uid_set, HEADER_ITEMS),
                 "IMAP UID FETCH",
             )
             .await
@@ -198,15 +292,18 @@ pub async fn sync_folder_messages_with_session(
             use futures::StreamExt;
             let mut stream = messages;
             while let Some(fetch_result) = stream.next().await {
+                // Past the error limit the rest of the batch is only read off the wire
+                if error_count > 5 {
+                    continue;
+                }
                 match fetch_result {
                     Ok(fetch) => match save_message_to_db(pool, account, folder, &fetch).await {
                         Ok(true) => new_count += 1,
                         Ok(false) => updated_count += 1,
-                        Err(e) => warn!(
-                            "Failed to save message UID {}: {}",
-                            fetch.uid.unwrap_or(0),
-                            e
-                        ),
+                        Err(e) => {
+                            warn!("Failed to save message UID {}: {}", fetch.uid.unwrap_or(0), e);
+                            unsaved += 1;
+                        }
                     },
                     Err(_e) => {
                          // ... error handling same as before
@@ -214,14 +311,20 @@ pub async fn sync_folder_messages_with_session(
                         error_count += 1;
                         if error_count > 5 {
                             warn!("Too many fetch errors in folder {}, aborting sync for this batch", folder);
-                            break;
                         }
                     }
                 }
             }
+            drop(stream);
+            conn::clear_unsolicited(session);
         }
     }
 
+    // Only advance the modseq when every change was applied, otherwise the next
+    // CHANGEDSINCE pass would skip the messages we failed to fetch or store.
+    let modseq = if error_count == 0 && unsaved == 0 { mailbox.highest_modseq } else { None };
+    save_folder_state(pool, &account.id, folder, &mailbox, modseq).await?;
+
     let duration_ms = start.elapsed().as_millis() as u64;
 
     info!(
@@ -240,113 +343,255 @@ pub async fn sync_folder_messages_with_session(
     })
 }
 
-/// Extract attachments metadata from a raw email
-fn extract_attachments_from_raw(raw: &[u8]) -> Vec<(Option<String>, Option<String>, i64, Option<String>, bool)> {
-    // ... existing logic ...
-    let mut atts = Vec::new();
-    if raw.len() > 50_000_000 { return atts; }
-    if let Some(message) = mail_parser::Message::parse(raw) {
-        let mut idx = 1;
-        for part in message.parts.iter() {
-             let ctype = part.content_type();
-             let c_type = ctype.map(|c| c.c_type.as_ref()).unwrap_or("application");
-             let subtype = ctype.and_then(|c| c.subtype()).unwrap_or("");
-             if c_type == "multipart" { continue; }
-             let is_body = c_type == "text" && (subtype == "plain" || subtype == "html");
-             let has_filename = part.attachment_name().is_some();
-             let ctype_full = format!("{}/{}", c_type, subtype);
-             let is_media = c_type == "image" || c_type == "video" || c_type == "audio" || c_type == "application";
-             if has_filename || (is_media && !is_body) {
-                  let fname = part.attachment_name().map(|s| s.to_string());
-                  let sz = part.contents().len() as i64;
-                  let cid = part.content_id().map(|s| s.to_string());
-                  let mut is_inline = false;
-                  if let Some(cd) = part.content_disposition() {
-                      if cd.c_type.eq_ignore_ascii_case("inline") { is_inline = true; }
-                  }
-                  if cid.is_some() { is_inline = true; }
-                  atts.push((fname, Some(ctype_full), sz, cid, is_inline));
-             }
-             idx += 1;
-        }
-    }
-    atts
+/// What a sync pass has to apply locally
+#[derive(Debug, Default)]
+struct SyncPlan {
+    new_uids: Vec<u32>,
+    deleted_uids: Vec<u32>,
+    /// (uid, flags JSON) for already-synced messages whose flags changed
+    changed_flags: Vec<(u32, String)>,
 }
 
-/// Save a single message to database
-async fn save_message_to_db(
+/// Classic plan: list every UID with its FLAGS and diff against the DB
+async fn plan_full_scan(
+    session: &mut conn::RawSession,
+    existing: &HashMap<u32, Option<String>>,
+) -> Result<SyncPlan> {
+    let mut plan = SyncPlan::default();
+    let mut server_uids: HashSet<u32> = HashSet::new();
+
+    let messages = with_timeout(session.uid_fetch("1:*", "(UID FLAGS)"), "IMAP UID FETCH FLAGS").await?;
+    {
+        use futures::StreamExt;
+        let mut stream = messages;
+        while let Some(fetch) = stream.next().await {
+            let Ok(f) = fetch else { continue };
+            let Some(uid) = f.uid else { continue };
+            server_uids.insert(uid);
+            match existing.get(&uid) {
+                Some(stored) => {
+                    let flags_json = flags_to_json(&f)?;
+                    if flags_differ(stored.as_deref(), &flags_json) {
+                        plan.changed_flags.push((uid, flags_json));
+                    }
+                }
+                None => plan.new_uids.push(uid),
+            }
+        }
+    }
+
+    plan.deleted_uids = existing
+        .keys()
+        .filter(|u| !server_uids.contains(u))
+        .copied()
+        .collect();
+    Ok(plan)
+}
+
+/// Incremental plan (RFC 7162): only messages whose MODSEQ moved past `since`
+/// are returned, and with QRESYNC the server also reports expunged UIDs via VANISHED.
+async fn plan_changes_since(
+    session: &mut conn::RawSession,
+    existing: &HashMap<u32, Option<String>>,
+    since: u64,
+    current: u64,
+    total_messages: u32,
+    qresync: bool,
+) -> Result<SyncPlan> {
+    let mut plan = SyncPlan::default();
+
+    if current > since {
+        let query = if qresync {
+            format!("(UID FLAGS) (CHANGEDSINCE {} VANISHED)", since)
+        } else {
+            format!("(UID FLAGS) (CHANGEDSINCE {})", since)
+        };
+        // Only VANISHED sent in answer to this FETCH belongs to this folder
+        conn::clear_unsolicited(session);
+        let fetches = with_timeout(session.uid_fetch("1:*", &query), "IMAP UID FETCH CHANGEDSINCE").await?;
+        {
+            use futures::StreamExt;
+            let mut stream = fetches;
+            while let Some(item) = stream.next().await {
+                let Ok(f) = item else { continue };
+                let Some(uid) = f.uid else { continue };
+                match existing.get(&uid) {
+                    Some(stored) => {
+                        let flags_json = flags_to_json(&f)?;
+                        if flags_differ(stored.as_deref(), &flags_json) {
+                            plan.changed_flags.push((uid, flags_json));
+                        }
+                    }
+                    None => plan.new_uids.push(uid),
+                }
+            }
+        }
+        if qresync {
+            plan.deleted_uids = drain_vanished(session)
+                .into_iter()
+                .filter(|u| existing.contains_key(u))
+                .collect();
+        }
+    }
+
+    if !qresync && existing.len() + plan.new_uids.len() != total_messages as usize {
+        // Plain CONDSTORE does not report expunges; fall back to a UID-only listing
+        let server_uids = with_timeout(session.uid_search("ALL"), "IMAP UID SEARCH").await?;
+        plan.deleted_uids = existing
+            .keys()
+            .filter(|u| !server_uids.contains(u))
+            .copied()
+            .collect();
+    }
+
+    Ok(plan)
+}
+
+/// Collect UIDs from VANISHED responses, which async-imap routes to the unsolicited channel
+fn drain_vanished(session: &mut conn::RawSession) -> Vec<u32> {
+    use async_imap::imap_proto::Response;
+    use async_imap::types::UnsolicitedResponse;
+
+    let mut out = Vec::new();
+    while let Ok(resp) = session.unsolicited_responses.try_recv() {
+        if let UnsolicitedResponse::Other(data) = resp {
+            if let Response::Vanished { uids, .. } = data.parsed() {
+                for range in uids {
+                    out.extend(range.clone());
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Per-folder sync cursor persisted in `folder_sync_state`
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct FolderSyncState {
+    pub uidvalidity: Option<u32>,
+    pub uidnext: Option<u32>,
+    pub highest_modseq: Option<u64>,
+}
+
+pub async fn load_folder_state(pool: &SqlitePool, account_id: &str, folder: &str) -> Result<FolderSyncState> {
+    let row = sqlx::query(
+        "SELECT uidvalidity, uidnext, highest_modseq FROM folder_sync_state WHERE account_id = ? AND folder = ?",
+    )
+    .bind(account_id)
+    .bind(folder)
+    .fetch_optional(pool)
+    .await?;
+    Ok(match row {
+        Some(r) => FolderSyncState {
+            uidvalidity: r.try_get::<Option<i64>, _>(0)?.map(|v| v as u32),
+            uidnext: r.try_get::<Option<i64>, _>(1)?.map(|v| v as u32),
+            highest_modseq: r.try_get::<Option<i64>, _>(2)?.map(|v| v as u64),
+        },
+        None => FolderSyncState::default(),
+    })
+}
+
+/// Persist the SELECT snapshot. `highest_modseq = None` keeps the stored value.
+async fn save_folder_state(
     pool: &SqlitePool,
-    account: &Account,
+    account_id: &str,
     folder: &str,
-    fetch: &Fetch,
-) -> Result<bool> {
-    let uid = fetch.uid.context("Message has no UID")?;
+    mailbox: &async_imap::types::Mailbox,
+    highest_modseq: Option<u64>,
+) -> Result<()> {
+    sqlx::query(
+        r#"INSERT INTO folder_sync_state (account_id, folder, uidvalidity, uidnext, highest_modseq, updated_at)
+           VALUES (?, ?, ?, ?, ?, strftime('%s','now'))
+           ON CONFLICT(account_id, folder) DO UPDATE SET
+               uidvalidity = excluded.uidvalidity,
+               uidnext = excluded.uidnext,
+               highest_modseq = COALESCE(excluded.highest_modseq, folder_sync_state.highest_modseq),
+               updated_at = excluded.updated_at"#,
+    )
+    .bind(account_id)
+    .bind(folder)
+    .bind(mailbox.uid_validity.map(|v| v as i64))
+    .bind(mailbox.uid_next.map(|v| v as i64))
+    .bind(highest_modseq.map(|v| v as i64))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
 
-    // Manually parse headers from the requested body section
-    // async-imap stores BODY[HEADER.FIELDS...] in fetch.body(section_name)? No, it's usually under a specific body index.
-    // However, since we requested 2 BODY items, logic might be complex. 
-    // Actually, `async-imap` exposes `body()` for the *default* body section or we iterate.
-    // Let's use `fetch.header()` if it was fetched as HEADER, or just parse the FULL body if we have it.
-    // We requested `BODY.PEEK[]` (full body). So we can just parse that!
-    // The previous code requested `ENVELOPE` AND `BODY.PEEK[]`.
-    
-    let full_body = fetch.body().unwrap_or(b"");
-    
-    let mut subject = String::new();
-    let mut from = String::new();
-    let mut to = String::new();
-    let mut date = String::new();
-    let mut message_id = String::new();
-
-    if !full_body.is_empty() {
-        if let Some(parsed) = mail_parser::Message::parse(full_body) {
-             subject = parsed.subject().unwrap_or("").to_string();
-             
-             // Handle From
-             match parsed.from() {
-                 mail_parser::HeaderValue::Address(addr) => {
-                     let name = addr.name.as_ref().map(|n| n.as_ref()).unwrap_or("");
-                     let email = addr.address.as_ref().map(|a| a.as_ref()).unwrap_or("");
-                     from = if !name.is_empty() { format!("{} <{}>", name, email) } else { email.to_string() };
-                 }
-                 mail_parser::HeaderValue::AddressList(list) => {
-                     if let Some(addr) = list.first() {
-                         let name = addr.name.as_ref().map(|n| n.as_ref()).unwrap_or("");
-                         let email = addr.address.as_ref().map(|a| a.as_ref()).unwrap_or("");
-                         from = if !name.is_empty() { format!("{} <{}>", name, email) } else { email.to_string() };
-                     }
-                 }
-                 _ => {}
-             }
-
-             // Handle To
-             match parsed.to() {
-                 mail_parser::HeaderValue::Address(addr) => {
-                     to = addr.address.as_ref().map(|a| a.as_ref()).unwrap_or("").to_string();
-                 }
-                 mail_parser::HeaderValue::AddressList(list) => {
-                     if let Some(addr) = list.first() {
-                         to = addr.address.as_ref().map(|a| a.as_ref()).unwrap_or("").to_string();
-                     }
-                 }
-                 _ => {}
-             }
-
-             if let Some(d) = parsed.date() {
-                 date = d.to_rfc3339();
-             }
-             message_id = parsed.message_id().unwrap_or("").to_string();
-        }
+/// Drop every cached row for a folder: messages, their attachments, cached bodies
+/// and the sync cursor. Returns the number of message rows removed.
+pub async fn purge_folder_cache(pool: &SqlitePool, account_id: &str, folder: &str) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "DELETE FROM attachments WHERE message_id IN (SELECT id FROM messages WHERE account_id = ? AND folder = ?)",
+    )
+    .bind(account_id)
+    .bind(folder)
+    .execute(&mut *tx)
+    .await?;
+    let removed = sqlx::query("DELETE FROM messages WHERE account_id = ? AND folder = ?")
+        .bind(account_id)
+        .bind(folder)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query("DELETE FROM message_bodies WHERE account_id = ? AND folder = ?")
+        .bind(account_id)
+        .bind(folder)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM folder_sync_state WHERE account_id = ? AND folder = ?")
+        .bind(account_id)
+        .bind(folder)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(removed)
+}
+
+/// Compare a stored flags snapshot with a fresh one, ignoring order and the
+/// session-scoped `\Recent` flag
+fn flags_differ(stored: Option<&str>, fresh: &str) -> bool {
+    fn normalize(json: &str) -> Vec<String> {
+        let mut v: Vec<String> = serde_json::from_str(json).unwrap_or_default();
+        v.retain(|f| f != "\\Recent");
+        v.sort();
+        v
     }
-    
-    // Fallback if full body parse failed but we have headers (from separate section?)
-    // Note: async-imap mapping of BODY[HEADER...] to struct fields isn't always direct.
-    // But since we fetch BODY.PEEK[], `fetch.body()` returns the full content.
-    // This is safer than `fetch.envelope()` which panics/errors on bad input inside the library.
+    normalize(stored.unwrap_or("[]")) != normalize(fresh)
+}
 
-    // Extract flags
-    let flags: Vec<String> = fetch
-        .flags()
+/// Broadcast a FlagChange event for the UIDs whose flags were just updated
+fn publish_flag_changes(account: &Account, folder: &str, changed: &[(u32, String)]) {
+    const MAX_EVENT_UPDATES: usize = 500;
+    let updates = changed
+        .iter()
+        .take(MAX_EVENT_UPDATES)
+        .map(|(uid, json)| FlagUpdate {
+            uid: *uid,
+            flags: serde_json::from_str(json).unwrap_or_default(),
+        })
+        .collect();
+    idle_watcher_service::publish(IdleEvent {
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        event_type: IdleEventType::FlagChange {
+            folder: folder.to_string(),
+            count: changed.len(),
+            updates,
+        },
+        timestamp: chrono::Utc::now().timestamp(),
+    });
+}
+
+/// Serialize the flags of a FETCH response to the JSON stored in `messages.flags`
+fn flags_to_json(fetch: &Fetch) -> Result<String> {
+    Ok(serde_json::to_string(&flag_names(fetch.flags()))?)
+}
+
+/// System flags by their backslash name and keywords (`$Forwarded`, `Junk`, ...) as sent
+fn flag_names<'a>(flags: impl Iterator<Item = Flag<'a>>) -> Vec<String> {
+    flags
         .filter_map(|f| match f {
             Flag::Seen => Some("\\Seen".to_string()),
             Flag::Answered => Some("\\Answered".to_string()),
@@ -354,17 +599,67 @@ async fn save_message_to_db(
             Flag::Deleted => Some("\\Deleted".to_string()),
             Flag::Draft => Some("\\Draft".to_string()),
             Flag::Recent => Some("\\Recent".to_string()),
-            _ => None,
+            Flag::Custom(k) => Some(k.into_owned()),
+            // Only ever listed in PERMANENTFLAGS
+            Flag::MayCreate => None,
         })
-        .collect();
+        .collect()
+}
+
+/// JSON address list for the `*_json` columns; NULL when no header block was fetched
+fn addr_json(list: &[headers::Address], raw: &[u8]) -> Result<Option<String>> {
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(list)?))
+}
+
+fn addr_text(list: &[headers::Address]) -> Option<String> {
+    (!list.is_empty()).then(|| headers::display_list(list))
+}
+
+fn ids_json(ids: &[String]) -> Result<Option<String>> {
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(ids)?))
+}
+
+/// Save a single message to database
+async fn save_message_to_db(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    fetch: &Fetch,
+) -> Result<bool> {
+    let uid = fetch.uid.context("Message has no UID")?;
 
-    let flags_json = serde_json::to_string(&flags)?;
+    // Parse headers from BODY[HEADER]; fall back to a full body if the caller fetched one.
+    // Parsing the raw header block is safer than `fetch.envelope()` which panics/errors on bad input inside the library.
+    let raw = fetch.header().or_else(|| fetch.body()).unwrap_or(b"");
+    let hdr = headers::parse(raw);
+    let internal_date = fetch.internal_date().map(|d| d.to_rfc3339());
+
+    // Bodies are fetched lazily; store them now only when the caller already has the full message
+    let (body_plain, body_html) = match fetch.body() {
+        Some(full) => {
+            let (text, html) = message_body_service::parse_bodies(full);
+            (Some(text), html)
+        }
+        None => (None, None),
+    };
+
+    // Extract flags
+    let flags_json = flags_to_json(fetch)?;
 
     // Message size
     let size = fetch.size.unwrap_or(0) as i64;
     
-    // Attachments
-    let attachments = extract_attachments_from_raw(full_body);
+    // Attachments (from BODYSTRUCTURE, no body download needed)
+    let attachments = fetch
+        .bodystructure()
+        .map(bodystructure::attachments)
+        .unwrap_or_default();
     let has_atts = !attachments.is_empty();
 
     // Check if exists
@@ -378,11 +673,31 @@ async fn save_message_to_db(
     .await?;
 
     if exists {
-        // Update flags only
+        // Update flags; fill header columns left empty by older syncs when we have the headers
         sqlx::query(
-            "UPDATE messages SET flags = ?, synced_at = datetime('now') WHERE account_id = ? AND folder = ? AND uid = ?",
+            r#"UPDATE messages SET flags = ?, synced_at = datetime('now'),
+                   from_json = COALESCE(from_json, ?), to_json = COALESCE(to_json, ?), cc_json = COALESCE(cc_json, ?),
+                   bcc_json = COALESCE(bcc_json, ?), reply_to_json = COALESCE(reply_to_json, ?),
+                   cc = COALESCE(cc, ?), bcc = COALESCE(bcc, ?), reply_to = COALESCE(reply_to, ?),
+                   in_reply_to = COALESCE(in_reply_to, ?), references_ids = COALESCE(references_ids, ?),
+                   list_id = COALESCE(list_id, ?), auto_submitted = COALESCE(auto_submitted, ?),
+                   internal_date = COALESCE(internal_date, ?)
+               WHERE account_id = ? AND folder = ? AND uid = ?"#,
         )
         .bind(&flags_json)
+        .bind(addr_json(&hdr.from, raw)?)
+        .bind(addr_json(&hdr.to, raw)?)
+        .bind(addr_json(&hdr.cc, raw)?)
+        .bind(addr_json(&hdr.bcc, raw)?)
+        .bind(addr_json(&hdr.reply_to, raw)?)
+        .bind(addr_text(&hdr.cc))
+        .bind(addr_text(&hdr.bcc))
+        .bind(addr_text(&hdr.reply_to))
+        .bind(hdr.in_reply_to.first())
+        .bind(ids_json(&hdr.references)?)
+        .bind(&hdr.list_id)
+        .bind(&hdr.auto_submitted)
+        .bind(&internal_date)
         .bind(&account.id)
         .bind(folder)
         .bind(uid)
@@ -396,23 +711,41 @@ async fn save_message_to_db(
             r#"
             INSERT INTO messages (
                 account_id, folder, uid, message_id,
-                subject, from_addr, to_addr, date,
+                subject, from_addr, to_addr, cc, bcc, reply_to, date,
+                from_json, to_json, cc_json, bcc_json, reply_to_json,
+                in_reply_to, references_ids, list_id, auto_submitted,
+                body_plain, body_html,
                 flags, size, has_attachments,
-                synced_at
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'))
+                internal_date, synced_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'))
             "#,
         )
         .bind(&account.id)
         .bind(folder)
         .bind(uid)
-        .bind(message_id)
-        .bind(&subject)
-        .bind(&from)
-        .bind(&to)
-        .bind(date)
+        .bind(&hdr.message_id)
+        .bind(&hdr.subject)
+        .bind(headers::display_list(&hdr.from))
+        .bind(headers::display_list(&hdr.to))
+        .bind(addr_text(&hdr.cc))
+        .bind(addr_text(&hdr.bcc))
+        .bind(addr_text(&hdr.reply_to))
+        .bind(hdr.date.clone().unwrap_or_default())
+        .bind(addr_json(&hdr.from, raw)?)
+        .bind(addr_json(&hdr.to, raw)?)
+        .bind(addr_json(&hdr.cc, raw)?)
+        .bind(addr_json(&hdr.bcc, raw)?)
+        .bind(addr_json(&hdr.reply_to, raw)?)
+        .bind(hdr.in_reply_to.first())
+        .bind(ids_json(&hdr.references)?)
+        .bind(&hdr.list_id)
+        .bind(&hdr.auto_submitted)
+        .bind(&body_plain)
+        .bind(&body_html)
         .bind(&flags_json)
         .bind(size as i64)
         .bind(has_atts)
+        .bind(&internal_date)
         .execute(pool)
         .await?;
 
@@ -433,73 +766,381 @@ async fn save_message_to_db(
                 .execute(pool)
                 .await?;
 
-            for (filename, content_type, sz, content_id, is_inline) in attachments {
+            for att in attachments {
                 sqlx::query(
                     "INSERT INTO attachments (message_id, filename, content_type, size, content_id, is_inline) VALUES (?, ?, ?, ?, ?, ?)",
                 )
                 .bind(msg_id)
-                .bind(filename)
-                .bind(content_type)
-                .bind(sz)
-                .bind(content_id)
-                .bind(is_inline)
+                .bind(att.filename)
+                .bind(att.content_type)
+                .bind(att.size as i64)
+                .bind(att.content_id)
+                .bind(att.is_inline)
                 .execute(pool)
                 .await?;
             }
         }
 
+        rule_service::notify_new(account, folder, uid, fetch.internal_date());
+
         Ok(true) // New
     }
 }
 
+/// Fetch and cache the headers of `uids` in the selected `folder`, as a sync would.
+/// Returns how many rows were new.
+pub async fn cache_headers(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    session: &mut conn::RawSession,
+    uids: &[u32],
+) -> Result<usize> {
+    use futures::StreamExt;
+
+    let mut inserted = 0;
+    for chunk in uids.chunks(50) {
+        let mut stream = with_timeout(
+            session.uid_fetch(crate::imap::transfer::uid_set(chunk), HEADER_ITEMS),
+            "IMAP UID FETCH",
+        )
+        .await?;
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(fetch) => match save_message_to_db(pool, account, folder, &fetch).await {
+                    Ok(true) => inserted += 1,
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to save message UID {}: {}", fetch.uid.unwrap_or(0), e),
+                },
+                Err(e) => warn!("Failed to parse fetched message in {}: {}", folder, e),
+            }
+        }
+    }
+    Ok(inserted)
+}
+
 /// Sync all folders for an account
 pub async fn sync_account_messages(pool: &SqlitePool, account: &Account) -> Result<Vec<SyncStats>> {
     let mut imap_session = with_timeout(
-        conn::connect(
-            &account.imap_host,
-            account.imap_port,
-            &account.email,
-            &account.password,
-        ),
+        pool::acquire_account(account),
         "IMAP connect",
     )
     .await?;
 
-    let session = &mut imap_session.session;
+    // Refresh the folder catalogue (LIST/LSUB/STATUS) and sync the selectable folders the
+    // account's policy allows, in its priority order
+    let folders = with_timeout(
+        folder_service::refresh_folders(pool, &account.id, &mut imap_session.session),
+        "IMAP folder refresh",
+    )
+    .await?;
+    let policy = sync_policy_service::load(pool, &account.id).await?;
+
+    let stats = if imap_session.caps.gmail {
+        sync_gmail_account(pool, account, &mut imap_session, &folders, &policy).await?
+    } else {
+        let folder_names: Vec<String> = sync_policy_service::select(&policy, &folders)
+            .into_iter()
+            .map(|f| f.name.clone())
+            .collect();
 
-    // List all folders
-    let folders = with_timeout(session.list(None, Some("*")), "IMAP LIST").await?;
+        info!(
+            "Syncing {} folders for {}",
+            folder_names.len(),
+            account.email
+        );
 
-    let mut folder_names: Vec<String> = Vec::new();
-    {
-        use futures::StreamExt;
-        let mut stream = folders;
-        while let Some(name_result) = stream.next().await {
-            if let Ok(name) = name_result {
-                // name.name() returns &str directly, no need for from_utf8
-                folder_names.push(name.name().to_string());
+        let mut stats = Vec::new();
+        for (i, folder) in folder_names.iter().enumerate() {
+            sync_job_service::check_cancelled()?;
+            sync_job_service::report_folder(folder, i, folder_names.len());
+            match sync_folder_messages_with_session(pool, account, folder, &mut imap_session).await {
+                Ok(s) => stats.push(s),
+                Err(e) => {
+                    warn!("Failed to sync folder {}: {}", folder, e);
+                    reconnect(account, &mut imap_session).await?;
+                }
             }
         }
+        stats
+    };
+    sync_job_service::check_cancelled()?;
+
+    // Phase two: download bodies of small/recent messages while the session is still open
+    let max_body_bytes = policy.max_body_bytes.unwrap_or_else(prefetch_max_bytes);
+    match prefetch_bodies(pool, account, &mut imap_session.session, max_body_bytes).await {
+        Ok(n) if n > 0 => info!("Prefetched {} message bodies for {}", n, account.email),
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Body prefetch failed for {}: {}", account.email, e);
+            reconnect(account, &mut imap_session).await?;
+        }
     }
 
-    info!(
-        "Syncing {} folders for {}",
-        folder_names.len(),
-        account.email
-    );
+    // Drafts written or edited by other clients
+    match draft_service::reconcile(pool, account, &mut imap_session).await {
+        Ok(n) if n > 0 => info!("Picked up {} draft(s) from the server for {}", n, account.email),
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Draft reconcile failed for {}: {}", account.email, e);
+            reconnect(account, &mut imap_session).await?;
+        }
+    }
 
-    let mut stats = Vec::new();
+    if imap_session.caps.quota {
+        match quota_service::check(pool, account, &mut imap_session).await {
+            Ok(n) if n > 0 => info!("Raised {} quota alert(s) for {}", n, account.email),
+            Ok(_) => {}
+            Err(e) => {
+                // Closed on drop instead of going back to the pool
+                warn!("Quota check failed for {}: {}", account.email, e);
+                return Ok(stats);
+            }
+        }
+    }
+
+    imap_session.release();
+    Ok(stats)
+}
+
+/// Swap a session a failed step may have left mid-command (unread responses, dead socket)
+/// for a fresh login, so the following steps don't trip over it
+async fn reconnect(account: &Account, imap: &mut pool::PooledSession) -> Result<()> {
+    with_timeout(imap.reconnect(account), "IMAP connect").await
+}
 
-    for folder in &folder_names {
-        match sync_folder_messages_with_session(pool, account, folder, session).await {
+/// Gmail exposes labels as folders, so syncing every folder stores the same message once per label.
+/// Instead sync `\All` once (plus `\Junk`/`\Trash`, which All Mail excludes) and keep the
+/// labels per message; label folders are then served from `message_labels`.
+async fn sync_gmail_account(
+    pool: &SqlitePool,
+    account: &Account,
+    imap: &mut pool::PooledSession,
+    folders: &[folder_service::FolderRecord],
+    policy: &sync_policy_service::SyncPolicy,
+) -> Result<Vec<SyncStats>> {
+    let roles = gmail::role_folders(folders);
+    let all_mail = roles
+        .get("\\All")
+        .cloned()
+        .unwrap_or_else(|| "[Gmail]/All Mail".to_string());
+    let mut targets = vec![all_mail.clone()];
+    for role in ["\\Junk", "\\Trash"] {
+        if let Some(name) = roles.get(role) {
+            targets.push(name.clone());
+        }
+    }
+    // Labels are views of All Mail, so an include list naming them must not drop All Mail
+    let order: Vec<&str> = sync_policy_service::select(policy, folders).iter().map(|f| f.name.as_str()).collect();
+    let excluded = |name: &str| folders.iter().any(|f| f.name == name && !sync_policy_service::allows(policy, f));
+    targets.retain(|t| if *t == all_mail { !excluded(t) } else { order.contains(&t.as_str()) });
+    targets.sort_by_key(|t| order.iter().position(|o| o == t).unwrap_or(order.len()));
+
+    // Rows cached per label folder (before Gmail-aware sync) duplicate All Mail
+    let cached: Vec<String> = sqlx::query_scalar("SELECT DISTINCT folder FROM messages WHERE account_id = ?")
+        .bind(&account.id)
+        .fetch_all(pool)
+        .await?;
+    for folder in cached.iter().filter(|f| !targets.contains(f)) {
+        let removed = purge_folder_cache(pool, &account.id, folder).await?;
+        info!("Gmail: dropped {} duplicate rows cached under {}", removed, folder);
+    }
+
+    info!("Syncing Gmail account {} via {}", account.email, all_mail);
+    let prev_modseq = load_folder_state(pool, &account.id, &all_mail).await?.highest_modseq;
+
+    let mut stats = Vec::new();
+    for (i, folder) in targets.iter().enumerate() {
+        sync_job_service::check_cancelled()?;
+        sync_job_service::report_folder(folder, i, targets.len());
+        match sync_folder_messages_with_session(pool, account, folder, imap).await {
             Ok(s) => stats.push(s),
-            Err(e) => warn!("Failed to sync folder {}: {}", folder, e),
+            Err(e) => {
+                warn!("Failed to sync folder {}: {}", folder, e);
+                reconnect(account, imap).await?;
+            }
+        }
+    }
+
+    match sync_gmail_labels(pool, account, imap, &all_mail, prev_modseq, &roles).await {
+        Ok(n) if n > 0 => info!("Updated Gmail labels of {} messages for {}", n, account.email),
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Gmail label sync failed for {}: {}", account.email, e);
+            reconnect(account, imap).await?;
         }
     }
 
     Ok(stats)
 }
 
+/// Store X-GM-MSGID/X-GM-THRID/X-GM-LABELS for new All Mail rows and, under CONDSTORE,
+/// for rows changed since `prev_modseq` (label edits bump MODSEQ). Returns rows updated.
+async fn sync_gmail_labels(
+    pool: &SqlitePool,
+    account: &Account,
+    imap: &mut conn::ImapSession,
+    all_mail: &str,
+    prev_modseq: Option<u64>,
+    roles: &HashMap<String, String>,
+) -> Result<usize> {
+    use futures::StreamExt;
+
+    let session = &mut imap.session;
+    with_timeout(session.select(all_mail), "IMAP SELECT").await?;
+    conn::clear_unsolicited(session);
+
+    let missing: Vec<i64> = sqlx::query_scalar(
+        "SELECT uid FROM messages WHERE account_id = ? AND folder = ? AND gm_msgid IS NULL",
+    )
+    .bind(&account.id)
+    .bind(all_mail)
+    .fetch_all(pool)
+    .await?;
+    let mut uids: HashSet<u32> = missing.into_iter().map(|u| u as u32).collect();
+
+    if let (true, Some(since)) = (imap.caps.condstore, prev_modseq) {
+        let mut stream = with_timeout(
+            session.uid_fetch("1:*", format!("(UID) (CHANGEDSINCE {})", since)),
+            "IMAP UID FETCH CHANGEDSINCE",
+        )
+        .await?;
+        while let Some(item) = stream.next().await {
+            if let Ok(f) = item {
+                if let Some(uid) = f.uid {
+                    uids.insert(uid);
+                }
+            }
+        }
+        drop(stream);
+        conn::clear_unsolicited(session);
+    }
+
+    let mut uids: Vec<u32> = uids.into_iter().collect();
+    uids.sort_unstable();
+
+    let mut updated = 0usize;
+    for chunk in uids.chunks(gmail::META_CHUNK) {
+        let metas = with_timeout(gmail::fetch_meta(session, chunk), "IMAP UID FETCH X-GM").await?;
+        let mut tx = pool.begin().await?;
+        for meta in metas {
+            let id: Option<i64> = sqlx::query_scalar(
+                "SELECT id FROM messages WHERE account_id = ? AND folder = ? AND uid = ?",
+            )
+            .bind(&account.id)
+            .bind(all_mail)
+            .bind(meta.uid as i64)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some(id) = id else { continue };
+
+            sqlx::query("UPDATE messages SET gm_msgid = ?, gm_thrid = ?, gm_labels = ? WHERE id = ?")
+                .bind(meta.msgid.map(|v| v as i64))
+                .bind(meta.thrid.map(|v| v as i64))
+                .bind(serde_json::to_string(&meta.labels)?)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM message_labels WHERE message_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            for folder in meta.labels.iter().filter_map(|l| gmail::label_folder(l, roles)) {
+                sqlx::query("INSERT OR IGNORE INTO message_labels (message_id, folder) VALUES (?, ?)")
+                    .bind(id)
+                    .bind(folder)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            updated += 1;
+        }
+        tx.commit().await?;
+    }
+    Ok(updated)
+}
+
+/// Bodies larger than this are only downloaded on demand (0 disables prefetch)
+fn prefetch_max_bytes() -> i64 {
+    std::env::var("MAILORA_BODY_PREFETCH_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(256 * 1024)
+}
+
+/// Only messages dated within the last N days are prefetched
+fn prefetch_max_age_days() -> i64 {
+    std::env::var("MAILORA_BODY_PREFETCH_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30)
+}
+
+/// Download and store bodies for messages synced header-only, up to `max_bytes` and within
+/// the age limit. Returns the number of bodies stored.
+pub async fn prefetch_bodies(
+    pool: &SqlitePool,
+    account: &Account,
+    session: &mut conn::RawSession,
+    max_bytes: i64,
+) -> Result<usize> {
+    use futures::StreamExt;
+
+    if max_bytes <= 0 {
+        return Ok(0);
+    }
+    let cutoff = format!("-{} days", prefetch_max_age_days());
+
+    let rows = sqlx::query(
+        "SELECT folder, uid FROM messages WHERE account_id = ? AND body_plain IS NULL AND size <= ? AND julianday(date) >= julianday('now', ?) ORDER BY date DESC LIMIT 200",
+    )
+    .bind(&account.id)
+    .bind(max_bytes)
+    .bind(&cutoff)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_folder: HashMap<String, Vec<u32>> = HashMap::new();
+    for r in &rows {
+        let folder: String = r.try_get("folder")?;
+        let uid: i64 = r.try_get("uid")?;
+        by_folder.entry(folder).or_default().push(uid as u32);
+    }
+
+    let mut stored = 0usize;
+    for (folder, uids) in by_folder {
+        if let Err(e) = with_timeout(session.select(&folder), "IMAP SELECT").await {
+            warn!("Prefetch: cannot select {}: {}", folder, e);
+            continue;
+        }
+        conn::clear_unsolicited(session);
+        for chunk in uids.chunks(20) {
+            let uid_set = chunk.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
+            let mut stream = match with_timeout(session.uid_fetch(&uid_set, "(UID BODY.PEEK[])"), "IMAP UID FETCH").await {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Prefetch fetch failed in {}: {}", folder, e);
+                    continue;
+                }
+            };
+            let mut bodies = Vec::new();
+            while let Some(item) = stream.next().await {
+                if let Ok(f) = item {
+                    if let (Some(uid), Some(raw)) = (f.uid, f.body()) {
+                        bodies.push((uid, message_body_service::parse_bodies(raw)));
+                    }
+                }
+            }
+            drop(stream);
+            conn::clear_unsolicited(session);
+            for (uid, (text, html)) in bodies {
+                message_body_service::store_message_body(pool, &account.id, &folder, uid, &text, html.as_deref()).await?;
+                stored += 1;
+            }
+        }
+    }
+    Ok(stored)
+}
+
 /// Upsert a minimal message row for Sent APPEND results
 pub async fn upsert_sent_message(
     pool: &SqlitePool,
@@ -556,26 +1197,28 @@ pub async fn quick_sync_sent_and_upsert(
     use futures::StreamExt;
 
     let mut imap = with_timeout(
-        conn::connect(
-            &account.imap_host,
-            account.imap_port,
-            &account.email,
-            &account.password,
-        ),
+        pool::acquire_account(account),
         "IMAP connect",
     )
     .await
     .context("Failed to connect to IMAP")?;
 
+    let gmail_ext = imap.caps.gmail;
     let session = &mut imap.session;
 
     // Build Sent candidates
     let mut candidates: Vec<String> = Vec::new();
+    let mut sent_role: Option<String> = folder_service::folder_by_role(pool, &account.id, "\\Sent")
+        .await
+        .unwrap_or(None);
     if let Ok(list_stream) = with_timeout(session.list(None, Some("*")), "IMAP LIST").await {
         let mut names = Vec::new();
         let mut s = list_stream;
         while let Some(item) = s.next().await {
             if let Ok(m) = item {
+                if sent_role.is_none() && crate::imap::folders::special_use(m.attributes()) == Some("\\Sent") {
+                    sent_role = Some(m.name().to_string());
+                }
                 names.push(m.name().to_string());
             }
         }
@@ -597,10 +1240,17 @@ pub async fn quick_sync_sent_and_upsert(
                 candidates.insert(0, f);
             }
         }
-        // Also consider All Mail for Gmail, sometimes visible before Sent label indexing
-        if !candidates.iter().any(|f| f == "[Gmail]/All Mail") {
-            candidates.insert(1.min(candidates.len()), "[Gmail]/All Mail".into());
+        // Also consider All Mail for Gmail, sometimes visible before Sent label indexing.
+        // With X-GM-EXT-1 the cache only holds All Mail rows, so look there first.
+        let all_mail_pos = if gmail_ext { 0 } else { 1.min(candidates.len()) };
+        if let Some(pos) = candidates.iter().position(|f| f == "[Gmail]/All Mail") {
+            candidates.remove(pos);
         }
+        candidates.insert(all_mail_pos.min(candidates.len()), "[Gmail]/All Mail".into());
+    }
+    // The RFC 6154 \Sent folder beats any name-based guess
+    if let Some(sent) = sent_role.filter(|_| !gmail_ext) {
+        crate::imap::folders::promote(&mut candidates, &sent);
     }
 
     let target = message_id.trim_matches(['<', '>']);
@@ -630,7 +1280,6 @@ pub async fn quick_sync_sent_and_upsert(
                     if let Some(uid) = uids.iter().copied().max() {
                         let _ = with_timeout(session.uid_store(&uid.to_string(), "+FLAGS (\\Seen)"), "IMAP UID STORE").await;
                         upsert_sent_message(pool, account, &folder, uid, subject, to).await?;
-                        let _ = session.logout().await;
                         return Ok(Some((folder, uid)));
                     }
                 }
@@ -642,7 +1291,6 @@ pub async fn quick_sync_sent_and_upsert(
                 if let Some(uid) = uids.iter().copied().max() {
                     let _ = with_timeout(session.uid_store(&uid.to_string(), "+FLAGS (\\Seen)"), "IMAP UID STORE").await;
                     upsert_sent_message(pool, account, &folder, uid, subject, to).await?;
-                    let _ = session.logout().await;
                     return Ok(Some((folder, uid)));
                 }
             }
@@ -707,13 +1355,11 @@ pub async fn quick_sync_sent_and_upsert(
             if let Some(uid) = found {
                 let _ = with_timeout(session.uid_store(&uid.to_string(), "+FLAGS (\\Seen)"), "IMAP UID STORE").await;
                 upsert_sent_message(pool, account, &folder, uid, subject, to).await?;
-                let _ = session.logout().await;
                 return Ok(Some((folder, uid)));
             }
         }
     }
 
-    let _ = session.logout().await;
     Ok(None)
 }
 
@@ -727,12 +1373,7 @@ pub async fn backfill_attachments(
     use futures::StreamExt;
     // Connect once
     let mut imap_session = with_timeout(
-        conn::connect(
-            &account.imap_host,
-            account.imap_port,
-            &account.email,
-            &account.password,
-        ),
+        pool::acquire_account(account),
         "IMAP connect",
     )
     .await?;
@@ -796,7 +1437,7 @@ pub async fn backfill_attachments(
                 .join(",");
 
             let fetches = match with_timeout(
-                session.uid_fetch(&uid_set, "(UID BODY.PEEK[])"),
+                session.uid_fetch(&uid_set, "(UID BODYSTRUCTURE)"),
                 "UID FETCH",
             )
             .await
@@ -811,8 +1452,8 @@ pub async fn backfill_attachments(
                     if let Some(uid) = f.uid {
                         scanned += 1;
                         total_scanned += 1;
-                        if let Some(body) = f.body() {
-                            let atts = extract_attachments_from_raw(body);
+                        if let Some(bs) = f.bodystructure() {
+                            let atts = bodystructure::attachments(bs);
                             if !atts.is_empty() {
                                 // persist
                                 if let Some((msg_id, _)) =
@@ -826,16 +1467,14 @@ pub async fn backfill_attachments(
                                     .execute(pool)
                                     .await;
 
-                                    for (filename, content_type, sz, content_id, is_inline) in
-                                        atts.into_iter()
-                                    {
+                                    for att in atts.into_iter() {
                                         let _ = sqlx::query("INSERT INTO attachments (message_id, filename, content_type, size, content_id, is_inline) VALUES (?, ?, ?, ?, ?, ?)")
                                             .bind(msg_id)
-                                            .bind(filename)
-                                            .bind(content_type)
-                                            .bind(sz)
-                                            .bind(content_id)
-                                            .bind(is_inline)
+                                            .bind(att.filename)
+                                            .bind(att.content_type)
+                                            .bind(att.size as i64)
+                                            .bind(att.content_id)
+                                            .bind(att.is_inline)
                                             .execute(pool)
                                             .await;
                                     }
@@ -863,7 +1502,6 @@ pub async fn backfill_attachments(
         });
     }
     // Logout
-    let _ = session.logout().await;
 
     Ok(BackfillStats {
         account_id: account.id.clone(),
@@ -875,3 +1513,17 @@ pub async fn backfill_attachments(
 
 #[allow(dead_code)]
 pub async fn update_last_sync_placeholder() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_survive_flag_reconciliation() {
+        let fetched = [Flag::Seen, Flag::Custom("$Forwarded".into()), Flag::Recent];
+        let fresh = serde_json::to_string(&flag_names(fetched.into_iter())).unwrap();
+        assert_eq!(fresh, r#"["\\Seen","$Forwarded","\\Recent"]"#);
+        assert!(!flags_differ(Some(r#"["$Forwarded","\\Seen"]"#), &fresh));
+        assert!(flags_differ(Some(r#"["\\Seen"]"#), &fresh));
+    }
+}
@@ -1,5 +1,9 @@
 pub mod account_service;
+pub mod bulk_service;
 pub mod outbox_service;
+pub mod pending_op_service;
+pub mod quota_service;
+pub mod draft_service;
 pub mod message_sync_service;
 pub mod message_body_service;
 pub mod diff_service;
@@ -8,9 +12,16 @@ pub mod imap_test_service;
 pub mod event_stream;
 pub mod maintenance_service;
 pub mod auth_service;
+pub mod mail_auth_service;
 pub mod scheduler;
+pub mod sync_job_service;
+pub mod sync_policy_service;
 pub mod message_service;
 pub mod idle_watcher_service;
+pub mod folder_service;
+pub mod rule_service;
+pub mod search_service;
+pub mod sieve_service;
 pub mod contact_service;
 pub mod carddav_service;
 pub mod caldav_service;
@@ -1,5 +1,5 @@
-use crate::models::{account::Account, outbox::OutboxEmail};
-use crate::services::account_service;
+use crate::models::{account::Account, draft::Draft, outbox::OutboxEmail};
+use crate::services::{account_service, draft_service};
 use sqlx::SqlitePool;
 use std::time::Duration;
 use tokio::time::sleep;
@@ -28,6 +28,25 @@ pub async fn queue_email(
     Ok(id)
 }
 
+/// Queue a draft; the outbox removes it (locally and from \Drafts) once it is sent
+pub async fn queue_draft(pool: &SqlitePool, draft: &Draft) -> Result<String, String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO outbox (id, account_id, to_addr, subject, body, draft_id) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&draft.account_id)
+    .bind(&draft.to_addr)
+    .bind(&draft.subject)
+    .bind(&draft.body)
+    .bind(&draft.id)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
 /// Background loop to process outbox
 pub async fn start_outbox_loop(pool: SqlitePool) {
     tracing::info!("Starting Outbox Service loop...");
@@ -42,7 +61,7 @@ pub async fn start_outbox_loop(pool: SqlitePool) {
 async fn process_batch(pool: &SqlitePool) -> Result<(), anyhow::Error> {
     // Select queued or failed (with retries < 3)
     let emails = sqlx::query_as::<_, OutboxEmail>(
-        "SELECT id, account_id, to_addr, subject, body, status, retries, last_error, 
+        "SELECT id, account_id, to_addr, subject, body, status, retries, last_error, draft_id,
          strftime('%s', created_at) as created_at, strftime('%s', updated_at) as updated_at
          FROM outbox 
          WHERE status = 'queued' OR (status = 'failed' AND retries < 3)
@@ -76,6 +95,9 @@ async fn process_batch(pool: &SqlitePool) -> Result<(), anyhow::Error> {
                             .bind(&email.id)
                             .execute(pool)
                             .await?;
+                        if let Some(draft_id) = email.draft_id.as_deref() {
+                            draft_service::on_sent(pool, draft_id).await;
+                        }
                     }
                     Err(e) => {
                         tracing::error!("Outbox: Failed to send {}: {}", email.id, e);
@@ -86,6 +108,10 @@ async fn process_batch(pool: &SqlitePool) -> Result<(), anyhow::Error> {
                         .bind(&email.id)
                         .execute(pool)
                         .await?;
+                        // Out of retries: give the draft back to the user
+                        if let (Some(draft_id), true) = (email.draft_id.as_deref(), email.retries + 1 >= 3) {
+                            draft_service::on_send_failed(pool, draft_id, &e.to_string()).await;
+                        }
                     }
                 }
             }
@@ -103,37 +129,24 @@ async fn process_batch(pool: &SqlitePool) -> Result<(), anyhow::Error> {
 }
 
 async fn send_via_smtp(account: &Account, to: &str, subject: &str, body: &str) -> Result<(), anyhow::Error> {
-    use lettre::{
-        transport::smtp::authentication::Credentials,
-        transport::smtp::client::Tls,
-        AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
-    };
-
-    let creds = Credentials::new(account.email.clone(), account.password.clone());
-    
-    // Tls logic (simplified from action.rs)
-    let tls = if account.smtp_port == 465 {
-        Tls::Wrapper(lettre::transport::smtp::client::TlsParameters::new(
-            account.smtp_host.clone(),
-        )?)
-    } else {
-        Tls::Required(lettre::transport::smtp::client::TlsParameters::new(
-            account.smtp_host.clone(),
-        )?)
-    };
-
-    let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(&account.smtp_host)?
-        .credentials(creds)
-        .port(account.smtp_port)
-        .tls(tls)
-        .build();
-
-    let email = Message::builder()
+    let email = lettre::Message::builder()
         .from(account.email.parse()?)
         .to(to.parse()?)
         .subject(subject)
         .body(body.to_string())?;
 
-    mailer.send(email).await?;
-    Ok(())
+    // Blocking transport: the shared sender applies the account's security mode and certificate pin
+    let auth = crate::services::mail_auth_service::for_account(account).await?;
+    let account = account.clone();
+    tokio::task::spawn_blocking(move || {
+        crate::smtp::send_prebuilt(
+            &account.id,
+            &account.smtp_host,
+            account.smtp_port,
+            account.smtp_security_mode(),
+            &auth,
+            &email,
+        )
+    })
+    .await?
 }
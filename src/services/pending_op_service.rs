@@ -0,0 +1,529 @@
+/// Offline-first message changes: when the server can't be reached (or the account already has
+/// changes waiting), a flag change, move or delete is applied to the cache right away and
+/// recorded in `pending_ops`, and a worker replays it to IMAP later, in the order the changes
+/// were made. A queued copy shows up in its destination with the sync after its replay.
+///
+/// A queued move re-keys the cached row to the destination folder under a provisional UID
+/// ([`LOCAL_UID_BASE`] + op id) until the replay learns the real one. While an op is pending,
+/// the sync leaves the UIDs it holds alone, so it doesn't undo the local change.
+///
+/// Replay conflicts: a message the server no longer has puts its op in `conflict` (a delete of
+/// one is simply done) and drops the cached row. Ops the server keeps refusing end up `failed`
+/// after `MAILORA_PENDING_OPS_MAX_ATTEMPTS` (default 5) tries with backoff, and the folder is
+/// resynced so the cache shows the server's state again. Unreachable servers don't use up
+/// attempts.
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::imap::conn::quote;
+use crate::imap::pool;
+use crate::imap::transfer::{self, Transfer};
+use crate::models::account::{Account, EmailProvider};
+use crate::models::pending_op::{Mutation, PendingOp};
+use crate::services::sync_job_service::{self, JobPriority};
+use crate::services::{account_service, folder_service, message_service};
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_FAILED: &str = "failed";
+pub const STATUS_CONFLICT: &str = "conflict";
+
+/// Provisional UIDs sit above anything servers hand out in practice (UIDs count up from 1)
+pub const LOCAL_UID_BASE: i64 = 4_000_000_000;
+
+const SELECT: &str = "SELECT id, account_id, folder, uid, mutation, dest, local_uid, status, attempts, next_attempt_at, last_error, created_at FROM pending_ops";
+
+/// Retry delay while the server is unreachable
+const OFFLINE_RETRY_SECS: i64 = 60;
+
+static WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+fn max_attempts() -> i64 {
+    std::env::var("MAILORA_PENDING_OPS_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(5)
+}
+
+/// 30s after the first refusal, doubling up to an hour
+fn backoff_secs(attempts: i64) -> i64 {
+    (30i64 << (attempts - 1).clamp(0, 7)).min(3600)
+}
+
+/// Whether `e` means the server couldn't be reached (as opposed to refusing the command)
+pub fn is_offline(e: &anyhow::Error) -> bool {
+    let msg = e.to_string();
+    msg.contains("connect timeout")
+        || msg.contains("connection closed")
+        || e.chain().any(|c| {
+            c.is::<std::io::Error>()
+                || c.is::<tokio::time::error::Elapsed>()
+                || c.downcast_ref::<reqwest::Error>().is_some_and(|r| r.is_connect() || r.is_timeout())
+                || matches!(
+                    c.downcast_ref::<async_imap::error::Error>(),
+                    Some(async_imap::error::Error::Io(_) | async_imap::error::Error::ConnectionLost)
+                )
+        })
+}
+
+/// Whether moves, copies and deletes can be queued; not on Gmail, where they relabel All Mail rows
+pub fn queues_transfers(account: &Account) -> bool {
+    account.provider != EmailProvider::Gmail
+}
+
+/// Whether a change to `uids` must be queued rather than sent: the account has changes waiting
+/// for the server, or a UID is provisional. A provisional UID whose move is no longer pending
+/// never existed on the server and is refused.
+pub async fn must_queue(pool: &SqlitePool, account_id: &str, folder: &str, uids: &[u32]) -> Result<bool> {
+    for &uid in uids.iter().filter(|u| i64::from(**u) >= LOCAL_UID_BASE) {
+        let moving: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM pending_ops WHERE account_id = ? AND dest = ? AND local_uid = ? AND status = ?",
+        )
+        .bind(account_id)
+        .bind(folder)
+        .bind(uid)
+        .bind(STATUS_PENDING)
+        .fetch_one(pool)
+        .await?;
+        if !moving {
+            anyhow::bail!("UID {} in {} is provisional and its move did not reach the server; reload the folder", uid, folder);
+        }
+    }
+    has_pending(pool, account_id).await
+}
+
+/// Changes of the account still waiting for the server; new ones must queue behind them
+pub async fn has_pending(pool: &SqlitePool, account_id: &str) -> Result<bool> {
+    let n: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_ops WHERE account_id = ? AND status = ?")
+        .bind(account_id)
+        .bind(STATUS_PENDING)
+        .fetch_one(pool)
+        .await?;
+    Ok(n > 0)
+}
+
+async fn insert(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    account_id: &str,
+    folder: &str,
+    uid: u32,
+    mutation: &Mutation,
+) -> Result<i64> {
+    let dest = match mutation {
+        Mutation::Move { to } => Some(to.as_str()),
+        _ => None,
+    };
+    let id = sqlx::query("INSERT INTO pending_ops (account_id, folder, uid, mutation, dest) VALUES (?, ?, ?, ?, ?)")
+        .bind(account_id)
+        .bind(folder)
+        .bind(uid)
+        .bind(serde_json::to_string(mutation)?)
+        .bind(dest)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+/// Queue a flag change and apply it to the cached flags
+pub async fn queue_flags(pool: &SqlitePool, account_id: &str, folder: &str, uid: u32, add: &[&str], remove: &[&str]) -> Result<()> {
+    let mutation = Mutation::Flags {
+        add: add.iter().map(|f| f.to_string()).collect(),
+        remove: remove.iter().map(|f| f.to_string()).collect(),
+    };
+    let mut tx = pool.begin().await?;
+    insert(&mut tx, account_id, folder, uid, &mutation).await?;
+    tx.commit().await?;
+    message_service::update_cached_flags(pool, account_id, folder, &[uid], add, remove).await?;
+    WAKE.notify_one();
+    Ok(())
+}
+
+/// Queue a move and move the cached row to `to`. Returns its provisional UID there.
+pub async fn queue_move(pool: &SqlitePool, account_id: &str, folder: &str, uid: u32, to: &str) -> Result<u32> {
+    let mut tx = pool.begin().await?;
+    let id = insert(&mut tx, account_id, folder, uid, &Mutation::Move { to: to.to_string() }).await?;
+    let local_uid = LOCAL_UID_BASE + id;
+    sqlx::query("UPDATE pending_ops SET local_uid = ? WHERE id = ?")
+        .bind(local_uid)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    for table in ["messages", "message_bodies"] {
+        sqlx::query(&format!("UPDATE OR REPLACE {table} SET folder = ?, uid = ? WHERE account_id = ? AND folder = ? AND uid = ?"))
+            .bind(to)
+            .bind(local_uid)
+            .bind(account_id)
+            .bind(folder)
+            .bind(uid)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    WAKE.notify_one();
+    Ok(local_uid as u32)
+}
+
+/// Queue a copy; the cache learns about it from the sync of `to` after the replay
+pub async fn queue_copy(pool: &SqlitePool, account_id: &str, folder: &str, uid: u32, to: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    insert(&mut tx, account_id, folder, uid, &Mutation::Copy { to: to.to_string() }).await?;
+    tx.commit().await?;
+    WAKE.notify_one();
+    Ok(())
+}
+
+/// Queue a delete: a move to Trash, or the removal from Trash when the message already is there.
+/// Returns the Trash folder and, for a move, the provisional UID there.
+pub async fn queue_delete(pool: &SqlitePool, account: &Account, folder: &str, uid: u32) -> Result<(String, Option<u32>)> {
+    let trash = folder_service::folder_by_role(pool, &account.id, "\\Trash")
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account has no \\Trash folder; messages are only removed through Trash"))?;
+    if folder == trash {
+        queue_expunge(pool, &account.id, folder, uid).await?;
+        return Ok((trash, None));
+    }
+    let local_uid = queue_move(pool, &account.id, folder, uid, &trash).await?;
+    Ok((trash, Some(local_uid)))
+}
+
+/// Queue the removal of a message from Trash and drop it from the cache
+pub async fn queue_expunge(pool: &SqlitePool, account_id: &str, folder: &str, uid: u32) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    insert(&mut tx, account_id, folder, uid, &Mutation::Expunge).await?;
+    tx.commit().await?;
+    message_service::delete_rows(pool, account_id, folder, &[uid]).await?;
+    WAKE.notify_one();
+    Ok(())
+}
+
+/// UIDs in `folder` the sync must not touch: messages with a pending change and moved rows
+/// waiting for their real UID
+pub async fn held_uids(pool: &SqlitePool, account_id: &str, folder: &str) -> Result<HashSet<u32>> {
+    let uids: Vec<i64> = sqlx::query_scalar(
+        r#"SELECT uid FROM pending_ops WHERE account_id = ? AND folder = ? AND status = ?
+           UNION SELECT local_uid FROM pending_ops WHERE account_id = ? AND dest = ? AND status = ? AND local_uid IS NOT NULL"#,
+    )
+    .bind(account_id)
+    .bind(folder)
+    .bind(STATUS_PENDING)
+    .bind(account_id)
+    .bind(folder)
+    .bind(STATUS_PENDING)
+    .fetch_all(pool)
+    .await?;
+    Ok(uids.into_iter().map(|u| u as u32).collect())
+}
+
+pub async fn list(pool: &SqlitePool, account_id: Option<&str>, status: Option<&str>, limit: i64) -> Result<Vec<PendingOp>> {
+    let ops = sqlx::query_as::<_, PendingOp>(&format!(
+        "{SELECT} WHERE (?1 IS NULL OR account_id = ?1) AND (?2 IS NULL OR status = ?2) ORDER BY id LIMIT ?3"
+    ))
+    .bind(account_id)
+    .bind(status)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(ops)
+}
+
+pub async fn get(pool: &SqlitePool, id: i64) -> Result<Option<PendingOp>> {
+    Ok(sqlx::query_as::<_, PendingOp>(&format!("{SELECT} WHERE id = ?")).bind(id).fetch_optional(pool).await?)
+}
+
+/// Put a failed or conflicting op back in the queue with fresh attempts
+pub async fn retry(pool: &SqlitePool, id: i64) -> Result<bool> {
+    let res = sqlx::query(
+        "UPDATE pending_ops SET status = ?, attempts = 0, next_attempt_at = strftime('%s','now'), last_error = NULL WHERE id = ? AND status != ?",
+    )
+    .bind(STATUS_PENDING)
+    .bind(id)
+    .bind(STATUS_PENDING)
+    .execute(pool)
+    .await?;
+    WAKE.notify_one();
+    Ok(res.rows_affected() > 0)
+}
+
+/// Drop an op without replaying it; the folders involved are resynced to undo its local effect
+pub async fn discard(pool: &SqlitePool, id: i64) -> Result<bool> {
+    let Some(op) = get(pool, id).await? else { return Ok(false) };
+    sqlx::query("DELETE FROM pending_ops WHERE id = ?").bind(id).execute(pool).await?;
+    if let (Some(dest), Some(local_uid)) = (&op.dest, op.local_uid) {
+        message_service::delete_rows(pool, &op.account_id, dest, &[local_uid as u32]).await?;
+    }
+    resync(pool, &op.account_id, &op.folder).await?;
+    Ok(true)
+}
+
+/// Full rescan of `folder` on its next sync (CONDSTORE would skip what the cache missed)
+async fn resync(pool: &SqlitePool, account_id: &str, folder: &str) -> Result<()> {
+    sqlx::query("UPDATE folder_sync_state SET highest_modseq = NULL WHERE account_id = ? AND folder = ?")
+        .bind(account_id)
+        .bind(folder)
+        .execute(pool)
+        .await?;
+    sync_job_service::enqueue(account_id, Some(folder), JobPriority::Scheduled).await;
+    Ok(())
+}
+
+/// Start the replay worker; it runs when changes are queued and every 30 seconds
+pub async fn init(pool: &SqlitePool) -> Result<()> {
+    let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_ops WHERE status = ?")
+        .bind(STATUS_PENDING)
+        .fetch_one(pool)
+        .await?;
+    if pending > 0 {
+        info!("{} pending message change(s) to replay", pending);
+    }
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = replay_due(&pool).await {
+                warn!("pending op replay failed: {e}");
+            }
+            let _ = tokio::time::timeout(Duration::from_secs(30), WAKE.notified()).await;
+        }
+    });
+    Ok(())
+}
+
+enum Replayed {
+    Done,
+    /// Moved; the new UID when the server reported it or it was found by Message-ID
+    Moved(Option<u32>),
+    /// Copied; the destination needs a sync to show it
+    Copied(String),
+    /// The server no longer has the message
+    Gone,
+}
+
+async fn replay_due(pool: &SqlitePool) -> Result<()> {
+    let ops = sqlx::query_as::<_, PendingOp>(&format!("{SELECT} WHERE status = ? ORDER BY id"))
+        .bind(STATUS_PENDING)
+        .fetch_all(pool)
+        .await?;
+    let now = chrono::Utc::now().timestamp();
+    // An account stops at its first op that isn't due or doesn't go through, so its changes
+    // reach the server in the order they were made
+    let mut blocked: HashSet<String> = HashSet::new();
+    for op in ops {
+        if blocked.contains(&op.account_id) {
+            continue;
+        }
+        if op.next_attempt_at > now {
+            blocked.insert(op.account_id.clone());
+            continue;
+        }
+        // Deleting the account cascades to its ops
+        let Some(account) = account_service::get_account(pool, &op.account_id).await? else { continue };
+
+        match replay(pool, &account, &op).await {
+            Ok(Replayed::Done) => {
+                sqlx::query("DELETE FROM pending_ops WHERE id = ?").bind(op.id).execute(pool).await?;
+            }
+            Ok(Replayed::Moved(new_uid)) => settle_move(pool, &op, new_uid).await?,
+            Ok(Replayed::Copied(to)) => {
+                sqlx::query("DELETE FROM pending_ops WHERE id = ?").bind(op.id).execute(pool).await?;
+                sync_job_service::enqueue(&op.account_id, Some(&to), JobPriority::Scheduled).await;
+            }
+            Ok(Replayed::Gone) => give_up(pool, &op, STATUS_CONFLICT, "the message is no longer on the server").await?,
+            Err(e) if is_offline(&e) => {
+                blocked.insert(op.account_id.clone());
+                sqlx::query("UPDATE pending_ops SET next_attempt_at = ?, last_error = ? WHERE id = ?")
+                    .bind(now + OFFLINE_RETRY_SECS)
+                    .bind(e.to_string())
+                    .bind(op.id)
+                    .execute(pool)
+                    .await?;
+            }
+            Err(e) => {
+                let attempts = op.attempts + 1;
+                warn!(account_id = %op.account_id, op_id = op.id, "replaying change failed (attempt {}): {}", attempts, e);
+                if attempts >= max_attempts() {
+                    give_up(pool, &op, STATUS_FAILED, &e.to_string()).await?;
+                    continue;
+                }
+                blocked.insert(op.account_id.clone());
+                sqlx::query("UPDATE pending_ops SET attempts = ?, next_attempt_at = ?, last_error = ? WHERE id = ?")
+                    .bind(attempts)
+                    .bind(now + backoff_secs(attempts))
+                    .bind(e.to_string())
+                    .bind(op.id)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn replay(pool: &SqlitePool, account: &Account, op: &PendingOp) -> Result<Replayed> {
+    if op.uid >= LOCAL_UID_BASE {
+        // Its move never got a real UID (see `settle_move`)
+        return Ok(Replayed::Gone);
+    }
+    let uid = op.uid as u32;
+    let message_id: Option<String> = match (&op.dest, op.local_uid) {
+        (Some(dest), Some(local_uid)) => {
+            sqlx::query_scalar("SELECT message_id FROM messages WHERE account_id = ? AND folder = ? AND uid = ?")
+                .bind(&op.account_id)
+                .bind(dest)
+                .bind(local_uid)
+                .fetch_optional(pool)
+                .await?
+                .flatten()
+        }
+        _ => None,
+    };
+
+    let mut imap = pool::acquire_account(account).await?;
+    let res = async {
+        imap.session.select(&op.folder).await?;
+        if !imap.session.uid_search(format!("UID {}", uid)).await?.contains(&uid) {
+            // Already expunged elsewhere is what a delete wanted anyway
+            return Ok(if op.mutation == Mutation::Expunge { Replayed::Done } else { Replayed::Gone });
+        }
+        match &op.mutation {
+            Mutation::Flags { add, remove } => {
+                for (sign, flags) in [("+", add), ("-", remove)] {
+                    if !flags.is_empty() {
+                        transfer::store(&mut imap.session, &[uid], &format!("{}FLAGS.SILENT ({})", sign, flags.join(" "))).await?;
+                    }
+                }
+                Ok(Replayed::Done)
+            }
+            Mutation::Move { to } => {
+                let copied = transfer::transfer(&mut imap, &[uid], to, Transfer::Move).await?;
+                let mut new_uid = copied.and_then(|c| c.uids.first().map(|(_, d)| *d));
+                if let (None, Some(mid)) = (new_uid, message_id.as_deref()) {
+                    imap.session.select(to).await?;
+                    new_uid = imap.session.uid_search(format!("HEADER Message-ID {}", quote(mid))).await?.into_iter().max();
+                }
+                Ok(Replayed::Moved(new_uid))
+            }
+            Mutation::Copy { to } => {
+                transfer::transfer(&mut imap, &[uid], to, Transfer::Copy).await?;
+                Ok(Replayed::Copied(to.clone()))
+            }
+            Mutation::Expunge => {
+                transfer::expunge_uids(&mut imap, &[uid]).await?;
+                Ok(Replayed::Done)
+            }
+        }
+    }
+    .await;
+    if res.is_ok() {
+        imap.release();
+    }
+    res
+}
+
+/// Give the moved row its real UID, and the ops queued against the provisional one with it.
+/// Without a UID the row is dropped and the destination synced instead.
+async fn settle_move(pool: &SqlitePool, op: &PendingOp, new_uid: Option<u32>) -> Result<()> {
+    let (Some(dest), Some(local_uid)) = (op.dest.as_deref(), op.local_uid) else {
+        sqlx::query("DELETE FROM pending_ops WHERE id = ?").bind(op.id).execute(pool).await?;
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM pending_ops WHERE id = ?").bind(op.id).execute(&mut *tx).await?;
+    match new_uid {
+        Some(new_uid) => {
+            for table in ["messages", "message_bodies"] {
+                sqlx::query(&format!("UPDATE OR REPLACE {table} SET uid = ? WHERE account_id = ? AND folder = ? AND uid = ?"))
+                    .bind(new_uid)
+                    .bind(&op.account_id)
+                    .bind(dest)
+                    .bind(local_uid)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query("UPDATE pending_ops SET uid = ? WHERE account_id = ? AND folder = ? AND uid = ?")
+                .bind(new_uid)
+                .bind(&op.account_id)
+                .bind(dest)
+                .bind(local_uid)
+                .execute(&mut *tx)
+                .await?;
+        }
+        None => {
+            sqlx::query("UPDATE pending_ops SET status = ?, last_error = ? WHERE account_id = ? AND folder = ? AND uid = ? AND status = ?")
+                .bind(STATUS_CONFLICT)
+                .bind("the message was moved but its new UID is unknown")
+                .bind(&op.account_id)
+                .bind(dest)
+                .bind(local_uid)
+                .bind(STATUS_PENDING)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    tx.commit().await?;
+    if new_uid.is_none() {
+        message_service::delete_rows(pool, &op.account_id, dest, &[local_uid as u32]).await?;
+        sync_job_service::enqueue(&op.account_id, Some(dest), JobPriority::Scheduled).await;
+    }
+    Ok(())
+}
+
+/// Stop replaying `op` and bring the cache back to the server's state: a moved row leaves its
+/// provisional place (with the ops queued against it), a message the server no longer has is
+/// dropped, and a refused change is undone by a full rescan of the folder.
+async fn give_up(pool: &SqlitePool, op: &PendingOp, status: &str, reason: &str) -> Result<()> {
+    warn!(account_id = %op.account_id, op_id = op.id, "giving up on queued change ({}): {}", status, reason);
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE pending_ops SET status = ?, last_error = ? WHERE id = ?")
+        .bind(status)
+        .bind(reason)
+        .bind(op.id)
+        .execute(&mut *tx)
+        .await?;
+    if let (Some(dest), Some(local_uid)) = (&op.dest, op.local_uid) {
+        sqlx::query("UPDATE pending_ops SET status = ?, last_error = ? WHERE account_id = ? AND folder = ? AND uid = ? AND status = ?")
+            .bind(STATUS_CONFLICT)
+            .bind(format!("depends on a move that did not reach the server: {}", reason))
+            .bind(&op.account_id)
+            .bind(dest)
+            .bind(local_uid)
+            .bind(STATUS_PENDING)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    if let (Some(dest), Some(local_uid)) = (&op.dest, op.local_uid) {
+        message_service::delete_rows(pool, &op.account_id, dest, &[local_uid as u32]).await?;
+    }
+    if status == STATUS_CONFLICT && op.uid < LOCAL_UID_BASE {
+        message_service::delete_rows(pool, &op.account_id, &op.folder, &[op.uid as u32]).await?;
+    }
+    if status == STATUS_FAILED {
+        resync(pool, &op.account_id, &op.folder).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_offline() {
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused");
+        assert!(is_offline(&anyhow::Error::from(refused)));
+        assert!(is_offline(&anyhow::anyhow!("connect timeout")));
+        assert!(is_offline(&anyhow::Error::from(async_imap::error::Error::ConnectionLost)));
+        assert!(!is_offline(&anyhow::anyhow!("UID MOVE failed: [TRYCREATE] no such mailbox")));
+        assert!(!is_offline(&anyhow::anyhow!("login failed: authentication failed")));
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff_secs(1), 30);
+        assert_eq!(backoff_secs(2), 60);
+        assert_eq!(backoff_secs(5), 480);
+        assert_eq!(backoff_secs(20), 3600);
+    }
+}
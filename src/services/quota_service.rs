@@ -0,0 +1,246 @@
+/// Mailbox quota (QUOTA, RFC 9208), read with GETQUOTAROOT INBOX at the end of every account
+/// sync. The last reading per quota root and resource is kept in `quota_state`; changes (and a
+/// daily sample) go to `quota_history` for `MAILORA_QUOTA_HISTORY_DAYS` (default 90).
+///
+/// When a resource's usage climbs past one of `MAILORA_QUOTA_THRESHOLDS` (percent, default
+/// "80,95"), a `QuotaWarning` event is published and an ALERT entry written to `events`, so
+/// users hear about it before senders get mailbox-full bounces.
+use anyhow::Result;
+use async_imap::types::{Quota, QuotaResourceName};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::imap::conn::ImapSession;
+use crate::models::account::Account;
+use crate::services::idle_watcher_service::{self, IdleEvent, IdleEventType};
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct QuotaUsage {
+    pub root: String,
+    /// STORAGE (KiB), MESSAGE or another RFC 9208 resource
+    pub resource: String,
+    pub usage: i64,
+    #[sqlx(rename = "quota_limit")]
+    pub limit: i64,
+    pub checked_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaReport {
+    pub resources: Vec<QuotaUsage>,
+    /// Oldest first
+    pub history: Vec<QuotaUsage>,
+}
+
+fn thresholds() -> Vec<u64> {
+    let mut t: Vec<u64> = std::env::var("MAILORA_QUOTA_THRESHOLDS")
+        .unwrap_or_else(|_| "80,95".to_string())
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .filter(|v| (1..=100).contains(v))
+        .collect();
+    t.sort_unstable();
+    t.dedup();
+    t
+}
+
+fn history_days() -> i64 {
+    std::env::var("MAILORA_QUOTA_HISTORY_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(90)
+}
+
+fn resource_name(name: &QuotaResourceName) -> String {
+    match name {
+        QuotaResourceName::Storage => "STORAGE".to_string(),
+        QuotaResourceName::Message => "MESSAGE".to_string(),
+        QuotaResourceName::Atom(a) => a.to_ascii_uppercase(),
+    }
+}
+
+fn percent(usage: u64, limit: u64) -> u64 {
+    usage.saturating_mul(100) / limit
+}
+
+/// The highest threshold usage climbed past since the previous reading (none before counts as 0%)
+fn crossed(thresholds: &[u64], before: Option<u64>, now: u64) -> Option<u64> {
+    let before = before.unwrap_or(0);
+    thresholds.iter().rev().copied().find(|&t| before < t && now >= t)
+}
+
+/// Read the quota of the account's INBOX on an open session and record it. Returns the
+/// number of alerts raised.
+pub async fn check(pool: &SqlitePool, account: &Account, imap: &mut ImapSession) -> Result<usize> {
+    let (roots, mut quotas) = imap.session.get_quota_root("INBOX").await?;
+    // Servers should answer with QUOTA for every root, but not all do
+    for name in roots.iter().flat_map(|r| &r.quota_root_names) {
+        if !quotas.iter().any(|q| &q.root_name == name) {
+            quotas.push(imap.session.get_quota(name).await?);
+        }
+    }
+    record(pool, account, &quotas).await
+}
+
+async fn record(pool: &SqlitePool, account: &Account, quotas: &[Quota]) -> Result<usize> {
+    let now = chrono::Utc::now().timestamp();
+    let thresholds = thresholds();
+    let mut alerts = Vec::new();
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE quota_state SET checked_at = -1 WHERE account_id = ?")
+        .bind(&account.id)
+        .execute(&mut *tx)
+        .await?;
+
+    for quota in quotas {
+        for res in &quota.resources {
+            let resource = resource_name(&res.name);
+            let previous: Option<(i64, i64)> = sqlx::query_as(
+                "SELECT usage, quota_limit FROM quota_state WHERE account_id = ? AND root = ? AND resource = ?",
+            )
+            .bind(&account.id)
+            .bind(&quota.root_name)
+            .bind(&resource)
+            .fetch_optional(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"INSERT INTO quota_state (account_id, root, resource, usage, quota_limit, checked_at) VALUES (?, ?, ?, ?, ?, ?)
+                   ON CONFLICT(account_id, root, resource) DO UPDATE SET usage = excluded.usage, quota_limit = excluded.quota_limit, checked_at = excluded.checked_at"#,
+            )
+            .bind(&account.id)
+            .bind(&quota.root_name)
+            .bind(&resource)
+            .bind(res.usage as i64)
+            .bind(res.limit as i64)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+            let last_sample: Option<i64> = sqlx::query_scalar(
+                "SELECT MAX(checked_at) FROM quota_history WHERE account_id = ? AND root = ? AND resource = ?",
+            )
+            .bind(&account.id)
+            .bind(&quota.root_name)
+            .bind(&resource)
+            .fetch_one(&mut *tx)
+            .await?;
+            let changed = previous != Some((res.usage as i64, res.limit as i64));
+            if changed || last_sample.is_none_or(|t| now - t >= 86_400) {
+                sqlx::query(
+                    "INSERT INTO quota_history (account_id, root, resource, usage, quota_limit, checked_at) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&account.id)
+                .bind(&quota.root_name)
+                .bind(&resource)
+                .bind(res.usage as i64)
+                .bind(res.limit as i64)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            // A limit of 0 means nothing may be stored; there is no usage level to warn about
+            if res.limit == 0 {
+                continue;
+            }
+            let before = previous.filter(|(_, l)| *l > 0).map(|(u, l)| percent(u as u64, l as u64));
+            let now_pct = percent(res.usage, res.limit);
+            if let Some(threshold) = crossed(&thresholds, before, now_pct) {
+                alerts.push(Alert { root: quota.root_name.clone(), resource, usage: res.usage, limit: res.limit, threshold });
+            }
+        }
+    }
+
+    // Roots or resources the server no longer reports
+    sqlx::query("DELETE FROM quota_state WHERE account_id = ? AND checked_at = -1")
+        .bind(&account.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM quota_history WHERE account_id = ? AND checked_at < ?")
+        .bind(&account.id)
+        .bind(now - history_days() * 86_400)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    for a in &alerts {
+        alert(pool, account, a).await?;
+    }
+    Ok(alerts.len())
+}
+
+struct Alert {
+    root: String,
+    resource: String,
+    usage: u64,
+    limit: u64,
+    threshold: u64,
+}
+
+async fn alert(pool: &SqlitePool, account: &Account, a: &Alert) -> Result<()> {
+    let Alert { root, resource, usage, limit, threshold } = a;
+    let pct = percent(*usage, *limit);
+    let amount = if resource == "STORAGE" {
+        format!("{:.1} of {:.1} MB", *usage as f64 / 1024.0, *limit as f64 / 1024.0)
+    } else {
+        format!("{} of {} {}", usage, limit, resource.to_ascii_lowercase())
+    };
+    let root_label = if root.is_empty() { String::new() } else { format!(" ({})", root) };
+    let summary = format!("Mailbox {}% full{}: {}", pct, root_label, amount);
+    info!(account_id = %account.id, "{}", summary);
+
+    sqlx::query("INSERT INTO events (direction, mailbox, actor, peer, subject, ts) VALUES ('ALERT', ?, 'quota', NULL, ?, ?)")
+        .bind(&account.id)
+        .bind(&summary)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await?;
+    idle_watcher_service::publish(IdleEvent {
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        event_type: IdleEventType::QuotaWarning {
+            root: root.clone(),
+            resource: resource.clone(),
+            usage: *usage,
+            limit: *limit,
+            percent: pct,
+            threshold: *threshold,
+        },
+        timestamp: chrono::Utc::now().timestamp(),
+    });
+    Ok(())
+}
+
+/// Last reading and the history of the last `days` days; empty when the server has no QUOTA
+pub async fn report(pool: &SqlitePool, account_id: &str, days: i64) -> Result<QuotaReport> {
+    let resources = sqlx::query_as::<_, QuotaUsage>(
+        "SELECT root, resource, usage, quota_limit, checked_at FROM quota_state WHERE account_id = ? ORDER BY root, resource",
+    )
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?;
+    let history = sqlx::query_as::<_, QuotaUsage>(
+        "SELECT root, resource, usage, quota_limit, checked_at FROM quota_history WHERE account_id = ? AND checked_at >= ? ORDER BY checked_at, id",
+    )
+    .bind(account_id)
+    .bind(chrono::Utc::now().timestamp() - days * 86_400)
+    .fetch_all(pool)
+    .await?;
+    Ok(QuotaReport { resources, history })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossed() {
+        let t = [80, 95];
+        assert_eq!(crossed(&t, Some(70), 85), Some(80));
+        assert_eq!(crossed(&t, Some(70), 97), Some(95));
+        assert_eq!(crossed(&t, Some(85), 90), None);
+        assert_eq!(crossed(&t, Some(96), 80), None);
+        assert_eq!(crossed(&t, None, 81), Some(80));
+        assert_eq!(crossed(&t, None, 10), None);
+        // Dropping below and climbing back warns again
+        assert_eq!(crossed(&t, Some(79), 80), Some(80));
+    }
+}
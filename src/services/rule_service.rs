@@ -0,0 +1,670 @@
+/// Client-side rules for accounts whose server has no usable Sieve (Gmail with an app
+/// password, Outlook): the hub runs them itself on every new INBOX message.
+///
+/// `save_message_to_db` hands each newly inserted message to [`notify_new`]; one worker
+/// evaluates the enabled rules (global and the account's own, by position) and runs the actions
+/// of those that match through the same paths as the API: UID STORE, COPY/MOVE with the cache
+/// update (queued in `pending_ops` like API changes), and the outbox for forwards. Only mail
+/// that arrived in the last `MAILORA_RULES_MAX_AGE_HOURS` (default 24) is processed, so the
+/// first sync of an old mailbox doesn't re-file its history; [`dry_run`] shows what a rule
+/// would do there instead. Every match is recorded in `rule_log`.
+///
+/// Gmail rows live in All Mail: a message is an INBOX message once the label sync has given it
+/// `\Inbox`, and moving it means copying (labelling) it and removing that label.
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use regex_automata::meta::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::imap::pool;
+use crate::imap::transfer::{self, Transfer};
+use crate::models::account::{Account, EmailProvider};
+use crate::models::rule::{Action, Condition, Rule, RuleLogEntry, Test, TextOp};
+use crate::services::{account_service, message_body_service, message_service, outbox_service, pending_op_service};
+
+const SELECT: &str = "SELECT id, account_id, name, position, enabled, match_any, conditions, actions, created_at, updated_at FROM rules";
+
+/// Tries to wait for the Gmail label sync before giving up on a message
+const LABEL_ATTEMPTS: u32 = 3;
+
+struct NewMessage {
+    account_id: String,
+    folder: String,
+    uid: u32,
+    attempt: u32,
+}
+
+static QUEUE: OnceCell<mpsc::UnboundedSender<NewMessage>> = OnceCell::new();
+
+/// Compiled patterns by source; an invalid pattern is cached as `None` and never matches
+static REGEXES: Lazy<Mutex<HashMap<String, Option<Regex>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A rule as created or replaced through the API
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleSpec {
+    pub account_id: Option<String>,
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub match_any: bool,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+    /// Default: after every existing rule
+    pub position: Option<i64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunMatch {
+    pub account_id: String,
+    pub folder: String,
+    pub uid: i64,
+    pub subject: Option<String>,
+    pub from: Option<String>,
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunOutcome {
+    pub scanned: usize,
+    pub matches: Vec<DryRunMatch>,
+    /// Messages a body condition couldn't decide because their body isn't cached
+    pub undecided: usize,
+}
+
+/// A cached message as the rules see it
+#[derive(Debug, Clone, Default, sqlx::FromRow)]
+struct Candidate {
+    account_id: String,
+    folder: String,
+    uid: i64,
+    message_id: Option<String>,
+    subject: Option<String>,
+    from_addr: Option<String>,
+    to_addr: Option<String>,
+    cc: Option<String>,
+    date: Option<String>,
+    list_id: Option<String>,
+    auto_submitted: Option<String>,
+    has_attachments: bool,
+    size: i64,
+    body: Option<String>,
+    gm_labels: Option<String>,
+}
+
+const CANDIDATE: &str = r#"SELECT m.account_id, m.folder, m.uid, m.message_id, m.subject, m.from_addr, m.to_addr, m.cc, m.date,
+       m.list_id, m.auto_submitted, COALESCE(m.has_attachments, 0) AS has_attachments, COALESCE(m.size, 0) AS size,
+       COALESCE(m.body_plain, b.body) AS body, m.gm_labels
+FROM messages m
+LEFT JOIN message_bodies b ON b.account_id = m.account_id AND b.folder = m.folder AND b.uid = m.uid"#;
+
+fn max_age_hours() -> i64 {
+    std::env::var("MAILORA_RULES_MAX_AGE_HOURS").ok().and_then(|v| v.parse().ok()).unwrap_or(24)
+}
+
+/// Start the worker and drop log entries older than 30 days
+pub async fn init(pool: &SqlitePool) -> Result<()> {
+    sqlx::query("DELETE FROM rule_log WHERE created_at < ?")
+        .bind(Utc::now().timestamp() - 30 * 86_400)
+        .execute(pool)
+        .await?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    if QUEUE.set(tx).is_ok() {
+        tokio::spawn(worker(pool.clone(), rx));
+    }
+    Ok(())
+}
+
+/// Queue a message a sync just inserted. Only INBOX (or, on Gmail, All Mail rows that may
+/// carry `\Inbox`) and only recent mail; without a worker this is a no-op.
+pub fn notify_new(account: &Account, folder: &str, uid: u32, internal_date: Option<DateTime<FixedOffset>>) {
+    let Some(queue) = QUEUE.get() else { return };
+    if !folder.eq_ignore_ascii_case("INBOX") && account.provider != EmailProvider::Gmail {
+        return;
+    }
+    if internal_date.is_some_and(|d| Utc::now().signed_duration_since(d) > chrono::Duration::hours(max_age_hours())) {
+        return;
+    }
+    let _ = queue.send(NewMessage { account_id: account.id.clone(), folder: folder.to_string(), uid, attempt: 0 });
+}
+
+async fn worker(pool: SqlitePool, mut rx: mpsc::UnboundedReceiver<NewMessage>) {
+    while let Some(msg) = rx.recv().await {
+        if let Err(e) = process(&pool, &msg).await {
+            warn!(account_id = %msg.account_id, "rules for {} uid {}: {}", msg.folder, msg.uid, e);
+        }
+    }
+}
+
+async fn process(pool: &SqlitePool, msg: &NewMessage) -> Result<()> {
+    let account_id = &msg.account_id;
+    let rules = active_rules(pool, account_id).await?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let Some(account) = account_service::get_account(pool, account_id).await? else { return Ok(()) };
+    let Some(mut m) = sqlx::query_as::<_, Candidate>(&format!("{CANDIDATE} WHERE m.account_id = ? AND m.folder = ? AND m.uid = ?"))
+        .bind(account_id)
+        .bind(&msg.folder)
+        .bind(msg.uid)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    if !msg.folder.eq_ignore_ascii_case("INBOX") {
+        match m.gm_labels.as_deref() {
+            // The label sync runs right after the folder sync; try again once it had a chance
+            None if msg.attempt + 1 < LABEL_ATTEMPTS => {
+                let retry = NewMessage { account_id: account_id.clone(), folder: msg.folder.clone(), uid: msg.uid, attempt: msg.attempt + 1 };
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    if let Some(queue) = QUEUE.get() {
+                        let _ = queue.send(retry);
+                    }
+                });
+                return Ok(());
+            }
+            Some(labels) if serde_json::from_str::<Vec<String>>(labels).is_ok_and(|l| l.iter().any(|l| l == "\\Inbox")) => {}
+            _ => return Ok(()),
+        }
+    }
+
+    for rule in &rules {
+        let mut matched = matches(rule, &m);
+        if matched.is_none() {
+            let body = message_body_service::fetch_message_body(&account, m.uid as u32, Some(&m.folder), pool, false).await?;
+            m.body = Some(body.plain_text);
+            matched = matches(rule, &m);
+        }
+        if matched != Some(true) {
+            continue;
+        }
+
+        let res = apply(pool, &account, &m, rule).await;
+        record(pool, rule, &m, res.as_ref().err()).await?;
+        match &res {
+            Ok(()) => info!(account_id = %account.id, "rule {:?} applied to {} uid {}", rule.name, m.folder, m.uid),
+            Err(e) => warn!(account_id = %account.id, "rule {:?} on {} uid {}: {}", rule.name, m.folder, m.uid, e),
+        }
+        if res.is_err() || rule.actions.iter().any(|a| matches!(a, Action::Stop | Action::Move { .. })) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn record(pool: &SqlitePool, rule: &Rule, m: &Candidate, error: Option<&anyhow::Error>) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO rule_log (rule_id, rule_name, account_id, folder, uid, message_id, subject, actions, error) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&rule.id)
+    .bind(&rule.name)
+    .bind(&m.account_id)
+    .bind(&m.folder)
+    .bind(m.uid)
+    .bind(&m.message_id)
+    .bind(&m.subject)
+    .bind(serde_json::to_string(&rule.actions)?)
+    .bind(error.map(|e| e.to_string()))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Run a matched rule's actions: flags first, then copies and forwards, the move last
+async fn apply(pool: &SqlitePool, account: &Account, m: &Candidate, rule: &Rule) -> Result<()> {
+    let uid = m.uid as u32;
+    // Gmail rows sit in All Mail; anything else reaching here is INBOX
+    let all_mail = !m.folder.eq_ignore_ascii_case("INBOX");
+
+    let flags: Vec<&str> = rule
+        .actions
+        .iter()
+        .filter_map(|a| match a {
+            Action::Flag => Some("\\Flagged"),
+            Action::MarkRead => Some("\\Seen"),
+            Action::AddKeyword { keyword } => Some(keyword.as_str()),
+            _ => None,
+        })
+        .collect();
+    // Like API changes, these queue behind changes still waiting for the server and whenever it
+    // can't be reached
+    let mut queue = pending_op_service::must_queue(pool, &account.id, &m.folder, &[uid]).await?;
+    if !flags.is_empty() {
+        if !queue {
+            match store(account, &m.folder, uid, &format!("+FLAGS.SILENT ({})", flags.join(" "))).await {
+                Ok(()) => message_service::update_cached_flags(pool, &account.id, &m.folder, &[uid], &flags, &[]).await?,
+                Err(e) if pending_op_service::is_offline(&e) => queue = true,
+                Err(e) => return Err(e),
+            }
+        }
+        if queue {
+            pending_op_service::queue_flags(pool, &account.id, &m.folder, uid, &flags, &[]).await?;
+        }
+    }
+
+    for action in &rule.actions {
+        match action {
+            Action::Copy { folder } => copy_or_move(pool, account, &m.folder, uid, folder, Transfer::Copy, &mut queue).await?,
+            Action::Forward { to } => forward(pool, account, m, to).await?,
+            _ => {}
+        }
+    }
+
+    let dest = rule.actions.iter().rev().find_map(|a| match a {
+        Action::Move { folder } => Some(folder),
+        _ => None,
+    });
+    if let Some(dest) = dest {
+        if !all_mail {
+            copy_or_move(pool, account, &m.folder, uid, dest, Transfer::Move, &mut queue).await?;
+        } else {
+            copy_or_move(pool, account, &m.folder, uid, dest, Transfer::Copy, &mut queue).await?;
+            store(account, &m.folder, uid, "-X-GM-LABELS (\\Inbox)").await?;
+            sqlx::query(
+                "DELETE FROM message_labels WHERE folder = 'INBOX' AND message_id = (SELECT id FROM messages WHERE account_id = ? AND folder = ? AND uid = ?)",
+            )
+            .bind(&account.id)
+            .bind(&m.folder)
+            .bind(m.uid)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// COPY/MOVE with the cache update, or queued for replay once `queue` is set (or the server turns
+/// out to be unreachable); Gmail relabels can't be queued
+async fn copy_or_move(
+    pool: &SqlitePool,
+    account: &Account,
+    folder: &str,
+    uid: u32,
+    dest: &str,
+    mode: Transfer,
+    queue: &mut bool,
+) -> Result<()> {
+    let queueable = pending_op_service::queues_transfers(account);
+    if !*queue {
+        match message_service::transfer_messages(pool, account, folder, &[uid], dest, mode).await {
+            Ok(_) => return Ok(()),
+            Err(e) if queueable && pending_op_service::is_offline(&e) => *queue = true,
+            Err(e) => return Err(e),
+        }
+    }
+    if !queueable {
+        anyhow::bail!("changes are still waiting for the server; try again later");
+    }
+    match mode {
+        Transfer::Move => pending_op_service::queue_move(pool, &account.id, folder, uid, dest).await.map(drop),
+        Transfer::Copy => pending_op_service::queue_copy(pool, &account.id, folder, uid, dest).await,
+    }
+}
+
+async fn store(account: &Account, folder: &str, uid: u32, item: &str) -> Result<()> {
+    let mut imap = pool::acquire_account(account).await?;
+    let res = async {
+        imap.session.select(folder).await?;
+        transfer::store(&mut imap.session, &[uid], item).await
+    }
+    .await;
+    if res.is_ok() {
+        imap.release();
+    }
+    res
+}
+
+/// Queue a plain-text forward in the outbox
+async fn forward(pool: &SqlitePool, account: &Account, m: &Candidate, to: &str) -> Result<()> {
+    // Forwarding automatic mail, or to ourselves, is how mail loops start
+    if m.auto_submitted.as_deref().is_some_and(|v| !v.eq_ignore_ascii_case("no")) {
+        anyhow::bail!("not forwarding an automatically submitted message");
+    }
+    if to.trim().eq_ignore_ascii_case(&account.email) {
+        anyhow::bail!("not forwarding to the account's own address");
+    }
+    let body = match &m.body {
+        Some(b) => b.clone(),
+        None => message_body_service::fetch_message_body(account, m.uid as u32, Some(&m.folder), pool, false).await?.plain_text,
+    };
+    let subject = m.subject.clone().unwrap_or_default();
+    let text = format!(
+        "---------- Forwarded message ----------\nFrom: {}\nDate: {}\nSubject: {}\nTo: {}\n\n{}",
+        m.from_addr.as_deref().unwrap_or(""),
+        m.date.as_deref().unwrap_or(""),
+        subject,
+        m.to_addr.as_deref().unwrap_or(""),
+        body
+    );
+    outbox_service::queue_email(pool, &account.id, to, &format!("Fwd: {}", subject), &text)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(())
+}
+
+/// Whether the message matches; `None` when a body condition decides and the body isn't known
+fn matches(rule: &Rule, m: &Candidate) -> Option<bool> {
+    combine(&rule.conditions, rule.match_any, m)
+}
+
+fn combine(conditions: &[Condition], any: bool, m: &Candidate) -> Option<bool> {
+    let mut unknown = false;
+    for c in conditions {
+        match test(&c.test, m).map(|r| r != c.not) {
+            Some(r) if r == any => return Some(any),
+            Some(_) => {}
+            None => unknown = true,
+        }
+    }
+    if unknown { None } else { Some(!any || conditions.is_empty()) }
+}
+
+fn test(t: &Test, m: &Candidate) -> Option<bool> {
+    Some(match t {
+        Test::From { op, value } => addresses(m.from_addr.as_deref()).any(|a| text(*op, value, a)),
+        Test::To { op, value } => addresses(m.to_addr.as_deref())
+            .chain(addresses(m.cc.as_deref()))
+            .any(|a| text(*op, value, a)),
+        Test::Subject { op, value } => text(*op, value, m.subject.as_deref().unwrap_or("")),
+        Test::ListId { op, value } => m.list_id.as_deref().is_some_and(|l| text(*op, value, l)),
+        Test::HasAttachment => m.has_attachments,
+        Test::SizeOver { bytes } => m.size > *bytes,
+        Test::SizeUnder { bytes } => m.size < *bytes,
+        Test::Body { pattern } => return m.body.as_deref().map(|b| regex_match(pattern, b)),
+    })
+}
+
+/// An address header's texts worth matching: the whole header, each entry and each bare address
+fn addresses(field: Option<&str>) -> impl Iterator<Item = &str> {
+    let field = field.unwrap_or("");
+    std::iter::once(field).chain(field.split(',').flat_map(|entry| {
+        let entry = entry.trim();
+        let bare = entry.rsplit_once('<').and_then(|(_, a)| a.strip_suffix('>'));
+        std::iter::once(entry).chain(bare)
+    }))
+}
+
+fn text(op: TextOp, value: &str, field: &str) -> bool {
+    if op == TextOp::Regex {
+        return regex_match(value, field);
+    }
+    let (field, value) = (field.to_lowercase(), value.to_lowercase());
+    match op {
+        TextOp::Contains => field.contains(&value),
+        TextOp::Is => field == value,
+        TextOp::StartsWith => field.starts_with(&value),
+        TextOp::EndsWith => field.ends_with(&value),
+        TextOp::Regex => unreachable!(),
+    }
+}
+
+fn regex_match(pattern: &str, haystack: &str) -> bool {
+    let re = {
+        let mut cache = REGEXES.lock().unwrap();
+        if cache.len() > 256 {
+            cache.clear();
+        }
+        cache.entry(pattern.to_string()).or_insert_with(|| Regex::new(pattern).ok()).clone()
+    };
+    re.is_some_and(|re| re.is_match(haystack))
+}
+
+/// Validate what the types can't express
+pub fn validate(spec: &RuleSpec) -> Result<(), String> {
+    if spec.name.trim().is_empty() {
+        return Err("rule needs a name".into());
+    }
+    if spec.actions.is_empty() {
+        return Err(format!("rule {:?} has no actions", spec.name));
+    }
+    for c in &spec.conditions {
+        match &c.test {
+            Test::From { op: TextOp::Regex, value }
+            | Test::To { op: TextOp::Regex, value }
+            | Test::Subject { op: TextOp::Regex, value }
+            | Test::ListId { op: TextOp::Regex, value }
+            | Test::Body { pattern: value } => {
+                Regex::new(value).map_err(|e| format!("rule {:?}: bad pattern {:?}: {}", spec.name, value, e))?;
+            }
+            Test::SizeOver { bytes } | Test::SizeUnder { bytes } if *bytes < 0 => {
+                return Err(format!("rule {:?}: size must not be negative", spec.name));
+            }
+            _ => {}
+        }
+    }
+    for a in &spec.actions {
+        match a {
+            Action::Move { folder } | Action::Copy { folder } if folder.is_empty() => {
+                return Err(format!("rule {:?}: move/copy needs a folder", spec.name));
+            }
+            Action::AddKeyword { keyword } if !valid_keyword(keyword) => {
+                return Err(format!("rule {:?}: {:?} is not a keyword", spec.name, keyword));
+            }
+            Action::Forward { to } if !to.contains('@') => {
+                return Err(format!("rule {:?}: {:?} is not an address", spec.name, to));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// IMAP flag keyword: an atom, not a system flag
+fn valid_keyword(k: &str) -> bool {
+    !k.is_empty() && k.chars().all(|c| c.is_ascii_graphic() && !"(){%*\"\\]".contains(c))
+}
+
+async fn active_rules(pool: &SqlitePool, account_id: &str) -> Result<Vec<Rule>> {
+    Ok(sqlx::query_as::<_, Rule>(&format!(
+        "{SELECT} WHERE enabled = 1 AND (account_id IS NULL OR account_id = ?) ORDER BY position, created_at"
+    ))
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Every rule, or the ones that apply to `account_id` (its own and global ones), in run order
+pub async fn list(pool: &SqlitePool, account_id: Option<&str>) -> Result<Vec<Rule>> {
+    Ok(sqlx::query_as::<_, Rule>(&format!(
+        "{SELECT} WHERE ? IS NULL OR account_id IS NULL OR account_id = ? ORDER BY position, created_at"
+    ))
+    .bind(account_id)
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn get(pool: &SqlitePool, id: &str) -> Result<Option<Rule>> {
+    Ok(sqlx::query_as::<_, Rule>(&format!("{SELECT} WHERE id = ?")).bind(id).fetch_optional(pool).await?)
+}
+
+/// Insert a validated rule
+pub async fn create(pool: &SqlitePool, spec: &RuleSpec) -> Result<Rule> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let position = match spec.position {
+        Some(p) => p,
+        None => sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(position), -1) + 1 FROM rules").fetch_one(pool).await?,
+    };
+    sqlx::query(
+        "INSERT INTO rules (id, account_id, name, position, enabled, match_any, conditions, actions) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&spec.account_id)
+    .bind(&spec.name)
+    .bind(position)
+    .bind(spec.enabled)
+    .bind(spec.match_any)
+    .bind(serde_json::to_string(&spec.conditions)?)
+    .bind(serde_json::to_string(&spec.actions)?)
+    .execute(pool)
+    .await?;
+    get(pool, &id).await?.ok_or_else(|| anyhow::anyhow!("rule {} vanished", id))
+}
+
+/// Replace a validated rule; its position stays unless the spec gives one
+pub async fn update(pool: &SqlitePool, id: &str, spec: &RuleSpec) -> Result<Option<Rule>> {
+    let res = sqlx::query(
+        r#"UPDATE rules SET account_id = ?, name = ?, position = COALESCE(?, position), enabled = ?, match_any = ?,
+               conditions = ?, actions = ?, updated_at = strftime('%s','now')
+           WHERE id = ?"#,
+    )
+    .bind(&spec.account_id)
+    .bind(&spec.name)
+    .bind(spec.position)
+    .bind(spec.enabled)
+    .bind(spec.match_any)
+    .bind(serde_json::to_string(&spec.conditions)?)
+    .bind(serde_json::to_string(&spec.actions)?)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(None);
+    }
+    get(pool, id).await
+}
+
+pub async fn delete(pool: &SqlitePool, id: &str) -> Result<bool> {
+    let res = sqlx::query("DELETE FROM rules WHERE id = ?").bind(id).execute(pool).await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Put the given rules first, in that order; the others keep their relative order after them
+pub async fn reorder(pool: &SqlitePool, ids: &[String]) -> Result<Vec<Rule>> {
+    let mut order: Vec<String> = ids.to_vec();
+    for rule in list(pool, None).await? {
+        if !order.contains(&rule.id) {
+            order.push(rule.id);
+        }
+    }
+    let mut tx = pool.begin().await?;
+    for (position, id) in order.iter().enumerate() {
+        sqlx::query("UPDATE rules SET position = ?, updated_at = strftime('%s','now') WHERE id = ?")
+            .bind(position as i64)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    list(pool, None).await
+}
+
+/// Which of the newest `scan` cached INBOX messages the rule would match. Nothing is changed
+/// and bodies aren't downloaded.
+pub async fn dry_run(pool: &SqlitePool, spec: &RuleSpec, account_id: Option<&str>, scan: i64) -> Result<DryRunOutcome> {
+    let candidates = sqlx::query_as::<_, Candidate>(&format!(
+        r#"{CANDIDATE}
+           WHERE (? IS NULL OR m.account_id = ?)
+             AND (m.folder = 'INBOX' OR m.id IN (SELECT message_id FROM message_labels WHERE folder = 'INBOX'))
+           ORDER BY m.id DESC LIMIT ?"#
+    ))
+    .bind(account_id)
+    .bind(account_id)
+    .bind(scan)
+    .fetch_all(pool)
+    .await?;
+
+    let mut outcome = DryRunOutcome { scanned: candidates.len(), matches: Vec::new(), undecided: 0 };
+    for m in candidates {
+        match combine(&spec.conditions, spec.match_any, &m) {
+            Some(true) => outcome.matches.push(DryRunMatch {
+                account_id: m.account_id,
+                folder: m.folder,
+                uid: m.uid,
+                subject: m.subject,
+                from: m.from_addr,
+                date: m.date,
+            }),
+            Some(false) => {}
+            None => outcome.undecided += 1,
+        }
+    }
+    Ok(outcome)
+}
+
+/// Newest first
+pub async fn log(pool: &SqlitePool, account_id: Option<&str>, rule_id: Option<&str>, limit: i64) -> Result<Vec<RuleLogEntry>> {
+    Ok(sqlx::query_as::<_, RuleLogEntry>(
+        r#"SELECT id, rule_id, rule_name, account_id, folder, uid, message_id, subject, actions, error, created_at
+           FROM rule_log WHERE (? IS NULL OR account_id = ?) AND (? IS NULL OR rule_id = ?)
+           ORDER BY id DESC LIMIT ?"#,
+    )
+    .bind(account_id)
+    .bind(account_id)
+    .bind(rule_id)
+    .bind(rule_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cond(json: &str) -> Condition {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_conditions() {
+        let m = Candidate {
+            from_addr: Some("Billing Team <billing@shop.example>".into()),
+            to_addr: Some("me@example.com".into()),
+            cc: Some("Ayşe <ayse@example.com>, team@example.com".into()),
+            subject: Some("Fatura #123".into()),
+            list_id: Some("<news.shop.example>".into()),
+            size: 2048,
+            ..Default::default()
+        };
+        let from_is = cond(r#"{"field": "from", "op": "is", "value": "BILLING@shop.example"}"#);
+        let cc_is = cond(r#"{"field": "to", "op": "is", "value": "ayse@example.com"}"#);
+        let subject_re = cond(r#"{"field": "subject", "op": "regex", "value": "^Fatura #\\d+$"}"#);
+        let big = cond(r#"{"field": "size_over", "bytes": 4096}"#);
+        let not_big = cond(r#"{"field": "size_over", "bytes": 4096, "not": true}"#);
+        let body = cond(r#"{"field": "body", "pattern": "(?i)unsubscribe"}"#);
+
+        assert_eq!(combine(&[from_is.clone(), cc_is, subject_re.clone(), not_big], false, &m), Some(true));
+        assert_eq!(combine(&[from_is.clone(), big.clone()], false, &m), Some(false));
+        assert_eq!(combine(&[big.clone(), subject_re], true, &m), Some(true));
+        assert_eq!(combine(&[], false, &m), Some(true));
+
+        // Unknown body: undecided unless the other conditions settle it
+        assert_eq!(combine(&[from_is.clone(), body.clone()], false, &m), None);
+        assert_eq!(combine(&[big.clone(), body.clone()], false, &m), Some(false));
+        assert_eq!(combine(&[from_is, body.clone()], true, &m), Some(true));
+        let m = Candidate { body: Some("Click to Unsubscribe".into()), ..m };
+        assert_eq!(combine(&[big, body], true, &m), Some(true));
+    }
+
+    #[test]
+    fn test_validate() {
+        let spec: RuleSpec = serde_json::from_str(
+            r#"{"name": "News", "conditions": [{"field": "list_id", "op": "contains", "value": "news"}],
+                "actions": [{"type": "add_keyword", "keyword": "$News"}, {"type": "move", "folder": "News"}]}"#,
+        )
+        .unwrap();
+        assert!(spec.enabled && validate(&spec).is_ok());
+        let mut bad = spec.clone();
+        bad.conditions.push(cond(r#"{"field": "body", "pattern": "(unclosed"}"#));
+        assert!(validate(&bad).is_err());
+        let mut bad = spec;
+        bad.actions[0] = Action::AddKeyword { keyword: "two words".into() };
+        assert!(validate(&bad).is_err());
+    }
+}
@@ -2,12 +2,12 @@ use std::time::Duration;
 use sqlx::SqlitePool;
 use tracing::{info, warn};
 
-use crate::models::account::EmailProvider;
-use crate::services::{account_service, message_sync_service};
+use crate::services::account_service;
+use crate::services::sync_job_service::{self, JobPriority};
 
 const CARDDAV_SYNC_INTERVAL_SECS: i64 = 900; // 15 dakika
 
-/// Starts a lightweight sync scheduler. Every tick it iterates accounts and runs a delta sync.
+/// Starts a lightweight sync scheduler. Every tick it iterates accounts and queues a delta sync.
 pub fn start(pool: SqlitePool) {
     tokio::spawn(async move {
         loop {
@@ -17,8 +17,6 @@ pub fn start(pool: SqlitePool) {
                     for acc_in in accounts {
                         // Skip disabled
                         if !acc_in.enabled { continue; }
-                        // Skip Gmail for now (user request)
-                        if matches!(acc_in.provider, EmailProvider::Gmail) { continue; }
 
                         // Decode credentials; skip if empty/invalid
                         let mut acc = acc_in.clone();
@@ -28,7 +26,7 @@ pub fn start(pool: SqlitePool) {
                                 Err(e) => { warn!(email=%acc.email, error=%e.to_string(), "scheduler: invalid credentials, skipping"); continue; }
                             }
                         }
-                        if acc.password.is_empty() {
+                        if acc.password.is_empty() && !acc.is_oauth() {
                             warn!(email=%acc.email, "scheduler: empty password, skipping");
                             continue;
                         }
@@ -40,17 +38,8 @@ pub fn start(pool: SqlitePool) {
                         } else { true };
 
                         if should_sync_email {
-                            let p = pool.clone();
-                            let acc_clone = acc.clone();
-                            tokio::spawn(async move {
-                                match message_sync_service::sync_account_messages(&p, &acc_clone).await {
-                                    Ok(stats) => {
-                                        info!(email=%acc_clone.email, folders=%stats.len(), "scheduled email sync completed");
-                                        let _ = crate::services::account_service::update_last_sync(&p, &acc_clone.id).await;
-                                    }
-                                    Err(e) => warn!(email=%acc_clone.email, error=%e.to_string(), "scheduled email sync failed"),
-                                }
-                            });
+                            // Joins a sync of this account that is still queued or running
+                            sync_job_service::enqueue(&acc.id, None, JobPriority::Scheduled).await;
                         }
 
                         // ── CardDAV sync (15 dakikada bir) ────────────────────────
@@ -0,0 +1,322 @@
+/// Server-side search for what the local index can't see: mail older than the sync window or
+/// in folders that aren't synced yet.
+///
+/// Each selected folder is searched with UID SEARCH, or on Gmail with X-GM-RAW (Gmail's own
+/// query syntax) over All Mail, narrowed to the folder with `in:`/`label:`. The newest hits whose
+/// headers aren't cached yet are fetched the way a sync fetches them and stored in `messages`,
+/// so they show up in later local searches and can be opened like any synced message.
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+
+use crate::imap::conn::quote;
+use crate::imap::folders::decode_utf7;
+use crate::imap::pool::{self, PooledSession};
+use crate::models::account::Account;
+use crate::services::{folder_service, message_sync_service};
+
+/// A search result as cached in `messages`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SearchHit {
+    pub account_id: String,
+    pub folder: String,
+    pub uid: i64,
+    pub message_id: Option<String>,
+    pub subject: Option<String>,
+    pub from_addr: Option<String>,
+    pub date: Option<String>,
+    pub flags: Option<String>,
+    pub has_attachments: bool,
+    pub size: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerQuery {
+    /// Words that must all occur (header or body)
+    pub text: Option<String>,
+    pub unread: bool,
+    pub attachments: bool,
+    pub since: Option<NaiveDate>,
+    /// Inclusive
+    pub until: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderOutcome {
+    pub account_id: String,
+    /// Folder searched on the server (All Mail on Gmail)
+    pub folder: String,
+    /// Server matches, before `limit`
+    pub matched: usize,
+    /// Hits whose headers were fetched and cached by this search
+    pub cached: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `YYYY-MM-DD...` as used by the search filters
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// Quoted IMAP strings carry UTF-8 only when the search announces it
+fn charset(q: &ServerQuery) -> &'static str {
+    if q.text.as_deref().is_some_and(|t| !t.is_ascii()) {
+        "CHARSET UTF-8 "
+    } else {
+        ""
+    }
+}
+
+/// UID SEARCH criteria (RFC 3501 §6.4.4); every word must match
+fn imap_criteria(q: &ServerQuery) -> String {
+    let mut keys: Vec<String> = q
+        .text
+        .as_deref()
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|w| format!("TEXT {}", quote(w)))
+        .collect();
+    if q.unread {
+        keys.push("UNSEEN".into());
+    }
+    if let Some(d) = q.since {
+        keys.push(format!("SINCE {}", d.format("%-d-%b-%Y")));
+    }
+    if let Some(d) = q.until.and_then(|d| d.succ_opt()) {
+        keys.push(format!("BEFORE {}", d.format("%-d-%b-%Y")));
+    }
+    if keys.is_empty() {
+        keys.push("ALL".into());
+    }
+    format!("{}{}", charset(q), keys.join(" "))
+}
+
+/// `X-GM-RAW` criteria; `scope` narrows All Mail to a folder (`in:inbox`, `label:"Work"`)
+fn gmail_criteria(q: &ServerQuery, scope: Option<&str>) -> String {
+    let mut terms: Vec<String> = q.text.iter().flat_map(|t| t.split_whitespace()).map(str::to_string).collect();
+    if q.unread {
+        terms.push("is:unread".into());
+    }
+    if q.attachments {
+        terms.push("has:attachment".into());
+    }
+    if let Some(d) = q.since {
+        terms.push(format!("after:{}", d.format("%Y/%m/%d")));
+    }
+    if let Some(d) = q.until.and_then(|d| d.succ_opt()) {
+        terms.push(format!("before:{}", d.format("%Y/%m/%d")));
+    }
+    terms.extend(scope.map(str::to_string));
+    if terms.is_empty() {
+        return "ALL".into();
+    }
+    format!("{}X-GM-RAW {}", charset(q), quote(&terms.join(" ")))
+}
+
+/// Gmail search operator for a label folder; `None` for All Mail itself
+fn gmail_scope(folder: &str, special_use: Option<&str>) -> Option<String> {
+    if folder.eq_ignore_ascii_case("INBOX") {
+        return Some("in:inbox".into());
+    }
+    Some(match special_use {
+        Some("\\All") => return None,
+        Some("\\Sent") => "in:sent".into(),
+        Some("\\Drafts") => "in:drafts".into(),
+        Some("\\Flagged") => "is:starred".into(),
+        Some("\\Important") => "is:important".into(),
+        _ => format!("label:{}", quote(&decode_utf7(folder))),
+    })
+}
+
+/// (folder to select, criteria) per requested folder. On Gmail the rows live in All Mail,
+/// except Junk and Trash which All Mail leaves out.
+async fn targets(pool: &SqlitePool, account: &Account, gmail: bool, folders: &[String], q: &ServerQuery) -> Result<Vec<(String, String)>> {
+    if !gmail {
+        let folders = if folders.is_empty() { vec!["INBOX".to_string()] } else { folders.to_vec() };
+        return Ok(folders.into_iter().map(|f| (f, imap_criteria(q))).collect());
+    }
+    let catalogue = folder_service::list_folders(pool, &account.id).await?;
+    let role_of = |name: &str| catalogue.iter().find(|f| f.name == name).and_then(|f| f.special_use.clone());
+    let all_mail = catalogue
+        .iter()
+        .find(|f| f.special_use.as_deref() == Some("\\All"))
+        .map(|f| f.name.clone())
+        .unwrap_or_else(|| "[Gmail]/All Mail".to_string());
+    if folders.is_empty() {
+        return Ok(vec![(all_mail, gmail_criteria(q, None))]);
+    }
+    Ok(folders
+        .iter()
+        .map(|f| match role_of(f).as_deref() {
+            Some("\\Junk") | Some("\\Trash") => (f.clone(), gmail_criteria(q, None)),
+            role => (all_mail.clone(), gmail_criteria(q, gmail_scope(f, role).as_deref())),
+        })
+        .collect())
+}
+
+/// Search `folders` (wire names; none means INBOX, or everything on Gmail) on the account's
+/// server. Returns up to `limit` newest hits per folder, cached first if needed.
+pub async fn search_account(
+    pool: &SqlitePool,
+    account: &Account,
+    folders: &[String],
+    q: &ServerQuery,
+    limit: usize,
+) -> Result<(Vec<SearchHit>, Vec<FolderOutcome>)> {
+    let mut imap: Option<PooledSession> = Some(pool::acquire_account(account).await?);
+    let gmail = imap.as_ref().is_some_and(|s| s.caps.gmail);
+
+    let mut hits = Vec::new();
+    let mut outcomes = Vec::new();
+    for (folder, criteria) in targets(pool, account, gmail, folders, q).await? {
+        let mut session = match imap.take() {
+            Some(s) => s,
+            None => pool::acquire_account(account).await?,
+        };
+        let mut outcome = FolderOutcome { account_id: account.id.clone(), folder: folder.clone(), matched: 0, cached: 0, error: None };
+        match search_folder(pool, account, &mut session, &folder, &criteria, limit).await {
+            Ok((matched, cached, uids)) => {
+                imap = Some(session);
+                outcome.matched = matched;
+                outcome.cached = cached;
+                let mut rows = load_hits(pool, &account.id, &folder, &uids).await?;
+                if q.attachments {
+                    rows.retain(|r| r.has_attachments);
+                }
+                hits.extend(rows);
+            }
+            Err(e) => {
+                // The session may be mid-response; the next folder gets a fresh one
+                session.discard();
+                outcome.error = Some(e.to_string());
+            }
+        }
+        outcomes.push(outcome);
+    }
+
+    if let Some(session) = imap {
+        session.release();
+    }
+
+    // Several Gmail label scopes search the same All Mail rows
+    let mut seen = HashSet::new();
+    hits.retain(|h| seen.insert((h.folder.clone(), h.uid)));
+    Ok((hits, outcomes))
+}
+
+/// Returns (server matches, newly cached, newest `limit` UIDs)
+async fn search_folder(
+    pool: &SqlitePool,
+    account: &Account,
+    imap: &mut PooledSession,
+    folder: &str,
+    criteria: &str,
+    limit: usize,
+) -> Result<(usize, usize, Vec<u32>)> {
+    imap.session.select(folder).await?;
+    let mut uids: Vec<u32> = imap.session.uid_search(criteria).await?.into_iter().collect();
+    let matched = uids.len();
+    uids.sort_unstable_by(|a, b| b.cmp(a));
+    uids.truncate(limit);
+    if uids.is_empty() {
+        return Ok((matched, 0, uids));
+    }
+
+    let cached: HashSet<i64> = load_hits(pool, &account.id, folder, &uids).await?.into_iter().map(|h| h.uid).collect();
+    let missing: Vec<u32> = uids.iter().copied().filter(|u| !cached.contains(&(*u as i64))).collect();
+    let inserted = if missing.is_empty() {
+        0
+    } else {
+        message_sync_service::cache_headers(pool, account, folder, &mut imap.session, &missing).await?
+    };
+    Ok((matched, inserted, uids))
+}
+
+async fn load_hits(pool: &SqlitePool, account_id: &str, folder: &str, uids: &[u32]) -> Result<Vec<SearchHit>> {
+    let mut hits = Vec::new();
+    for chunk in uids.chunks(500) {
+        let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let sql = format!(
+            "SELECT account_id, folder, uid, message_id, subject, from_addr, date, flags, COALESCE(has_attachments, 0) AS has_attachments, size \
+             FROM messages WHERE account_id = ? AND folder = ? AND uid IN ({}) ORDER BY uid DESC",
+            placeholders
+        );
+        let mut q = sqlx::query_as::<_, SearchHit>(&sql).bind(account_id).bind(folder);
+        for uid in chunk {
+            q = q.bind(*uid as i64);
+        }
+        hits.extend(q.fetch_all(pool).await?);
+    }
+    Ok(hits)
+}
+
+/// Local hits first, then server hits not already among them (same Message-ID, or the same
+/// cached row when there's none)
+pub fn merge(local: Vec<SearchHit>, server: Vec<SearchHit>) -> Vec<SearchHit> {
+    let mut ids: HashSet<String> = HashSet::new();
+    let mut rows: HashSet<(String, String, i64)> = HashSet::new();
+    let mut out = Vec::with_capacity(local.len() + server.len());
+    for hit in local.into_iter().chain(server) {
+        let new_row = rows.insert((hit.account_id.clone(), hit.folder.clone(), hit.uid));
+        let new_id = match hit.message_id.as_deref().map(|id| id.trim().to_ascii_lowercase()).filter(|id| !id.is_empty()) {
+            Some(id) => ids.insert(id),
+            None => true,
+        };
+        if new_row && new_id {
+            out.push(hit);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(folder: &str, uid: i64, message_id: Option<&str>) -> SearchHit {
+        SearchHit {
+            account_id: "a".into(),
+            folder: folder.into(),
+            uid,
+            message_id: message_id.map(str::to_string),
+            subject: None,
+            from_addr: None,
+            date: None,
+            flags: None,
+            has_attachments: false,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn test_criteria() {
+        let q = ServerQuery {
+            text: Some("invoice  march".into()),
+            unread: true,
+            since: parse_date("2023-01-05"),
+            until: parse_date("2023-02-28T00:00:00Z"),
+            ..Default::default()
+        };
+        assert_eq!(imap_criteria(&q), "TEXT \"invoice\" TEXT \"march\" UNSEEN SINCE 5-Jan-2023 BEFORE 1-Mar-2023");
+        assert_eq!(
+            gmail_criteria(&q, Some("in:inbox")),
+            "X-GM-RAW \"invoice march is:unread after:2023/01/05 before:2023/03/01 in:inbox\""
+        );
+        let q = ServerQuery { text: Some("fatura ödeme".into()), ..Default::default() };
+        assert_eq!(imap_criteria(&q), "CHARSET UTF-8 TEXT \"fatura\" TEXT \"ödeme\"");
+        assert_eq!(imap_criteria(&ServerQuery::default()), "ALL");
+        assert_eq!(gmail_scope("Work/Q&APw-", None).as_deref(), Some("label:\"Work/Qü\""));
+        assert_eq!(gmail_scope("[Gmail]/All Mail", Some("\\All")), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let local = vec![hit("INBOX", 10, Some("<a@x>")), hit("INBOX", 11, None)];
+        let server = vec![hit("Archive", 3, Some("<A@x>")), hit("INBOX", 11, None), hit("Archive", 4, None), hit("Archive", 5, Some("<b@x>"))];
+        let merged: Vec<(String, i64)> = merge(local, server).into_iter().map(|h| (h.folder, h.uid)).collect();
+        assert_eq!(merged, vec![("INBOX".into(), 10), ("INBOX".into(), 11), ("Archive".into(), 4), ("Archive".into(), 5)]);
+    }
+}
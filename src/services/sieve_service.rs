@@ -0,0 +1,197 @@
+/// Server-side filters over ManageSieve: where an account's Sieve server is, raw script
+/// management, and the hub's rule set kept in one script (`MAILORA_SIEVE_SCRIPT`, default
+/// "mailora").
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::models::account::{Account, SecurityMode};
+use crate::services::discovery_service::DiscoveryService;
+use crate::services::mail_auth_service;
+use crate::sieve::client::{self, ScriptInfo, SieveCapabilities, SieveClient};
+use crate::sieve::rules::{self, RuleSet};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SieveEndpoint {
+    pub host: String,
+    pub port: u16,
+    /// "account" (configured), "dns" (SRV record) or "imap_host" (guess)
+    pub source: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SieveStatus {
+    pub endpoint: SieveEndpoint,
+    pub capabilities: SieveCapabilities,
+    pub scripts: Vec<ScriptInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RulesState {
+    pub script: String,
+    /// Whether the hub's script is the active one
+    pub active: bool,
+    pub rules: RuleSet,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RulesOutcome {
+    pub script: String,
+    pub active: bool,
+    /// Script that was active before ours was activated (only one can be)
+    pub replaced_active: Option<String>,
+    pub sieve: String,
+}
+
+pub fn script_name() -> String {
+    std::env::var("MAILORA_SIEVE_SCRIPT").unwrap_or_else(|_| "mailora".to_string())
+}
+
+/// The account's ManageSieve server: configured, else `_sieve._tcp` SRV, else the IMAP host on 4190
+pub async fn endpoint(pool: &SqlitePool, account: &Account) -> Result<SieveEndpoint> {
+    let (host, port) = sqlx::query_as::<_, (Option<String>, Option<i64>)>(
+        "SELECT sieve_host, sieve_port FROM accounts WHERE id = ?",
+    )
+    .bind(&account.id)
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or_default();
+    let port = port.map(|p| p as u16).unwrap_or(client::DEFAULT_PORT);
+    if let Some(host) = host.filter(|h| !h.is_empty()) {
+        return Ok(SieveEndpoint { host, port, source: "account" });
+    }
+    if let Some(domain) = account.email.split('@').nth(1) {
+        if let Some(srv) = DiscoveryService::new().lookup_sieve_srv(domain).await {
+            return Ok(SieveEndpoint { host: srv.host, port: srv.port, source: "dns" });
+        }
+    }
+    Ok(SieveEndpoint { host: account.imap_host.clone(), port, source: "imap_host" })
+}
+
+async fn open(pool: &SqlitePool, account: &Account) -> Result<(SieveEndpoint, SieveClient)> {
+    let ep = endpoint(pool, account).await?;
+    // STARTTLS is mandatory on ManageSieve; plaintext only where IMAP is plaintext too (local test servers)
+    let security = match account.imap_security_mode() {
+        SecurityMode::None => SecurityMode::None,
+        _ => SecurityMode::StartTls,
+    };
+    let auth = mail_auth_service::for_account(account).await?;
+    let client = client::connect(&account.id, &ep.host, ep.port, security, &auth)
+        .await
+        .map_err(|e| anyhow::anyhow!("ManageSieve at {}:{}: {}", ep.host, ep.port, e))?;
+    Ok((ep, client))
+}
+
+/// Connect, report capabilities and scripts; an error means no usable Sieve server
+pub async fn status(pool: &SqlitePool, account: &Account) -> Result<SieveStatus> {
+    let (endpoint, mut client) = open(pool, account).await?;
+    let scripts = client.list_scripts().await?;
+    let capabilities = client.caps.clone();
+    client.logout().await;
+    Ok(SieveStatus { endpoint, capabilities, scripts })
+}
+
+pub async fn get_script(pool: &SqlitePool, account: &Account, name: &str) -> Result<String> {
+    let (_, mut client) = open(pool, account).await?;
+    let script = client.get_script(name).await;
+    client.logout().await;
+    script
+}
+
+/// `Ok(Err(reason))` when the server rejects the script
+pub async fn check_script(pool: &SqlitePool, account: &Account, script: &str) -> Result<std::result::Result<(), String>> {
+    let (_, mut client) = open(pool, account).await?;
+    let res = client.check_script(script).await;
+    client.logout().await;
+    res
+}
+
+/// Validate, upload and optionally activate a script. `Ok(Err(reason))` when it's invalid.
+pub async fn put_script(
+    pool: &SqlitePool,
+    account: &Account,
+    name: &str,
+    script: &str,
+    activate: bool,
+) -> Result<std::result::Result<Option<String>, String>> {
+    let (_, mut client) = open(pool, account).await?;
+    let res = upload(&mut client, name, script, activate).await;
+    client.logout().await;
+    res
+}
+
+async fn upload(
+    client: &mut SieveClient,
+    name: &str,
+    script: &str,
+    activate: bool,
+) -> Result<std::result::Result<Option<String>, String>> {
+    if let Err(reason) = client.check_script(script).await? {
+        return Ok(Err(reason));
+    }
+    client.put_script(name, script).await?;
+    if !activate {
+        return Ok(Ok(None));
+    }
+    let previous = client.list_scripts().await?.into_iter().find(|s| s.active && s.name != name);
+    client.set_active(name).await?;
+    Ok(Ok(previous.map(|s| s.name)))
+}
+
+pub async fn delete_script(pool: &SqlitePool, account: &Account, name: &str) -> Result<()> {
+    let (_, mut client) = open(pool, account).await?;
+    let res = client.delete_script(name).await;
+    client.logout().await;
+    res
+}
+
+/// Activate `name`, or deactivate every script with `None`
+pub async fn set_active(pool: &SqlitePool, account: &Account, name: Option<&str>) -> Result<()> {
+    let (_, mut client) = open(pool, account).await?;
+    let res = client.set_active(name.unwrap_or("")).await;
+    client.logout().await;
+    res
+}
+
+/// The hub's rule set as stored on the server (empty before the first save)
+pub async fn get_rules(pool: &SqlitePool, account: &Account) -> Result<RulesState> {
+    let script = script_name();
+    let (_, mut client) = open(pool, account).await?;
+    let res = async {
+        let Some(info) = client.list_scripts().await?.into_iter().find(|s| s.name == script) else {
+            return Ok(RulesState { script: script.clone(), active: false, rules: RuleSet::default() });
+        };
+        let body = client.get_script(&script).await?;
+        let rules = rules::extract(&body)
+            .ok_or_else(|| anyhow::anyhow!("script {:?} was not written by the hub; remove or rename it first", script))?;
+        Ok(RulesState { script: script.clone(), active: info.active, rules })
+    }
+    .await;
+    client.logout().await;
+    res
+}
+
+/// Compile the rule set, check it against the server's extensions and the server's own
+/// validation, upload it and activate it. `Ok(Err(reason))` when the rules can't be used.
+pub async fn put_rules(pool: &SqlitePool, account: &Account, set: &RuleSet) -> Result<std::result::Result<RulesOutcome, String>> {
+    if let Err(reason) = rules::validate(set) {
+        return Ok(Err(reason));
+    }
+    let sieve = rules::compile(set);
+    let script = script_name();
+
+    let (_, mut client) = open(pool, account).await?;
+    let unsupported: Vec<&str> = rules::required_extensions(set)
+        .into_iter()
+        .filter(|ext| !client.caps.supports(ext))
+        .collect();
+    let res = if !unsupported.is_empty() {
+        Ok(Err(format!("server does not support Sieve extension(s): {}", unsupported.join(", "))))
+    } else {
+        upload(&mut client, &script, &sieve, true).await.map(|r| {
+            r.map(|replaced_active| RulesOutcome { script: script.clone(), active: true, replaced_active, sieve })
+        })
+    };
+    client.logout().await;
+    res
+}
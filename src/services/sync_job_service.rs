@@ -0,0 +1,537 @@
+/// Sync job queue: every e-mail sync (user-triggered, scheduled, startup) runs as a job.
+///
+/// At most `MAILORA_SYNC_CONCURRENCY` jobs run at once and never two for the same account;
+/// user-triggered jobs are started before scheduled ones. Asking for a sync that is already
+/// queued or running joins that job. Jobs report progress (folder, fetched/total) to SSE
+/// subscribers, can be cancelled between batches and are recorded in `sync_jobs`.
+use anyhow::Result;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, watch};
+use tracing::{info, warn};
+
+use crate::services::account_service;
+use crate::services::message_sync_service::{self, SyncStats};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPriority {
+    /// Requested through the API; runs before anything scheduled
+    User,
+    /// Scheduler ticks and the startup sync
+    Scheduled,
+}
+
+impl JobPriority {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::User => "user",
+            Self::Scheduled => "scheduled",
+        }
+    }
+    fn parse(s: &str) -> Self {
+        if s == "user" { Self::User } else { Self::Scheduled }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+    /// Queued or running when the process stopped
+    Interrupted,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Done => "done",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+            Self::Interrupted => "interrupted",
+        }
+    }
+    fn parse(s: &str) -> Self {
+        match s {
+            "queued" => Self::Queued,
+            "running" => Self::Running,
+            "done" => Self::Done,
+            "cancelled" => Self::Cancelled,
+            "interrupted" => Self::Interrupted,
+            _ => Self::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobProgress {
+    /// Folder being synced
+    pub folder: Option<String>,
+    pub folders_done: usize,
+    pub folders_total: usize,
+    /// New messages fetched in the current folder, out of `total`
+    pub fetched: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncJob {
+    pub id: String,
+    pub account_id: String,
+    /// `None` syncs every folder of the account
+    pub folder: Option<String>,
+    pub priority: JobPriority,
+    pub status: JobStatus,
+    /// Live progress; only present while the job is running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<JobProgress>,
+    pub cancel_requested: bool,
+    pub new_messages: u32,
+    pub updated_messages: u32,
+    pub deleted_messages: u32,
+    pub folders_synced: u32,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
+
+type Outcome = std::result::Result<Vec<SyncStats>, String>;
+
+struct Entry {
+    job: SyncJob,
+    seq: u64,
+    cancel: Arc<AtomicBool>,
+    done: watch::Sender<Option<Outcome>>,
+}
+
+/// A queued or running job; `wait` resolves with its sync stats
+pub struct JobHandle {
+    pub job: SyncJob,
+    done: watch::Receiver<Option<Outcome>>,
+}
+
+impl JobHandle {
+    pub async fn wait(mut self) -> Outcome {
+        loop {
+            if let Some(outcome) = self.done.borrow().clone() {
+                return outcome;
+            }
+            if self.done.changed().await.is_err() {
+                return Err("sync job was dropped".to_string());
+            }
+        }
+    }
+}
+
+/// Queued and running jobs; finished jobs only live in `sync_jobs`
+static JOBS: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static EVENTS: Lazy<broadcast::Sender<SyncJob>> = Lazy::new(|| broadcast::channel(256).0);
+static SEQ: AtomicU64 = AtomicU64::new(0);
+static DB: OnceCell<SqlitePool> = OnceCell::new();
+
+tokio::task_local! {
+    /// The job the current task is running, for progress and cancellation checks deep in sync
+    static CURRENT: (String, Arc<AtomicBool>);
+}
+
+fn concurrency() -> usize {
+    std::env::var("MAILORA_SYNC_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(3)
+        .max(1)
+}
+
+/// Register the pool and close out jobs left over from the previous run
+pub async fn init(pool: &SqlitePool) -> Result<()> {
+    DB.set(pool.clone()).ok();
+    let now = chrono::Utc::now().timestamp();
+    let res = sqlx::query("UPDATE sync_jobs SET status = 'interrupted', finished_at = ? WHERE status IN ('queued', 'running')")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    if res.rows_affected() > 0 {
+        info!("sync jobs: marked {} unfinished job(s) from the last run as interrupted", res.rows_affected());
+    }
+    // History is for diagnosis, not an audit log
+    sqlx::query("DELETE FROM sync_jobs WHERE created_at < ?")
+        .bind(now - 30 * 86_400)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub fn subscribe() -> broadcast::Receiver<SyncJob> {
+    EVENTS.subscribe()
+}
+
+fn emit(job: &SyncJob) {
+    let _ = EVENTS.send(job.clone());
+}
+
+/// Queue a sync of one folder (or the whole account). Joins the job already queued or running
+/// for the same account and scope, raising its priority if needed.
+pub async fn enqueue(account_id: &str, folder: Option<&str>, priority: JobPriority) -> JobHandle {
+    let (handle, created) = {
+        let mut jobs = JOBS.lock().unwrap();
+        let existing = jobs.values_mut().find(|e| {
+            e.job.account_id == account_id && e.job.folder.as_deref() == folder && !e.job.cancel_requested
+        });
+        match existing {
+            Some(entry) => {
+                if priority < entry.job.priority && entry.job.status == JobStatus::Queued {
+                    entry.job.priority = priority;
+                    emit(&entry.job);
+                }
+                (JobHandle { job: entry.job.clone(), done: entry.done.subscribe() }, false)
+            }
+            None => {
+                let job = SyncJob {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    account_id: account_id.to_string(),
+                    folder: folder.map(str::to_string),
+                    priority,
+                    status: JobStatus::Queued,
+                    progress: None,
+                    cancel_requested: false,
+                    new_messages: 0,
+                    updated_messages: 0,
+                    deleted_messages: 0,
+                    folders_synced: 0,
+                    error: None,
+                    created_at: chrono::Utc::now().timestamp(),
+                    started_at: None,
+                    finished_at: None,
+                };
+                let (done, rx) = watch::channel(None);
+                let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+                jobs.insert(job.id.clone(), Entry { job: job.clone(), seq, cancel: Arc::new(AtomicBool::new(false)), done });
+                emit(&job);
+                (JobHandle { job, done: rx }, true)
+            }
+        }
+    };
+    if created {
+        if let Err(e) = persist(&handle.job, true).await {
+            warn!(job_id = %handle.job.id, "failed to record sync job: {e}");
+        }
+        pump();
+    }
+    handle
+}
+
+/// Start queued jobs while there is capacity, best priority first, one per account
+fn pump() {
+    let mut jobs = JOBS.lock().unwrap();
+    loop {
+        let running: HashSet<&str> = jobs
+            .values()
+            .filter(|e| e.job.status == JobStatus::Running)
+            .map(|e| e.job.account_id.as_str())
+            .collect();
+        if running.len() >= concurrency() {
+            return;
+        }
+        let next = jobs
+            .values()
+            .filter(|e| e.job.status == JobStatus::Queued && !running.contains(e.job.account_id.as_str()))
+            .min_by_key(|e| (e.job.priority, e.seq))
+            .map(|e| e.job.id.clone());
+        let Some(id) = next else { return };
+
+        let entry = jobs.get_mut(&id).expect("picked from the map");
+        entry.job.status = JobStatus::Running;
+        entry.job.started_at = Some(chrono::Utc::now().timestamp());
+        entry.job.progress = Some(JobProgress::default());
+        emit(&entry.job);
+        let (job, cancel) = (entry.job.clone(), entry.cancel.clone());
+        tokio::spawn(run(job, cancel));
+    }
+}
+
+async fn run(job: SyncJob, cancel: Arc<AtomicBool>) {
+    if let Err(e) = persist(&job, false).await {
+        warn!(job_id = %job.id, "failed to record sync job start: {e}");
+    }
+    let outcome = match DB.get() {
+        Some(pool) => {
+            // Own task, so a panic in the sync still finishes the job instead of leaving the
+            // account blocked and a concurrency slot taken
+            let (pool, scoped) = (pool.clone(), job.clone());
+            let task = tokio::spawn(CURRENT.scope((job.id.clone(), cancel.clone()), async move {
+                execute(&pool, &scoped).await
+            }));
+            match task.await {
+                Ok(res) => res.map_err(|e| e.to_string()),
+                Err(e) => Err(format!("sync crashed: {e}")),
+            }
+        }
+        None => Err("sync jobs are not initialised".to_string()),
+    };
+
+    let finished = {
+        let mut jobs = JOBS.lock().unwrap();
+        let Some(entry) = jobs.remove(&job.id) else { return };
+        let mut job = entry.job;
+        job.progress = None;
+        job.finished_at = Some(chrono::Utc::now().timestamp());
+        match &outcome {
+            Ok(stats) => {
+                job.status = JobStatus::Done;
+                job.folders_synced = stats.len() as u32;
+                job.new_messages = stats.iter().map(|s| s.new_messages).sum();
+                job.updated_messages = stats.iter().map(|s| s.updated_messages).sum();
+                job.deleted_messages = stats.iter().map(|s| s.deleted_messages).sum();
+            }
+            Err(e) => {
+                job.status = if cancel.load(Ordering::Relaxed) { JobStatus::Cancelled } else { JobStatus::Failed };
+                job.error = Some(e.clone());
+            }
+        }
+        let _ = entry.done.send(Some(outcome));
+        emit(&job);
+        job
+    };
+    match finished.status {
+        JobStatus::Done => info!(
+            job_id = %finished.id, account_id = %finished.account_id,
+            folders = finished.folders_synced, new = finished.new_messages,
+            "sync job completed"
+        ),
+        status => warn!(
+            job_id = %finished.id, account_id = %finished.account_id,
+            error = finished.error.as_deref().unwrap_or(""), "sync job {}", status.as_str()
+        ),
+    }
+    if let Err(e) = persist(&finished, false).await {
+        warn!(job_id = %finished.id, "failed to record sync job result: {e}");
+    }
+    pump();
+}
+
+async fn execute(pool: &SqlitePool, job: &SyncJob) -> Result<Vec<SyncStats>> {
+    let mut account = account_service::get_account(pool, &job.account_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account {} not found", job.account_id))?;
+    if account.password.is_empty() {
+        account = account.with_password()?;
+    }
+    match &job.folder {
+        Some(folder) => Ok(vec![message_sync_service::sync_folder_messages(pool, &account, folder).await?]),
+        None => {
+            let stats = message_sync_service::sync_account_messages(pool, &account).await?;
+            let _ = account_service::update_last_sync(pool, &account.id).await;
+            Ok(stats)
+        }
+    }
+}
+
+/// Cancel a job: a queued job is dropped, a running one stops before its next folder or batch.
+/// Returns `None` when the job is not queued or running.
+pub async fn cancel(id: &str) -> Option<SyncJob> {
+    let dropped = {
+        let mut jobs = JOBS.lock().unwrap();
+        let entry = jobs.get_mut(id)?;
+        if entry.job.status == JobStatus::Running {
+            entry.cancel.store(true, Ordering::Relaxed);
+            entry.job.cancel_requested = true;
+            emit(&entry.job);
+            return Some(entry.job.clone());
+        }
+        let entry = jobs.remove(id)?;
+        let mut job = entry.job;
+        job.status = JobStatus::Cancelled;
+        job.cancel_requested = true;
+        job.finished_at = Some(chrono::Utc::now().timestamp());
+        let _ = entry.done.send(Some(Err("sync job cancelled".to_string())));
+        emit(&job);
+        job
+    };
+    if let Err(e) = persist(&dropped, false).await {
+        warn!(job_id = %dropped.id, "failed to record sync job cancellation: {e}");
+    }
+    Some(dropped)
+}
+
+/// Whether the job running in this task was asked to stop; always false outside a job
+pub fn cancelled() -> bool {
+    CURRENT.try_with(|(_, cancel)| cancel.load(Ordering::Relaxed)).unwrap_or(false)
+}
+
+/// Bail out of a sync whose job was cancelled
+pub fn check_cancelled() -> Result<()> {
+    if cancelled() {
+        anyhow::bail!("sync job cancelled");
+    }
+    Ok(())
+}
+
+fn update_progress(f: impl FnOnce(&mut JobProgress)) {
+    let Ok(id) = CURRENT.try_with(|(id, _)| id.clone()) else { return };
+    let mut jobs = JOBS.lock().unwrap();
+    if let Some(entry) = jobs.get_mut(&id) {
+        f(entry.job.progress.get_or_insert_with(JobProgress::default));
+        emit(&entry.job);
+    }
+}
+
+/// Report that the current job starts folder `done + 1` of `total`
+pub fn report_folder(folder: &str, done: usize, total: usize) {
+    update_progress(|p| {
+        p.folder = Some(folder.to_string());
+        p.folders_done = done;
+        p.folders_total = total;
+        p.fetched = 0;
+        p.total = 0;
+    });
+}
+
+/// Report fetch progress within the current folder
+pub fn report_fetched(fetched: usize, total: usize) {
+    update_progress(|p| {
+        p.fetched = fetched;
+        p.total = total;
+    });
+}
+
+/// Queued and running jobs, in the order they will run
+pub fn active() -> Vec<SyncJob> {
+    let jobs = JOBS.lock().unwrap();
+    let mut active: Vec<(&SyncJob, u64)> = jobs.values().map(|e| (&e.job, e.seq)).collect();
+    active.sort_by_key(|(job, seq)| (job.status != JobStatus::Running, job.priority, *seq));
+    active.into_iter().map(|(job, _)| job.clone()).collect()
+}
+
+fn live(id: &str) -> Option<SyncJob> {
+    JOBS.lock().unwrap().get(id).map(|e| e.job.clone())
+}
+
+/// Job history, newest first; queued and running jobs carry their live progress
+pub async fn list(pool: &SqlitePool, account_id: Option<&str>, status: Option<&str>, limit: i64) -> Result<Vec<SyncJob>> {
+    let mut sql = String::from("SELECT * FROM sync_jobs WHERE 1=1");
+    if account_id.is_some() { sql.push_str(" AND account_id = ?"); }
+    if status.is_some() { sql.push_str(" AND status = ?"); }
+    sql.push_str(" ORDER BY created_at DESC, rowid DESC LIMIT ?");
+    let mut q = sqlx::query(&sql);
+    if let Some(a) = account_id { q = q.bind(a); }
+    if let Some(s) = status { q = q.bind(s); }
+    let rows = q.bind(limit).fetch_all(pool).await?;
+    Ok(rows.iter().map(|r| {
+        let job = from_row(r);
+        live(&job.id).unwrap_or(job)
+    }).collect())
+}
+
+pub async fn get(pool: &SqlitePool, id: &str) -> Result<Option<SyncJob>> {
+    if let Some(job) = live(id) {
+        return Ok(Some(job));
+    }
+    let row = sqlx::query("SELECT * FROM sync_jobs WHERE id = ?").bind(id).fetch_optional(pool).await?;
+    Ok(row.as_ref().map(from_row))
+}
+
+fn from_row(r: &sqlx::sqlite::SqliteRow) -> SyncJob {
+    SyncJob {
+        id: r.get("id"),
+        account_id: r.get("account_id"),
+        folder: r.get("folder"),
+        priority: JobPriority::parse(r.get("priority")),
+        status: JobStatus::parse(r.get("status")),
+        progress: None,
+        cancel_requested: false,
+        new_messages: r.get::<i64, _>("new_messages") as u32,
+        updated_messages: r.get::<i64, _>("updated_messages") as u32,
+        deleted_messages: r.get::<i64, _>("deleted_messages") as u32,
+        folders_synced: r.get::<i64, _>("folders_synced") as u32,
+        error: r.get("error"),
+        created_at: r.get("created_at"),
+        started_at: r.get("started_at"),
+        finished_at: r.get("finished_at"),
+    }
+}
+
+/// Write a job row; the initial insert must not overwrite a state recorded by the runner
+async fn persist(job: &SyncJob, initial: bool) -> Result<()> {
+    let Some(pool) = DB.get() else { return Ok(()) };
+    let verb = if initial { "INSERT OR IGNORE" } else { "INSERT OR REPLACE" };
+    sqlx::query(&format!(
+        "{verb} INTO sync_jobs (id, account_id, folder, priority, status, new_messages, updated_messages, deleted_messages, folders_synced, error, created_at, started_at, finished_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    ))
+    .bind(&job.id)
+    .bind(&job.account_id)
+    .bind(&job.folder)
+    .bind(job.priority.as_str())
+    .bind(job.status.as_str())
+    .bind(job.new_messages as i64)
+    .bind(job.updated_messages as i64)
+    .bind(job.deleted_messages as i64)
+    .bind(job.folders_synced as i64)
+    .bind(&job.error)
+    .bind(job.created_at)
+    .bind(job.started_at)
+    .bind(job.finished_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_jobs_run_first() {
+        assert!(JobPriority::User < JobPriority::Scheduled);
+        assert_eq!(JobPriority::parse(JobPriority::User.as_str()), JobPriority::User);
+        assert_eq!(JobStatus::parse("interrupted"), JobStatus::Interrupted);
+    }
+
+    #[tokio::test]
+    async fn test_queue_one_job_per_account_user_first() {
+        let mut events = subscribe();
+        let first = enqueue("queue-a", Some("INBOX"), JobPriority::Scheduled).await;
+        let scheduled = enqueue("queue-a", Some("Archive"), JobPriority::Scheduled).await;
+        let user = enqueue("queue-a", Some("Sent"), JobPriority::User).await;
+        let other = enqueue("queue-b", None, JobPriority::Scheduled).await;
+
+        // Asking for the same scope again joins the queued job
+        let joined = enqueue("queue-a", Some("Archive"), JobPriority::Scheduled).await;
+        assert_eq!(joined.job.id, scheduled.job.id);
+
+        // No pool in tests, so each run fails straight away and frees the account
+        let ids = [&first, &scheduled, &user].map(|h| h.job.id.clone());
+        for handle in [first, scheduled, user, other, joined] {
+            assert!(handle.wait().await.is_err());
+        }
+
+        let (mut running, mut started) = (HashSet::new(), Vec::new());
+        while let Ok(job) = events.try_recv() {
+            if job.account_id != "queue-a" {
+                continue;
+            }
+            match job.status {
+                JobStatus::Running if running.insert(job.id.clone()) => {
+                    assert_eq!(running.len(), 1, "two jobs running for one account");
+                    started.push(job.id);
+                }
+                JobStatus::Failed => {
+                    running.remove(&job.id);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(started, [ids[0].clone(), ids[2].clone(), ids[1].clone()]);
+        assert!(active().iter().all(|j| !j.account_id.starts_with("queue-")));
+    }
+}
@@ -0,0 +1,178 @@
+/// Per-account sync policy: which folders a full account sync visits and in what order, how
+/// far back new messages are downloaded, and up to which size bodies are prefetched.
+///
+/// Folder patterns match a folder's wire or display name (case-insensitively), a trailing `*`
+/// matches a prefix (`Archive/*`), and a special-use attribute matches by role (`\Junk`,
+/// `\Trash`). On Gmail only All Mail, Spam and Trash are synced as folders, so the lists only
+/// decide about those; All Mail is kept unless excluded explicitly.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::services::folder_service::FolderRecord;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncPolicy {
+    /// Folders to sync; empty means every selectable folder
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Folders to sync first, in this order; empty means INBOX first
+    #[serde(default)]
+    pub priority: Vec<String>,
+    /// Only download messages received in the last N days (UID SEARCH SINCE); cached older
+    /// messages are kept
+    pub window_days: Option<i64>,
+    /// Bodies of larger messages are only downloaded when opened (0: never prefetch);
+    /// defaults to `MAILORA_BODY_PREFETCH_MAX_BYTES`
+    pub max_body_bytes: Option<i64>,
+}
+
+type PolicyRow = (Option<String>, Option<String>, Option<String>, Option<i64>, Option<i64>);
+
+fn patterns(json: Option<String>) -> Vec<String> {
+    json.and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default()
+}
+
+fn patterns_json(list: &[String]) -> Result<Option<String>> {
+    Ok(if list.is_empty() { None } else { Some(serde_json::to_string(list)?) })
+}
+
+pub async fn load(pool: &SqlitePool, account_id: &str) -> Result<SyncPolicy> {
+    let row: Option<PolicyRow> = sqlx::query_as(
+        "SELECT sync_include, sync_exclude, sync_priority, sync_window_days, sync_max_body_bytes FROM accounts WHERE id = ?",
+    )
+    .bind(account_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((include, exclude, priority, window_days, max_body_bytes)) = row else {
+        return Ok(SyncPolicy::default());
+    };
+    Ok(SyncPolicy { include: patterns(include), exclude: patterns(exclude), priority: patterns(priority), window_days, max_body_bytes })
+}
+
+/// Replace the account's policy. A changed window resets the CONDSTORE cursors, so the next
+/// pass lists whole folders and picks up messages a narrower window skipped.
+pub async fn save(pool: &SqlitePool, account_id: &str, policy: &SyncPolicy) -> Result<()> {
+    let previous = load(pool, account_id).await?;
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE accounts SET sync_include = ?, sync_exclude = ?, sync_priority = ?, sync_window_days = ?, sync_max_body_bytes = ?, updated_at = strftime('%s','now') WHERE id = ?",
+    )
+    .bind(patterns_json(&policy.include)?)
+    .bind(patterns_json(&policy.exclude)?)
+    .bind(patterns_json(&policy.priority)?)
+    .bind(policy.window_days)
+    .bind(policy.max_body_bytes)
+    .bind(account_id)
+    .execute(&mut *tx)
+    .await?;
+    if previous.window_days != policy.window_days {
+        sqlx::query("UPDATE folder_sync_state SET highest_modseq = NULL WHERE account_id = ?")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub fn validate(policy: &SyncPolicy) -> Result<(), String> {
+    let lists = [&policy.include, &policy.exclude, &policy.priority];
+    if lists.iter().flat_map(|l| l.iter()).any(|p| p.trim().is_empty() || p.trim() == "*") {
+        return Err("folder patterns must name a folder, prefix or role".to_string());
+    }
+    if policy.window_days.is_some_and(|d| !(1..=36_500).contains(&d)) {
+        return Err("window_days must be between 1 and 36500".to_string());
+    }
+    if policy.max_body_bytes.is_some_and(|b| b < 0) {
+        return Err("max_body_bytes must not be negative".to_string());
+    }
+    Ok(())
+}
+
+fn matches(pattern: &str, folder: &FolderRecord) -> bool {
+    let pattern = pattern.trim();
+    if pattern.starts_with('\\') {
+        return folder.special_use.as_deref().is_some_and(|r| r.eq_ignore_ascii_case(pattern));
+    }
+    let names = [folder.name.as_str(), folder.display_name.as_str()];
+    match pattern.strip_suffix('*') {
+        Some(prefix) => names.iter().any(|n| {
+            n.get(..prefix.len()).is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+        }),
+        None => names.iter().any(|n| n.eq_ignore_ascii_case(pattern)),
+    }
+}
+
+pub fn allows(policy: &SyncPolicy, folder: &FolderRecord) -> bool {
+    (policy.include.is_empty() || policy.include.iter().any(|p| matches(p, folder)))
+        && !policy.exclude.iter().any(|p| matches(p, folder))
+}
+
+/// Position in the sync order; folders no priority pattern matches come last
+fn rank(policy: &SyncPolicy, folder: &FolderRecord) -> usize {
+    let inbox = ["INBOX".to_string()];
+    let priority: &[String] = if policy.priority.is_empty() { &inbox } else { &policy.priority };
+    priority.iter().position(|p| matches(p, folder)).unwrap_or(priority.len())
+}
+
+/// Selectable folders the policy allows, in sync order (catalogue order within a rank)
+pub fn select<'a>(policy: &SyncPolicy, folders: &'a [FolderRecord]) -> Vec<&'a FolderRecord> {
+    let mut out: Vec<&FolderRecord> = folders.iter().filter(|f| f.selectable && allows(policy, f)).collect();
+    out.sort_by_key(|f| rank(policy, f));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(name: &str, special_use: Option<&str>) -> FolderRecord {
+        FolderRecord {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            delimiter: Some("/".to_string()),
+            flags: Vec::new(),
+            special_use: special_use.map(str::to_string),
+            subscribed: true,
+            selectable: true,
+            total: None,
+            unread: None,
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let folders = vec![
+            folder("Archive", None),
+            folder("Archive/2019", None),
+            folder("Junk", Some("\\Junk")),
+            folder("Sent", Some("\\Sent")),
+            folder("INBOX", None),
+        ];
+        let names = |p: &SyncPolicy| select(p, &folders).iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(names(&SyncPolicy::default()), ["INBOX", "Archive", "Archive/2019", "Junk", "Sent"]);
+
+        let policy = SyncPolicy {
+            exclude: vec!["\\junk".into(), "archive/*".into()],
+            priority: vec!["\\Sent".into(), "inbox".into()],
+            ..Default::default()
+        };
+        assert_eq!(names(&policy), ["Sent", "INBOX", "Archive"]);
+
+        let policy = SyncPolicy { include: vec!["INBOX".into(), "Archive*".into()], ..Default::default() };
+        assert_eq!(names(&policy), ["INBOX", "Archive", "Archive/2019"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&SyncPolicy::default()).is_ok());
+        assert!(validate(&SyncPolicy { window_days: Some(0), ..Default::default() }).is_err());
+        assert!(validate(&SyncPolicy { max_body_bytes: Some(-1), ..Default::default() }).is_err());
+        assert!(validate(&SyncPolicy { exclude: vec![" ".into()], ..Default::default() }).is_err());
+        assert!(validate(&SyncPolicy { window_days: Some(90), max_body_bytes: Some(0), ..Default::default() }).is_ok());
+    }
+}
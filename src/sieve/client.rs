@@ -0,0 +1,433 @@
+/// ManageSieve (RFC 5804) client.
+///
+/// The protocol is line based: every command ends in `OK`, `NO` or `BYE`, optionally with a
+/// response code in parentheses and a human-readable string. Strings are quoted or literals
+/// (`{n}` CRLF followed by n octets); scripts are always sent as non-synchronizing literals
+/// (`{n+}`), which every server must accept.
+use anyhow::Result;
+use base64::Engine;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+use crate::imap::conn::{secure, MailStream};
+use crate::models::account::SecurityMode;
+use crate::services::mail_auth_service::MailAuth;
+
+/// IANA port for ManageSieve; STARTTLS is mandatory there
+pub const DEFAULT_PORT: u16 = 4190;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Atom(String),
+    Str(String),
+    /// Contents of a parenthesized response code
+    Code(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    No,
+    Bye,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: Status,
+    pub code: Option<String>,
+    pub message: Option<String>,
+    /// Lines before the status line
+    pub data: Vec<Vec<Token>>,
+}
+
+impl Response {
+    /// `NO`/`BYE` as an error carrying the server's explanation
+    fn ok(self, command: &str) -> Result<Self> {
+        if self.status == Status::Ok {
+            return Ok(self);
+        }
+        let mut reason = self.message.clone().unwrap_or_else(|| "no reason given".to_string());
+        if let Some(code) = &self.code {
+            reason = format!("[{}] {}", code, reason);
+        }
+        anyhow::bail!("{} failed: {}", command, reason)
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct SieveCapabilities {
+    pub implementation: Option<String>,
+    /// Sieve extensions the server supports (`fileinto`, `vacation`, `imap4flags`, ...)
+    pub extensions: Vec<String>,
+    pub sasl: Vec<String>,
+    pub starttls: bool,
+    /// Protocol version; servers that report one support CHECKSCRIPT
+    pub version: Option<String>,
+    pub max_redirects: Option<u32>,
+}
+
+impl SieveCapabilities {
+    fn from_lines(lines: &[Vec<Token>]) -> Self {
+        let mut caps = SieveCapabilities::default();
+        for line in lines {
+            let mut strs = line.iter().filter_map(|t| match t {
+                Token::Str(s) | Token::Atom(s) => Some(s.as_str()),
+                Token::Code(_) => None,
+            });
+            let (Some(name), value) = (strs.next(), strs.next()) else { continue };
+            let list = || value.unwrap_or("").split_whitespace().map(str::to_string).collect::<Vec<_>>();
+            match name.to_ascii_uppercase().as_str() {
+                "IMPLEMENTATION" => caps.implementation = value.map(str::to_string),
+                "SIEVE" => caps.extensions = list(),
+                "SASL" => caps.sasl = list().into_iter().map(|m| m.to_ascii_uppercase()).collect(),
+                "STARTTLS" => caps.starttls = true,
+                "VERSION" => caps.version = value.map(str::to_string),
+                "MAXREDIRECTS" => caps.max_redirects = value.and_then(|v| v.parse().ok()),
+                _ => {}
+            }
+        }
+        caps
+    }
+
+    pub fn supports(&self, extension: &str) -> bool {
+        self.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ScriptInfo {
+    pub name: String,
+    pub active: bool,
+}
+
+pub struct SieveClient {
+    stream: MailStream,
+    buf: Vec<u8>,
+    pub caps: SieveCapabilities,
+}
+
+/// Connect and authenticate; `account_id` selects the TLS certificate policy
+pub async fn connect(
+    account_id: &str,
+    host: &str,
+    port: u16,
+    security: SecurityMode,
+    auth: &MailAuth,
+) -> Result<SieveClient> {
+    timeout(Duration::from_secs(15), async {
+        security.ensure_allowed(host)?;
+        let tcp = TcpStream::connect((host, port)).await?;
+        let stream = match security {
+            SecurityMode::Tls => secure(account_id, host, port, tcp).await?,
+            _ => MailStream::Plain(tcp),
+        };
+        let mut client = SieveClient { stream, buf: Vec::new(), caps: SieveCapabilities::default() };
+        client.read_capabilities().await?;
+
+        if security == SecurityMode::StartTls {
+            if !client.caps.starttls {
+                anyhow::bail!("{}:{} does not offer STARTTLS", host, port);
+            }
+            client.command("STARTTLS").await?.ok("STARTTLS")?;
+            let MailStream::Plain(tcp) = client.stream else { anyhow::bail!("STARTTLS on a TLS stream") };
+            client.stream = secure(account_id, host, port, tcp).await?;
+            client.buf.clear();
+            // RFC 5804 §2.2: capabilities are sent again after the TLS handshake
+            client.read_capabilities().await?;
+        }
+
+        client.authenticate(auth).await?;
+        Ok(client)
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("ManageSieve connect to {}:{} timed out", host, port))?
+}
+
+impl SieveClient {
+    async fn read_capabilities(&mut self) -> Result<()> {
+        let resp = self.read_response().await?.ok("CAPABILITY")?;
+        self.caps = SieveCapabilities::from_lines(&resp.data);
+        Ok(())
+    }
+
+    async fn authenticate(&mut self, auth: &MailAuth) -> Result<()> {
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let (mechanism, initial) = match auth {
+            MailAuth::Password { user, password } => ("PLAIN", b64.encode(format!("\0{}\0{}", user, password))),
+            MailAuth::XOAuth2 { user, access_token } => (
+                "XOAUTH2",
+                b64.encode(crate::imap::xoauth2::generate_xoauth2_string(user, access_token)),
+            ),
+        };
+        if !self.caps.sasl.is_empty() && !self.caps.sasl.iter().any(|m| m == mechanism) {
+            anyhow::bail!("ManageSieve server does not offer SASL {}", mechanism);
+        }
+        self.write(format!("AUTHENTICATE {} {}\r\n", quote(mechanism), quote(&initial)).as_bytes()).await?;
+        loop {
+            let line = self.read_line().await?;
+            if let Some(resp) = status_line(&line) {
+                resp.ok("AUTHENTICATE")?;
+                return Ok(());
+            }
+            // A challenge after the initial response carries the error; answer empty to get NO
+            self.write(b"\"\"\r\n").await?;
+        }
+    }
+
+    pub async fn list_scripts(&mut self) -> Result<Vec<ScriptInfo>> {
+        let resp = self.command("LISTSCRIPTS").await?.ok("LISTSCRIPTS")?;
+        Ok(resp
+            .data
+            .iter()
+            .filter_map(|line| match line.as_slice() {
+                [Token::Str(name), rest @ ..] => Some(ScriptInfo {
+                    name: name.clone(),
+                    active: rest.iter().any(|t| matches!(t, Token::Atom(a) if a.eq_ignore_ascii_case("ACTIVE"))),
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub async fn get_script(&mut self, name: &str) -> Result<String> {
+        let resp = self.command(&format!("GETSCRIPT {}", quote(name))).await?.ok("GETSCRIPT")?;
+        resp.data
+            .iter()
+            .flatten()
+            .find_map(|t| match t {
+                Token::Str(s) => Some(s.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("GETSCRIPT returned no script"))
+    }
+
+    /// Upload (create or replace) a script; the server validates it and refuses invalid ones
+    pub async fn put_script(&mut self, name: &str, script: &str) -> Result<()> {
+        self.command(&format!("PUTSCRIPT {} {}", quote(name), literal(script))).await?.ok("PUTSCRIPT")?;
+        Ok(())
+    }
+
+    /// `Err` with the server's error text when the script does not compile. Servers without
+    /// CHECKSCRIPT (no VERSION capability) validate through a temporary PUTSCRIPT.
+    pub async fn check_script(&mut self, script: &str) -> Result<std::result::Result<(), String>> {
+        let resp = if self.caps.version.is_some() {
+            self.command(&format!("CHECKSCRIPT {}", literal(script))).await?
+        } else {
+            let name = "mailora-check";
+            let resp = self.command(&format!("PUTSCRIPT {} {}", quote(name), literal(script))).await?;
+            if resp.status == Status::Ok {
+                self.command(&format!("DELETESCRIPT {}", quote(name))).await?;
+            }
+            resp
+        };
+        Ok(match resp.status {
+            Status::Ok => Ok(()),
+            Status::No => Err(resp.message.unwrap_or_else(|| "script is invalid".to_string())),
+            Status::Bye => anyhow::bail!("server closed the connection: {}", resp.message.unwrap_or_default()),
+        })
+    }
+
+    /// Make `name` the active script; an empty name deactivates all scripts
+    pub async fn set_active(&mut self, name: &str) -> Result<()> {
+        self.command(&format!("SETACTIVE {}", quote(name))).await?.ok("SETACTIVE")?;
+        Ok(())
+    }
+
+    pub async fn delete_script(&mut self, name: &str) -> Result<()> {
+        self.command(&format!("DELETESCRIPT {}", quote(name))).await?.ok("DELETESCRIPT")?;
+        Ok(())
+    }
+
+    pub async fn logout(mut self) {
+        let _ = self.command("LOGOUT").await;
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Response> {
+        self.write(format!("{}\r\n", command).as_bytes()).await?;
+        self.read_response().await
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    async fn read_response(&mut self) -> Result<Response> {
+        let mut data = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if let Some(mut resp) = status_line(&line) {
+                resp.data = data;
+                return Ok(resp);
+            }
+            data.push(line);
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<Vec<Token>> {
+        loop {
+            if let Some((line, used)) = parse_line(&self.buf)? {
+                self.buf.drain(..used);
+                return Ok(line);
+            }
+            let mut chunk = [0u8; 4096];
+            let n = self.stream.read(&mut chunk).await?;
+            if n == 0 {
+                anyhow::bail!("ManageSieve connection closed");
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+fn status_line(line: &[Token]) -> Option<Response> {
+    let Some(Token::Atom(status)) = line.first() else { return None };
+    let status = match status.to_ascii_uppercase().as_str() {
+        "OK" => Status::Ok,
+        "NO" => Status::No,
+        "BYE" => Status::Bye,
+        _ => return None,
+    };
+    let code = line.iter().find_map(|t| match t {
+        Token::Code(c) => Some(c.clone()),
+        _ => None,
+    });
+    let message = line.iter().skip(1).find_map(|t| match t {
+        Token::Str(s) => Some(s.clone()),
+        _ => None,
+    });
+    Some(Response { status, code, message, data: Vec::new() })
+}
+
+/// Quoted string
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Non-synchronizing literal
+fn literal(s: &str) -> String {
+    format!("{{{}+}}\r\n{}", s.len(), s)
+}
+
+/// One response line from the start of `buf`, with the number of bytes it used; `None` when
+/// more input is needed
+pub fn parse_line(buf: &[u8]) -> Result<Option<(Vec<Token>, usize)>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    loop {
+        let Some(&c) = buf.get(i) else { return Ok(None) };
+        match c {
+            b' ' => i += 1,
+            b'\r' | b'\n' => {
+                if c == b'\r' {
+                    match buf.get(i + 1) {
+                        None => return Ok(None),
+                        Some(b'\n') => i += 1,
+                        Some(_) => {}
+                    }
+                }
+                return Ok(Some((tokens, i + 1)));
+            }
+            b'"' => {
+                let mut s = Vec::new();
+                i += 1;
+                loop {
+                    match buf.get(i) {
+                        None => return Ok(None),
+                        Some(b'\\') => {
+                            let Some(&next) = buf.get(i + 1) else { return Ok(None) };
+                            s.push(next);
+                            i += 2;
+                        }
+                        Some(b'"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&b) => {
+                            s.push(b);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(String::from_utf8_lossy(&s).into_owned()));
+            }
+            b'{' => {
+                let Some(close) = buf[i..].iter().position(|&b| b == b'}') else { return Ok(None) };
+                let len: usize = std::str::from_utf8(&buf[i + 1..i + close])?
+                    .trim_end_matches('+')
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("bad literal length"))?;
+                let mut start = i + close + 1;
+                if buf.get(start) == Some(&b'\r') {
+                    start += 1;
+                }
+                match buf.get(start) {
+                    None => return Ok(None),
+                    Some(b'\n') => start += 1,
+                    Some(_) => anyhow::bail!("literal length not followed by CRLF"),
+                }
+                if buf.len() < start + len {
+                    return Ok(None);
+                }
+                tokens.push(Token::Str(String::from_utf8_lossy(&buf[start..start + len]).into_owned()));
+                i = start + len;
+            }
+            b'(' => {
+                let Some(close) = buf[i..].iter().position(|&b| b == b')') else { return Ok(None) };
+                tokens.push(Token::Code(String::from_utf8_lossy(&buf[i + 1..i + close]).into_owned()));
+                i += close + 1;
+            }
+            _ => {
+                let end = buf[i..]
+                    .iter()
+                    .position(|&b| matches!(b, b' ' | b'\r' | b'\n' | b'(' | b'"'))
+                    .map(|p| i + p);
+                let Some(end) = end else { return Ok(None) };
+                tokens.push(Token::Atom(String::from_utf8_lossy(&buf[i..end]).into_owned()));
+                i = end;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(mut input: &[u8]) -> Vec<Vec<Token>> {
+        let mut out = Vec::new();
+        while let Some((line, used)) = parse_line(input).unwrap() {
+            out.push(line);
+            input = &input[used..];
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_greeting() {
+        let greeting = b"\"IMPLEMENTATION\" \"Dovecot Pigeonhole\"\r\n\"SIEVE\" \"fileinto vacation imap4flags\"\r\n\"STARTTLS\"\r\n\"SASL\" \"plain\"\r\n\"VERSION\" \"1.0\"\r\nOK \"Ready.\"\r\n";
+        let parsed = lines(greeting);
+        assert_eq!(parsed.len(), 6);
+        let resp = status_line(&parsed[5]).unwrap();
+        assert_eq!(resp.status, Status::Ok);
+        let caps = SieveCapabilities::from_lines(&parsed[..5]);
+        assert!(caps.starttls && caps.supports("vacation") && caps.version.is_some());
+        assert_eq!(caps.sasl, vec!["PLAIN"]);
+    }
+
+    #[test]
+    fn test_parse_literal_and_codes() {
+        let input = b"{19}\r\nkeep;\r\n# \"quoted\" \\\r\nOK\r\nNO (QUOTA/MAXSIZE) \"Script too big\"\r\n";
+        let parsed = lines(input);
+        assert_eq!(parsed[0], vec![Token::Str("keep;\r\n# \"quoted\" \\".into())]);
+        let no = status_line(&parsed[2]).unwrap();
+        assert_eq!(no.status, Status::No);
+        assert_eq!(no.code.as_deref(), Some("QUOTA/MAXSIZE"));
+        assert_eq!(no.message.as_deref(), Some("Script too big"));
+        // Incomplete input waits for more
+        assert!(parse_line(b"{10}\r\nabc").unwrap().is_none());
+        assert!(parse_line(b"\"unterminated").unwrap().is_none());
+    }
+}
@@ -0,0 +1,3 @@
+// Server-side filtering: ManageSieve client and the hub's rule model compiled to Sieve
+pub mod client;
+pub mod rules;
@@ -0,0 +1,309 @@
+/// Structured filter rules and vacation reply, compiled to one Sieve script (RFC 5228).
+///
+/// The generated script carries its rule set as a JSON comment, so the hub reads back exactly
+/// what it wrote instead of parsing Sieve. Folder names are wire names (modified UTF-7) like
+/// everywhere else in the hub; Sieve wants UTF-8, so `fileinto` gets the decoded form.
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::imap::folders::decode_utf7;
+
+const MARKER: &str = "# mailora-rules: ";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub vacation: Option<Vacation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Match when any condition holds instead of all of them
+    #[serde(default)]
+    pub any: bool,
+    /// No conditions: the rule applies to every message
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Condition {
+    /// "from", "to", "cc", "recipient" (to or cc), "subject", "size" or any header name
+    pub field: String,
+    pub op: MatchOp,
+    pub value: String,
+    #[serde(default)]
+    pub not: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchOp {
+    Contains,
+    Is,
+    /// Wildcards `*` and `?`
+    Matches,
+    /// `size` only; value such as "500K" or "10M"
+    Over,
+    Under,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    FileInto { folder: String },
+    Copy { folder: String },
+    Redirect { address: String },
+    Flag { flag: String },
+    MarkRead,
+    Discard,
+    Reject { reason: String },
+    Keep,
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Vacation {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub subject: Option<String>,
+    pub body: String,
+    /// Minimum days between replies to the same sender
+    pub days: Option<u32>,
+    /// The account's other addresses, so mail to them is answered too
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    pub from: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Sieve extensions a rule set needs, in `require` order
+pub fn required_extensions(set: &RuleSet) -> Vec<&'static str> {
+    let mut req = Vec::new();
+    let mut need = |ext: &'static str| {
+        if !req.contains(&ext) {
+            req.push(ext);
+        }
+    };
+    for action in set.rules.iter().filter(|r| r.enabled).flat_map(|r| &r.actions) {
+        match action {
+            Action::FileInto { .. } => need("fileinto"),
+            Action::Copy { .. } => {
+                need("fileinto");
+                need("copy");
+            }
+            Action::Flag { .. } | Action::MarkRead => need("imap4flags"),
+            Action::Reject { .. } => need("reject"),
+            _ => {}
+        }
+    }
+    if set.vacation.as_ref().is_some_and(|v| v.enabled) {
+        need("vacation");
+    }
+    req
+}
+
+/// Validate what the model can't express in types; the server checks the script itself
+pub fn validate(set: &RuleSet) -> Result<(), String> {
+    for rule in &set.rules {
+        if rule.actions.is_empty() {
+            return Err(format!("rule {:?} has no actions", rule.name));
+        }
+        for c in &rule.conditions {
+            let size = c.field.eq_ignore_ascii_case("size");
+            match c.op {
+                MatchOp::Over | MatchOp::Under if !size => {
+                    return Err(format!("rule {:?}: over/under only apply to size", rule.name))
+                }
+                MatchOp::Contains | MatchOp::Is | MatchOp::Matches if size => {
+                    return Err(format!("rule {:?}: size takes over/under", rule.name))
+                }
+                _ => {}
+            }
+            if size && !valid_size(&c.value) {
+                return Err(format!("rule {:?}: size {:?} is not a number with optional K/M/G", rule.name, c.value));
+            }
+            if !size && !c.field.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-') {
+                return Err(format!("rule {:?}: {:?} is not a header name", rule.name, c.field));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn valid_size(v: &str) -> bool {
+    let digits = v.trim_end_matches(['K', 'M', 'G', 'k', 'm', 'g']);
+    !digits.is_empty() && digits.len() + 1 >= v.len() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Sieve quoted string
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn string_list(items: &[String]) -> String {
+    format!("[{}]", items.iter().map(|s| quoted(s)).collect::<Vec<_>>().join(", "))
+}
+
+fn test(c: &Condition) -> String {
+    let op = match c.op {
+        MatchOp::Contains => ":contains",
+        MatchOp::Is => ":is",
+        MatchOp::Matches => ":matches",
+        MatchOp::Over => ":over",
+        MatchOp::Under => ":under",
+    };
+    let field = c.field.to_ascii_lowercase();
+    let t = match field.as_str() {
+        "size" => format!("size {} {}", op, c.value.to_ascii_uppercase()),
+        "from" | "to" | "cc" => format!("address {} {} {}", op, quoted(&field), quoted(&c.value)),
+        "recipient" => format!("address {} [\"to\", \"cc\"] {}", op, quoted(&c.value)),
+        _ => format!("header {} {} {}", op, quoted(&field), quoted(&c.value)),
+    };
+    if c.not {
+        format!("not {}", t)
+    } else {
+        t
+    }
+}
+
+fn action(a: &Action) -> String {
+    match a {
+        Action::FileInto { folder } => format!("fileinto {};", quoted(&decode_utf7(folder))),
+        Action::Copy { folder } => format!("fileinto :copy {};", quoted(&decode_utf7(folder))),
+        Action::Redirect { address } => format!("redirect {};", quoted(address)),
+        Action::Flag { flag } => format!("addflag {};", quoted(flag)),
+        Action::MarkRead => "addflag \"\\\\Seen\";".to_string(),
+        Action::Discard => "discard;".to_string(),
+        Action::Reject { reason } => format!("reject {};", quoted(reason)),
+        Action::Keep => "keep;".to_string(),
+        Action::Stop => "stop;".to_string(),
+    }
+}
+
+/// The Sieve script for a rule set
+pub fn compile(set: &RuleSet) -> String {
+    let mut out = String::new();
+    out.push_str("# Generated by Mailora Hub; changes made here are overwritten from the hub.\n");
+    let json = serde_json::to_vec(set).unwrap_or_default();
+    out.push_str(MARKER);
+    out.push_str(&base64::engine::general_purpose::STANDARD.encode(json));
+    out.push('\n');
+
+    let req = required_extensions(set);
+    if !req.is_empty() {
+        let req: Vec<String> = req.into_iter().map(str::to_string).collect();
+        out.push_str(&format!("require {};\n", string_list(&req)));
+    }
+
+    // Vacation first: it must see every message, including ones a rule files away
+    if let Some(v) = set.vacation.as_ref().filter(|v| v.enabled) {
+        out.push_str("\nvacation");
+        if let Some(days) = v.days {
+            out.push_str(&format!(" :days {}", days));
+        }
+        if let Some(subject) = &v.subject {
+            out.push_str(&format!(" :subject {}", quoted(subject)));
+        }
+        if let Some(from) = &v.from {
+            out.push_str(&format!(" :from {}", quoted(from)));
+        }
+        if !v.addresses.is_empty() {
+            out.push_str(&format!(" :addresses {}", string_list(&v.addresses)));
+        }
+        out.push_str(&format!(" {};\n", quoted(&v.body)));
+    }
+
+    for rule in set.rules.iter().filter(|r| r.enabled) {
+        out.push_str(&format!("\n# {}\n", rule.name.replace('\n', " ")));
+        let body: String = rule.actions.iter().map(|a| format!("    {}\n", action(a))).collect();
+        let tests: Vec<String> = rule.conditions.iter().map(test).collect();
+        match tests.len() {
+            0 => rule.actions.iter().for_each(|a| out.push_str(&format!("{}\n", action(a)))),
+            1 => out.push_str(&format!("if {} {{\n{}}}\n", tests[0], body)),
+            _ => out.push_str(&format!(
+                "if {}({}) {{\n{}}}\n",
+                if rule.any { "anyof" } else { "allof" },
+                tests.join(", "),
+                body
+            )),
+        }
+    }
+    out
+}
+
+/// The rule set a script generated by [`compile`] was built from
+pub fn extract(script: &str) -> Option<RuleSet> {
+    let encoded = script.lines().find_map(|l| l.strip_prefix(MARKER))?;
+    let json = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> RuleSet {
+        RuleSet {
+            rules: vec![
+                Rule {
+                    name: "Invoices".into(),
+                    enabled: true,
+                    any: true,
+                    conditions: vec![
+                        Condition { field: "from".into(), op: MatchOp::Contains, value: "billing@".into(), not: false },
+                        Condition { field: "subject".into(), op: MatchOp::Matches, value: "Fatura*".into(), not: false },
+                    ],
+                    actions: vec![Action::FileInto { folder: "G&APY-nderilmi&AV8-".into() }, Action::MarkRead, Action::Stop],
+                },
+                Rule {
+                    name: "Big".into(),
+                    enabled: true,
+                    any: false,
+                    conditions: vec![Condition { field: "size".into(), op: MatchOp::Over, value: "10M".into(), not: false }],
+                    actions: vec![Action::Flag { flag: "\\Flagged".into() }],
+                },
+            ],
+            vacation: Some(Vacation {
+                enabled: true,
+                subject: Some("Out of office".into()),
+                body: "Back on \"Monday\".".into(),
+                days: Some(7),
+                addresses: vec![],
+                from: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        let script = compile(&sample());
+        assert!(script.contains("require [\"fileinto\", \"imap4flags\", \"vacation\"];"));
+        assert!(script.contains("vacation :days 7 :subject \"Out of office\" \"Back on \\\"Monday\\\".\";"));
+        assert!(script.contains(
+            "if anyof(address :contains \"from\" \"billing@\", header :matches \"subject\" \"Fatura*\") {\n    fileinto \"Gönderilmiş\";\n    addflag \"\\\\Seen\";\n    stop;\n}"
+        ));
+        assert!(script.contains("if size :over 10M {\n    addflag \"\\\\Flagged\";\n}"));
+        assert_eq!(extract(&script), Some(sample()));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&sample()).is_ok());
+        let mut bad = sample();
+        bad.rules[1].conditions[0].value = "lots".into();
+        assert!(validate(&bad).is_err());
+        bad.rules[1].conditions[0] = Condition { field: "subject".into(), op: MatchOp::Over, value: "1".into(), not: false };
+        assert!(validate(&bad).is_err());
+        assert!(valid_size("500K") && valid_size("12") && !valid_size("K") && !valid_size("5KK"));
+    }
+}
@@ -48,6 +48,9 @@ use lettre::transport::smtp::authentication::Credentials;
 use lettre::{Message, SmtpTransport, Transport};
 use std::env;
 
+use crate::models::account::SecurityMode;
+use crate::services::mail_auth_service::MailAuth;
+
 pub struct SmtpClient {
     smtp_transport: SmtpTransport,
 }
@@ -86,37 +89,95 @@ impl SmtpClient {
     }
 }
 
-pub fn send_simple(
+/// lettre TLS setting for an SMTP security mode (implicit TLS, STARTTLS or plaintext)
+pub fn tls_for(
+    security: SecurityMode,
+    host: &str,
+    params: lettre::transport::smtp::client::TlsParameters,
+) -> Result<lettre::transport::smtp::client::Tls> {
+    use lettre::transport::smtp::client::Tls;
+    security.ensure_allowed(host)?;
+    Ok(match security {
+        SecurityMode::Tls => Tls::Wrapper(params),
+        SecurityMode::StartTls => Tls::Required(params),
+        SecurityMode::None => Tls::None,
+    })
+}
+
+/// Send over the account's transport. Pinned accounts get a one-off connection whose certificate
+/// is checked before credentials go out (lettre's pooled transport hides the peer certificate).
+#[allow(clippy::too_many_arguments)]
+fn deliver(
+    account_id: &str,
     host: &str,
     port: u16,
-    username: &str,
-    password: &str,
-    to: &str,
-    subject: &str,
-    body: &str,
+    security: SecurityMode,
+    auth: &MailAuth,
+    hello: ClientId,
+    timeout: std::time::Duration,
+    msg: &Message,
 ) -> Result<()> {
-    use lettre::{
-        transport::smtp::{
-            authentication::{Credentials, Mechanism},
-            client::{Tls, TlsParameters},
-        },
-        Message, SmtpTransport, Transport,
+    use lettre::transport::smtp::client::SmtpConnection;
+
+    let (creds, mechanisms) = match auth {
+        // Trim whitespace that may sneak in from copied app passwords
+        MailAuth::Password { user, password } => (
+            Credentials::new(user.clone(), password.chars().filter(|c| !c.is_whitespace()).collect()),
+            vec![Mechanism::Plain, Mechanism::Login],
+        ),
+        MailAuth::XOAuth2 { user, access_token } => {
+            (Credentials::new(user.clone(), access_token.clone()), vec![Mechanism::Xoauth2])
+        }
     };
-    use std::net::IpAddr;
-    use std::time::Duration;
-
-    // Trim whitespace that may sneak in from copied app passwords
-    let clean_password: String = password.chars().filter(|c| !c.is_whitespace()).collect();
-    let creds = Credentials::new(username.to_string(), clean_password);
-
+    let pinned = crate::tls::accepts_self_signed(account_id);
     let tls = TlsParameters::builder(host.into())
-        .dangerous_accept_invalid_certs(true)
+        .dangerous_accept_invalid_certs(pinned)
         .build()?;
 
-    let mut builder = match SmtpTransport::relay(host) {
+    if pinned && security != SecurityMode::None {
+        let mut conn = if security == SecurityMode::Tls {
+            SmtpConnection::connect((host, port), Some(timeout), &hello, Some(&tls), None)?
+        } else {
+            let mut conn = SmtpConnection::connect((host, port), Some(timeout), &hello, None, None)?;
+            conn.starttls(&tls, &hello)?;
+            conn
+        };
+        crate::tls::check_peer(account_id, host, port, &conn.peer_certificate()?)?;
+        conn.auth(&mechanisms, &creds)?;
+        conn.send(msg.envelope(), &msg.formatted())?;
+        conn.quit().ok();
+        return Ok(());
+    }
+
+    let builder = match SmtpTransport::relay(host) {
         Ok(b) => b,
         Err(_) => SmtpTransport::builder_dangerous(host),
     };
+    let mailer = builder
+        .port(port)
+        .hello_name(hello)
+        .authentication(mechanisms)
+        .credentials(creds)
+        .timeout(Some(timeout))
+        .tls(tls_for(security, host, tls)?)
+        .build();
+    mailer.send(msg)?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn send_simple(
+    account_id: &str,
+    host: &str,
+    port: u16,
+    security: SecurityMode,
+    auth: &MailAuth,
+    to: &str,
+    subject: &str,
+    body: &str,
+) -> Result<()> {
+    use std::net::IpAddr;
+    use std::time::Duration;
 
     let client_id = std::env::var("SMTP_HELLO_NAME")
         .ok()
@@ -130,22 +191,7 @@ pub fn send_simple(
                 .unwrap_or_else(|_| ClientId::Domain(host.to_string()))
         });
 
-    builder = builder
-        .port(port)
-        .hello_name(client_id)
-        .authentication(vec![Mechanism::Plain, Mechanism::Login])
-        .credentials(creds)
-        .timeout(Some(Duration::from_secs(20)));
-
-    let builder = if port == 465 {
-        builder.tls(Tls::Wrapper(tls))
-    } else {
-        builder.tls(Tls::Required(tls))
-    };
-
-    let mailer = builder.build();
-
-    let from_addr = username.parse()?;
+    let from_addr = auth.user().parse()?;
     let to_addr = to.parse()?;
     let email = Message::builder()
         .from(from_addr)
@@ -153,11 +199,11 @@ pub fn send_simple(
         .subject(subject)
         .body(body.to_string())?;
 
-    match mailer.send(&email) {
+    match deliver(account_id, host, port, security, auth, client_id, Duration::from_secs(20), &email) {
         Ok(_) => Ok(()),
         Err(e) => {
             tracing::error!("SMTP gönderim başarısız: {:?}", e);
-            Err(e.into())
+            Err(e)
         }
     }
 }
@@ -185,42 +231,19 @@ pub fn build_email(from: &str, to: &str, subject: &str, body: &str) -> Result<(M
 
 /// Send a prebuilt Message via lettre
 pub fn send_prebuilt(
+    account_id: &str,
     host: &str,
     port: u16,
-    username: &str,
-    password: &str,
+    security: SecurityMode,
+    auth: &MailAuth,
     msg: &Message,
 ) -> Result<()> {
-    use lettre::transport::smtp::{authentication::Mechanism, client::{Tls, TlsParameters}};
-
-    let clean_password: String = password.chars().filter(|c| !c.is_whitespace()).collect();
-    let creds = Credentials::new(username.to_string(), clean_password);
-
-    let tls = TlsParameters::builder(host.into())
-        .dangerous_accept_invalid_certs(true)
-        .build()?;
-
-    let mut builder = match SmtpTransport::relay(host) {
-        Ok(b) => b,
-        Err(_) => SmtpTransport::builder_dangerous(host),
-    };
-
     let client_id = std::env::var("SMTP_HELLO_NAME")
         .ok()
-        .map(|val| ClientId::Domain(val))
+        .map(ClientId::Domain)
         .unwrap_or_else(|| ClientId::Domain(host.to_string()));
 
-    builder = builder
-        .port(port)
-        .hello_name(client_id)
-        .authentication(vec![Mechanism::Plain, Mechanism::Login])
-        .credentials(creds);
-
-    let builder = if port == 465 { builder.tls(Tls::Wrapper(tls)) } else { builder.tls(Tls::Required(tls)) };
-
-    let mailer = builder.build();
-    mailer.send(msg)?;
-    Ok(())
+    deliver(account_id, host, port, security, auth, client_id, std::time::Duration::from_secs(60), msg)
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -237,18 +260,26 @@ pub async fn append_to_sent(
     from_addr: &str,
     subject: &str,
 ) -> Result<AppendResult> {
-    use crate::imap::conn;
+    use crate::imap::pool;
 
-    let mut imap = conn::connect(&account.imap_host, account.imap_port, &account.email, &account.password).await?;
+    let mut imap = pool::acquire_account(account).await?;
     let session = &mut imap.session;
 
     // Collect Sent candidates
     let mut candidates: Vec<String> = Vec::new();
+    let mut sent_role: Option<String> = None;
     if let Ok(list_stream) = session.list(None, Some("*" )).await {
         use futures::StreamExt;
         let mut names = Vec::new();
         let mut s = list_stream;
-        while let Some(item) = s.next().await { if let Ok(m) = item { names.push(m.name().to_string()); } }
+        while let Some(item) = s.next().await {
+            if let Ok(m) = item {
+                if sent_role.is_none() && crate::imap::folders::special_use(m.attributes()) == Some("\\Sent") {
+                    sent_role = Some(m.name().to_string());
+                }
+                names.push(m.name().to_string());
+            }
+        }
         candidates = crate::imap::folders::detect_sent_candidates(&names);
     }
     if candidates.is_empty() {
@@ -267,6 +298,10 @@ pub async fn append_to_sent(
             if pos != 0 { let f = candidates.remove(pos); candidates.insert(0, f); }
         }
     }
+    // The RFC 6154 \Sent folder beats any name-based guess
+    if let Some(sent) = sent_role.as_deref() {
+        crate::imap::folders::promote(&mut candidates, sent);
+    }
 
     // Providers that auto-save Sent: do not APPEND unless policy overrides
     let policy = account.append_policy_enum();
@@ -334,7 +369,6 @@ pub async fn append_to_sent(
     // If found without APPEND, set Seen and return
     if let (Some(folder), Some(uid)) = (found_folder.clone(), uid_opt) {
         let _ = session.uid_store(&uid.to_string(), "+FLAGS (\\Seen)").await;
-        let _ = session.logout().await;
         return Ok(AppendResult { folder, uid: Some(uid) });
     }
 
@@ -358,12 +392,10 @@ pub async fn append_to_sent(
         if uid_opt.is_none() && account.provider.as_str() != "gmail" { for q in &fallback_variants { if let Ok(uids) = session.uid_search(q).await { if let Some(uid) = uids.iter().copied().max() { uid_opt = Some(uid); break; } } } }
         // If still none, fallback to existing retry loop below
         if uid_opt.is_some() { let _ = session.uid_store(&uid_opt.unwrap().to_string(), "+FLAGS (\\Seen)").await; }
-        let _ = session.logout().await;
         return Ok(AppendResult { folder, uid: uid_opt });
     } else {
         // Skip APPEND for auto-sent providers; return expected Sent folder and uid if any
         let folder = candidates.first().cloned().unwrap_or_else(|| "Sent".to_string());
-        let _ = session.logout().await;
         return Ok(AppendResult { folder, uid: uid_opt });
     }
 }
@@ -382,7 +414,9 @@ pub async fn send_and_log(
 ) -> Result<()> {
     // Send email
     // Varsayılan olarak 587 portu kullanılır, istenirse parametre eklenebilir
-    send_simple(host, 587, username, password, to, subject, body)?;
+    // No account behind the legacy mailbox, so the default (verifying) certificate policy applies
+    let auth = MailAuth::password(username, password);
+    send_simple("", host, 587, SecurityMode::for_smtp_port(587), &auth, to, subject, body)?;
 
     // Log OUT event
     let ts = std::time::SystemTime::now()
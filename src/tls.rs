@@ -0,0 +1,217 @@
+/// Per-account TLS certificate trust shared by IMAP, SMTP and DAV connections.
+///
+/// `verify` (the default) requires a valid CA chain and hostname. `pin` is for self-signed
+/// servers: chain validation is skipped, the leaf certificate's SHA-256 fingerprint is pinned
+/// per `host:port` on first connect and any later mismatch is refused. Policies and pins live
+/// in the database and are cached here so synchronous transports can check them too.
+use anyhow::Result;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsPolicy {
+    #[default]
+    Verify,
+    Pin,
+}
+
+impl TlsPolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "verify" => Some(Self::Verify),
+            "pin" | "tofu" => Some(Self::Pin),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Verify => "verify",
+            Self::Pin => "pin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Pin {
+    pub endpoint: String,
+    pub sha256: String,
+}
+
+#[derive(Default)]
+struct Trust {
+    policies: HashMap<String, TlsPolicy>,
+    /// (account id, host:port) -> fingerprint
+    pins: HashMap<(String, String), String>,
+}
+
+static TRUST: Lazy<RwLock<Trust>> = Lazy::new(|| RwLock::new(Trust::default()));
+static DB: OnceCell<SqlitePool> = OnceCell::new();
+
+/// Load policies and pins; pins seen later are written back through `pool`
+pub async fn load(pool: &SqlitePool) -> Result<()> {
+    DB.set(pool.clone()).ok();
+    let accounts = sqlx::query("SELECT id, tls_policy FROM accounts").fetch_all(pool).await?;
+    let pins = sqlx::query("SELECT account_id, endpoint, sha256 FROM tls_pins").fetch_all(pool).await?;
+
+    let mut trust = TRUST.write().unwrap();
+    for row in accounts {
+        let policy: Option<String> = row.try_get("tls_policy").unwrap_or(None);
+        if let Some(policy) = policy.as_deref().and_then(TlsPolicy::parse) {
+            trust.policies.insert(row.get("id"), policy);
+        }
+    }
+    for row in pins {
+        trust.pins.insert((row.get("account_id"), row.get("endpoint")), row.get("sha256"));
+    }
+    Ok(())
+}
+
+/// Policy of an account; unknown accounts are verified
+pub fn policy(account_id: &str) -> TlsPolicy {
+    TRUST.read().unwrap().policies.get(account_id).copied().unwrap_or_default()
+}
+
+/// Change the policy (persisted); existing pins are kept until cleared
+pub async fn set_policy(pool: &SqlitePool, account_id: &str, policy: TlsPolicy) -> Result<()> {
+    sqlx::query("UPDATE accounts SET tls_policy = ? WHERE id = ?")
+        .bind(policy.as_str())
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    TRUST.write().unwrap().policies.insert(account_id.to_string(), policy);
+    crate::imap::pool::invalidate(account_id);
+    Ok(())
+}
+
+pub fn pins(account_id: &str) -> Vec<Pin> {
+    let trust = TRUST.read().unwrap();
+    let mut pins: Vec<Pin> = trust
+        .pins
+        .iter()
+        .filter(|((id, _), _)| id == account_id)
+        .map(|((_, endpoint), sha256)| Pin { endpoint: endpoint.clone(), sha256: sha256.clone() })
+        .collect();
+    pins.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    pins
+}
+
+/// Forget pins of an account (all, or one `host:port`) so the next connect pins afresh
+pub async fn clear_pins(pool: &SqlitePool, account_id: &str, endpoint: Option<&str>) -> Result<u64> {
+    let res = match endpoint {
+        Some(ep) => {
+            sqlx::query("DELETE FROM tls_pins WHERE account_id = ? AND endpoint = ?")
+                .bind(account_id)
+                .bind(ep)
+                .execute(pool)
+                .await?
+        }
+        None => {
+            sqlx::query("DELETE FROM tls_pins WHERE account_id = ?")
+                .bind(account_id)
+                .execute(pool)
+                .await?
+        }
+    };
+    TRUST
+        .write()
+        .unwrap()
+        .pins
+        .retain(|(id, ep), _| id != account_id || endpoint.is_some_and(|e| e != ep));
+    crate::imap::pool::invalidate(account_id);
+    Ok(res.rows_affected())
+}
+
+/// Drop everything known about a deleted account
+pub fn forget(account_id: &str) {
+    let mut trust = TRUST.write().unwrap();
+    trust.policies.remove(account_id);
+    trust.pins.retain(|(id, _), _| id != account_id);
+}
+
+/// Colon-separated upper-case hex SHA-256 of a DER certificate, as shown by `openssl x509 -fingerprint`
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der).iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":")
+}
+
+/// Whether connections for this account skip CA validation (their certificate is pinned instead)
+pub fn accepts_self_signed(account_id: &str) -> bool {
+    policy(account_id) == TlsPolicy::Pin
+}
+
+/// Check a peer certificate against the account's pin for `host:port`, pinning it on first use.
+/// A no-op for `verify` accounts: the TLS handshake already validated the chain.
+pub fn check_peer(account_id: &str, host: &str, port: u16, der: &[u8]) -> Result<()> {
+    if policy(account_id) != TlsPolicy::Pin {
+        return Ok(());
+    }
+    let endpoint = format!("{}:{}", host, port);
+    let seen = fingerprint(der);
+    let key = (account_id.to_string(), endpoint.clone());
+
+    let mut trust = TRUST.write().unwrap();
+    match trust.pins.get(&key) {
+        Some(pinned) if *pinned == seen => Ok(()),
+        Some(pinned) => anyhow::bail!(
+            "TLS certificate for {} changed: pinned SHA-256 {}, server presented {}. \
+             If the server's certificate was renewed, clear the pin (DELETE /accounts/{}/tls-pins) to trust the new one",
+            endpoint,
+            pinned,
+            seen,
+            account_id
+        ),
+        None => {
+            tracing::info!(account_id, endpoint = %endpoint, sha256 = %seen, "pinning TLS certificate on first use");
+            trust.pins.insert(key, seen.clone());
+            persist_pin(account_id, &endpoint, &seen);
+            Ok(())
+        }
+    }
+}
+
+fn persist_pin(account_id: &str, endpoint: &str, sha256: &str) {
+    let (Some(pool), Ok(rt)) = (DB.get().cloned(), tokio::runtime::Handle::try_current()) else { return };
+    let (account_id, endpoint, sha256) = (account_id.to_string(), endpoint.to_string(), sha256.to_string());
+    rt.spawn(async move {
+        let res = sqlx::query("INSERT OR REPLACE INTO tls_pins (account_id, endpoint, sha256) VALUES (?, ?, ?)")
+            .bind(&account_id)
+            .bind(&endpoint)
+            .bind(&sha256)
+            .execute(&pool)
+            .await;
+        if let Err(e) = res {
+            tracing::warn!(account_id = %account_id, endpoint = %endpoint, "failed to persist TLS pin: {e}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_on_first_use() {
+        TRUST.write().unwrap().policies.insert("tofu-test".into(), TlsPolicy::Pin);
+        let (cert_a, cert_b) = (b"certificate A".as_slice(), b"certificate B".as_slice());
+
+        assert!(check_peer("tofu-test", "mail.local", 993, cert_a).is_ok());
+        assert!(check_peer("tofu-test", "mail.local", 993, cert_a).is_ok());
+        let err = check_peer("tofu-test", "mail.local", 993, cert_b).unwrap_err().to_string();
+        assert!(err.contains("mail.local:993"));
+        assert!(err.contains(&fingerprint(cert_a)) && err.contains(&fingerprint(cert_b)));
+        // Pins are per endpoint; verify-mode accounts never consult them
+        assert!(check_peer("tofu-test", "mail.local", 465, cert_b).is_ok());
+        assert!(check_peer("verify-test", "mail.local", 993, cert_b).is_ok());
+    }
+
+    #[test]
+    fn test_fingerprint_format() {
+        let fp = fingerprint(b"");
+        assert_eq!(fp.len(), 32 * 3 - 1);
+        assert!(fp.starts_with("E3:B0:C4:42"));
+    }
+}